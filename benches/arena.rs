@@ -0,0 +1,71 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serializable::arena::{ArenaString, ArenaVec, Bump, DeserializeIn};
+use serializable::Serializable;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[derive(Serializable)]
+struct OwnedMessage
+{
+    id: u64,
+    words: Vec<String>,
+}
+
+#[derive(Serializable)]
+#[serializable(arena)]
+struct ArenaMessage<'a>
+{
+    id: u64,
+    words: ArenaVec<'a, ArenaString<'a>>,
+}
+
+fn count_allocations(f: impl FnOnce()) -> usize
+{
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn arena_deserialization(c: &mut Criterion)
+{
+    let message = OwnedMessage { id: 1, words: (0..1000).map(|i| format!("word number {i}")).collect() };
+    let serialized = message.serialize();
+    let mut arena = Bump::new();
+    // Warm up the arena so that its chunks are already allocated
+    black_box(ArenaMessage::deserialize_in(&serialized, &arena).unwrap());
+    arena.reset();
+
+    let owned_allocations = count_allocations(|| { black_box(OwnedMessage::deserialize(&serialized).unwrap()); });
+    let arena_allocations = count_allocations(|| { black_box(ArenaMessage::deserialize_in(&serialized, &arena).unwrap()); });
+    arena.reset();
+    println!("allocations per message: owned {owned_allocations}, arena {arena_allocations}");
+
+    c.bench_function("deserialize owned 1000 strings", |b| b.iter(|| OwnedMessage::deserialize(black_box(&serialized)).unwrap()));
+    c.bench_function("deserialize arena 1000 strings", |b| b.iter(|| {
+        black_box(ArenaMessage::deserialize_in(black_box(&serialized), &arena).unwrap());
+        arena.reset();
+    }));
+}
+
+criterion_group!(benches, arena_deserialization);
+criterion_main!(benches);
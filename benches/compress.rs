@@ -0,0 +1,16 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serializable::compress::{deserialize_compressed, serialize_compressed};
+use serializable::Serializable;
+
+fn compress_repeated_strings(c: &mut Criterion)
+{
+    let value: Vec<String> = (0..10_000).map(|i| format!("GET /api/v1/items/{} HTTP/1.1", i % 20)).collect();
+    let serialized = value.serialize();
+    let compressed = serialize_compressed(&value);
+    println!("compression ratio: {} -> {} bytes ({:.1}x)", serialized.len(), compressed.len(), serialized.len() as f64 / compressed.len() as f64);
+    c.bench_function("serialize_compressed Vec<String> 10k", |b| b.iter(|| serialize_compressed(black_box(&value))));
+    c.bench_function("deserialize_compressed Vec<String> 10k", |b| b.iter(|| deserialize_compressed::<Vec<String>>(black_box(&compressed)).unwrap()));
+}
+
+criterion_group!(benches, compress_repeated_strings);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serializable::Serializable;
+use serializable::collections::InternedStringVec;
+
+fn interned_strings(c: &mut Criterion)
+{
+    let levels: Vec<String> = (0..10_000).map(|i| ["error", "warning", "info", "debug"][i % 4].to_string()).collect();
+    let interned = InternedStringVec(levels.clone());
+    c.bench_function("serialize Vec<String> 10k repeated", |b| b.iter(|| black_box(&levels).serialize()));
+    c.bench_function("serialize InternedStringVec 10k repeated", |b| b.iter(|| black_box(&interned).serialize()));
+    let serialized = levels.serialize();
+    let serialized_interned = interned.serialize();
+    c.bench_function("deserialize Vec<String> 10k repeated", |b| b.iter(|| Vec::<String>::deserialize(black_box(&serialized)).unwrap()));
+    c.bench_function("deserialize InternedStringVec 10k repeated", |b| b.iter(|| InternedStringVec::deserialize(black_box(&serialized_interned)).unwrap()));
+}
+
+criterion_group!(benches, interned_strings);
+criterion_main!(benches);
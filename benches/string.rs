@@ -0,0 +1,26 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serializable::Serializable;
+
+#[derive(Serializable)]
+struct LogLine
+{
+    host: String,
+    service: String,
+    message: String,
+}
+
+fn string_heavy_message(c: &mut Criterion)
+{
+    let lines: Vec<LogLine> = (0..10_000).map(|i| LogLine {
+        host: format!("host-{}", i % 16),
+        service: "authentication".to_string(),
+        message: format!("user {i} logged in from a rather long and descriptive location"),
+    }).collect();
+    let serialized = lines.serialize();
+    c.bench_function("deserialize Vec<LogLine> 10k", |b| b.iter(|| Vec::<LogLine>::deserialize(black_box(&serialized)).unwrap()));
+    // SAFETY: the data was just written by serialize
+    c.bench_function("deserialize_trusted Vec<LogLine> 10k", |b| b.iter(|| unsafe { Vec::<LogLine>::deserialize_trusted(black_box(&serialized)) }.unwrap()));
+}
+
+criterion_group!(benches, string_heavy_message);
+criterion_main!(benches);
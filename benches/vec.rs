@@ -0,0 +1,32 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serializable::Serializable;
+
+fn vec_u32(c: &mut Criterion)
+{
+    let items: Vec<u32> = (0..1_000_000).collect();
+    let serialized = items.serialize();
+    c.bench_function("serialize Vec<u32> 1M", |b| b.iter(|| black_box(&items).serialize()));
+    c.bench_function("deserialize Vec<u32> 1M", |b| b.iter(|| Vec::<u32>::deserialize(black_box(&serialized)).unwrap()));
+}
+
+fn vec_u8(c: &mut Criterion)
+{
+    let bytes: Vec<u8> = (0..10_000).map(|i| i as u8).collect();
+    let serialized = bytes.serialize();
+    c.bench_function("serialize Vec<u8> 10k", |b| b.iter(|| black_box(&bytes).serialize()));
+    // What the generic impl used to do, one serialization per byte
+    c.bench_function("serialize Vec<u8> 10k byte by byte", |b| b.iter(|| {
+        let bytes = black_box(&bytes);
+        let mut ret = Vec::new();
+        ret.extend((bytes.len() as u32).to_be_bytes());
+        for byte in bytes
+        {
+            ret.extend(byte.serialize());
+        }
+        ret
+    }));
+    c.bench_function("deserialize Vec<u8> 10k", |b| b.iter(|| Vec::<u8>::deserialize(black_box(&serialized)).unwrap()));
+}
+
+criterion_group!(benches, vec_u32, vec_u8);
+criterion_main!(benches);
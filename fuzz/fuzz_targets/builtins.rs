@@ -0,0 +1,23 @@
+#![no_main]
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+
+use libfuzzer_sys::fuzz_target;
+use serializable::fuzz::fuzz_roundtrip;
+
+fuzz_target!(|data: &[u8]| {
+    fuzz_roundtrip::<Vec<u8>>(data);
+    fuzz_roundtrip::<Vec<u32>>(data);
+    fuzz_roundtrip::<Vec<String>>(data);
+    fuzz_roundtrip::<VecDeque<i16>>(data);
+    fuzz_roundtrip::<String>(data);
+    fuzz_roundtrip::<[u8; 16]>(data);
+    fuzz_roundtrip::<[u64; 4]>(data);
+    fuzz_roundtrip::<[String; 3]>(data);
+    fuzz_roundtrip::<Option<Vec<bool>>>(data);
+    fuzz_roundtrip::<(u8, i128, f32, Option<String>)>(data);
+    fuzz_roundtrip::<HashMap<u32, String>>(data);
+    fuzz_roundtrip::<BTreeMap<String, Vec<u16>>>(data);
+    fuzz_roundtrip::<(IpAddr, SocketAddr)>(data);
+});
@@ -0,0 +1,27 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use serializable::fuzz::fuzz_roundtrip;
+use serializable::Serializable;
+
+#[derive(Serializable, Arbitrary, Debug, PartialEq)]
+struct Header
+{
+    id: u64,
+    flags: [u8; 4],
+    name: String,
+}
+
+#[derive(Serializable, Arbitrary, Debug, PartialEq)]
+enum Message
+{
+    Ping,
+    Data(Header, Vec<u8>),
+    Batch { headers: Vec<Header>, compressed: bool },
+}
+
+fuzz_target!(|data: &[u8]| {
+    fuzz_roundtrip::<Header>(data);
+    fuzz_roundtrip::<Message>(data);
+});
@@ -2,11 +2,137 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::{self, spanned::Spanned, DataEnum};
 
-#[proc_macro_derive(Serializable)]
+#[proc_macro_derive(Serializable, attributes(serializable))]
 pub fn serializable_derive(input: TokenStream) -> TokenStream
 {
-    let ast = syn::parse(input).expect("Error during parsing");
-    impl_serializable(&ast)
+    let ast: syn::DeriveInput = syn::parse(input).expect("Error during parsing");
+    match ContainerAttributes::parse(&ast.attrs)
+    {
+        Ok(attributes) => {
+            let mut gen = proc_macro2::TokenStream::from(impl_serializable(&ast, &attributes));
+            if attributes.impl_conversions
+            {
+                gen.extend(impl_conversions(&ast));
+            }
+            if attributes.protobuf
+            {
+                gen.extend(impl_protobuf(&ast).unwrap_or_else(syn::Error::into_compile_error));
+            }
+            if attributes.bincode_compat
+            {
+                gen.extend(impl_bincode_compat(&ast).unwrap_or_else(syn::Error::into_compile_error));
+            }
+            if attributes.patch
+            {
+                gen.extend(impl_patch(&ast).unwrap_or_else(syn::Error::into_compile_error));
+            }
+            else if let Some(field) = find_patch_field(&ast.data)
+            {
+                gen.extend(syn::Error::new(field.span(), "The patch attribute on a field needs the patch attribute on the struct").to_compile_error());
+            }
+            gen.into()
+        },
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Encodings that can replace the default layout of a type
+enum EncodeAs
+{
+    /// The value is written as its `Display` representation and read back with `FromStr`
+    String,
+}
+
+/// Options set with `#[serializable(...)]` on the type itself
+#[derive(Default)]
+struct ContainerAttributes
+{
+    encode_as: Option<EncodeAs>,
+    /// Also implement `DeserializeIn` so that the type can hold data allocated in an arena
+    arena: bool,
+    /// Fail to compile if the in-memory layout of the struct has padding bytes
+    assert_no_padding: bool,
+    /// Also implement `TryFrom<&[u8]>` for the type and `From` the type for `Vec<u8>`
+    impl_conversions: bool,
+    /// Write every field of the struct with its `field_index` and length, so that unknown fields can be skipped.
+    /// Every field must implement `Default`, which is the value of the fields missing from the data
+    indexed: bool,
+    /// Also implement `protobuf::Message`, using the `field_index` of every field as its field number
+    protobuf: bool,
+    /// Also implement `bincode_compat::BincodeCompat`, to read the data written by bincode 1 for the same type
+    bincode_compat: bool,
+    /// Also emit a companion type holding the changes to the struct, and implement `patch::Patchable`
+    patch: bool,
+    /// Also implement `schema::Describe`, which fails to compile for recursive types
+    describe: bool,
+}
+
+impl ContainerAttributes
+{
+    fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self>
+    {
+        let mut ret = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("serializable"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("encode_as")
+                {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    match value.value().as_str()
+                    {
+                        "String" => ret.encode_as = Some(EncodeAs::String),
+                        _ => return Err(syn::Error::new(value.span(), "Unsupported encoding, expected \"String\"")),
+                    }
+                    Ok(())
+                }
+                else if meta.path.is_ident("arena")
+                {
+                    ret.arena = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("assert_no_padding")
+                {
+                    ret.assert_no_padding = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("impl_conversions")
+                {
+                    ret.impl_conversions = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("indexed")
+                {
+                    ret.indexed = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("protobuf")
+                {
+                    ret.protobuf = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("bincode_compat")
+                {
+                    ret.bincode_compat = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("patch")
+                {
+                    ret.patch = true;
+                    Ok(())
+                }
+                else if meta.path.is_ident("describe")
+                {
+                    ret.describe = true;
+                    Ok(())
+                }
+                else
+                {
+                    Err(meta.error("Unknown serializable attribute"))
+                }
+            })?;
+        }
+        Ok(ret)
+    }
 }
 
 fn get_field_names(fields: &syn::Fields) -> Vec<syn::Ident>
@@ -65,83 +191,74 @@ fn get_field_types(fields: &syn::Fields) -> Vec<syn::Type>
     }
 }
 
-fn build_serialize_body(fields: &syn::Fields, prepend_self: bool, use_ref: bool) -> proc_macro2::TokenStream
+/// Builds the statement that appends the serialization of `value` to `bytes` with `method`,
+/// `serialize` writes into `bytes` directly with `serialize_append`
+fn append_value(method: &str, value: proc_macro2::TokenStream) -> proc_macro2::TokenStream
+{
+    if method == "serialize"
+    {
+        quote!{ Serializable::serialize_append(#value, bytes); }
+    }
+    else
+    {
+        let method = syn::Ident::new(method, proc_macro2::Span::call_site());
+        quote!{ bytes.extend(Serializable::#method(#value)); }
+    }
+}
+
+/// Builds the statements that append the serialization of every field to `bytes`
+/// with `method`, either `serialize` or `serialize_deterministic`
+fn build_serialize_body(fields: &syn::Fields, prepend_self: bool, use_ref: bool, method: &str) -> proc_macro2::TokenStream
 {
     let field_names = get_field_names(fields);
-    match fields
+    let values: Vec<proc_macro2::TokenStream> = match fields
     {
-        syn::Fields::Named(_fields) => {
-            match (prepend_self, use_ref)
-            {
-                (true,true) =>
-                    quote!{
-                        #(bytes.extend(Serializable::serialize(&self.#field_names));)*   
-                    },
-                (false,true) =>
-                    quote!{
-                        #(bytes.extend(Serializable::serialize(&#field_names));)*   
-                    },
-                (true,false) =>
-                    quote!{
-                        #(bytes.extend(Serializable::serialize(self.#field_names));)*   
-                    },
-                (false,false) =>
-                    quote!{
-                        #(bytes.extend(Serializable::serialize(#field_names));)*   
-                    },
-            }
-        },
-        syn::Fields::Unnamed(fields) => 
-        {
-            let fields = fields.unnamed.iter().enumerate().map(|(i, f)| {
-                let name = syn::Ident::new(&format!("f{}", i), f.span());
-                let ty = f.ty.clone();
-                (name, ty)
-            });
-            let field_numbers = (0..fields.len()).map(syn::Index::from);
-            match (prepend_self, use_ref)
-            {
-                (true, true) => 
-                    quote! {
-                        #(bytes.extend(Serializable::serialize(&self.#field_numbers));)*    
-                    },
-                (false, true) => 
-                    quote! {
-                        #(bytes.extend(Serializable::serialize(&#field_names));)*    
-                    },
-                (true, false) => 
-                    quote! {
-                        #(bytes.extend(Serializable::serialize(self.#field_numbers));)*    
-                    },
-                (false, false) => 
-                    quote! {
-                        #(bytes.extend(Serializable::serialize(#field_names));)*    
-                    },
-            }
-        },
-        syn::Fields::Unit => 
+        syn::Fields::Named(_) if prepend_self => field_names.iter().map(|name| quote!{ self.#name }).collect(),
+        syn::Fields::Unnamed(_) if prepend_self => (0..fields.len()).map(|i| {
+            let index = syn::Index::from(i);
+            quote!{ self.#index }
+        }).collect(),
+        _ => field_names.iter().map(|name| quote!{ #name }).collect(),
+    };
+    let statements = values.into_iter().map(|value| match use_ref
+    {
+        true => append_value(method, quote!{ &#value }),
+        false => append_value(method, value),
+    });
+    quote!{ #(#statements)* }
+}
+
+/// Returns the names of the fields in the paths of deserialization errors, unnamed fields are named by their position
+fn get_field_labels(fields: &syn::Fields) -> Vec<String>
+{
+    fields.iter().enumerate().map(|(i, field)| {
+        match &field.ident
         {
-            quote! {}
-        },
-    }
+            Some(ident) => ident.to_string(),
+            None => i.to_string(),
+        }
+    }).collect()
 }
 
 fn build_deserialize_body(fields: &syn::Fields) -> proc_macro2::TokenStream
 {
     let field_names = get_field_names(fields);
     let field_types = get_field_types(fields);
+    let field_labels = get_field_labels(fields);
     match fields
     {
         syn::Fields::Named(_fields) => {
             quote!{
-                #(let (#field_names,len) = <#field_types as Serializable>::deserialize(&bytes[offset..])?;
+                #(let (#field_names,len) = <#field_types as Serializable>::deserialize(serializable::serializable::remaining(bytes, offset)?)
+                    .map_err(|e| serializable::serializable::error_at_offset(serializable::trace::in_field(e, #field_labels), offset))?;
                 offset += len;)*
             }
         },
         syn::Fields::Unnamed(_fields) => 
         {
             quote! {
-                #(let (#field_names,len) = <#field_types as Serializable>::deserialize(&bytes[offset..])?;
+                #(let (#field_names,len) = <#field_types as Serializable>::deserialize(serializable::serializable::remaining(bytes, offset)?)
+                    .map_err(|e| serializable::serializable::error_at_offset(serializable::trace::in_field(e, #field_labels), offset))?;
                 offset += len;)*
             }
         },
@@ -152,6 +269,40 @@ fn build_deserialize_body(fields: &syn::Fields) -> proc_macro2::TokenStream
     }
 }
 
+/// Builds the expression that adds up the serialized sizes of the fields, given the references to them,
+/// without serializing them. Indexed structs also write the number of fields, and the index and length of each.
+fn build_size_expr(fields: &syn::Fields, field_refs: &[proc_macro2::TokenStream], indexed: bool) -> proc_macro2::TokenStream
+{
+    let sizes = fields.iter().zip(field_refs).map(|(field, field_ref)| {
+        let attributes = FieldAttributes::parse(field).unwrap_or_default();
+        match attributes.fixed_point
+        {
+            Some(FixedPoint { repr, .. }) => quote!{ <#repr as serializable::FixedSize>::SIZE },
+            // The tag of the field is written by the tag field
+            None if attributes.tag_field.is_some() => quote!{ (Serializable::serialized_size(#field_ref) - 1) },
+            None => quote!{ Serializable::serialized_size(#field_ref) },
+        }
+    });
+    if indexed
+    {
+        quote!{ 2 #(+ 6 + #sizes)* }
+    }
+    else
+    {
+        quote!{ 0 #(+ #sizes)* }
+    }
+}
+
+/// Builds the statements that check every field with `check_bytes`, moving `offset` past them
+fn build_check_body(fields: &syn::Fields) -> proc_macro2::TokenStream
+{
+    let field_types = get_wire_types(fields);
+    quote!{
+        #(offset += <#field_types as Serializable>::check_bytes(serializable::serializable::remaining(bytes, offset)?)
+            .map_err(|e| serializable::serializable::error_at_offset(e, offset))?;)*
+    }
+}
+
 fn build_constructor(fields: &syn::Fields, variation: Option<&syn::Ident>) -> proc_macro2::TokenStream
 {
     let field_names = get_field_names(fields);
@@ -213,113 +364,1644 @@ fn build_constructor(fields: &syn::Fields, variation: Option<&syn::Ident>) -> pr
     }
 }
 
-fn impl_serializable(ast: &syn::DeriveInput) -> TokenStream
+fn impl_string_encoded(name: &syn::Ident) -> proc_macro2::TokenStream
 {
-    let name = &ast.ident;
-    let gen = match &ast.data
+    let name_string = name.to_string();
+    quote!
     {
-        syn::Data::Struct(syn::DataStruct{fields,..}) => 
-        {
-            let serialize_body = build_serialize_body(fields,true, true);
-            let deserialize_body = build_deserialize_body(fields);
-            let constructor_body = build_constructor(fields, None);
-            quote !
+        impl Serializable for #name {
+            fn serialize(&self) -> Vec<u8> {
+                Serializable::serialize(&std::string::ToString::to_string(self))
+            }
+            fn deserialize(bytes: &[u8]) -> std::io::Result<(#name,usize)>{
+                // The error of FromStr is dropped, it is not required to implement Display
+                let result = <String as Serializable>::deserialize(bytes).and_then(|(string, len)| {
+                    match <#name as std::str::FromStr>::from_str(&string) {
+                        Ok(value) => Ok((value, len)),
+                        Err(_) => Err(serializable::serializable::Error::InvalidValue {
+                            type_name: #name_string,
+                            reason: format!("{string:?} is not a valid representation"),
+                        }.into()),
+                    }
+                });
+                serializable::trace::deserialized(#name_string, result)
+            }
+        }
+
+        impl serializable::schema::Describe for #name {
+            fn describe() -> serializable::schema::Schema {
+                <String as serializable::schema::Describe>::describe()
+            }
+        }
+    }
+}
+
+/// Returns true if the type has a `#[repr(packed)]` or `#[repr(packed(N))]` attribute
+fn is_packed(attrs: &[syn::Attribute]) -> bool
+{
+    let mut packed = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("repr"))
+    {
+        // Other representation hints, like `align(N)`, are skipped along with their arguments
+        let _ = attr.parse_nested_meta(|meta| {
+            packed |= meta.path.is_ident("packed");
+            if meta.input.peek(syn::token::Paren)
             {
-                impl Serializable for #name {
-                    fn serialize(&self) -> Vec<u8> {
-                        let mut bytes = Vec::new();
-                        #serialize_body
-                        bytes
+                let _content;
+                syn::parenthesized!(_content in meta.input);
+            }
+            Ok(())
+        });
+    }
+    packed
+}
+
+/// Returns references to every field of `self`.
+/// The fields of a packed struct may be unaligned, so they are copied before being referenced.
+fn self_field_refs(fields: &syn::Fields, packed: bool) -> Vec<proc_macro2::TokenStream>
+{
+    let accesses: Vec<proc_macro2::TokenStream> = match fields
+    {
+        syn::Fields::Named(_) => get_field_names(fields).into_iter().map(|name| quote!{ #name }).collect(),
+        _ => (0..fields.len()).map(syn::Index::from).map(|index| quote!{ #index }).collect(),
+    };
+    accesses.into_iter().map(|access| if packed { quote!{ &{ self.#access } } } else { quote!{ &self.#access } }).collect()
+}
+
+/// Emits a check that every field of a packed struct is `Copy`,
+/// failing to compile with an error pointing at the first field that is not.
+fn assert_packed_fields_copy(fields: &syn::Fields) -> proc_macro2::TokenStream
+{
+    let assertions = fields.iter().map(|field| {
+        let ty = &field.ty;
+        quote::quote_spanned!{ ty.span() => assert_packed_field_is_copy::<#ty>(); }
+    });
+    quote!
+    {
+        fn assert_packed_field_is_copy<T: Copy>() {}
+        #(#assertions)*
+    }
+}
+
+/// Implements `FixedSize` for a struct when all of its fields implement it.
+/// The bounds are higher ranked so that the impl is simply not applicable,
+/// instead of being rejected, when some field is not `FixedSize`.
+fn impl_fixed_size(name: &syn::Ident, generics: &syn::Generics, fields: &syn::Fields, packed: bool) -> proc_macro2::TokenStream
+{
+    let field_types = get_wire_types(fields);
+    let field_refs = get_wire_refs(fields, &self_field_refs(fields, packed));
+    let field_offsets = (0..field_types.len()).map(|i| {
+        let previous_types = &field_types[..i];
+        quote!{ 0 #(+ <#previous_types as serializable::FixedSize>::SIZE)* }
+    });
+    let mut generics = generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in &field_types
+    {
+        where_clause.predicates.push(syn::parse_quote!{ for<'__fixed_size> #ty: serializable::FixedSize });
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote!
+    {
+        impl #impl_generics serializable::FixedSize for #name #ty_generics #where_clause
+        {
+            const SIZE: usize = 0 #(+ <#field_types as serializable::FixedSize>::SIZE)*;
+
+            fn serialize_to_slice(&self, out: &mut [u8]) {
+                #(serializable::FixedSize::serialize_to_slice(#field_refs, &mut out[#field_offsets..]);)*
+            }
+        }
+    }
+}
+
+/// Returns the expressions building the `Field` descriptions of `fields`, unnamed fields are named by their position
+fn build_field_schemas(fields: &syn::Fields) -> Vec<proc_macro2::TokenStream>
+{
+    fields.iter().enumerate().map(|(i, field)| {
+        let name = match &field.ident
+        {
+            Some(ident) => ident.to_string(),
+            None => i.to_string(),
+        };
+        let ty = match get_fixed_point(field)
+        {
+            Some(fixed_point) => fixed_point.repr,
+            None => field.ty.clone(),
+        };
+        quote!{
+            serializable::schema::Field { name: #name, schema: <#ty as serializable::schema::Describe>::describe() }
+        }
+    }).collect()
+}
+
+/// Returns true if `tokens` name the type `name` or `Self`, which is how a recursive type refers to itself
+fn mentions_type(tokens: proc_macro2::TokenStream, name: &syn::Ident) -> bool
+{
+    tokens.into_iter().any(|token| match token
+    {
+        proc_macro2::TokenTree::Ident(ident) => ident == *name || ident == "Self",
+        proc_macro2::TokenTree::Group(group) => mentions_type(group.stream(), name),
+        _ => false,
+    })
+}
+
+/// Implements `Describe` for a struct or an enum marked with `#[serializable(describe)]`,
+/// describing indexed structs with the `field_index` of every field, failing to compile with an error pointing at the first field whose type does not implement it.
+/// Recursive types, with a field whose type mentions the type itself, fail to compile too.
+fn impl_describe(name: &syn::Ident, generics: &syn::Generics, data: &syn::Data, attributes: &ContainerAttributes) -> proc_macro2::TokenStream
+{
+    if !attributes.describe
+    {
+        return quote!{};
+    }
+    let name_string = name.to_string();
+    let (field_types, describe_body) = match data
+    {
+        syn::Data::Struct(syn::DataStruct { fields, .. }) if attributes.indexed => {
+            let field_schemas = build_field_schemas(fields);
+            // Missing field indices are reported by the Serializable impl
+            let field_indices = fields.iter().map(|field| FieldAttributes::parse(field).ok().and_then(|attributes| attributes.field_index).unwrap_or_default());
+            (
+                get_wire_types(fields),
+                quote!{
+                    serializable::schema::Schema::IndexedStruct {
+                        name: #name_string,
+                        fields: vec![#(serializable::schema::IndexedField { index: #field_indices, field: #field_schemas }),*],
                     }
-                    fn deserialize(bytes: &[u8]) -> std::io::Result<(#name,usize)>{
-                        let mut offset: usize = 0;
-                        #deserialize_body
-                        Ok((#constructor_body, offset))
+                },
+            )
+        },
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => {
+            let field_schemas = build_field_schemas(fields);
+            (
+                get_wire_types(fields),
+                quote!{
+                    serializable::schema::Schema::Struct { name: #name_string, fields: vec![#(#field_schemas),*] }
+                },
+            )
+        },
+        syn::Data::Enum(DataEnum { variants, .. }) => {
+            let variant_names = variants.iter().map(|variant| variant.ident.to_string());
+            let variant_fields = variants.iter().map(|variant| build_field_schemas(&variant.fields));
+            (
+                variants.iter().flat_map(|variant| get_field_types(&variant.fields)).collect(),
+                quote!{
+                    serializable::schema::Schema::Enum {
+                        name: #name_string,
+                        variants: vec![#(serializable::schema::Variant { name: #variant_names, fields: vec![#(#variant_fields),*] }),*],
                     }
-                }
+                },
+            )
+        },
+        syn::Data::Union(_) => return quote!{},
+    };
+    // The schema of a recursive type would be infinite
+    if let Some(ty) = field_types.iter().find(|ty| mentions_type(quote!{ #ty }, name))
+    {
+        return syn::Error::new(ty.span(), "The describe attribute is not supported on recursive types").to_compile_error();
+    }
+    let mut generics = generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in &field_types
+    {
+        where_clause.predicates.push(syn::parse_quote_spanned!{ ty.span() => #ty: serializable::schema::Describe });
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote!
+    {
+        impl #impl_generics serializable::schema::Describe for #name #ty_generics #where_clause
+        {
+            fn describe() -> serializable::schema::Schema {
+                #describe_body
             }
+        }
+    }
+}
+
+/// Implements `DeserializeIn` for a struct by deserializing every field in the same arena.
+/// The arena lifetime is the first lifetime parameter of the struct, if any.
+fn impl_deserialize_in(name: &syn::Ident, generics: &syn::Generics, fields: &syn::Fields) -> proc_macro2::TokenStream
+{
+    let field_names = get_field_names(fields);
+    let field_types = get_field_types(fields);
+    let constructor_body = build_constructor(fields, None);
+    let mut impl_generics = generics.clone();
+    let arena_lifetime = match generics.lifetimes().next()
+    {
+        Some(lifetime) => lifetime.lifetime.clone(),
+        None => {
+            let lifetime = syn::Lifetime::new("'__arena", proc_macro2::Span::call_site());
+            impl_generics.params.insert(0, syn::GenericParam::Lifetime(syn::LifetimeParam::new(lifetime.clone())));
+            lifetime
         },
-        syn::Data::Enum(DataEnum { variants , ..}) => {
-            let variant_indices_0 = (0..variants.len()).map(syn::Index::from);
-            let variant_indices_1 = (0..variants.len()).map(syn::Index::from);
-            let variant_names = variants.iter().map(|v| v.ident.clone());
-            
-            let variant_fields = variants.iter().map(|v| v.fields.clone());
-            let variant_fields_serialization = variant_fields.clone().map(|fields|
+    };
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+    quote!
+    {
+        impl #impl_generics serializable::arena::DeserializeIn<#arena_lifetime> for #name #ty_generics #where_clause
+        {
+            fn deserialize_in(bytes: &[u8], arena: &#arena_lifetime serializable::arena::Bump) -> std::io::Result<(Self,usize)> {
+                let mut offset: usize = 0;
+                #(let (#field_names,len) = <#field_types as serializable::arena::DeserializeIn<#arena_lifetime>>::deserialize_in(serializable::serializable::remaining(bytes, offset)?, arena)?;
+                offset += len;)*
+                Ok((#constructor_body, offset))
+            }
+        }
+    }
+}
+
+/// Options set with `#[serializable(...)]` on a field
+#[derive(Default)]
+struct FieldAttributes
+{
+    /// The field borrows from the input buffer
+    borrow: bool,
+    /// Preceding field that holds the tag of this enum field, which is then written without its tag
+    tag_field: Option<syn::Ident>,
+    /// Stable number of the field in an indexed struct
+    field_index: Option<u16>,
+    /// The float field is written as a scaled integer
+    fixed_point: Option<FixedPoint>,
+    /// The changes to the field are themselves a patch, instead of a whole new value
+    patch: bool,
+}
+
+/// Options of `#[serializable(fixed_point(scale = 100, repr = "i16"))]`
+struct FixedPoint
+{
+    /// Literal the value is multiplied by before being rounded
+    scale: syn::Lit,
+    /// Integer type that is written
+    repr: syn::Type,
+    /// Out of range values are written as the closest integer instead of panicking
+    saturate: bool,
+}
+
+impl FixedPoint
+{
+    fn parse(meta: &syn::meta::ParseNestedMeta) -> syn::Result<Self>
+    {
+        let mut scale = None;
+        let mut repr = None;
+        let mut saturate = false;
+        meta.parse_nested_meta(|inner| {
+            if inner.path.is_ident("scale")
             {
-                build_serialize_body(&fields, false, false)
-            });
-            let variant_fields_deserialization = variant_fields.clone().map(|fields|
+                let value: syn::Lit = inner.value()?.parse()?;
+                let parsed = match &value
+                {
+                    syn::Lit::Int(value) => value.base10_parse::<f64>(),
+                    syn::Lit::Float(value) => value.base10_parse::<f64>(),
+                    _ => Err(syn::Error::new(value.span(), "The scale must be a number")),
+                }?;
+                if !(parsed > 0.0 && parsed.is_finite())
+                {
+                    return Err(syn::Error::new(value.span(), "The scale must be greater than zero"));
+                }
+                scale = Some(value);
+                Ok(())
+            }
+            else if inner.path.is_ident("repr")
             {
-                build_deserialize_body(&fields)
-            });
-
-            let variant_names_and_fields = variant_names.zip(variant_fields.clone());
-            let variant_constructors = variant_names_and_fields.clone().map(|(name, fields)|
+                let value: syn::LitStr = inner.value()?.parse()?;
+                if !["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64"].contains(&value.value().as_str())
+                {
+                    return Err(syn::Error::new(value.span(), "Unsupported repr, expected an integer type up to 64 bits"));
+                }
+                repr = Some(value.parse()?);
+                Ok(())
+            }
+            else if inner.path.is_ident("saturate")
             {
-                build_constructor(&fields, Some(&name))
-            });
-
-            let variant_names_match = variant_names_and_fields.map(|(name, fields)|
+                saturate = true;
+                Ok(())
+            }
+            else
             {
-                let field_names = get_field_names(&fields);
-                match fields
+                Err(inner.error("Unknown fixed_point option"))
+            }
+        })?;
+        match (scale, repr)
+        {
+            (Some(scale), Some(repr)) => Ok(Self { scale, repr, saturate }),
+            _ => Err(meta.error("The fixed_point attribute needs a scale and a repr")),
+        }
+    }
+}
+
+impl FieldAttributes
+{
+    fn parse(field: &syn::Field) -> syn::Result<Self>
+    {
+        let mut ret = Self::default();
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("serializable"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("borrow")
                 {
-                    syn::Fields::Named(_) => 
-                    {
-                        quote! {
-                            Self::#name{
-                                #(#field_names),*
-                            }
-                        }
-                    },
-                    syn::Fields::Unnamed(_) => 
-                    {
-                        quote! {
-                            Self::#name(
-                                #(#field_names),*
-                            )
-                        }
-                    },
-                    syn::Fields::Unit => 
-                    {
-                        quote! {
-                            Self::#name
-                        }
-                    },
+                    ret.borrow = true;
+                    Ok(())
                 }
-            });
-            
-            quote!{
-                impl Serializable for #name {
-                    fn serialize(&self) -> Vec<u8>
-                    {
-                        let mut bytes = Vec::new();
+                else if meta.path.is_ident("tag_field")
+                {
+                    let value: syn::LitStr = meta.value()?.parse()?;
+                    ret.tag_field = Some(value.parse()?);
+                    Ok(())
+                }
+                else if meta.path.is_ident("field_index")
+                {
+                    let value: syn::LitInt = meta.value()?.parse()?;
+                    ret.field_index = Some(value.base10_parse()?);
+                    Ok(())
+                }
+                else if meta.path.is_ident("fixed_point")
+                {
+                    ret.fixed_point = Some(FixedPoint::parse(&meta)?);
+                    Ok(())
+                }
+                else if meta.path.is_ident("patch")
+                {
+                    ret.patch = true;
+                    Ok(())
+                }
+                else
+                {
+                    Err(meta.error("Unknown serializable field attribute"))
+                }
+            })?;
+        }
+        Ok(ret)
+    }
+}
+
+/// Builds the statements that deserialize every field with `deserialize_trusted`,
+/// which is only called by the `deserialize_trusted` of the type on data the caller vouches for
+fn build_trusted_body(fields: &syn::Fields) -> proc_macro2::TokenStream
+{
+    let field_names = get_field_names(fields);
+    let wire_types = get_wire_types(fields);
+    let from_wire = fields.iter().zip(&field_names).map(|(field, name)| from_wire_value(field, name));
+    quote!{
+        #(let (#field_names,len) = unsafe { <#wire_types as Serializable>::deserialize_trusted(serializable::serializable::remaining(bytes, offset)?) }
+            .map_err(|e| serializable::serializable::error_at_offset(e, offset))?;
+        #from_wire
+        offset += len;)*
+    }
+}
+
+/// Builds the statements that read every field from `reader` with `deserialize_from`, moving `offset` past them
+fn build_read_body(fields: &syn::Fields) -> proc_macro2::TokenStream
+{
+    let field_names = get_field_names(fields);
+    let wire_types = get_wire_types(fields);
+    let field_labels = get_field_labels(fields);
+    let from_wire = fields.iter().zip(&field_names).map(|(field, name)| from_wire_value(field, name));
+    quote!{
+        #(let (#field_names,len) = <#wire_types as Serializable>::deserialize_from(reader)
+            .map_err(|e| serializable::serializable::error_at_offset(serializable::trace::in_field(e, #field_labels), offset))?;
+        #from_wire
+        offset += len;)*
+    }
+}
+
+/// Returns the fixed point options of the field, errors in the attributes are reported by `build_struct_bodies`
+fn get_fixed_point(field: &syn::Field) -> Option<FixedPoint>
+{
+    FieldAttributes::parse(field).ok().and_then(|attributes| attributes.fixed_point)
+}
+
+/// Returns the types that are written for the fields, which is the integer type of fixed point fields
+fn get_wire_types(fields: &syn::Fields) -> Vec<syn::Type>
+{
+    fields.iter().map(|field| match get_fixed_point(field)
+    {
+        Some(fixed_point) => fixed_point.repr,
+        None => field.ty.clone(),
+    }).collect()
+}
+
+/// Returns references to the values that are written for the fields, given the references to the fields
+fn get_wire_refs(fields: &syn::Fields, field_refs: &[proc_macro2::TokenStream]) -> Vec<proc_macro2::TokenStream>
+{
+    fields.iter().zip(field_refs).zip(get_field_labels(fields)).map(|((field, field_ref), label)| match get_fixed_point(field)
+    {
+        Some(FixedPoint { scale, repr, saturate }) => quote!{
+            &serializable::fixed_point::encode::<#repr>(*#field_ref as f64, #scale as f64, #saturate, #label)
+        },
+        None => field_ref.clone(),
+    }).collect()
+}
+
+/// Builds the statement that turns the value read for a fixed point field, bound to `name`, into the field value
+fn from_wire_value(field: &syn::Field, name: &syn::Ident) -> proc_macro2::TokenStream
+{
+    match get_fixed_point(field)
+    {
+        Some(FixedPoint { scale, .. }) => {
+            let ty = &field.ty;
+            quote!{ let #name = serializable::fixed_point::decode(#name, #scale as f64) as #ty; }
+        },
+        None => quote!{},
+    }
+}
+
+/// Returns true if the field is marked with `#[serializable(borrow)]`
+fn is_borrowed(field: &syn::Field) -> syn::Result<bool>
+{
+    Ok(FieldAttributes::parse(field)?.borrow)
+}
+
+/// Builds the statements that serialize the fields of a struct with `method`, either `serialize`
+/// or `serialize_deterministic`, and the statements that deserialize them.
+/// Enum fields with a tag field are written without their tag, and the tag field is written with the tag
+/// of their variant instead of its own value, so that the encoding can always be read back.
+/// A tag field that doesn't match the variant fails a debug assertion.
+fn build_struct_bodies(fields: &syn::Fields, packed: bool, method: &str) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)>
+{
+    let append = |value| append_value(method, value);
+    let field_names = get_field_names(fields);
+    let field_refs = self_field_refs(fields, packed);
+    let field_labels = get_field_labels(fields);
+    let mut serialize_body = quote!{};
+    let mut deserialize_body = quote!{};
+    for (i, field) in fields.iter().enumerate()
+    {
+        let (name, field_ref, ty, label) = (&field_names[i], &field_refs[i], &field.ty, &field_labels[i]);
+        let attributes = FieldAttributes::parse(field)?;
+        if let Some(FixedPoint { scale, repr, saturate }) = attributes.fixed_point
+        {
+            if attributes.tag_field.is_some()
+            {
+                return Err(syn::Error::new(field.span(), "The fixed_point and tag_field attributes cannot be used together"));
+            }
+            if !matches!(ty, syn::Type::Path(path) if path.path.is_ident("f32") || path.path.is_ident("f64"))
+            {
+                return Err(syn::Error::new(ty.span(), "The fixed_point attribute is only supported on f32 and f64 fields"));
+            }
+            serialize_body.extend(append(quote!{
+                &serializable::fixed_point::encode::<#repr>(*#field_ref as f64, #scale as f64, #saturate, #label)
+            }));
+            deserialize_body.extend(quote!{
+                let (#name,len) = <#repr as Serializable>::deserialize(serializable::serializable::remaining(bytes, offset)?)
+                    .map_err(|e| serializable::serializable::error_at_offset(serializable::trace::in_field(e, #label), offset))?;
+                let #name = serializable::fixed_point::decode(#name, #scale as f64) as #ty;
+                offset += len;
+            });
+            continue;
+        }
+        let tag_field = match attributes.tag_field
+        {
+            Some(tag_field) => tag_field,
+            None => {
+                let tagged = fields.iter().enumerate().skip(i + 1)
+                    .find(|(_, later)| FieldAttributes::parse(later).is_ok_and(|later| later.tag_field.as_ref() == Some(name)));
+                match tagged
+                {
+                    Some((tagged_index, _)) => {
+                        let (tagged_ref, tagged_label) = (&field_refs[tagged_index], &field_labels[tagged_index]);
+                        serialize_body.extend(quote!{
+                            let tag = serializable::serializable::ExternallyTagged::tag(#tagged_ref);
+                            debug_assert!(
+                                <u8 as std::convert::TryFrom<#ty>>::try_from(Clone::clone(#field_ref)).ok() == Some(tag),
+                                "The tag field {} does not match the variant of {}", #label, #tagged_label
+                            );
+                        });
+                        serialize_body.extend(append(quote!{ &<#ty as std::convert::From<u8>>::from(tag) }));
+                    },
+                    None => serialize_body.extend(append(quote!{ #field_ref })),
+                }
+                deserialize_body.extend(quote!{
+                    let (#name,len) = <#ty as Serializable>::deserialize(serializable::serializable::remaining(bytes, offset)?)
+                        .map_err(|e| serializable::serializable::error_at_offset(serializable::trace::in_field(e, #label), offset))?;
+                    offset += len;
+                });
+                continue;
+            },
+        };
+        if !matches!(fields, syn::Fields::Named(_)) || !field_names[..i].contains(&tag_field)
+        {
+            return Err(syn::Error::new(tag_field.span(), "The tag field must be a named field that precedes the tagged field"));
+        }
+        // The tag is already in the tag field, so it is removed from the serialization of the enum
+        let append_tagged = append(quote!{ #field_ref });
+        serialize_body.extend(quote!{
+            let start = bytes.len();
+            #append_tagged
+            bytes.remove(start);
+        });
+        let tag_field_label = tag_field.to_string();
+        deserialize_body.extend(quote!{
+            let (#name,len) = match <u8 as std::convert::TryFrom<_>>::try_from(Clone::clone(&#tag_field))
+            {
+                Ok(tag) => <#ty as serializable::serializable::ExternallyTagged>::deserialize_variant(tag, serializable::serializable::remaining(bytes, offset)?),
+                Err(_) => Err(serializable::serializable::Error::InvalidValue {
+                    type_name: std::any::type_name::<#ty>(),
+                    reason: format!("the tag field {} is not a variant index", #tag_field_label),
+                }.into()),
+            }.map_err(|e| serializable::serializable::error_at_offset(serializable::trace::in_field(e, #label), offset))?;
+            offset += len;
+        });
+    }
+    Ok((serialize_body, deserialize_body))
+}
+
+/// Builds the statements that serialize the fields of an indexed struct with `method`, either `serialize`
+/// or `serialize_deterministic`, and the statements that deserialize them.
+///
+/// The serialization is the number of fields as a `u16` followed by the `u16` field index, the `u32` length
+/// and the serialization of every field. Fields with an unknown index, written by a newer version, are skipped,
+/// and fields missing from the data, written by an older version, take their default value,
+/// failing to compile with an error pointing at the type of the first field that does not implement `Default`.
+fn build_indexed_bodies(fields: &syn::Fields, packed: bool, method: &str) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)>
+{
+    let method = syn::Ident::new(method, proc_macro2::Span::call_site());
+    let field_names = get_field_names(fields);
+    let field_types = get_field_types(fields);
+    let field_refs = self_field_refs(fields, packed);
+    let field_labels = get_field_labels(fields);
+    let mut field_indices: Vec<u16> = Vec::new();
+    for field in fields
+    {
+        let attributes = FieldAttributes::parse(field)?;
+        if let Some(tag_field) = attributes.tag_field
+        {
+            return Err(syn::Error::new(tag_field.span(), "The tag_field attribute is not supported on indexed structs"));
+        }
+        if attributes.fixed_point.is_some()
+        {
+            return Err(syn::Error::new(field.span(), "The fixed_point attribute is not supported on indexed structs"));
+        }
+        match attributes.field_index
+        {
+            Some(index) if field_indices.contains(&index) => return Err(syn::Error::new(field.span(), format!("Field index {index} is used more than once"))),
+            Some(index) => field_indices.push(index),
+            None => return Err(syn::Error::new(field.span(), "Every field of an indexed struct needs a field_index")),
+        }
+    }
+    let defaults = fields.iter().zip(&field_names).map(|(field, name)| {
+        let ty = &field.ty;
+        quote::quote_spanned!{ ty.span() => let #name = indexed_field_default_if_missing::<#ty>(#name); }
+    });
+    let field_count = u16::try_from(field_indices.len()).map_err(|_| syn::Error::new(fields.span(), "An indexed struct can have at most 65535 fields"))?;
+    let serialize_body = quote!{
+        bytes.extend(#field_count.to_be_bytes());
+        #({
+            let field_bytes = Serializable::#method(#field_refs);
+            bytes.extend(#field_indices.to_be_bytes());
+            bytes.extend(serializable::serializable::length_prefix(field_bytes.len()));
+            bytes.extend(field_bytes);
+        })*
+    };
+    let deserialize_body = quote!{
+        fn indexed_field_default_if_missing<T: Default>(value: Option<T>) -> T
+        {
+            value.unwrap_or_default()
+        }
+        #(let mut #field_names: Option<#field_types> = None;)*
+        let (field_count, len) = <u16 as Serializable>::deserialize(bytes)?;
+        offset += len;
+        for _ in 0..field_count
+        {
+            let ((field_index, field_len), len) = <(u16, u32) as Serializable>::deserialize(serializable::serializable::remaining(bytes, offset)?)
+                .map_err(|e| serializable::serializable::error_at_offset(e, offset))?;
+            offset += len;
+            let field_bytes = match serializable::serializable::remaining(bytes, offset)?.get(..field_len as usize)
+            {
+                Some(field_bytes) => field_bytes,
+                None => {
+                    let available = bytes.len() - offset;
+                    let error = serializable::serializable::Error::UnexpectedEof { needed: field_len as usize, available }.into();
+                    return Err(serializable::serializable::error_at_offset(error, offset));
+                },
+            };
+            match field_index
+            {
+                #(#field_indices => {
+                    if #field_names.is_some()
+                    {
+                        let error = serializable::serializable::Error::DuplicateField { index: field_index }.into();
+                        return Err(serializable::serializable::error_at_offset(error, offset - len));
+                    }
+                    #field_names = Some(<#field_types as Serializable>::deserialize_exact(field_bytes)
+                        .map_err(|e| serializable::serializable::error_at_offset(serializable::trace::in_field(e, #field_labels), offset))?);
+                },)*
+                _ => {},
+            }
+            offset += field_bytes.len();
+        }
+        #(#defaults)*
+    };
+    Ok((serialize_body, deserialize_body))
+}
+
+/// Fails if a field that is not in a struct, or that is in a struct with borrowed fields,
+/// has a tag field or a field index
+fn check_struct_field_attributes(data: &syn::Data) -> syn::Result<()>
+{
+    let fields: Vec<&syn::Field> = match data
+    {
+        syn::Data::Struct(_) if !has_borrowed_fields(data)? => Vec::new(),
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => fields.iter().collect(),
+        syn::Data::Enum(DataEnum { variants, .. }) => variants.iter().flat_map(|variant| &variant.fields).collect(),
+        syn::Data::Union(_) => Vec::new(),
+    };
+    for field in fields
+    {
+        let attributes = FieldAttributes::parse(field)?;
+        if let Some(tag_field) = attributes.tag_field
+        {
+            return Err(syn::Error::new(tag_field.span(), "The tag_field attribute is only supported on the fields of structs without borrowed fields"));
+        }
+        if attributes.field_index.is_some()
+        {
+            return Err(syn::Error::new(field.span(), "The field_index attribute is only supported on the fields of structs without borrowed fields"));
+        }
+        if attributes.fixed_point.is_some()
+        {
+            return Err(syn::Error::new(field.span(), "The fixed_point attribute is only supported on the fields of structs without borrowed fields"));
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if some field of the type is marked with `#[serializable(borrow)]`
+fn has_borrowed_fields(data: &syn::Data) -> syn::Result<bool>
+{
+    let fields: Vec<&syn::Field> = match data
+    {
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => fields.iter().collect(),
+        syn::Data::Enum(DataEnum { variants, .. }) => variants.iter().flat_map(|variant| &variant.fields).collect(),
+        syn::Data::Union(_) => Vec::new(),
+    };
+    for field in fields
+    {
+        if is_borrowed(field)?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Builds the statements that serialize the fields bound to `field_names` and those that deserialize them
+/// from `bytes`, borrowed fields go through `SerializableRef` and the others through `Serializable`
+fn build_ref_bodies(fields: &syn::Fields, field_names: &[proc_macro2::TokenStream], lifetime: &syn::Lifetime) -> syn::Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)>
+{
+    let mut serialize_body = quote!{};
+    let mut deserialize_body = quote!{};
+    for (field, (binding, access)) in fields.iter().zip(get_field_names(fields).iter().zip(field_names))
+    {
+        let ty = &field.ty;
+        if is_borrowed(field)?
+        {
+            serialize_body.extend(quote!{
+                bytes.extend(serializable::borrow::SerializableRef::serialize(#access));
+            });
+            deserialize_body.extend(quote!{
+                let (#binding,len) = <#ty as serializable::borrow::SerializableRef<#lifetime>>::deserialize_ref(serializable::serializable::remaining(bytes, offset)?)
+                    .map_err(|e| serializable::serializable::error_at_offset(e, offset))?;
+                offset += len;
+            });
+        }
+        else
+        {
+            serialize_body.extend(quote!{
+                bytes.extend(Serializable::serialize(#access));
+            });
+            deserialize_body.extend(quote!{
+                let (#binding,len) = <#ty as Serializable>::deserialize(serializable::serializable::remaining(bytes, offset)?)
+                    .map_err(|e| serializable::serializable::error_at_offset(e, offset))?;
+                offset += len;
+            });
+        }
+    }
+    Ok((serialize_body, deserialize_body))
+}
+
+/// Implements `SerializableRef` for a type with fields marked `#[serializable(borrow)]`,
+/// which borrow from the input buffer for the first lifetime parameter of the type.
+/// Such a type cannot implement `Serializable`, whose input doesn't outlive the deserialized value.
+fn impl_serializable_ref(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream>
+{
+    let name = &ast.ident;
+    let name_string = name.to_string();
+    let lifetime = match ast.generics.lifetimes().next()
+    {
+        Some(lifetime) => lifetime.lifetime.clone(),
+        None => return Err(syn::Error::new(name.span(), "Borrowed fields need a lifetime parameter on the type")),
+    };
+    let generics = add_serializable_bounds(&ast.generics);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (serialize_body, deserialize_body) = match &ast.data
+    {
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => {
+            let accesses = self_field_refs(fields, is_packed(&ast.attrs));
+            let (serialize_body, deserialize_body) = build_ref_bodies(fields, &accesses, &lifetime)?;
+            let constructor = build_constructor(fields, None);
+            (
+                serialize_body,
+                quote!{
+                    let mut offset: usize = 0;
+                    #deserialize_body
+                    Ok((#constructor, offset))
+                },
+            )
+        },
+        syn::Data::Enum(DataEnum { variants, .. }) => {
+            let mut serialize_arms = quote!{};
+            let mut deserialize_arms = quote!{};
+            for (index, variant) in variants.iter().enumerate()
+            {
+                let index = index as u8;
+                let variant_name = &variant.ident;
+                let bindings = get_field_names(&variant.fields);
+                let accesses: Vec<proc_macro2::TokenStream> = bindings.iter().map(|binding| quote!{ #binding }).collect();
+                let (serialize_body, deserialize_body) = build_ref_bodies(&variant.fields, &accesses, &lifetime)?;
+                let pattern = match &variant.fields
+                {
+                    syn::Fields::Named(_) => quote!{ Self::#variant_name { #(#bindings),* } },
+                    syn::Fields::Unnamed(_) => quote!{ Self::#variant_name ( #(#bindings),* ) },
+                    syn::Fields::Unit => quote!{ Self::#variant_name },
+                };
+                let constructor = build_constructor(&variant.fields, Some(variant_name));
+                serialize_arms.extend(quote!{
+                    #pattern => {
+                        bytes.push(#index);
+                        #serialize_body
+                    }
+                });
+                deserialize_arms.extend(quote!{
+                    #index => {
+                        #deserialize_body
+                        Ok((#constructor, offset))
+                    }
+                });
+            }
+            (
+                quote!{
+                    match self {
+                        #serialize_arms
+                    }
+                },
+                quote!{
+                    let mut offset: usize = 1;
+                    match bytes.first() {
+                        None => Err(serializable::serializable::Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+                        Some(variant_index) => match *variant_index {
+                            #deserialize_arms
+                            found => Err(serializable::serializable::Error::InvalidVariant { found, type_name: #name_string }.into()),
+                        },
+                    }
+                },
+            )
+        },
+        syn::Data::Union(_) => return Err(syn::Error::new(name.span(), "Unions are not supported")),
+    };
+    Ok(quote!
+    {
+        impl #impl_generics serializable::borrow::SerializableRef<#lifetime> for #name #ty_generics #where_clause
+        {
+            fn serialize(&self) -> Vec<u8> {
+                let mut bytes = Vec::new();
+                #serialize_body
+                bytes
+            }
+            fn deserialize_ref(bytes: &#lifetime [u8]) -> std::io::Result<(Self,usize)> {
+                #deserialize_body
+            }
+        }
+    })
+}
+
+/// Emits a compile time assertion that the size of the struct is the sum of the sizes of its fields.
+/// The assertion is a free constant, so it cannot refer to generic parameters.
+fn assert_no_padding(name: &syn::Ident, generics: &syn::Generics, fields: &syn::Fields) -> proc_macro2::TokenStream
+{
+    if !generics.params.is_empty()
+    {
+        return syn::Error::new(name.span(), "The assert_no_padding attribute is not supported on generic structs").to_compile_error();
+    }
+    let field_types = get_field_types(fields);
+    quote!
+    {
+        const _: () = assert!(
+            std::mem::size_of::<#name>() == 0 #(+ std::mem::size_of::<#field_types>())*,
+            "struct has padding bytes"
+        );
+    }
+}
+
+/// Implements `TryFrom<&[u8]>` for the type, which fails on trailing bytes like `deserialize_exact`,
+/// and `From` the type and references to it for `Vec<u8>`
+fn impl_conversions(ast: &syn::DeriveInput) -> proc_macro2::TokenStream
+{
+    let name = &ast.ident;
+    match has_borrowed_fields(&ast.data)
+    {
+        Ok(false) => {},
+        Ok(true) => return syn::Error::new(name.span(), "The impl_conversions attribute is not supported on types with borrowed fields").to_compile_error(),
+        Err(e) => return e.to_compile_error(),
+    }
+    let generics = add_serializable_bounds(&ast.generics);
+    let (_, ty_generics, where_clause) = generics.split_for_impl();
+    let mut bytes_generics = generics.clone();
+    bytes_generics.params.insert(0, syn::parse_quote!('__bytes));
+    let (bytes_impl_generics, _, _) = bytes_generics.split_for_impl();
+    let (impl_generics, _, _) = generics.split_for_impl();
+    quote!
+    {
+        impl #bytes_impl_generics std::convert::TryFrom<&'__bytes [u8]> for #name #ty_generics #where_clause
+        {
+            type Error = std::io::Error;
+
+            fn try_from(bytes: &'__bytes [u8]) -> std::io::Result<Self> {
+                <Self as Serializable>::deserialize_exact(bytes)
+            }
+        }
+
+        impl #bytes_impl_generics std::convert::From<&'__bytes #name #ty_generics> for Vec<u8> #where_clause
+        {
+            fn from(value: &'__bytes #name #ty_generics) -> Vec<u8> {
+                Serializable::serialize(value)
+            }
+        }
+
+        impl #impl_generics std::convert::From<#name #ty_generics> for Vec<u8> #where_clause
+        {
+            fn from(value: #name #ty_generics) -> Vec<u8> {
+                Serializable::serialize(&value)
+            }
+        }
+    }
+}
+
+/// Implements `protobuf::Message` for a struct whose fields all have a `field_index`,
+/// which is the field number, and `protobuf::Value` so that it can be nested in other messages
+fn impl_protobuf(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream>
+{
+    let name = &ast.ident;
+    let fields = match &ast.data
+    {
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => fields,
+        _ => return Err(syn::Error::new(name.span(), "The protobuf attribute is only supported on structs")),
+    };
+    if has_borrowed_fields(&ast.data)?
+    {
+        return Err(syn::Error::new(name.span(), "The protobuf attribute is not supported on types with borrowed fields"));
+    }
+    let mut field_numbers: Vec<u32> = Vec::new();
+    for field in fields
+    {
+        match FieldAttributes::parse(field)?.field_index
+        {
+            Some(0) => return Err(syn::Error::new(field.span(), "Protobuf field numbers start from 1")),
+            Some(index) if field_numbers.contains(&(index as u32)) => return Err(syn::Error::new(field.span(), format!("Field index {index} is used more than once"))),
+            Some(index) => field_numbers.push(index as u32),
+            None => return Err(syn::Error::new(field.span(), "Every field of a protobuf message needs a field_index")),
+        }
+    }
+    let field_names = get_field_names(fields);
+    let field_types = get_field_types(fields);
+    let field_refs = self_field_refs(fields, is_packed(&ast.attrs));
+    let constructor = build_constructor(fields, None);
+    // Spanned on the field types, so that unsupported types are reported there
+    let encode_fields = field_types.iter().zip(&field_refs).zip(&field_numbers).map(|((ty, field_ref), number)| {
+        quote::quote_spanned!{ ty.span() => serializable::protobuf::Field::encode_field(#field_ref, #number, &mut bytes); }
+    });
+    let mut generics = ast.generics.clone();
+    for param in generics.type_params_mut()
+    {
+        param.bounds.push(syn::parse_quote!(serializable::protobuf::Field));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote!
+    {
+        impl #impl_generics serializable::protobuf::Message for #name #ty_generics #where_clause
+        {
+            fn to_protobuf(&self) -> Vec<u8> {
+                let mut bytes = Vec::new();
+                #(#encode_fields)*
+                bytes
+            }
+
+            fn from_protobuf(bytes: &[u8]) -> std::io::Result<Self> {
+                #(let mut #field_names: #field_types = Default::default();)*
+                let mut offset: usize = 0;
+                while offset < bytes.len()
+                {
+                    let ((number, wire_type), len) = serializable::protobuf::read_key(serializable::serializable::remaining(bytes, offset)?)
+                        .map_err(|e| serializable::serializable::error_at_offset(e, offset))?;
+                    offset += len;
+                    let len = match number
+                    {
+                        #(#field_numbers => serializable::protobuf::Field::merge_field(&mut #field_names, wire_type, serializable::serializable::remaining(bytes, offset)?),)*
+                        _ => serializable::protobuf::skip_field(wire_type, serializable::serializable::remaining(bytes, offset)?),
+                    }.map_err(|e| serializable::serializable::error_at_offset(e, offset))?;
+                    offset += len;
+                }
+                Ok(#constructor)
+            }
+        }
+
+        impl #impl_generics serializable::protobuf::Value for #name #ty_generics #where_clause
+        {
+            const WIRE_TYPE: u8 = serializable::protobuf::LEN;
+
+            fn encode_value(&self, out: &mut Vec<u8>) {
+                serializable::protobuf::write_len_delimited(&serializable::protobuf::Message::to_protobuf(self), out)
+            }
+
+            fn decode_value(data: &[u8]) -> std::io::Result<(Self,usize)> {
+                let (bytes, len) = serializable::protobuf::read_len_delimited(data)?;
+                let message = serializable::protobuf::Message::from_protobuf(bytes)
+                    .map_err(|e| serializable::serializable::error_at_offset(e, len - bytes.len()))?;
+                Ok((message, len))
+            }
+        }
+    })
+}
+
+/// Reads the fields in order with `BincodeCompat` into local variables named like the fields
+fn build_bincode_compat_body(fields: &syn::Fields) -> proc_macro2::TokenStream
+{
+    let field_names = get_field_names(fields);
+    let field_types = get_field_types(fields);
+    quote!
+    {
+        #(
+            let (#field_names, len) = <#field_types as serializable::bincode_compat::BincodeCompat>::deserialize_bincode_compat(serializable::serializable::remaining(data, offset)?)
+                .map_err(|e| serializable::serializable::error_at_offset(e, offset))?;
+            offset += len;
+        )*
+    }
+}
+
+/// Implements `bincode_compat::BincodeCompat` with the layout serde gives to the type,
+/// the fields in order and, for enums, the index of the variant as a `u32`
+fn impl_bincode_compat(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream>
+{
+    let name = &ast.ident;
+    let name_string = name.to_string();
+    if has_borrowed_fields(&ast.data)?
+    {
+        return Err(syn::Error::new(name.span(), "The bincode_compat attribute is not supported on types with borrowed fields"));
+    }
+    let body = match &ast.data
+    {
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => {
+            let read_fields = build_bincode_compat_body(fields);
+            let constructor = build_constructor(fields, None);
+            quote!
+            {
+                #[allow(unused_mut)]
+                let mut offset: usize = 0;
+                #read_fields
+                Ok((#constructor, offset))
+            }
+        },
+        syn::Data::Enum(DataEnum { variants, .. }) => {
+            let arms = variants.iter().enumerate().map(|(i, variant)| {
+                let index = i as u32;
+                let read_fields = build_bincode_compat_body(&variant.fields);
+                let constructor = build_constructor(&variant.fields, Some(&variant.ident));
+                quote!
+                {
+                    #index => {
+                        #read_fields
+                        Ok((#constructor, offset))
+                    }
+                }
+            });
+            quote!
+            {
+                #[allow(unused_mut)]
+                let (variant, mut offset) = <u32 as serializable::bincode_compat::BincodeCompat>::deserialize_bincode_compat(data)?;
+                match variant
+                {
+                    #(#arms,)*
+                    found => match u8::try_from(found)
+                    {
+                        Ok(found) => Err(serializable::serializable::Error::InvalidVariant { found, type_name: #name_string }.into()),
+                        Err(_) => {
+                            let reason = format!("variant index {found} is out of range");
+                            Err(serializable::serializable::Error::InvalidValue { type_name: #name_string, reason }.into())
+                        },
+                    },
+                }
+            }
+        },
+        syn::Data::Union(_) => return Err(syn::Error::new(name.span(), "Unions are not supported")),
+    };
+    let mut generics = ast.generics.clone();
+    for param in generics.type_params_mut()
+    {
+        param.bounds.push(syn::parse_quote!(serializable::bincode_compat::BincodeCompat));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote!
+    {
+        impl #impl_generics serializable::bincode_compat::BincodeCompat for #name #ty_generics #where_clause
+        {
+            fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)> {
+                #body
+            }
+        }
+    })
+}
+
+/// Returns the first field of the type marked with `#[serializable(patch)]`
+fn find_patch_field(data: &syn::Data) -> Option<&syn::Field>
+{
+    let mut fields: Box<dyn Iterator<Item = &syn::Field>> = match data
+    {
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => Box::new(fields.iter()),
+        syn::Data::Enum(DataEnum { variants, .. }) => Box::new(variants.iter().flat_map(|variant| &variant.fields)),
+        syn::Data::Union(_) => return None,
+    };
+    fields.find(|field| FieldAttributes::parse(field).is_ok_and(|attributes| attributes.patch))
+}
+
+/// Emits `<Name>Patch`, a struct with an `Option` for every field of the struct that is `Some` when
+/// the field changed, written as a bitmap of the fields that are present followed by their values.
+/// Implements `patch::Patchable` for the struct, nested patches are used for the fields marked with
+/// `#[serializable(patch)]`.
+fn impl_patch(ast: &syn::DeriveInput) -> syn::Result<proc_macro2::TokenStream>
+{
+    let name = &ast.ident;
+    let fields = match &ast.data
+    {
+        syn::Data::Struct(syn::DataStruct { fields, .. }) => fields,
+        _ => return Err(syn::Error::new(name.span(), "The patch attribute is only supported on structs")),
+    };
+    if has_borrowed_fields(&ast.data)?
+    {
+        return Err(syn::Error::new(name.span(), "The patch attribute is not supported on types with borrowed fields"));
+    }
+    let patch_name = quote::format_ident!("{}Patch", name);
+    let patch_string = patch_name.to_string();
+    let doc = format!("Changes to the fields of a [`{name}`], made by `Patchable::diff` and applied by `Patchable::apply`");
+    let vis = &ast.vis;
+    let field_names = get_field_names(fields);
+    let field_labels = get_field_labels(fields);
+    let accesses: Vec<proc_macro2::TokenStream> = match fields
+    {
+        syn::Fields::Named(_) => field_names.iter().map(|name| quote!{ #name }).collect(),
+        _ => (0..fields.len()).map(syn::Index::from).map(|index| quote!{ #index }).collect(),
+    };
+    let bitmap_len = fields.len().div_ceil(8);
+    let field_count = fields.len();
+
+    let mut struct_generics = ast.generics.clone();
+    let mut impl_generics = ast.generics.clone();
+    let mut patch_fields = Vec::new();
+    let mut diffs = Vec::new();
+    let mut applies = Vec::new();
+    for (field, access) in fields.iter().zip(&accesses)
+    {
+        let ty = &field.ty;
+        let field_vis = &field.vis;
+        let inner = if FieldAttributes::parse(field)?.patch
+        {
+            struct_generics.make_where_clause().predicates.push(syn::parse_quote!{ #ty: serializable::patch::Patchable });
+            impl_generics.make_where_clause().predicates.push(syn::parse_quote!{ #ty: serializable::patch::Patchable });
+            diffs.push(quote!{
+                {
+                    let patch = serializable::patch::Patchable::diff(&self.#access, &other.#access);
+                    if <#ty as serializable::patch::Patchable>::is_unchanged(&patch) { None } else { Some(patch) }
+                }
+            });
+            applies.push(quote!{
+                if let Some(patch) = patch.#access
+                {
+                    serializable::patch::Patchable::apply(&mut self.#access, patch);
+                }
+            });
+            quote!{ <#ty as serializable::patch::Patchable>::Patch }
+        }
+        else
+        {
+            impl_generics.make_where_clause().predicates.push(syn::parse_quote!{ #ty: Serializable + Clone + PartialEq });
+            diffs.push(quote!{
+                if self.#access != other.#access { Some(other.#access.clone()) } else { None }
+            });
+            applies.push(quote!{
+                if let Some(value) = patch.#access
+                {
+                    self.#access = value;
+                }
+            });
+            quote!{ #ty }
+        };
+        patch_fields.push((field_vis, inner));
+    }
+    let (patch_vis, inner_types): (Vec<_>, Vec<_>) = patch_fields.into_iter().unzip();
+    let indices = 0..field_count;
+    let (struct_impl_generics, struct_ty_generics, struct_where_clause) = struct_generics.split_for_impl();
+    let patch_struct = match fields
+    {
+        syn::Fields::Named(_) => quote!{
+            #[doc = #doc]
+            #vis struct #patch_name #struct_impl_generics #struct_where_clause
+            {
+                #(#patch_vis #field_names: Option<#inner_types>,)*
+            }
+        },
+        syn::Fields::Unnamed(_) => quote!{
+            #[doc = #doc]
+            #vis struct #patch_name #struct_impl_generics (#(#patch_vis Option<#inner_types>),*) #struct_where_clause;
+        },
+        syn::Fields::Unit => quote!{
+            #[doc = #doc]
+            #vis struct #patch_name #struct_impl_generics #struct_where_clause;
+        },
+    };
+    let constructor = build_constructor(fields, None);
+    let (impl_generics, ty_generics, where_clause) = impl_generics.split_for_impl();
+    let serialize_bodies = ["serialize", "serialize_deterministic"].map(|method| {
+        let method = syn::Ident::new(method, proc_macro2::Span::call_site());
+        let indices = indices.clone();
+        quote!{
+            serializable::__trace_span!(#patch_string, "serialize");
+            let mut bytes = vec![0u8; #bitmap_len];
+            #(
+                if let Some(value) = &self.#accesses
+                {
+                    serializable::patch::mark_present(&mut bytes, #indices);
+                    bytes.extend(Serializable::#method(value));
+                }
+            )*
+            serializable::trace::serialized(&bytes);
+            bytes
+        }
+    });
+    let [serialize_body, serialize_deterministic_body] = serialize_bodies;
+    Ok(quote!
+    {
+        #patch_struct
+
+        impl #struct_impl_generics Default for #patch_name #struct_ty_generics #struct_where_clause
+        {
+            fn default() -> Self {
+                Self { #(#accesses: None),* }
+            }
+        }
+
+        impl #impl_generics Serializable for #patch_name #ty_generics #where_clause
+        {
+            fn serialize(&self) -> Vec<u8> {
+                #serialize_body
+            }
+
+            fn serialize_deterministic(&self) -> Vec<u8> {
+                #serialize_deterministic_body
+            }
+
+            fn deserialize(bytes: &[u8]) -> std::io::Result<(Self,usize)> {
+                serializable::__trace_span!(#patch_string, "deserialize");
+                let result = (|| {
+                    let bitmap = serializable::patch::read_bitmap(bytes, #field_count)?;
+                    #[allow(unused_mut)]
+                    let mut offset: usize = bitmap.len();
+                    #(
+                        let #field_names = if serializable::patch::is_present(bitmap, #indices)
+                        {
+                            let (value,len) = <#inner_types as Serializable>::deserialize(serializable::serializable::remaining(bytes, offset)?)
+                                .map_err(|e| serializable::serializable::error_at_offset(serializable::trace::in_field(e, #field_labels), offset))?;
+                            offset += len;
+                            Some(value)
+                        }
+                        else
+                        {
+                            None
+                        };
+                    )*
+                    Ok((#constructor, offset))
+                })();
+                serializable::trace::deserialized(#patch_string, result)
+            }
+        }
+
+        impl #impl_generics serializable::patch::Patchable for #name #ty_generics #where_clause
+        {
+            type Patch = #patch_name #ty_generics;
+
+            fn diff(&self, other: &Self) -> Self::Patch {
+                #patch_name { #(#accesses: #diffs),* }
+            }
+
+            fn apply(&mut self, patch: Self::Patch) {
+                #(#applies)*
+            }
+
+            fn is_unchanged(patch: &Self::Patch) -> bool {
+                true #(&& patch.#accesses.is_none())*
+            }
+        }
+    })
+}
+
+/// Returns the generics of the type with a `Serializable` bound added to every type parameter
+fn add_serializable_bounds(generics: &syn::Generics) -> syn::Generics
+{
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut()
+    {
+        param.bounds.push(syn::parse_quote!(Serializable));
+    }
+    generics
+}
+
+fn impl_serializable(ast: &syn::DeriveInput, attributes: &ContainerAttributes) -> TokenStream
+{
+    let name = &ast.ident;
+    if let Some(EncodeAs::String) = attributes.encode_as
+    {
+        return impl_string_encoded(name).into();
+    }
+    if let Err(e) = check_struct_field_attributes(&ast.data)
+    {
+        return e.to_compile_error().into();
+    }
+    match has_borrowed_fields(&ast.data)
+    {
+        Ok(true) if attributes.indexed => {
+            return syn::Error::new(name.span(), "The indexed attribute is not supported on types with borrowed fields").to_compile_error().into();
+        },
+        Ok(true) => return impl_serializable_ref(ast).unwrap_or_else(syn::Error::into_compile_error).into(),
+        Ok(false) => {},
+        Err(e) => return e.to_compile_error().into(),
+    }
+    let generics = add_serializable_bounds(&ast.generics);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let describe_impl = impl_describe(name, &generics, &ast.data, attributes);
+    let name_string = name.to_string();
+    let gen = match &ast.data
+    {
+        syn::Data::Struct(_) if attributes.indexed && attributes.arena => {
+            syn::Error::new(name.span(), "The arena attribute is not supported on indexed structs").to_compile_error()
+        },
+        syn::Data::Struct(syn::DataStruct{fields,..}) if attributes.arena && fields.iter().any(|field| get_fixed_point(field).is_some()) => {
+            syn::Error::new(name.span(), "The arena attribute is not supported on structs with fixed_point fields").to_compile_error()
+        },
+        syn::Data::Struct(syn::DataStruct{fields,..}) =>
+        {
+            let packed = is_packed(&ast.attrs);
+            if !attributes.indexed && !attributes.protobuf
+            {
+                for field in fields
+                {
+                    match FieldAttributes::parse(field)
+                    {
+                        Ok(FieldAttributes { field_index: None, .. }) => {},
+                        Ok(_) => return syn::Error::new(field.span(), "The field_index attribute needs the indexed or the protobuf attribute on the struct").to_compile_error().into(),
+                        Err(e) => return e.to_compile_error().into(),
+                    }
+                }
+            }
+            let build_bodies = if attributes.indexed { build_indexed_bodies } else { build_struct_bodies };
+            let bodies = build_bodies(fields, packed, "serialize")
+                .and_then(|(serialize_body, deserialize_body)| {
+                    let (serialize_deterministic_body, _) = build_bodies(fields, packed, "serialize_deterministic")?;
+                    Ok((serialize_body, serialize_deterministic_body, deserialize_body))
+                });
+            let (serialize_body, serialize_deterministic_body, deserialize_body) = match bodies
+            {
+                Ok(bodies) => bodies,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            // Indexed fields and fields read according to a tag field need the values of other fields,
+            // so they are checked by deserializing them, and read from streams one byte at a time
+            let has_tag_fields = fields.iter().any(|field| FieldAttributes::parse(field).is_ok_and(|field| field.tag_field.is_some()));
+            let check_bytes_fn = if attributes.indexed || has_tag_fields
+            {
+                quote!{}
+            }
+            else
+            {
+                let check_body = build_check_body(fields);
+                let trusted_body = build_trusted_body(fields);
+                let read_body = build_read_body(fields);
+                let constructor_body = build_constructor(fields, None);
+                quote!{
+                    fn check_bytes(bytes: &[u8]) -> std::io::Result<usize> {
+                        let mut offset: usize = 0;
+                        #check_body
+                        Ok(offset)
+                    }
+                    unsafe fn deserialize_trusted(bytes: &[u8]) -> std::io::Result<(Self,usize)> {
+                        let mut offset: usize = 0;
+                        #trusted_body
+                        Ok((#constructor_body, offset))
+                    }
+                    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+                        let mut offset: usize = 0;
+                        #read_body
+                        Ok((#constructor_body, offset))
+                    }
+                }
+            };
+            let packed_assertion = if packed
+            {
+                assert_packed_fields_copy(fields)
+            }
+            else
+            {
+                quote!{}
+            };
+            let constructor_body = build_constructor(fields, None);
+            let size_expr = build_size_expr(fields, &self_field_refs(fields, packed), attributes.indexed);
+            let wire_types = get_wire_types(fields);
+            // The size of an indexed struct includes the index and the length of every field
+            let (fixed_size, fixed_size_impl) = if attributes.indexed
+            {
+                (quote!{ None }, quote!{})
+            }
+            else
+            {
+                (
+                    quote!{ serializable::serializable::sum_fixed_sizes(&[#(<#wire_types as Serializable>::FIXED_SIZE),*]) },
+                    impl_fixed_size(name, &generics, fields, packed),
+                )
+            };
+            let padding_assertion = if attributes.assert_no_padding
+            {
+                assert_no_padding(name, &ast.generics, fields)
+            }
+            else
+            {
+                quote!{}
+            };
+            let deserialize_in_impl = if attributes.arena
+            {
+                impl_deserialize_in(name, &generics, fields)
+            }
+            else
+            {
+                quote!{}
+            };
+            quote !
+            {
+                #padding_assertion
+
+                #fixed_size_impl
+
+                #describe_impl
+
+                #deserialize_in_impl
+
+                impl #impl_generics Serializable for #name #ty_generics #where_clause {
+                    const FIXED_SIZE: Option<usize> = #fixed_size;
+
+                    fn serialize(&self) -> Vec<u8> {
+                        let mut bytes = Vec::new();
+                        Serializable::serialize_append(self, &mut bytes);
+                        bytes
+                    }
+                    fn serialize_append(&self, bytes: &mut Vec<u8>) {
+                        #packed_assertion
+                        serializable::__trace_span!(#name_string, "serialize");
+                        let start = bytes.len();
+                        #serialize_body
+                        serializable::trace::serialized(&bytes[start..]);
+                    }
+                    fn serialized_size(&self) -> usize {
+                        #size_expr
+                    }
+                    fn serialize_deterministic(&self) -> Vec<u8> {
+                        let mut bytes = Vec::new();
+                        #serialize_deterministic_body
+                        bytes
+                    }
+                    fn deserialize(bytes: &[u8]) -> std::io::Result<(Self,usize)>{
+                        serializable::__trace_span!(#name_string, "deserialize");
+                        let result = (|| {
+                            let mut offset: usize = 0;
+                            #deserialize_body
+                            Ok((#constructor_body, offset))
+                        })();
+                        serializable::trace::deserialized(#name_string, result)
+                    }
+                    #check_bytes_fn
+                }
+            }
+        },
+        syn::Data::Enum(_) if attributes.indexed => {
+            syn::Error::new(name.span(), "The indexed attribute is only supported on structs").to_compile_error()
+        },
+        syn::Data::Enum(_) if attributes.arena => {
+            syn::Error::new(name.span(), "The arena attribute is only supported on structs").to_compile_error()
+        },
+        syn::Data::Enum(_) if attributes.assert_no_padding => {
+            syn::Error::new(name.span(), "The assert_no_padding attribute is only supported on structs").to_compile_error()
+        },
+        syn::Data::Enum(DataEnum { variants , ..}) => {
+            let variant_indices_0 = (0..variants.len()).map(syn::Index::from);
+            let variant_indices_1 = (0..variants.len()).map(syn::Index::from);
+            let variant_indices_2 = (0..variants.len()).map(syn::Index::from);
+            let variant_indices_3 = (0..variants.len()).map(syn::Index::from);
+            let variant_indices_4 = (0..variants.len()).map(syn::Index::from);
+            let variant_names = variants.iter().map(|v| v.ident.clone());
+            
+            let variant_fields = variants.iter().map(|v| v.fields.clone());
+            let variant_fields_serialization = variant_fields.clone().map(|fields|
+            {
+                build_serialize_body(&fields, false, false, "serialize")
+            });
+            let variant_fields_deterministic_serialization = variant_fields.clone().map(|fields|
+            {
+                build_serialize_body(&fields, false, false, "serialize_deterministic")
+            });
+            let variant_fields_deserialization = variant_fields.clone().map(|fields|
+            {
+                build_deserialize_body(&fields)
+            }).collect::<Vec<_>>();
+
+            let variant_names_and_fields = variant_names.zip(variant_fields.clone());
+            let variant_fields_check = variant_fields.clone().map(|fields|
+            {
+                build_check_body(&fields)
+            });
+            let variant_indices_5 = (0..variants.len()).map(syn::Index::from);
+            let variant_fields_trusted = variant_fields.clone().map(|fields|
+            {
+                build_trusted_body(&fields)
+            });
+            let variant_indices_6 = (0..variants.len()).map(syn::Index::from);
+            let variant_fields_read = variant_fields.clone().map(|fields|
+            {
+                build_read_body(&fields)
+            });
+            let variant_indices_7 = (0..variants.len()).map(syn::Index::from);
+            let variant_sizes = variant_fields.clone().map(|fields|
+            {
+                let field_refs = get_field_names(&fields).into_iter().map(|name| quote!{ #name }).collect::<Vec<_>>();
+                build_size_expr(&fields, &field_refs, false)
+            });
+
+            let variant_constructors = variant_names_and_fields.clone().map(|(name, fields)|
+            {
+                build_constructor(&fields, Some(&name))
+            }).collect::<Vec<_>>();
+
+            let variant_names_match = variant_names_and_fields.map(|(name, fields)|
+            {
+                let field_names = get_field_names(&fields);
+                match fields
+                {
+                    syn::Fields::Named(_) => 
+                    {
+                        quote! {
+                            Self::#name{
+                                #(#field_names),*
+                            }
+                        }
+                    },
+                    syn::Fields::Unnamed(_) => 
+                    {
+                        quote! {
+                            Self::#name(
+                                #(#field_names),*
+                            )
+                        }
+                    },
+                    syn::Fields::Unit => 
+                    {
+                        quote! {
+                            Self::#name
+                        }
+                    },
+                }
+            }).collect::<Vec<_>>();
+            let variant_patterns = variants.iter().map(|variant|
+            {
+                let name = &variant.ident;
+                match variant.fields
+                {
+                    syn::Fields::Named(_) => quote!{ Self::#name { .. } },
+                    syn::Fields::Unnamed(_) => quote!{ Self::#name(..) },
+                    syn::Fields::Unit => quote!{ Self::#name },
+                }
+            });
+            
+            quote!{
+                #describe_impl
+
+                impl #impl_generics Serializable for #name #ty_generics #where_clause {
+                    fn serialize(&self) -> Vec<u8>
+                    {
+                        let mut bytes = Vec::new();
+                        Serializable::serialize_append(self, &mut bytes);
+                        bytes
+                    }
+                    fn serialize_append(&self, bytes: &mut Vec<u8>)
+                    {
+                        serializable::__trace_span!(#name_string, "serialize");
+                        let start = bytes.len();
                         match self {
                             #(#variant_names_match => {
                                 bytes.push(#variant_indices_0);
                                 #variant_fields_serialization
                             })*
                         }
-                        bytes
+                        serializable::trace::serialized(&bytes[start..]);
                     }
-                    fn deserialize(bytes: &[u8]) -> std::io::Result<(#name,usize)>
+                    fn serialized_size(&self) -> usize
                     {
-                        let mut offset: usize = 0;
-                        if bytes.len() == 0 {
-                            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data size"))
+                        match self {
+                            #(#variant_names_match => 1 + #variant_sizes,)*
                         }
-                        else
-                        {
-                            let variant_index = bytes[0];
-                            offset += 1;
-                            match variant_index {
-                                #(#variant_indices_1 => {
-                                    #variant_fields_deserialization
-                                    Ok((#variant_constructors, offset))
-                                })*
-                                _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid variant index")),
+                    }
+                    fn serialize_deterministic(&self) -> Vec<u8>
+                    {
+                        let mut bytes = Vec::new();
+                        match self {
+                            #(#variant_names_match => {
+                                bytes.push(#variant_indices_2);
+                                #variant_fields_deterministic_serialization
+                            })*
+                        }
+                        bytes
+                    }
+                    fn deserialize(bytes: &[u8]) -> std::io::Result<(Self,usize)>
+                    {
+                        serializable::__trace_span!(#name_string, "deserialize");
+                        let result = (|| {
+                            let mut offset: usize = 0;
+                            if bytes.len() == 0 {
+                                Err(serializable::serializable::Error::UnexpectedEof { needed: 1, available: 0 }.into())
                             }
+                            else
+                            {
+                                let variant_index = bytes[0];
+                                offset += 1;
+                                match variant_index {
+                                    #(#variant_indices_1 => {
+                                        #variant_fields_deserialization
+                                        Ok((#variant_constructors, offset))
+                                    })*
+                                    found => Err(serializable::serializable::Error::InvalidVariant { found, type_name: #name_string }.into()),
+                                }
+                            }
+                        })();
+                        serializable::trace::deserialized(#name_string, result)
+                    }
+                    fn check_bytes(bytes: &[u8]) -> std::io::Result<usize>
+                    {
+                        let mut offset: usize = 1;
+                        match bytes.first() {
+                            None => Err(serializable::serializable::Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+                            #(Some(#variant_indices_5) => {
+                                #variant_fields_check
+                                Ok(offset)
+                            })*
+                            Some(&found) => Err(serializable::serializable::Error::InvalidVariant { found, type_name: #name_string }.into()),
+                        }
+                    }
+                    unsafe fn deserialize_trusted(bytes: &[u8]) -> std::io::Result<(Self,usize)>
+                    {
+                        let mut offset: usize = 1;
+                        match bytes.first() {
+                            None => Err(serializable::serializable::Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+                            #(Some(#variant_indices_6) => {
+                                #variant_fields_trusted
+                                Ok((#variant_constructors, offset))
+                            })*
+                            Some(&found) => {
+                                debug_assert!(false, "Invalid variant index");
+                                Err(serializable::serializable::Error::InvalidVariant { found, type_name: #name_string }.into())
+                            },
+                        }
+                    }
+                    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)>
+                    {
+                        let mut offset: usize = 1;
+                        match <u8 as Serializable>::deserialize_from(reader)?.0 {
+                            #(#variant_indices_7 => {
+                                #variant_fields_read
+                                Ok((#variant_constructors, offset))
+                            })*
+                            found => Err(serializable::serializable::Error::InvalidVariant { found, type_name: #name_string }.into()),
+                        }
+                    }
+                }
+
+                impl #impl_generics serializable::serializable::ExternallyTagged for #name #ty_generics #where_clause {
+                    fn tag(&self) -> u8
+                    {
+                        match self {
+                            #(#variant_patterns => #variant_indices_3,)*
+                        }
+                    }
+                    fn deserialize_variant(tag: u8, bytes: &[u8]) -> std::io::Result<(Self,usize)>
+                    {
+                        let mut offset: usize = 0;
+                        match tag {
+                            #(#variant_indices_4 => {
+                                #variant_fields_deserialization
+                                Ok((#variant_constructors, offset))
+                            })*
+                            _ => Err(serializable::serializable::Error::InvalidVariant { found: tag, type_name: #name_string }.into()),
                         }
                     }
                 }
@@ -0,0 +1,233 @@
+//! Files of serialized records with an index for random access.
+//!
+//! An archive is a sequence of records, each one its length as a 4 byte big endian number
+//! followed by the serialization of the value, then the index and a trailer.
+//! The index is the offset of every record as an 8 byte big endian number, the trailer is the number
+//! of records as an 8 byte big endian number, the CRC-32 of the index and of the count and the [`MAGIC`].
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::log::crc32;
+use crate::Serializable;
+
+/// Bytes that end every complete archive
+pub const MAGIC: [u8; 4] = *b"SRLA";
+
+/// Length of the trailer at the end of the file
+const TRAILER_LEN: usize = 16;
+
+/// Writes an archive, the archive can only be read after [`ArchiveWriter::finish`]
+pub struct ArchiveWriter<T: Serializable>
+{
+    file: BufWriter<File>,
+    offsets: Vec<u64>,
+    len: u64,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Serializable> ArchiveWriter<T>
+{
+    /// Creates the archive at `path`, replacing the file if it exists
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self>
+    {
+        Ok(Self { file: BufWriter::new(File::create(path)?), offsets: Vec::new(), len: 0, _phantom: PhantomData })
+    }
+
+    /// Appends a record, returning its index
+    pub fn append(&mut self, value: &T) -> std::io::Result<usize>
+    {
+        let payload = value.serialize();
+        let len: u32 = payload.len().try_into()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Record too long"))?;
+        self.file.write_all(&len.to_be_bytes())?;
+        self.file.write_all(&payload)?;
+        self.offsets.push(self.len);
+        self.len += 4 + payload.len() as u64;
+        Ok(self.offsets.len() - 1)
+    }
+
+    /// Writes the index and the trailer and waits until the archive is stored on the disk
+    pub fn finish(mut self) -> std::io::Result<()>
+    {
+        let mut footer = Vec::with_capacity(self.offsets.len() * 8 + TRAILER_LEN);
+        for offset in &self.offsets
+        {
+            footer.extend_from_slice(&offset.to_be_bytes());
+        }
+        footer.extend_from_slice(&(self.offsets.len() as u64).to_be_bytes());
+        let checksum = crc32(&footer);
+        footer.extend_from_slice(&checksum.to_be_bytes());
+        footer.extend_from_slice(&MAGIC);
+        self.file.write_all(&footer)?;
+        self.file.flush()?;
+        self.file.get_ref().sync_all()
+    }
+}
+
+/// Reads the records of an archive in any order through a memory map of the file
+pub struct ArchiveReader<T: Serializable>
+{
+    map: Mmap,
+    /// Offset of the index, which is also the end of the records
+    index_offset: usize,
+    len: usize,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Serializable> ArchiveReader<T>
+{
+    /// Opens the archive at `path`.
+    /// Fails with [`std::io::ErrorKind::InvalidData`] if the archive is incomplete or its index is corrupt.
+    ///
+    /// The file must not be modified while the reader is alive, as it is memory mapped.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self>
+    {
+        let file = File::open(path)?;
+        if file.metadata()?.len() < TRAILER_LEN as u64
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Archive is incomplete"));
+        }
+        // SAFETY: the file is only read, the caller is told not to modify it while it is mapped
+        let map = unsafe { Mmap::map(&file)? };
+        let trailer = &map[map.len() - TRAILER_LEN..];
+        if trailer[12..] != MAGIC
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Archive is incomplete"));
+        }
+        let len = u64::from_be_bytes(trailer[..8].try_into().expect("Slice has 8 bytes"));
+        let checksum = u32::from_be_bytes(trailer[8..12].try_into().expect("Slice has 4 bytes"));
+        let index_len = len.checked_mul(8).filter(|index_len| *index_len <= (map.len() - TRAILER_LEN) as u64)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Corrupt archive index"))?;
+        let index_offset = map.len() - TRAILER_LEN - index_len as usize;
+        if crc32(&map[index_offset..map.len() - 4 - 4]) != checksum
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Corrupt archive index"));
+        }
+        Ok(Self { map, index_offset, len: len as usize, _phantom: PhantomData })
+    }
+
+    /// Returns the number of records
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+
+    /// Returns true if the archive has no records
+    pub fn is_empty(&self) -> bool
+    {
+        self.len == 0
+    }
+
+    /// Deserializes the record at `index`, fails with [`std::io::ErrorKind::InvalidInput`] if it is out of range
+    pub fn get(&self, index: usize) -> std::io::Result<T>
+    {
+        T::deserialize_exact(self.record(index)?)
+    }
+
+    /// Returns the serialization of the record at `index`
+    pub fn record(&self, index: usize) -> std::io::Result<&[u8]>
+    {
+        if index >= self.len
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Record {index} out of range, the archive has {} records", self.len)));
+        }
+        let entry = self.index_offset + index * 8;
+        let offset = u64::from_be_bytes(self.map[entry..entry + 8].try_into().expect("Slice has 8 bytes"));
+        let record = usize::try_from(offset).ok()
+            .and_then(|offset| self.map[..self.index_offset].get(offset..))
+            .and_then(|record| {
+                let len = u32::from_be_bytes(record.get(..4)?.try_into().expect("Slice has 4 bytes")) as usize;
+                record.get(4..4usize.checked_add(len)?)
+            });
+        record.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "Corrupt archive record"))
+    }
+
+    /// Returns an iterator over the records in order
+    pub fn iter(&self) -> impl Iterator<Item = std::io::Result<T>> + '_
+    {
+        (0..self.len).map(|index| self.get(index))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{ArchiveReader, ArchiveWriter};
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, Clone, PartialEq)]
+    struct Sample
+    {
+        id: u32,
+        label: String,
+        values: Vec<f32>,
+    }
+
+    fn sample(id: u32) -> Sample
+    {
+        Sample { id, label: format!("sample-{id}"), values: (0..id % 17).map(|i| i as f32 / 2.0).collect() }
+    }
+
+    fn write_archive(path: &std::path::Path, len: u32)
+    {
+        let mut writer = ArchiveWriter::<Sample>::create(path).unwrap();
+        for id in 0..len
+        {
+            assert_eq!(writer.append(&sample(id)).unwrap(), id as usize);
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn random_access()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        write_archive(file.path(), 5000);
+        let reader = ArchiveReader::<Sample>::open(file.path()).unwrap();
+        assert_eq!(reader.len(), 5000);
+        for id in [0, 1, 17, 2500, 4321, 4999]
+        {
+            assert_eq!(reader.get(id as usize).unwrap(), sample(id));
+        }
+        assert_eq!(reader.get(5000).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(reader.iter().map(Result::unwrap).collect::<Vec<_>>(), (0..5000).map(sample).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn empty_archive()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        write_archive(file.path(), 0);
+        let reader = ArchiveReader::<Sample>::open(file.path()).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(reader.iter().count(), 0);
+    }
+
+    #[test]
+    fn reject_incomplete_and_corrupt_archives()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = ArchiveWriter::<Sample>::create(file.path()).unwrap();
+        writer.append(&sample(1)).unwrap();
+        drop(writer);
+        assert_eq!(ArchiveReader::<Sample>::open(file.path()).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        write_archive(file.path(), 100);
+        let mut data = std::fs::read(file.path()).unwrap();
+        let index_entry = data.len() - 16 - 8 * 50;
+        data[index_entry + 7] ^= 1;
+        std::fs::write(file.path(), &data).unwrap();
+        assert_eq!(ArchiveReader::<Sample>::open(file.path()).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+
+        let len = data.len();
+        data[len - 16..len - 8].copy_from_slice(&u64::MAX.to_be_bytes());
+        std::fs::write(file.path(), &data).unwrap();
+        assert_eq!(ArchiveReader::<Sample>::open(file.path()).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,298 @@
+//! Deserialization of allocation heavy messages into a [`Bump`] arena.
+//!
+//! Structs marked with `#[serializable(arena)]` implement [`DeserializeIn`]:
+//! their [`ArenaString`] and [`ArenaVec`] fields borrow memory from the arena
+//! while the other fields are deserialized as usual.
+//! The values borrow the arena, so it can be [`Bump::reset`] and reused
+//! for the next message only once they have been dropped.
+
+use std::ops::Deref;
+
+pub use bumpalo::Bump;
+
+use crate::serializable::{bounded_capacity, error_at_offset, items_size, length_prefix};
+use crate::{Error, Serializable};
+
+/// Types that can be deserialized with their data allocated in an arena
+pub trait DeserializeIn<'a>: Sized
+{
+    /// Returns the deserialized object and the number of bytes read,
+    /// the data of the object is allocated in `arena`
+    fn deserialize_in(data: &[u8], arena: &'a Bump) -> std::io::Result<(Self,usize)>;
+}
+
+/// Implements [`DeserializeIn`] for types that don't allocate, or whose allocation is owned,
+/// by forwarding to [`Serializable::deserialize`]
+macro_rules! impl_deserialize_in_owned {
+    ($($ty:ty),*) => {
+        $(
+            impl<'a> DeserializeIn<'a> for $ty
+            {
+                fn deserialize_in(data: &[u8], _arena: &'a Bump) -> std::io::Result<(Self,usize)>
+                {
+                    <$ty as Serializable>::deserialize(data)
+                }
+            }
+        )*
+    };
+}
+
+impl_deserialize_in_owned!(u128, u64, u32, u16, u8, i128, i64, i32, i16, i8, f64, f32, bool, String,
+    std::time::SystemTime, std::net::IpAddr, std::net::SocketAddr);
+
+impl<'a, T: DeserializeIn<'a>> DeserializeIn<'a> for Option<T>
+{
+    fn deserialize_in(data: &[u8], arena: &'a Bump) -> std::io::Result<(Self,usize)>
+    {
+        match data.first()
+        {
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => Ok((None, 1)),
+            Some(1) => {
+                let (item, len) = T::deserialize_in(&data[1..], arena)?;
+                Ok((Some(item), len + 1))
+            },
+            Some(&found) => Err(Error::InvalidVariant { found, type_name: "Option" }.into()),
+        }
+    }
+}
+
+impl<'a, T: DeserializeIn<'a>> DeserializeIn<'a> for Vec<T>
+{
+    fn deserialize_in(data: &[u8], arena: &'a Bump) -> std::io::Result<(Self,usize)>
+    {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = Vec::with_capacity(bounded_capacity(len as usize, data.len() - read));
+        for _ in 0..len
+        {
+            let (item, item_len) = T::deserialize_in(&data[read..], arena)?;
+            ret.push(item);
+            read += item_len;
+        }
+        Ok((ret, read))
+    }
+}
+
+/// A string stored in an arena, it has the same serialization as [`String`].
+/// It can only be deserialized with [`DeserializeIn`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ArenaString<'a>(&'a str);
+
+impl<'a> ArenaString<'a>
+{
+    /// Returns the string slice borrowed from the arena
+    pub fn as_str(&self) -> &'a str
+    {
+        self.0
+    }
+}
+
+impl<'a> From<&'a str> for ArenaString<'a>
+{
+    fn from(value: &'a str) -> Self
+    {
+        Self(value)
+    }
+}
+
+impl Deref for ArenaString<'_>
+{
+    type Target = str;
+
+    fn deref(&self) -> &str
+    {
+        self.0
+    }
+}
+
+impl Serializable for ArenaString<'_>
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut vec = Vec::with_capacity(self.0.len() + 4);
+        self.serialize_append(&mut vec);
+        vec
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&length_prefix(self.0.len()));
+        out.extend_from_slice(self.0.as_bytes());
+    }
+
+    fn serialized_size(&self) -> usize {
+        4 + self.0.len()
+    }
+
+    fn deserialize(_data: &[u8]) -> std::io::Result<(Self,usize)> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "ArenaString can only be deserialized in an arena"))
+    }
+}
+
+impl<'a> DeserializeIn<'a> for ArenaString<'a>
+{
+    fn deserialize_in(data: &[u8], arena: &'a Bump) -> std::io::Result<(Self,usize)>
+    {
+        let (len, read) = u32::deserialize(data)?;
+        let bytes = match read.checked_add(len as usize).and_then(|end| data.get(read..end))
+        {
+            Some(bytes) => bytes,
+            None => return Err(Error::UnexpectedEof { needed: (len as usize).saturating_add(read), available: data.len() }.into()),
+        };
+        match std::str::from_utf8(bytes)
+        {
+            Ok(string) => Ok((Self(arena.alloc_str(string)), read + bytes.len())),
+            Err(e) => Err(error_at_offset(Error::InvalidUtf8.into(), read + e.valid_up_to())),
+        }
+    }
+}
+
+/// A slice stored in an arena, it has the same serialization as [`Vec`].
+/// It can only be deserialized with [`DeserializeIn`], and only for `Copy` items
+/// because the arena never runs the destructors of its values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ArenaVec<'a, T>(&'a [T]);
+
+impl<'a, T> ArenaVec<'a, T>
+{
+    /// Returns the slice borrowed from the arena
+    pub fn as_slice(&self) -> &'a [T]
+    {
+        self.0
+    }
+}
+
+impl<'a, T> From<&'a [T]> for ArenaVec<'a, T>
+{
+    fn from(value: &'a [T]) -> Self
+    {
+        Self(value)
+    }
+}
+
+impl<T> Deref for ArenaVec<'_, T>
+{
+    type Target = [T];
+
+    fn deref(&self) -> &[T]
+    {
+        self.0
+    }
+}
+
+impl<T: Serializable> Serializable for ArenaVec<'_, T>
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend(length_prefix(self.0.len()));
+        T::serialize_slice(self.0, out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        4 + items_size(self.0, self.0.len())
+    }
+
+    fn deserialize(_data: &[u8]) -> std::io::Result<(Self,usize)> {
+        Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "ArenaVec can only be deserialized in an arena"))
+    }
+}
+
+impl<'a, T: DeserializeIn<'a> + Copy> DeserializeIn<'a> for ArenaVec<'a, T>
+{
+    fn deserialize_in(data: &[u8], arena: &'a Bump) -> std::io::Result<(Self,usize)>
+    {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = bumpalo::collections::Vec::with_capacity_in(bounded_capacity(len as usize, data.len() - read), arena);
+        for _ in 0..len
+        {
+            let (item, item_len) = T::deserialize_in(&data[read..], arena)?;
+            ret.push(item);
+            read += item_len;
+        }
+        Ok((Self(ret.into_bump_slice()), read))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate as serializable;
+    use super::{ArenaString, ArenaVec, Bump, DeserializeIn};
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(arena)]
+    pub struct Packet<'a>
+    {
+        id: u32,
+        name: ArenaString<'a>,
+        tags: ArenaVec<'a, ArenaString<'a>>,
+        values: ArenaVec<'a, u64>,
+        comment: Option<String>,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub struct OwnedPacket
+    {
+        id: u32,
+        name: String,
+        tags: Vec<String>,
+        values: Vec<u64>,
+        comment: Option<String>,
+    }
+
+    fn owned_packet(id: u32) -> OwnedPacket
+    {
+        OwnedPacket {
+            id,
+            name: format!("packet {id}"),
+            tags: vec!["first".to_string(), "second".to_string(), format!("tag {id}")],
+            values: vec![1, 2, 3, id as u64],
+            comment: Some("owned".to_string()),
+        }
+    }
+
+    #[test]
+    fn deserialize_in_arena()
+    {
+        let owned = owned_packet(42);
+        let serialized = owned.serialize();
+        let arena = Bump::new();
+        let (packet, bytes_read) = Packet::deserialize_in(&serialized, &arena).unwrap();
+        assert_eq!(bytes_read, serialized.len());
+        assert_eq!(packet.id, owned.id);
+        assert_eq!(&*packet.name, owned.name);
+        assert_eq!(packet.tags.iter().map(|tag| tag.as_str()).collect::<Vec<_>>(), owned.tags);
+        assert_eq!(*packet.values, owned.values);
+        assert_eq!(packet.comment, owned.comment);
+        assert_eq!(packet.serialize(), serialized);
+    }
+
+    #[test]
+    fn reuse_arena_across_messages()
+    {
+        let mut arena = Bump::new();
+        for id in 0..10
+        {
+            let serialized = owned_packet(id).serialize();
+            {
+                let (packet, _) = Packet::deserialize_in(&serialized, &arena).unwrap();
+                assert_eq!(packet.name.as_str(), format!("packet {id}"));
+            }
+            arena.reset();
+        }
+    }
+
+    #[test]
+    fn arena_types_require_an_arena()
+    {
+        let serialized = owned_packet(1).serialize();
+        assert_eq!(Packet::deserialize(&serialized).unwrap_err().kind(), std::io::ErrorKind::Unsupported);
+        let arena = Bump::new();
+        assert!(Packet::deserialize_in(&serialized[..serialized.len() - 1], &arena).is_err());
+        assert!(ArenaString::deserialize_in(&[0, 0, 0, 2, 0xC3, 0x28], &arena).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Serialized request and response bodies for axum handlers.
+
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use crate::Serializable;
+
+/// Content type of serialized bodies
+pub const CONTENT_TYPE: &str = "application/octet-stream";
+
+/// A request or response body holding the serialization of a `T`.
+///
+/// As an extractor it requires an `application/octet-stream` content type and a body that
+/// deserializes exactly to a `T`, otherwise the request is rejected with
+/// `415 Unsupported Media Type` or `400 Bad Request` respectively. The body is limited to
+/// the size set with axum's `DefaultBodyLimit`, larger bodies are rejected with `413 Payload Too Large`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bin<T>(pub T);
+
+impl<T: Serializable, S: Send + Sync> FromRequest<S> for Bin<T>
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection>
+    {
+        if !has_binary_content_type(req.headers())
+        {
+            return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("Expected request with `Content-Type: {CONTENT_TYPE}`")).into_response());
+        }
+        let body = Bytes::from_request(req, state).await.map_err(IntoResponse::into_response)?;
+        match T::deserialize_exact(&body)
+        {
+            Ok(value) => Ok(Bin(value)),
+            Err(e) => Err((StatusCode::BAD_REQUEST, format!("Failed to deserialize the request body: {e}")).into_response()),
+        }
+    }
+}
+
+impl<T: Serializable> IntoResponse for Bin<T>
+{
+    fn into_response(self) -> Response
+    {
+        let body = self.0.serialize();
+        (
+            [
+                (header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE)),
+                (header::CONTENT_LENGTH, HeaderValue::from(body.len())),
+            ],
+            body,
+        ).into_response()
+    }
+}
+
+fn has_binary_content_type(headers: &HeaderMap) -> bool
+{
+    headers.get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case(CONTENT_TYPE))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use super::{Bin, CONTENT_TYPE};
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, Clone, PartialEq)]
+    struct Order
+    {
+        id: u64,
+        items: Vec<String>,
+    }
+
+    fn app() -> Router
+    {
+        Router::new().route("/echo", post(|Bin(order): Bin<Order>| async move { Bin(order) }))
+    }
+
+    fn request(content_type: &str, body: Vec<u8>) -> Request<Body>
+    {
+        Request::post("/echo").header(header::CONTENT_TYPE, content_type).body(Body::from(body)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn echo_serialized_body()
+    {
+        let order = Order { id: 7, items: vec!["apple".to_string(), "pear".to_string()] };
+        let response = app().oneshot(request(CONTENT_TYPE, order.serialize())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], CONTENT_TYPE);
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], order.serialize().len().to_string().as_str());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), order.serialize().as_slice());
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_bodies()
+    {
+        let order = Order { id: 7, items: Vec::new() };
+        let response = app().oneshot(request("application/json", order.serialize())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let mut trailing = order.serialize();
+        trailing.push(0);
+        let response = app().oneshot(request(CONTENT_TYPE, trailing)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("Trailing bytes"));
+
+        let limited = app().layer(axum::extract::DefaultBodyLimit::max(8));
+        let response = limited.oneshot(request(CONTENT_TYPE, order.serialize())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,345 @@
+//! Reading data written by bincode 1, for a transition period while stored data is migrated
+//! to the format of this crate.
+//!
+//! The layout matched is the one of the `bincode::serialize` and `bincode::deserialize` functions
+//! of bincode 1.x, which is also `bincode::options().with_fixint_encoding()`:
+//! - integers and floats are fixed width and little endian
+//! - `bool` and the tag of `Option` are one byte, 0 or 1
+//! - the lengths of strings, sequences and maps are `u64`
+//! - the variant index of enums is a `u32`
+//! - structs, tuples and arrays are their items in order, with no length
+//!
+//! The variable length integer encoding, big endian data and bincode 2 are not supported.
+//! Derived types implement [`BincodeCompat`] with `#[serializable(bincode_compat)]`,
+//! their variant indices are their position in the enum, like serde does.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
+
+use crate::serializable::{bounded_capacity, error_at_offset, remaining, Error};
+
+/// Types that can be read from the bincode 1 layout described in the [module documentation](self)
+pub trait BincodeCompat: Sized
+{
+    /// Returns the object deserialized from the bincode layout and the number of bytes read
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>;
+}
+
+/// Returns the object written by bincode 1 at the start of `data` and the number of bytes read
+pub fn deserialize_bincode_compat<T: BincodeCompat>(data: &[u8]) -> std::io::Result<(T,usize)>
+{
+    T::deserialize_bincode_compat(data)
+}
+
+/// Implements [`BincodeCompat`] for a number type read with `from_le_bytes`
+macro_rules! impl_le_number {
+    ($($ty:ty),+) => {
+        $(
+            impl BincodeCompat for $ty
+            {
+                fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+                {
+                    match data.first_chunk::<{ std::mem::size_of::<$ty>() }>()
+                    {
+                        Some(bytes) => Ok((<$ty>::from_le_bytes(*bytes), std::mem::size_of::<$ty>())),
+                        None => Err(Error::UnexpectedEof { needed: std::mem::size_of::<$ty>(), available: data.len() }.into()),
+                    }
+                }
+            }
+        )+
+    };
+}
+
+impl_le_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl BincodeCompat for bool
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        match data.first()
+        {
+            Some(0) => Ok((false, 1)),
+            Some(1) => Ok((true, 1)),
+            Some(&found) => Err(Error::InvalidVariant { found, type_name: "bool" }.into()),
+            None => Err(Error::UnexpectedEof { needed: 1, available: data.len() }.into()),
+        }
+    }
+}
+
+impl BincodeCompat for ()
+{
+    fn deserialize_bincode_compat(_data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        Ok(((), 0))
+    }
+}
+
+/// Reads the `u64` length of a string, a sequence or a map
+fn read_len(data: &[u8]) -> std::io::Result<(usize,usize)>
+{
+    let (len, read) = u64::deserialize_bincode_compat(data)?;
+    match usize::try_from(len)
+    {
+        Ok(len) => Ok((len, read)),
+        Err(_) => Err(Error::UnexpectedEof { needed: usize::MAX, available: data.len() }.into()),
+    }
+}
+
+/// Reads a length followed by that many items, passing each of them to `push`
+fn read_items<T: BincodeCompat>(data: &[u8], mut push: impl FnMut(T)) -> std::io::Result<usize>
+{
+    let (len, mut read) = read_len(data)?;
+    for _ in 0..len
+    {
+        let (item, item_len) = T::deserialize_bincode_compat(remaining(data, read)?).map_err(|e| error_at_offset(e, read))?;
+        push(item);
+        read += item_len;
+    }
+    Ok(read)
+}
+
+impl BincodeCompat for String
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let (len, read) = read_len(data)?;
+        let bytes = match remaining(data, read)?.get(..len)
+        {
+            Some(bytes) => bytes,
+            None => return Err(Error::UnexpectedEof { needed: len.saturating_add(read), available: data.len() }.into()),
+        };
+        match std::str::from_utf8(bytes)
+        {
+            Ok(string) => Ok((string.to_owned(), read + len)),
+            Err(e) => Err(error_at_offset(Error::InvalidUtf8.into(), read + e.valid_up_to())),
+        }
+    }
+}
+
+impl<T: BincodeCompat> BincodeCompat for Vec<T>
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let (len, _) = read_len(data)?;
+        let mut ret = Vec::with_capacity(bounded_capacity(len, data.len()));
+        let read = read_items(data, |item| ret.push(item))?;
+        Ok((ret, read))
+    }
+}
+
+impl<T: BincodeCompat> BincodeCompat for VecDeque<T>
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let (ret, read) = Vec::<T>::deserialize_bincode_compat(data)?;
+        Ok((ret.into(), read))
+    }
+}
+
+impl<T: BincodeCompat> BincodeCompat for Option<T>
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        match data.first()
+        {
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => Ok((None, 1)),
+            Some(1) => {
+                let (item, len) = T::deserialize_bincode_compat(&data[1..]).map_err(|e| error_at_offset(e, 1))?;
+                Ok((Some(item), len + 1))
+            },
+            Some(&found) => Err(Error::InvalidVariant { found, type_name: "Option" }.into()),
+        }
+    }
+}
+
+impl<const L: usize, T: BincodeCompat> BincodeCompat for [T;L]
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let mut items = Vec::with_capacity(bounded_capacity(L, data.len()));
+        let mut read = 0;
+        for _ in 0..L
+        {
+            let (item, len) = T::deserialize_bincode_compat(remaining(data, read)?).map_err(|e| error_at_offset(e, read))?;
+            items.push(item);
+            read += len;
+        }
+        let ret: [T;L] = items.try_into().unwrap_or_else(|_| unreachable!("Exactly L items were deserialized"));
+        Ok((ret, read))
+    }
+}
+
+/// Maps keep the last value of a repeated key, like serde does
+impl<K: BincodeCompat + Eq + Hash, V: BincodeCompat, S: BuildHasher + Default> BincodeCompat for HashMap<K, V, S>
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let mut ret = HashMap::default();
+        let read = read_items(data, |(key, value)| { ret.insert(key, value); })?;
+        Ok((ret, read))
+    }
+}
+
+impl<K: BincodeCompat + Ord, V: BincodeCompat> BincodeCompat for BTreeMap<K, V>
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let mut ret = BTreeMap::new();
+        let read = read_items(data, |(key, value)| { ret.insert(key, value); })?;
+        Ok((ret, read))
+    }
+}
+
+impl<T: BincodeCompat + Eq + Hash, S: BuildHasher + Default> BincodeCompat for HashSet<T, S>
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let mut ret = HashSet::default();
+        let read = read_items(data, |item| { ret.insert(item); })?;
+        Ok((ret, read))
+    }
+}
+
+impl<T: BincodeCompat + Ord> BincodeCompat for BTreeSet<T>
+{
+    fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let mut ret = BTreeSet::new();
+        let read = read_items(data, |item| { ret.insert(item); })?;
+        Ok((ret, read))
+    }
+}
+
+/// Implements [`BincodeCompat`] for a tuple, read as its items in order
+macro_rules! impl_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: BincodeCompat),+> BincodeCompat for ($($name,)+)
+        {
+            fn deserialize_bincode_compat(data: &[u8]) -> std::io::Result<(Self,usize)>
+            {
+                let mut offset = 0;
+                let ret = ($({
+                    let (item, len) = $name::deserialize_bincode_compat(remaining(data, offset)?).map_err(|e| error_at_offset(e, offset))?;
+                    offset += len;
+                    item
+                },)+);
+                Ok((ret, offset))
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
+impl_tuple!(A, B, C, D, E, F, G, H, I);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::BTreeMap;
+
+    use super::deserialize_bincode_compat;
+    use crate as serializable;
+    use crate::Serializable;
+
+    // The fixtures in src/testdata/bincode were written by `bincode::serialize` of bincode 1.3.3,
+    // from the same types deriving `serde::Serialize` and the values below
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(bincode_compat)]
+    struct Account
+    {
+        id: u64,
+        name: String,
+        balance: i32,
+        tags: Vec<String>,
+        limit: Option<u16>,
+        missing: Option<u16>,
+        ratio: f64,
+        flags: [u8; 3],
+        active: bool,
+        scores: BTreeMap<String, u32>,
+        pair: (u8, i64),
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(bincode_compat)]
+    enum Event
+    {
+        Created,
+        Renamed(String),
+        Transfer { from: u64, to: u64, amount: i128 },
+        Batch(Vec<BatchedEvent>),
+    }
+
+    // Written as `Event` by bincode, the schema of this crate can't describe recursive types
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(bincode_compat)]
+    enum BatchedEvent
+    {
+        Created,
+        Renamed(String),
+    }
+
+    #[test]
+    fn read_struct_written_by_bincode()
+    {
+        let data = include_bytes!("testdata/bincode/account.bin");
+        let expected = Account {
+            id: 0x0102030405060708,
+            name: "Grace".to_string(),
+            balance: -1200,
+            tags: vec!["admin".to_string(), "ops".to_string()],
+            limit: Some(500),
+            missing: None,
+            ratio: 0.75,
+            flags: [1, 2, 3],
+            active: true,
+            scores: BTreeMap::from([("go".to_string(), 7), ("rust".to_string(), 9)]),
+            pair: (255, -2),
+        };
+        assert_eq!(deserialize_bincode_compat::<Account>(data).unwrap(), (expected, data.len()));
+        // Migrating is reading the old layout and writing the new one
+        let (account, _) = deserialize_bincode_compat::<Account>(data).unwrap();
+        assert_eq!(Account::deserialize_exact(&account.serialize()).unwrap(), account);
+    }
+
+    #[test]
+    fn read_enum_written_by_bincode()
+    {
+        let data = include_bytes!("testdata/bincode/events.bin");
+        let expected = vec![
+            Event::Created,
+            Event::Renamed("ledger".to_string()),
+            Event::Transfer { from: 1, to: 2, amount: i128::MIN },
+            Event::Batch(vec![BatchedEvent::Created, BatchedEvent::Renamed("x".to_string())]),
+        ];
+        assert_eq!(deserialize_bincode_compat::<Vec<Event>>(data).unwrap(), (expected, data.len()));
+    }
+
+    #[test]
+    fn reject_invalid_bincode_data()
+    {
+        let data = include_bytes!("testdata/bincode/events.bin");
+        let error = deserialize_bincode_compat::<Vec<Event>>(&data[..data.len() - 1]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        // The last string, "x", is cut short
+        assert_eq!(crate::DeserializeError::offset_of(&error), 86);
+        // Variant 4 does not exist
+        let error = deserialize_bincode_compat::<Event>(&[4, 0, 0, 0]).unwrap_err();
+        assert_eq!(crate::DeserializeError::offset_of(&error), 0);
+        assert!(deserialize_bincode_compat::<String>(&[u8::MAX; 8]).is_err());
+        assert!(deserialize_bincode_compat::<Vec<u64>>(&[u8::MAX; 16]).is_err());
+        assert!(deserialize_bincode_compat::<Option<u8>>(&[2, 0]).is_err());
+    }
+}
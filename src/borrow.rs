@@ -0,0 +1,202 @@
+//! Zero-copy deserialization of values that borrow from the input.
+//!
+//! Types that derive `Serializable` with fields marked `#[serializable(borrow)]` implement
+//! [`SerializableRef`] instead of [`crate::Serializable`]: the marked fields point into the buffer
+//! they were deserialized from, while the other fields are deserialized as usual.
+//! The encoding is the same as that of the owned equivalent, a `&[u8]` is written like a `Vec<u8>`
+//! and a `&str` like a `String`.
+//!
+//! ```
+//! use serializable::{Serializable, SerializableRef};
+//!
+//! #[derive(Serializable)]
+//! struct Packet<'a>
+//! {
+//!     kind: u8,
+//!     #[serializable(borrow)]
+//!     payload: &'a [u8],
+//! }
+//!
+//! let bytes = Packet { kind: 1, payload: b"data" }.serialize();
+//! let (packet, _) = Packet::deserialize_ref(&bytes).unwrap();
+//! assert_eq!(packet.payload, b"data");
+//! ```
+
+use crate::serializable::{error_at_offset, length_prefix};
+use crate::{Error, Serializable};
+
+/// Values that can be deserialized borrowing from the input buffer, which must outlive them
+pub trait SerializableRef<'a>: Sized
+{
+    /// Returns the serialized object as a vector of bytes
+    fn serialize(&self) -> Vec<u8>;
+    /// Returns the deserialized object, which may point into `data`, and the number of bytes read
+    fn deserialize_ref(data: &'a [u8]) -> std::io::Result<(Self,usize)>;
+}
+
+/// Serialized like a `Vec<u8>`
+impl<'a> SerializableRef<'a> for &'a [u8]
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(4 + self.len());
+        ret.extend(length_prefix(self.len()));
+        ret.extend_from_slice(self);
+        ret
+    }
+
+    fn deserialize_ref(data: &'a [u8]) -> std::io::Result<(Self,usize)> {
+        let (len, read) = u32::deserialize(data)?;
+        match data[read..].get(..len as usize)
+        {
+            Some(bytes) => Ok((bytes, read + bytes.len())),
+            None => Err(Error::UnexpectedEof { needed: (len as usize).saturating_add(read), available: data.len() }.into()),
+        }
+    }
+}
+
+/// Serialized like a `String`
+impl<'a> SerializableRef<'a> for &'a str
+{
+    fn serialize(&self) -> Vec<u8> {
+        SerializableRef::serialize(&self.as_bytes())
+    }
+
+    fn deserialize_ref(data: &'a [u8]) -> std::io::Result<(Self,usize)> {
+        let (bytes, read) = <&[u8]>::deserialize_ref(data)?;
+        match std::str::from_utf8(bytes)
+        {
+            Ok(string) => Ok((string, read)),
+            Err(e) => {
+                Err(error_at_offset(Error::InvalidUtf8.into(), read - bytes.len() + e.valid_up_to()))
+            },
+        }
+    }
+}
+
+/// Serialized like an `Option` of the owned equivalent
+impl<'a, T: SerializableRef<'a>> SerializableRef<'a> for Option<T>
+{
+    fn serialize(&self) -> Vec<u8> {
+        match self
+        {
+            Some(item) => {
+                let mut ret = vec![1];
+                ret.extend(item.serialize());
+                ret
+            },
+            None => vec![0],
+        }
+    }
+
+    fn deserialize_ref(data: &'a [u8]) -> std::io::Result<(Self,usize)> {
+        match data.first()
+        {
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => Ok((None, 1)),
+            Some(1) => {
+                let (item, len) = T::deserialize_ref(&data[1..]).map_err(|e| error_at_offset(e, 1))?;
+                Ok((Some(item), len + 1))
+            },
+            Some(&found) => Err(Error::InvalidVariant { found, type_name: "Option" }.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::SerializableRef;
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Packet<'a>
+    {
+        kind: u8,
+        #[serializable(borrow)]
+        payload: &'a [u8],
+        #[serializable(borrow)]
+        name: &'a str,
+        sequence: u32,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct OwnedPacket
+    {
+        kind: u8,
+        payload: Vec<u8>,
+        name: String,
+        sequence: u32,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    enum Frame<'a>
+    {
+        Ping,
+        Data(u16, #[serializable(borrow)] &'a [u8]),
+        Named { id: u64, #[serializable(borrow)] label: Option<&'a str> },
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    enum OwnedFrame
+    {
+        Ping,
+        Data(u16, Vec<u8>),
+        Named { id: u64, label: Option<String> },
+    }
+
+    /// Returns true if `inner` lies within `outer`
+    fn points_into(outer: &[u8], inner: &[u8]) -> bool
+    {
+        let outer = outer.as_ptr_range();
+        let inner = inner.as_ptr_range();
+        outer.start <= inner.start && inner.end <= outer.end
+    }
+
+    #[test]
+    fn borrow_struct_fields_from_the_input()
+    {
+        let owned = OwnedPacket { kind: 3, payload: vec![1, 2, 3, 4], name: "sensor".to_string(), sequence: 42 };
+        let bytes = owned.serialize();
+        let (packet, bytes_read) = Packet::deserialize_ref(&bytes).unwrap();
+        assert_eq!(bytes_read, bytes.len());
+        assert_eq!(packet, Packet { kind: 3, payload: &[1, 2, 3, 4], name: "sensor", sequence: 42 });
+        assert!(points_into(&bytes, packet.payload));
+        assert!(points_into(&bytes, packet.name.as_bytes()));
+        assert_eq!(packet.serialize(), bytes);
+        assert!(Packet::deserialize_ref(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn borrow_enum_fields_from_the_input()
+    {
+        let owned = [
+            OwnedFrame::Ping,
+            OwnedFrame::Data(7, vec![9; 10]),
+            OwnedFrame::Named { id: 1, label: Some("first".to_string()) },
+            OwnedFrame::Named { id: 2, label: None },
+        ];
+        for owned in owned
+        {
+            let bytes = owned.serialize();
+            let (frame, bytes_read) = Frame::deserialize_ref(&bytes).unwrap();
+            assert_eq!(bytes_read, bytes.len());
+            assert_eq!(frame.serialize(), bytes);
+            match frame
+            {
+                Frame::Data(_, payload) => assert!(points_into(&bytes, payload)),
+                Frame::Named { label: Some(label), .. } => assert!(points_into(&bytes, label.as_bytes())),
+                _ => {},
+            }
+        }
+        assert!(Frame::deserialize_ref(&[3]).is_err());
+    }
+
+    #[test]
+    fn reject_invalid_borrowed_strings()
+    {
+        let error = <&str>::deserialize_ref(&[0, 0, 0, 2, b'a', 0xff]).unwrap_err();
+        assert_eq!(crate::DeserializeError::offset_of(&error), 5);
+        assert!(<&[u8]>::deserialize_ref(&[0, 0, 0, 2, 1]).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! Consistent Overhead Byte Stuffing framing for serial links.
+//!
+//! A frame is the COBS encoding of the serialized bytes, which contains no zero byte,
+//! followed by a single `0x00` delimiter. A receiver that loses track of the stream
+//! resynchronizes at the next delimiter.
+
+use crate::Serializable;
+
+/// Returns the COBS encoding of `value` followed by the zero delimiter
+pub fn encode_frame<T: Serializable>(value: &T) -> Vec<u8>
+{
+    let mut ret = encode(&value.serialize());
+    ret.push(0);
+    ret
+}
+
+/// Returns the COBS encoding of `data`, without the delimiter
+pub fn encode(data: &[u8]) -> Vec<u8>
+{
+    let mut ret = Vec::with_capacity(data.len() + data.len() / 254 + 1);
+    let mut code_index = 0;
+    let mut code = 1;
+    ret.push(0);
+    for &byte in data
+    {
+        if byte != 0
+        {
+            ret.push(byte);
+            code += 1;
+        }
+        if byte == 0 || code == 0xFF
+        {
+            ret[code_index] = code;
+            code_index = ret.len();
+            ret.push(0);
+            code = 1;
+        }
+    }
+    ret[code_index] = code;
+    ret
+}
+
+/// Decodes COBS encoded `data` that doesn't include the delimiter
+pub fn decode(data: &[u8]) -> std::io::Result<Vec<u8>>
+{
+    let mut ret = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len()
+    {
+        let code = data[i] as usize;
+        let end = i + code;
+        if code == 0 || end > data.len() || data[i + 1..end].contains(&0)
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid COBS encoding"));
+        }
+        ret.extend_from_slice(&data[i + 1..end]);
+        i = end;
+        if code != 0xFF && i < data.len()
+        {
+            ret.push(0);
+        }
+    }
+    Ok(ret)
+}
+
+/// Splits a stream of COBS frames into their decoded payloads without doing any I/O.
+/// Bytes are fed with [`CobsDecoder::push`] in chunks of any size and frames are taken
+/// with [`CobsDecoder::next_frame`] or [`CobsDecoder::next_value`].
+#[derive(Debug, Clone)]
+pub struct CobsDecoder
+{
+    buffer: Vec<u8>,
+    /// Bytes at the start of the buffer that are known not to be delimiters
+    scanned: usize,
+    /// The current frame was already reported as too long and is dropped up to its delimiter
+    discarding: bool,
+    max_len: usize,
+}
+
+impl CobsDecoder
+{
+    /// Returns a decoder that rejects frames whose payload is longer than `max_len`
+    pub fn new(max_len: usize) -> Self
+    {
+        Self { buffer: Vec::new(), scanned: 0, discarding: false, max_len }
+    }
+
+    /// Appends received bytes to the decoder
+    pub fn push(&mut self, bytes: &[u8])
+    {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the payload of the next complete frame, or `None` if no more frames are complete.
+    /// A frame that is corrupted or longer than the maximum fails with [`std::io::ErrorKind::InvalidData`]
+    /// and is discarded, so the following call continues with the next frame.
+    pub fn next_frame(&mut self) -> std::io::Result<Option<Vec<u8>>>
+    {
+        loop
+        {
+            let Some(delimiter) = self.buffer[self.scanned..].iter().position(|&byte| byte == 0) else
+            {
+                self.scanned = self.buffer.len();
+                if self.buffer.len() > Self::max_encoded_len(self.max_len)
+                {
+                    self.buffer.clear();
+                    self.scanned = 0;
+                    if !self.discarding
+                    {
+                        self.discarding = true;
+                        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame too long"));
+                    }
+                }
+                return Ok(None);
+            };
+            let end = self.scanned + delimiter;
+            let encoded: Vec<u8> = self.buffer.drain(..=end).take(end).collect();
+            self.scanned = 0;
+            if std::mem::take(&mut self.discarding) || encoded.is_empty()
+            {
+                continue;
+            }
+            let payload = decode(&encoded)?;
+            if payload.len() > self.max_len
+            {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame too long"));
+            }
+            return Ok(Some(payload));
+        }
+    }
+
+    /// Like [`CobsDecoder::next_frame`], but deserializes the payload, which the value must take entirely
+    pub fn next_value<T: Serializable>(&mut self) -> std::io::Result<Option<T>>
+    {
+        match self.next_frame()?
+        {
+            Some(payload) => T::deserialize_exact(&payload).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn max_encoded_len(max_len: usize) -> usize
+    {
+        max_len.saturating_add(max_len / 254 + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{decode, encode, encode_frame, CobsDecoder};
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Telemetry
+    {
+        sequence: u32,
+        samples: Vec<i16>,
+    }
+
+    #[test]
+    fn encode_known_vectors()
+    {
+        assert_eq!(encode(&[]), [0x01]);
+        assert_eq!(encode(&[0x00]), [0x01, 0x01]);
+        assert_eq!(encode(&[0x00, 0x00]), [0x01, 0x01, 0x01]);
+        assert_eq!(encode(&[0x11, 0x22, 0x00, 0x33]), [0x03, 0x11, 0x22, 0x02, 0x33]);
+        assert_eq!(encode(&[0x11, 0x00, 0x00, 0x00]), [0x02, 0x11, 0x01, 0x01, 0x01]);
+        let long: Vec<u8> = (1..=254).collect();
+        let mut expected = vec![0xFF];
+        expected.extend(&long);
+        expected.push(0x01);
+        assert_eq!(encode(&long), expected);
+        for data in [Vec::new(), vec![0; 300], long, (0..=255).cycle().take(1000).collect()]
+        {
+            let encoded = encode(&data);
+            assert!(!encoded.contains(&0));
+            assert_eq!(decode(&encoded).unwrap(), data);
+        }
+        assert!(decode(&[0x05, 0x11]).is_err());
+    }
+
+    #[test]
+    fn decode_frames_with_zero_bytes()
+    {
+        let telemetry = Telemetry { sequence: 0, samples: vec![0; 500] };
+        let frame = encode_frame(&telemetry);
+        assert_eq!(frame.iter().filter(|&&byte| byte == 0).count(), 1);
+        let mut decoder = CobsDecoder::new(2048);
+        for chunk in frame.chunks(7)
+        {
+            assert!(decoder.next_value::<Telemetry>().unwrap().is_none());
+            decoder.push(chunk);
+        }
+        assert_eq!(decoder.next_value::<Telemetry>().unwrap(), Some(telemetry));
+        assert!(decoder.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn decode_back_to_back_frames()
+    {
+        let mut stream = Vec::new();
+        for sequence in 0..3
+        {
+            stream.extend(encode_frame(&Telemetry { sequence, samples: vec![sequence as i16; 3] }));
+        }
+        let mut decoder = CobsDecoder::new(64);
+        decoder.push(&stream);
+        for sequence in 0..3
+        {
+            let telemetry = decoder.next_value::<Telemetry>().unwrap().unwrap();
+            assert_eq!(telemetry.sequence, sequence);
+        }
+        assert!(decoder.next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn resynchronize_after_corruption()
+    {
+        let good = Telemetry { sequence: 7, samples: vec![1, -1] };
+        let mut corrupted = encode_frame(&Telemetry { sequence: 6, samples: vec![2, -2] });
+        corrupted[0] = 0x40;
+        let mut decoder = CobsDecoder::new(64);
+        decoder.push(&corrupted);
+        decoder.push(&encode_frame(&good));
+        assert_eq!(decoder.next_frame().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(decoder.next_value::<Telemetry>().unwrap(), Some(good));
+    }
+
+    #[test]
+    fn discard_frames_longer_than_the_maximum()
+    {
+        let small = Telemetry { sequence: 1, samples: Vec::new() };
+        let mut decoder = CobsDecoder::new(16);
+        decoder.push(&[0x05; 40]);
+        assert_eq!(decoder.next_frame().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        decoder.push(&[0x05; 40]);
+        assert!(decoder.next_frame().unwrap().is_none());
+        decoder.push(&[0x00]);
+        decoder.push(&encode_frame(&small));
+        assert_eq!(decoder.next_value::<Telemetry>().unwrap(), Some(small));
+    }
+}
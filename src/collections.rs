@@ -0,0 +1,346 @@
+//! Collections with a specialized serialization.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::serializable::{bounded_capacity, error_at_offset, length_prefix, Error};
+use crate::Serializable;
+
+/// A list of strings serialized with a table of the distinct strings followed by indices into it,
+/// which is much more compact than a `Vec<String>` when the same strings are repeated.
+///
+/// The serialization is the number of distinct strings as a `u16`, each distinct string
+/// in order of first appearance, the number of items as a `u32` and the `u16` index of each item.
+/// Serializing panics if there are more than 65535 distinct strings.
+/// Deserializing fails if the strings add up to more than [`set_max_interned_string_bytes`] bytes,
+/// since a short input can repeat a long string many times.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct InternedStringVec(pub Vec<String>);
+
+/// Default of [`set_max_interned_string_bytes`]
+pub const DEFAULT_MAX_INTERNED_STRING_BYTES: usize = 1 << 26;
+
+static MAX_INTERNED_STRING_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_INTERNED_STRING_BYTES);
+
+/// Sets the largest total length of the strings of a deserialized [`InternedStringVec`], for every thread
+pub fn set_max_interned_string_bytes(limit: usize)
+{
+    MAX_INTERNED_STRING_BYTES.store(limit, Ordering::Relaxed);
+}
+
+impl Deref for InternedStringVec
+{
+    type Target = Vec<String>;
+
+    fn deref(&self) -> &Vec<String>
+    {
+        &self.0
+    }
+}
+
+impl DerefMut for InternedStringVec
+{
+    fn deref_mut(&mut self) -> &mut Vec<String>
+    {
+        &mut self.0
+    }
+}
+
+impl From<Vec<String>> for InternedStringVec
+{
+    fn from(value: Vec<String>) -> Self
+    {
+        Self(value)
+    }
+}
+
+impl Serializable for InternedStringVec
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut table: Vec<&str> = Vec::new();
+        let mut indices: HashMap<&str, u16> = HashMap::new();
+        let mut sequence = Vec::with_capacity(self.0.len() * 2 + 4);
+        sequence.extend_from_slice(&length_prefix(self.0.len()));
+        for string in &self.0
+        {
+            let index = *indices.entry(string).or_insert_with(|| {
+                let index = u16::try_from(table.len()).ok().filter(|&index| index < u16::MAX)
+                    .expect("InternedStringVec can hold at most 65535 distinct strings");
+                table.push(string);
+                index
+            });
+            sequence.extend_from_slice(&index.to_be_bytes());
+        }
+        let mut ret = Vec::new();
+        ret.extend_from_slice(&(table.len() as u16).to_be_bytes());
+        for string in table
+        {
+            ret.extend_from_slice(&length_prefix(string.len()));
+            ret.extend_from_slice(string.as_bytes());
+        }
+        ret.extend_from_slice(&sequence);
+        ret
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (table_len, mut read) = u16::deserialize(data)?;
+        let mut table = Vec::with_capacity(bounded_capacity(table_len as usize, data.len() - read));
+        for _ in 0..table_len
+        {
+            let (string, len) = String::deserialize(&data[read..]).map_err(|e| error_at_offset(e, read))?;
+            table.push(string);
+            read += len;
+        }
+        let indices_offset = read;
+        let (indices, len) = Vec::<u16>::deserialize(&data[read..]).map_err(|e| error_at_offset(e, read))?;
+        read += len;
+        let max_bytes = MAX_INTERNED_STRING_BYTES.load(Ordering::Relaxed);
+        let mut total_bytes = 0;
+        let ret = indices.into_iter().enumerate().map(|(i, index)| {
+            // Past the u32 count, each index takes two bytes
+            let index_offset = indices_offset + 4 + 2 * i;
+            let string = table.get(index as usize).ok_or_else(|| {
+                let reason = format!("string index {index} is past the {} distinct strings", table.len());
+                error_at_offset(Error::InvalidValue { type_name: "InternedStringVec", reason }.into(), index_offset)
+            })?;
+            total_bytes += string.len();
+            if total_bytes > max_bytes
+            {
+                let error = Error::LimitExceeded { what: "interned string bytes", limit: max_bytes }.into();
+                return Err(error_at_offset(error, index_offset));
+            }
+            Ok(string.clone())
+        }).collect::<std::io::Result<Vec<String>>>()?;
+        Ok((Self(ret), read))
+    }
+}
+
+/// A table of strings indexed by sparse ids, where an id can also be present without a string.
+/// It is serialized like the underlying [`BTreeMap`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SparseStringTable(pub BTreeMap<u32, Option<String>>);
+
+impl SparseStringTable
+{
+    /// Returns an empty table
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Returns the string of `id`, or `None` if the id is missing or has no string
+    pub fn get(&self, id: u32) -> Option<&str>
+    {
+        self.0.get(&id).and_then(|value| value.as_deref())
+    }
+
+    /// Sets the entry of `id`, replacing the previous one
+    pub fn insert(&mut self, id: u32, value: Option<String>)
+    {
+        self.0.insert(id, value);
+    }
+}
+
+impl Deref for SparseStringTable
+{
+    type Target = BTreeMap<u32, Option<String>>;
+
+    fn deref(&self) -> &BTreeMap<u32, Option<String>>
+    {
+        &self.0
+    }
+}
+
+impl DerefMut for SparseStringTable
+{
+    fn deref_mut(&mut self) -> &mut BTreeMap<u32, Option<String>>
+    {
+        &mut self.0
+    }
+}
+
+impl Serializable for SparseStringTable
+{
+    fn serialize(&self) -> Vec<u8> {
+        self.0.serialize()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (table, len) = BTreeMap::deserialize(data)?;
+        Ok((Self(table), len))
+    }
+}
+
+/// Header names mapped to all of their values, in the order they were received,
+/// like the headers of an HTTP request. It is serialized like the underlying [`HashMap`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap(pub HashMap<String, Vec<String>>);
+
+impl HeaderMap
+{
+    /// Returns an empty map
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    /// Returns the first value of the header `key`, or `None` if it is missing or has no values
+    pub fn get_first(&self, key: &str) -> Option<&str>
+    {
+        self.0.get(key).and_then(|values| values.first()).map(String::as_str)
+    }
+
+    /// Adds `value` after the other values of the header `key`
+    pub fn append(&mut self, key: impl Into<String>, value: impl Into<String>)
+    {
+        self.0.entry(key.into()).or_default().push(value.into());
+    }
+}
+
+impl Deref for HeaderMap
+{
+    type Target = HashMap<String, Vec<String>>;
+
+    fn deref(&self) -> &HashMap<String, Vec<String>>
+    {
+        &self.0
+    }
+}
+
+impl DerefMut for HeaderMap
+{
+    fn deref_mut(&mut self) -> &mut HashMap<String, Vec<String>>
+    {
+        &mut self.0
+    }
+}
+
+impl Serializable for HeaderMap
+{
+    fn serialize(&self) -> Vec<u8> {
+        self.0.serialize()
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        self.0.serialize_deterministic()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (headers, len) = HashMap::deserialize(data)?;
+        Ok((Self(headers), len))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{HeaderMap, InternedStringVec, SparseStringTable, DEFAULT_MAX_INTERNED_STRING_BYTES};
+    use crate::serializable::Error;
+    use crate::Serializable;
+
+    #[test]
+    fn serialize_and_deserialize_interned_strings()
+    {
+        let strings: Vec<String> = (0..1000).map(|i| ["error", "warning", "info"][i % 3].to_string()).collect();
+        let interned = InternedStringVec(strings.clone());
+        let serialized = interned.serialize();
+        assert_eq!(serialized.len(), 2 + (4 + 5) + (4 + 7) + (4 + 4) + 4 + 1000 * 2);
+        let (deserialized, bytes_read) = InternedStringVec::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized.0, strings);
+        assert_eq!(serialized.len(), bytes_read);
+
+        let repeated = InternedStringVec(vec!["error".to_string(); 1000]);
+        assert_eq!(repeated.serialize().len(), 2 + 9 + 4 + 2000);
+        assert!(repeated.serialize().len() < repeated.0.serialize().len() / 4);
+    }
+
+    #[test]
+    fn deserialize_interned_strings_edge_cases()
+    {
+        let empty = InternedStringVec::default();
+        assert_eq!(InternedStringVec::deserialize(&empty.serialize()).unwrap(), (empty, 6));
+        let mut invalid_index = 1u16.serialize();
+        invalid_index.extend("a".to_string().serialize());
+        invalid_index.extend(vec![0u16, 1].serialize());
+        let error = InternedStringVec::deserialize(&invalid_index).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(Error::find(&error), Some(Error::InvalidValue { type_name: "InternedStringVec", .. })), "{error}");
+        assert_eq!(crate::DeserializeError::offset_of(&error), 2 + 5 + 4 + 2);
+        // Errors inside the strings and the indices are reported at their offset in the whole data
+        let mut invalid_utf8 = 2u16.serialize();
+        invalid_utf8.extend("a".to_string().serialize());
+        invalid_utf8.extend([0, 0, 0, 1, 0xFF]);
+        let error = InternedStringVec::deserialize(&invalid_utf8).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidUtf8));
+        assert_eq!(crate::DeserializeError::offset_of(&error), 2 + 5 + 4);
+        let truncated = &invalid_index[..invalid_index.len() - 1];
+        let error = InternedStringVec::deserialize(truncated).unwrap_err();
+        assert!(matches!(Error::find(&error), Some(Error::UnexpectedEof { .. })), "{error}");
+        assert_eq!(crate::DeserializeError::offset_of(&error), 2 + 5 + 4);
+    }
+
+    #[test]
+    fn reject_interned_strings_that_expand_too_much()
+    {
+        // A 64 KiB string repeated 1025 times is just over the default limit, from less than 70 KB of data
+        let mut hostile = 1u16.serialize();
+        hostile.extend("a".repeat(1 << 16).serialize());
+        hostile.extend(vec![0u16; 1025].serialize());
+        assert!(hostile.len() < 70_000);
+        let error = InternedStringVec::deserialize(&hostile).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(Error::find(&error), Some(&Error::LimitExceeded { what: "interned string bytes", limit: DEFAULT_MAX_INTERNED_STRING_BYTES }));
+        hostile.truncate(hostile.len() - (4 + 2 * 1025));
+        hostile.extend(vec![0u16; 1024].serialize());
+        assert_eq!(InternedStringVec::deserialize_exact(&hostile).unwrap().len(), 1024);
+    }
+
+    #[test]
+    fn serialize_and_deserialize_sparse_string_table()
+    {
+        let mut table = SparseStringTable::new();
+        for id in 0..100
+        {
+            let value = if id % 2 == 0 { Some(format!("name-{id}")) } else { None };
+            table.insert(id * 1000, value);
+        }
+        let serialized = table.serialize();
+        assert_eq!(serialized, table.0.serialize());
+        let (deserialized, bytes_read) = SparseStringTable::deserialize(&serialized).unwrap();
+        assert_eq!(table, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert_eq!(deserialized.len(), 100);
+        assert_eq!(deserialized.get(42000), Some("name-42"));
+        assert_eq!(deserialized.get(43000), None);
+        assert!(deserialized.contains_key(&43000));
+        assert_eq!(deserialized.get(42), None);
+        assert_eq!(deserialized.values().filter(|value| value.is_none()).count(), 50);
+    }
+
+    #[test]
+    fn serialize_and_deserialize_header_map()
+    {
+        let mut headers = HeaderMap::new();
+        for i in 0..10
+        {
+            for j in 0..=i % 3
+            {
+                headers.append(format!("x-header-{i}"), format!("value-{i}-{j}"));
+            }
+        }
+        headers.append("accept", "text/html");
+        headers.append("accept", "application/json");
+        let serialized = headers.serialize();
+        let (deserialized, bytes_read) = HeaderMap::deserialize(&serialized).unwrap();
+        assert_eq!(headers, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert_eq!(deserialized.len(), 11);
+        assert_eq!(deserialized.get_first("accept"), Some("text/html"));
+        assert_eq!(deserialized["accept"], ["text/html", "application/json"]);
+        assert_eq!(deserialized.get_first("x-header-5"), Some("value-5-0"));
+        assert_eq!(deserialized["x-header-5"].len(), 3);
+        assert_eq!(deserialized.get_first("missing"), None);
+        assert_eq!(headers.serialize_deterministic(), deserialized.serialize_deterministic());
+    }
+}
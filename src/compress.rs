@@ -0,0 +1,69 @@
+//! Compressed serialization using DEFLATE.
+//!
+//! The compressed format is the length of the uncompressed serialization
+//! as a 4 byte big endian number, followed by the raw DEFLATE stream.
+
+use crate::serializable::{error_at_offset, length_prefix};
+use crate::{Error, Serializable};
+
+/// Compression level used by [`serialize_compressed`], on the usual 0 to 10 scale
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// Returns the serialization of `value` compressed with DEFLATE
+///
+/// # Panics
+///
+/// Panics if the serialization is longer than `u32::MAX` bytes, like [`Serializable::serialize`] of a longer string
+pub fn serialize_compressed<T: Serializable>(value: &T) -> Vec<u8>
+{
+    let serialized = value.serialize();
+    let compressed = miniz_oxide::deflate::compress_to_vec(&serialized, COMPRESSION_LEVEL);
+    let mut ret = Vec::with_capacity(compressed.len() + 4);
+    ret.extend_from_slice(&length_prefix(serialized.len()));
+    ret.extend_from_slice(&compressed);
+    ret
+}
+
+/// Returns the object decompressed and deserialized from the output of [`serialize_compressed`].
+/// The decompressed data must match the declared length, so the length prefix also bounds the memory used.
+pub fn deserialize_compressed<T: Serializable>(data: &[u8]) -> std::io::Result<T>
+{
+    let (len, read) = u32::deserialize(data)?;
+    let decompressed = miniz_oxide::inflate::decompress_to_vec_with_limit(&data[read..], len as usize)
+        .map_err(|e| error_at_offset(Error::InvalidValue { type_name: "compressed data", reason: e.to_string() }.into(), read))?;
+    if decompressed.len() != len as usize
+    {
+        let reason = format!("{} bytes were decompressed instead of {len}", decompressed.len());
+        return Err(error_at_offset(Error::InvalidValue { type_name: "compressed data", reason }.into(), read));
+    }
+    let (ret, _) = T::deserialize(&decompressed)?;
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{deserialize_compressed, serialize_compressed};
+    use crate::Serializable;
+
+    #[test]
+    fn serialize_and_deserialize_compressed()
+    {
+        let value: Vec<String> = (0..1000).map(|i| format!("repeated entry {}", i % 10)).collect();
+        let compressed = serialize_compressed(&value);
+        assert!(compressed.len() < value.serialize().len() / 10);
+        let deserialized: Vec<String> = deserialize_compressed(&compressed).unwrap();
+        assert_eq!(value, deserialized);
+    }
+
+    #[test]
+    fn deserialize_compressed_rejects_wrong_length()
+    {
+        let mut compressed = serialize_compressed(&"Hello world".to_string());
+        compressed[3] -= 1;
+        assert!(deserialize_compressed::<String>(&compressed).is_err());
+        compressed[3] += 2;
+        assert!(deserialize_compressed::<String>(&compressed).is_err());
+        assert!(deserialize_compressed::<String>(&compressed[..2]).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+//! Tools to inspect serialized bytes.
+//!
+//! [`annotate`] lays a [`Schema`] over the bytes and labels every value with its path,
+//! which shows where a blob that fails to deserialize stops making sense.
+
+use std::fmt::Debug;
+
+use crate::schema::Schema;
+use crate::{DeserializeError, Error, Serializable};
+
+/// Maximum number of bytes shown in hex on a single line
+const HEX_BYTES_PER_LINE: usize = 8;
+
+/// Returns a hex dump of `data` where every value is on its own line, labeled with its path
+/// and decoded according to `schema`, e.g. `0004..0006  01 bb  peers[0].port = 443`.
+/// Values that are invalid but whose length is known are marked and skipped,
+/// otherwise the dump marks where parsing stopped and lists the remaining bytes unlabeled.
+pub fn annotate(schema: &Schema, data: &[u8]) -> String
+{
+    annotate_with_failure(schema, data).0
+}
+
+/// Returns the path of the first value in `data` that is invalid or where parsing stops,
+/// like `peers[0].port`, or `None` if the data is valid. The path of the whole value is empty.
+pub fn failing_path(schema: &Schema, data: &[u8]) -> Option<String>
+{
+    annotate_with_failure(schema, data).1
+}
+
+fn annotate_with_failure(schema: &Schema, data: &[u8]) -> (String, Option<String>)
+{
+    let mut annotator = Annotator { data, offset: 0, lines: Vec::new(), failed: None };
+    let stopped = annotator.value(schema, "").err();
+    let rest = annotator.offset;
+    match stopped
+    {
+        Some(reason) => annotator.lines.push(format!("{rest:04x}  !! parsing stopped: {reason}")),
+        None if rest < data.len() => annotator.lines.push(format!("{rest:04x}  !! trailing bytes")),
+        None => {},
+    }
+    for start in (rest..data.len()).step_by(HEX_BYTES_PER_LINE)
+    {
+        let end = (start + HEX_BYTES_PER_LINE).min(data.len());
+        annotator.line(start, end, "??", "unparsed");
+    }
+    let mut ret = annotator.lines.join("\n");
+    ret.push('\n');
+    (ret, annotator.failed)
+}
+
+struct Annotator<'a>
+{
+    data: &'a [u8],
+    offset: usize,
+    lines: Vec<String>,
+    /// Path of the first value that is invalid or where parsing stopped
+    failed: Option<String>,
+}
+
+impl Annotator<'_>
+{
+    /// Annotates the value at the current offset, returns the reason if parsing cannot go on
+    fn value(&mut self, schema: &Schema, path: &str) -> Result<(), String>
+    {
+        match schema
+        {
+            Schema::Primitive(name) => self.primitive(name, path),
+            Schema::Option(item) => {
+                match self.tag(path, path, |tag| match tag { 0 => Some("None".to_string()), 1 => Some("Some".to_string()), _ => None })?
+                {
+                    0 => Ok(()),
+                    _ => self.value(item, &join(path, "0")),
+                }
+            },
+            Schema::Sequence(item) => {
+                let len = self.len::<u32>(path)?;
+                (0..len).try_for_each(|i| self.value(item, &format!("{path}[{i}]")))
+            },
+            Schema::Array(item, len) => (0..*len).try_for_each(|i| self.value(item, &format!("{path}[{i}]"))),
+            Schema::Map(key, value) => {
+                let len = self.len::<u32>(path)?;
+                (0..len).try_for_each(|i| {
+                    self.value(key, &format!("{path}[{i}].key"))?;
+                    self.value(value, &format!("{path}[{i}].value"))
+                })
+            },
+            Schema::Tuple(items) => items.iter().enumerate().try_for_each(|(i, item)| self.value(item, &join(path, &i.to_string()))),
+            Schema::Struct { fields, .. } => fields.iter().try_for_each(|field| self.value(&field.schema, &join(path, field.name))),
+            Schema::Enum { name, variants } => {
+                let label = if path.is_empty() { name } else { path };
+                let tag = self.tag(path, label, |tag| variants.get(tag as usize).map(|variant| format!("{name}::{}", variant.name)))?;
+                variants[tag as usize].fields.iter().try_for_each(|field| self.value(&field.schema, &join(path, field.name)))
+            },
+            Schema::IndexedStruct { fields, .. } => {
+                let len = self.len::<u16>(path)?;
+                (0..len).try_for_each(|_| {
+                    let (index, field_len) = match <(u16, u32)>::deserialize(&self.data[self.offset..])
+                    {
+                        Ok(((index, field_len), _)) => (index, field_len as usize),
+                        Err(e) => return Err(self.fail(path, e.to_string())),
+                    };
+                    let field = fields.iter().find(|field| field.index == index);
+                    let field_path = match field
+                    {
+                        Some(field) => join(path, field.field.name),
+                        None => join(path, &format!("#{index}")),
+                    };
+                    self.line(self.offset, self.offset + 2, &format!("{field_path}.index"), &index.to_string());
+                    self.line(self.offset + 2, self.offset + 6, &format!("{field_path}.len"), &field_len.to_string());
+                    self.offset += 6;
+                    let end = self.offset.saturating_add(field_len);
+                    match field
+                    {
+                        _ if end > self.data.len() => Err(self.fail(&field_path, format!("Field of {field_len} bytes past the end of the data"))),
+                        Some(field) => {
+                            self.value(&field.field.schema, &field_path)?;
+                            match self.offset == end
+                            {
+                                true => Ok(()),
+                                false => Err(self.fail(&field_path, format!("Field value is not {field_len} bytes long"))),
+                            }
+                        },
+                        None => {
+                            self.line(self.offset, end, &field_path, "unknown field, skipped");
+                            self.offset = end;
+                            Ok(())
+                        },
+                    }
+                })
+            },
+        }
+    }
+
+    /// Annotates a tag byte labeled `label`, `name` returns the name of valid tags
+    fn tag(&mut self, path: &str, label: &str, name: impl Fn(u8) -> Option<String>) -> Result<u8, String>
+    {
+        let label = if label.is_empty() { "value" } else { label };
+        let tag = match self.data.get(self.offset)
+        {
+            Some(tag) => *tag,
+            None => return Err(self.fail(path, Error::UnexpectedEof { needed: 1, available: 0 }.to_string())),
+        };
+        match name(tag)
+        {
+            Some(name) => {
+                self.line(self.offset, self.offset + 1, label, &name);
+                self.offset += 1;
+                Ok(tag)
+            },
+            None => Err(self.fail(path, format!("Invalid tag {tag} for {label}"))),
+        }
+    }
+
+    /// Annotates the count of a sequence, a map or the fields of an indexed struct
+    fn len<T: Serializable + Into<u32>>(&mut self, path: &str) -> Result<usize, String>
+    {
+        let (len, read) = match T::deserialize(&self.data[self.offset..])
+        {
+            Ok((len, read)) => (len.into(), read),
+            Err(e) => return Err(self.fail(&format!("{path}.len"), e.to_string())),
+        };
+        self.line(self.offset, self.offset + read, &format!("{path}.len"), &len.to_string());
+        self.offset += read;
+        Ok(len as usize)
+    }
+
+    fn primitive(&mut self, name: &str, path: &str) -> Result<(), String>
+    {
+        let key = name.rsplit("::").next().unwrap_or(name).trim();
+        match key
+        {
+            "u8" => self.decode::<u8>(path, None),
+            "u16" => self.decode::<u16>(path, None),
+            "u32" => self.decode::<u32>(path, None),
+            "u64" => self.decode::<u64>(path, None),
+            "u128" => self.decode::<u128>(path, None),
+            "i8" => self.decode::<i8>(path, None),
+            "i16" => self.decode::<i16>(path, None),
+            "i32" => self.decode::<i32>(path, None),
+            "i64" => self.decode::<i64>(path, None),
+            "i128" => self.decode::<i128>(path, None),
+            "f32" => self.decode::<f32>(path, None),
+            "f64" => self.decode::<f64>(path, None),
+            "bool" => self.decode::<bool>(path, None),
+            "char" => self.decode::<char>(path, None),
+            "String" => {
+                // The length prefix tells how many bytes to skip when they are not valid UTF-8
+                let len = u32::deserialize(&self.data[self.offset..]).ok().map(|(len, read)| (len as usize).saturating_add(read));
+                self.decode::<String>(path, len)
+            },
+            "SystemTime" => self.decode::<std::time::SystemTime>(path, None),
+            "Ipv4Addr" => self.decode::<std::net::Ipv4Addr>(path, None),
+            "SocketAddrV4" => self.decode::<std::net::SocketAddrV4>(path, None),
+            "IpAddr" => self.decode::<std::net::IpAddr>(path, None),
+            "SocketAddr" => self.decode::<std::net::SocketAddr>(path, None),
+            _ => Err(self.fail(path, format!("Unknown primitive {name}"))),
+        }
+    }
+
+    /// Annotates a value with its own deserialization. Invalid values are skipped if their length
+    /// is fixed or given as `len`, such as a bool that is not 0 or 1 or a string that is not UTF-8.
+    fn decode<T: Serializable + Debug>(&mut self, path: &str, len: Option<usize>) -> Result<(), String>
+    {
+        let label = if path.is_empty() { "value" } else { path };
+        let rest = &self.data[self.offset..];
+        match T::deserialize(rest)
+        {
+            Ok((value, read)) => {
+                self.line(self.offset, self.offset + read, label, &format!("{value:?}"));
+                self.offset += read;
+                Ok(())
+            },
+            Err(e) => {
+                // The offset inside the value is already shown by the hex dump
+                let reason = DeserializeError::find(&e).map_or_else(|| e.to_string(), |inner| inner.error().to_string());
+                match T::FIXED_SIZE.or(len)
+                {
+                    Some(len) if len <= rest.len() => {
+                        self.fail(path, String::new());
+                        self.line(self.offset, self.offset + len, label, &format!("!! {reason}"));
+                        self.offset += len;
+                        Ok(())
+                    },
+                    _ => Err(self.fail(path, reason)),
+                }
+            },
+        }
+    }
+
+    /// Records `path` as the failing path unless an earlier value failed, returns `reason`
+    fn fail(&mut self, path: &str, reason: String) -> String
+    {
+        self.failed.get_or_insert_with(|| path.to_string());
+        reason
+    }
+
+    fn line(&mut self, start: usize, end: usize, label: &str, value: &str)
+    {
+        let bytes = &self.data[start..end];
+        let mut hex = bytes.iter().take(HEX_BYTES_PER_LINE).map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(" ");
+        if bytes.len() > HEX_BYTES_PER_LINE
+        {
+            hex.push_str(" ..");
+        }
+        self.lines.push(format!("{start:04x}..{end:04x}  {hex:<26}  {label} = {value}"));
+    }
+}
+
+fn join(path: &str, name: &str) -> String
+{
+    if path.is_empty()
+    {
+        name.to_string()
+    }
+    else
+    {
+        format!("{path}.{name}")
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{annotate, failing_path};
+    use crate as serializable;
+    use crate::schema::Describe;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug)]
+    #[serializable(describe)]
+    struct Peer
+    {
+        addr: std::net::Ipv4Addr,
+        port: u16,
+    }
+
+    #[derive(Serializable, Debug)]
+    #[serializable(describe)]
+    enum Status
+    {
+        Down,
+        Up { peers: Vec<Peer>, name: Option<String> },
+    }
+
+    #[test]
+    fn annotate_nested_values()
+    {
+        let status = Status::Up {
+            peers: vec![Peer { addr: std::net::Ipv4Addr::new(10, 0, 0, 1), port: 443 }],
+            name: Some("edge".to_string()),
+        };
+        let expected = "\
+0000..0001  01                          Status = Status::Up
+0001..0005  00 00 00 01                 peers.len = 1
+0005..0009  0a 00 00 01                 peers[0].addr = 10.0.0.1
+0009..000b  01 bb                       peers[0].port = 443
+000b..000c  01                          name = Some
+000c..0014  00 00 00 04 65 64 67 65     name.0 = \"edge\"
+";
+        assert_eq!(annotate(&Status::describe(), &status.serialize()), expected);
+    }
+
+    #[test]
+    fn skip_invalid_values_of_known_length()
+    {
+        let mut serialized = (true, "ab".to_string(), 7u8).serialize();
+        serialized[0] = 2;
+        serialized[6] = 0xff;
+        let expected = "\
+0000..0001  02                          0 = !! Invalid variant index 2 for bool
+0001..0007  00 00 00 02 61 ff           1 = !! Invalid utf8 string format
+0007..0008  07                          2 = 7
+";
+        assert_eq!(annotate(&<(bool, String, u8)>::describe(), &serialized), expected);
+        assert_eq!(failing_path(&<(bool, String, u8)>::describe(), &serialized), Some("0".to_string()));
+        let expected = "\
+0000  !! parsing stopped: Invalid tag 3 for Status
+0000..0003  03 00 00                    ?? = unparsed
+";
+        assert_eq!(annotate(&Status::describe(), &[3, 0, 0]), expected);
+        assert_eq!(failing_path(&Status::describe(), &[3, 0, 0]), Some(String::new()));
+        assert_eq!(failing_path(&Status::describe(), &[1, 0, 0, 0, 1, 10, 0]), Some("peers[0].addr".to_string()));
+        assert_eq!(failing_path(&Status::describe(), &[0]), None);
+    }
+
+    #[derive(Serializable, Debug, Default)]
+    #[serializable(describe, indexed)]
+    struct Settings
+    {
+        #[serializable(field_index = 1)]
+        port: u16,
+        #[serializable(field_index = 3)]
+        verbose: bool,
+    }
+
+    #[test]
+    fn annotate_indexed_structs()
+    {
+        // A field with the unknown index 2 between the known ones
+        let mut serialized = 3u16.serialize();
+        serialized.extend((1u16, 2u32, 443u16).serialize());
+        serialized.extend((2u16, 1u32, 9u8).serialize());
+        serialized.extend((3u16, 1u32, true).serialize());
+        let expected = "\
+0000..0002  00 03                       .len = 3
+0002..0004  00 01                       port.index = 1
+0004..0008  00 00 00 02                 port.len = 2
+0008..000a  01 bb                       port = 443
+000a..000c  00 02                       #2.index = 2
+000c..0010  00 00 00 01                 #2.len = 1
+0010..0011  09                          #2 = unknown field, skipped
+0011..0013  00 03                       verbose.index = 3
+0013..0017  00 00 00 01                 verbose.len = 1
+0017..0018  01                          verbose = true
+";
+        assert_eq!(annotate(&Settings::describe(), &serialized), expected);
+        assert_eq!(Settings::deserialize_exact(&serialized).unwrap().port, 443);
+        serialized[7] = 3;
+        assert_eq!(failing_path(&Settings::describe(), &serialized), Some("port".to_string()));
+    }
+}
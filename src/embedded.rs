@@ -0,0 +1,135 @@
+//! Writing and reading serialized values through the `embedded-io` traits.
+//!
+//! Transport errors are mapped to [`std::io::Error`] with the matching [`std::io::ErrorKind`].
+
+use embedded_io::{Read, ReadExactError, Write};
+
+use crate::Serializable;
+
+/// Writes the serialization of `value` to `writer` and flushes it, returning the number of bytes written
+pub fn write_to_embedded<W: Write + ?Sized, T: Serializable>(writer: &mut W, value: &T) -> std::io::Result<usize>
+{
+    let serialized = value.serialize();
+    writer.write_all(&serialized).map_err(into_io_error)?;
+    writer.flush().map_err(into_io_error)?;
+    Ok(serialized.len())
+}
+
+/// Reads a single value from `reader` without consuming any byte after it.
+///
+/// Values with a fixed size are read with a single exact read. Otherwise the stream is read one byte
+/// at a time until the bytes read so far deserialize, so the cost grows with the square of the length
+/// of the value; this is meant for the short messages usually exchanged by small devices.
+/// Fails with [`std::io::ErrorKind::UnexpectedEof`] if the stream ends before the value is complete.
+pub fn read_from_embedded<R: Read + ?Sized, T: Serializable>(reader: &mut R) -> std::io::Result<T>
+{
+    if let Some(size) = T::FIXED_SIZE
+    {
+        let mut buffer = vec![0; size];
+        reader.read_exact(&mut buffer).map_err(|e| match e
+        {
+            ReadExactError::UnexpectedEof => std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Stream ended inside a value"),
+            ReadExactError::Other(e) => into_io_error(e),
+        })?;
+        return T::deserialize_exact(&buffer);
+    }
+    let mut buffer = Vec::new();
+    loop
+    {
+        if let Ok((value, _)) = T::deserialize(&buffer)
+        {
+            return Ok(value);
+        }
+        let mut byte = [0];
+        match reader.read(&mut byte)
+        {
+            Ok(0) => return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Stream ended inside a value")),
+            Ok(_) => buffer.push(byte[0]),
+            Err(e) if embedded_io::Error::kind(&e) == embedded_io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(into_io_error(e)),
+        }
+    }
+}
+
+fn into_io_error<E: embedded_io::Error>(error: E) -> std::io::Error
+{
+    std::io::Error::new(error.kind().into(), format!("{error:?}"))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{read_from_embedded, write_to_embedded};
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Reading
+    {
+        sensor: u16,
+        celsius: f32,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Announcement
+    {
+        id: u8,
+        name: String,
+    }
+
+    /// A link that fails every operation
+    struct BrokenLink;
+
+    impl embedded_io::ErrorType for BrokenLink
+    {
+        type Error = embedded_io::ErrorKind;
+    }
+
+    impl embedded_io::Read for BrokenLink
+    {
+        fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Self::Error>
+        {
+            Err(embedded_io::ErrorKind::TimedOut)
+        }
+    }
+
+    impl embedded_io::Write for BrokenLink
+    {
+        fn write(&mut self, _buf: &[u8]) -> Result<usize, Self::Error>
+        {
+            Err(embedded_io::ErrorKind::BrokenPipe)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error>
+        {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_and_read_embedded()
+    {
+        let reading = Reading { sensor: 3, celsius: 21.5 };
+        let announcement = Announcement { id: 1, name: "thermometer".to_string() };
+        let mut stream = Vec::new();
+        assert_eq!(write_to_embedded(&mut stream, &reading).unwrap(), 6);
+        assert_eq!(write_to_embedded(&mut stream, &announcement).unwrap(), 16);
+        write_to_embedded(&mut stream, &reading).unwrap();
+
+        let mut reader = stream.as_slice();
+        assert_eq!(read_from_embedded::<_, Reading>(&mut reader).unwrap(), reading);
+        assert_eq!(read_from_embedded::<_, Announcement>(&mut reader).unwrap(), announcement);
+        assert_eq!(reader.len(), 6);
+        assert_eq!(read_from_embedded::<_, Reading>(&mut reader).unwrap(), reading);
+        assert_eq!(read_from_embedded::<_, Reading>(&mut reader).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_from_embedded::<_, Announcement>(&mut &stream[6..10]).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn map_transport_errors()
+    {
+        assert_eq!(write_to_embedded(&mut BrokenLink, &1u8).unwrap_err().kind(), std::io::ErrorKind::BrokenPipe);
+        assert_eq!(read_from_embedded::<_, u8>(&mut BrokenLink).unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(read_from_embedded::<_, String>(&mut BrokenLink).unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,266 @@
+//! A self-describing header for serialized values.
+//!
+//! An envelope is the 4 byte [`MAGIC`], the [`FORMAT_VERSION`] as a big endian `u16`,
+//! a flag byte telling whether a type fingerprint follows, the optional fingerprint
+//! as a big endian `u64` and finally the serialized value.
+
+use crate::{Error, Serializable};
+
+/// Bytes that start every envelope
+pub const MAGIC: [u8; 4] = *b"SRLZ";
+
+/// Version of the envelope format written by this crate
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Length of the envelope header without the fingerprint
+const HEADER_LEN: usize = 7;
+
+/// A value wrapped in an envelope.
+/// It implements [`Serializable`] with the fingerprint included, so it can be passed
+/// directly to the [`crate::framing`] helpers or stored in a [`crate::message_queue::MessageQueue`].
+/// [`crate::framing::write_enveloped`], [`crate::framing::read_enveloped`] and
+/// [`crate::persistent::Persistent::open_enveloped`] wrap and open it on their own.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Envelope<T>(pub T);
+
+impl<T: Serializable> Envelope<T>
+{
+    /// Returns the serialization of `value` in an envelope with the fingerprint of `T`
+    pub fn wrap(value: &T) -> Vec<u8>
+    {
+        Self::wrap_with(value, Some(Self::fingerprint()))
+    }
+
+    /// Returns the serialization of `value` in an envelope without a fingerprint,
+    /// for values that are read back as a different but compatible type
+    pub fn wrap_untyped(value: &T) -> Vec<u8>
+    {
+        Self::wrap_with(value, None)
+    }
+
+    /// Checks the header of the envelope in `bytes` and deserializes the value, which must take the rest of `bytes`.
+    /// An envelope without a fingerprint is accepted as any type.
+    pub fn open(bytes: &[u8]) -> Result<T, EnvelopeError>
+    {
+        let (value, len) = Self::open_prefix(bytes)?;
+        if len != bytes.len()
+        {
+            return Err(EnvelopeError::Payload(Error::TrailingBytes.into()));
+        }
+        Ok(value)
+    }
+
+    /// Returns a fingerprint of `T` derived from its type name, which identifies the type
+    /// between programs built with the same compiler but is not guaranteed to be stable across compiler versions
+    pub fn fingerprint() -> u64
+    {
+        // FNV-1a, which unlike the standard hasher is specified and doesn't depend on a random seed
+        std::any::type_name::<T>().bytes().fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    }
+
+    fn wrap_with(value: &T, fingerprint: Option<u64>) -> Vec<u8>
+    {
+        let mut ret = Vec::new();
+        ret.extend_from_slice(&MAGIC);
+        ret.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        match fingerprint
+        {
+            Some(fingerprint) => {
+                ret.push(1);
+                ret.extend_from_slice(&fingerprint.to_be_bytes());
+            },
+            None => ret.push(0),
+        }
+        value.serialize_append(&mut ret);
+        ret
+    }
+
+    fn open_prefix(bytes: &[u8]) -> Result<(T, usize), EnvelopeError>
+    {
+        let header = match bytes.first_chunk::<HEADER_LEN>()
+        {
+            Some(header) => header,
+            None => return Err(EnvelopeError::Truncated),
+        };
+        let magic = [header[0], header[1], header[2], header[3]];
+        if magic != MAGIC
+        {
+            return Err(EnvelopeError::BadMagic(magic));
+        }
+        let version = u16::from_be_bytes([header[4], header[5]]);
+        if version != FORMAT_VERSION
+        {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+        let offset = match header[6]
+        {
+            0 => HEADER_LEN,
+            1 => {
+                let found = match bytes[HEADER_LEN..].first_chunk::<8>()
+                {
+                    Some(fingerprint) => u64::from_be_bytes(*fingerprint),
+                    None => return Err(EnvelopeError::Truncated),
+                };
+                let expected = Self::fingerprint();
+                if found != expected
+                {
+                    return Err(EnvelopeError::TypeMismatch { expected, found });
+                }
+                HEADER_LEN + 8
+            },
+            flag => return Err(EnvelopeError::Payload(Error::InvalidValue { type_name: "Envelope", reason: format!("invalid fingerprint flag {flag}") }.into())),
+        };
+        let (value, len) = T::deserialize(&bytes[offset..]).map_err(EnvelopeError::Payload)?;
+        Ok((value, offset + len))
+    }
+}
+
+impl<T: Serializable> Serializable for Envelope<T>
+{
+    fn serialize(&self) -> Vec<u8> {
+        Self::wrap(&self.0)
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (value, len) = Self::open_prefix(data)?;
+        Ok((Envelope(value), len))
+    }
+}
+
+/// Reasons why an envelope cannot be opened
+#[derive(Debug)]
+pub enum EnvelopeError
+{
+    /// The data ends inside the header
+    Truncated,
+    /// The data doesn't start with [`MAGIC`], it holds these bytes instead
+    BadMagic([u8; 4]),
+    /// The envelope was written with a format version this crate cannot read
+    UnsupportedVersion(u16),
+    /// The envelope holds a value of a different type
+    TypeMismatch
+    {
+        expected: u64,
+        found: u64,
+    },
+    /// The header is valid but the value cannot be deserialized
+    Payload(std::io::Error),
+}
+
+impl std::fmt::Display for EnvelopeError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self
+        {
+            EnvelopeError::Truncated => write!(f, "Envelope header is truncated"),
+            EnvelopeError::BadMagic(magic) => write!(f, "Invalid envelope magic {magic:02x?}"),
+            EnvelopeError::UnsupportedVersion(version) => write!(f, "Unsupported envelope format version {version}"),
+            EnvelopeError::TypeMismatch { expected, found } => {
+                write!(f, "Envelope holds a different type, expected fingerprint {expected:016x} but found {found:016x}")
+            },
+            EnvelopeError::Payload(e) => write!(f, "Invalid envelope payload: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self
+        {
+            EnvelopeError::Payload(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<EnvelopeError> for std::io::Error
+{
+    fn from(value: EnvelopeError) -> Self {
+        match value
+        {
+            EnvelopeError::Payload(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{Envelope, EnvelopeError, FORMAT_VERSION, MAGIC};
+    use crate as serializable;
+    use crate::{Error, Serializable};
+
+    #[derive(Serializable, Debug, Clone, PartialEq)]
+    struct Settings
+    {
+        volume: u8,
+        device: String,
+    }
+
+    fn settings() -> Settings
+    {
+        Settings { volume: 80, device: "speakers".to_string() }
+    }
+
+    #[test]
+    fn wrap_and_open()
+    {
+        let wrapped = Envelope::wrap(&settings());
+        assert_eq!(&wrapped[..4], &MAGIC);
+        assert_eq!(&wrapped[4..6], &FORMAT_VERSION.to_be_bytes());
+        assert_eq!(wrapped[6], 1);
+        assert_eq!(&wrapped[15..], settings().serialize().as_slice());
+        assert_eq!(Envelope::<Settings>::open(&wrapped).unwrap(), settings());
+
+        let untyped = Envelope::wrap_untyped(&settings());
+        assert_eq!(untyped.len(), wrapped.len() - 8);
+        assert_eq!(Envelope::<Settings>::open(&untyped).unwrap(), settings());
+
+        let mut stream = Vec::new();
+        crate::framing::write_value(&mut stream, &Envelope(settings())).unwrap();
+        let read: Envelope<Settings> = crate::framing::read_value(&mut stream.as_slice(), 100).unwrap();
+        assert_eq!(read.0, settings());
+    }
+
+    #[test]
+    fn open_invalid_envelopes()
+    {
+        let wrapped = Envelope::wrap(&settings());
+        assert!(matches!(Envelope::<Settings>::open(&wrapped[..5]), Err(EnvelopeError::Truncated)));
+        assert!(matches!(Envelope::<Settings>::open(&wrapped[..10]), Err(EnvelopeError::Truncated)));
+
+        let mut bad_magic = wrapped.clone();
+        bad_magic[..4].copy_from_slice(b"JSON");
+        assert!(matches!(Envelope::<Settings>::open(&bad_magic), Err(EnvelopeError::BadMagic(magic)) if &magic == b"JSON"));
+
+        let mut future_version = wrapped.clone();
+        future_version[4..6].copy_from_slice(&2u16.to_be_bytes());
+        assert!(matches!(Envelope::<Settings>::open(&future_version), Err(EnvelopeError::UnsupportedVersion(2))));
+
+        match Envelope::<(u8, String)>::open(&wrapped)
+        {
+            Err(EnvelopeError::TypeMismatch { expected, found }) => {
+                assert_eq!(expected, Envelope::<(u8, String)>::fingerprint());
+                assert_eq!(found, Envelope::<Settings>::fingerprint());
+            },
+            other => panic!("Expected a type mismatch, got {other:?}"),
+        }
+        // Without a fingerprint the same bytes decode as a compatible type
+        assert_eq!(Envelope::<(u8, String)>::open(&Envelope::wrap_untyped(&settings())).unwrap(), (80, "speakers".to_string()));
+
+        let mut trailing = wrapped.clone();
+        trailing.push(0);
+        assert!(matches!(Envelope::<Settings>::open(&trailing), Err(EnvelopeError::Payload(e)) if Error::find(&e) == Some(&Error::TrailingBytes)));
+        let mut bad_flag = wrapped.clone();
+        bad_flag[6] = 2;
+        assert!(matches!(Envelope::<Settings>::open(&bad_flag), Err(EnvelopeError::Payload(e)) if matches!(Error::find(&e), Some(Error::InvalidValue { .. }))));
+        assert!(matches!(Envelope::<Settings>::open(&wrapped[..wrapped.len() - 1]), Err(EnvelopeError::Payload(_))));
+
+        let error: std::io::Error = Envelope::<Settings>::open(&bad_magic).unwrap_err().into();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,168 @@
+//! Float fields written as scaled integers, with `#[serializable(fixed_point(scale = 100, repr = "i16"))]`.
+//!
+//! An `f32` or `f64` field with the attribute is multiplied by `scale`, rounded to the nearest integer
+//! and written as `repr`, which is one of the integer types up to 64 bits; reading divides it back.
+//! Only multiples of `1 / scale` survive the round trip, other values come back rounded, and large
+//! scales on `f32` fields are limited by the 24 bit precision of `f32` itself.
+//!
+//! A value that doesn't fit in `repr` after scaling makes `serialize` panic, like any other value
+//! that cannot be read back. With `fixed_point(scale = 100, repr = "i16", saturate)` it is written as
+//! the closest value of `repr` instead. NaN always panics.
+//!
+//! ```
+//! use serializable::Serializable;
+//!
+//! #[derive(Serializable)]
+//! struct Reading
+//! {
+//!     #[serializable(fixed_point(scale = 100, repr = "i16"))]
+//!     celsius: f32,
+//! }
+//!
+//! assert_eq!(Reading { celsius: 21.37 }.serialize(), 2137i16.to_be_bytes());
+//! ```
+
+use crate::FixedSize;
+
+/// Integer types that can hold the scaled value of a fixed point field
+pub trait FixedPointRepr: FixedSize + Copy
+{
+    /// Returns the integer equal to `value`, which is already rounded, if it is in range
+    fn from_rounded(value: f64) -> Option<Self>;
+    /// Returns the integer closest to `value`, which is already rounded and not NaN
+    fn saturating_from(value: f64) -> Self;
+    /// Returns the integer as a float, which is exact up to 2^53
+    fn to_f64(self) -> f64;
+}
+
+/// Implements [`FixedPointRepr`] for integer types
+macro_rules! impl_fixed_point_repr {
+    ($($ty:ty),+) => {
+        $(
+            impl FixedPointRepr for $ty
+            {
+                fn from_rounded(value: f64) -> Option<Self>
+                {
+                    // MAX + 1 is a power of two, which is exact even when MAX itself is not
+                    if value >= <$ty>::MIN as f64 && value < <$ty>::MAX as f64 + 1.0
+                    {
+                        Some(value as $ty)
+                    }
+                    else
+                    {
+                        None
+                    }
+                }
+
+                fn saturating_from(value: f64) -> Self
+                {
+                    value as $ty
+                }
+
+                fn to_f64(self) -> f64
+                {
+                    self as f64
+                }
+            }
+        )+
+    };
+}
+
+impl_fixed_point_repr!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+/// Returns the scaled value of the fixed point field `field`, used by derived code
+#[doc(hidden)]
+pub fn encode<R: FixedPointRepr>(value: f64, scale: f64, saturate: bool, field: &str) -> R
+{
+    let scaled = (value * scale).round();
+    if scaled.is_nan()
+    {
+        panic!("The fixed point field {field} cannot hold NaN");
+    }
+    match R::from_rounded(scaled)
+    {
+        Some(repr) => repr,
+        None if saturate => R::saturating_from(scaled),
+        None => panic!("The value {value} is out of the range of the fixed point field {field}"),
+    }
+}
+
+/// Returns the value of a fixed point field from its scaled value, used by derived code
+#[doc(hidden)]
+pub fn decode<R: FixedPointRepr>(repr: R, scale: f64) -> f64
+{
+    repr.to_f64() / scale
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate as serializable;
+    use crate::schema::{Describe, Schema};
+    use crate::{FixedSize, Serializable};
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(describe)]
+    struct SensorReading
+    {
+        sensor: u8,
+        #[serializable(fixed_point(scale = 100, repr = "i16"))]
+        temperature: f32,
+        #[serializable(fixed_point(scale = 10, repr = "u16", saturate))]
+        humidity: f64,
+        #[serializable(fixed_point(scale = 0.5, repr = "i8"))]
+        altitude: f64,
+    }
+
+    #[test]
+    fn reference_encodings()
+    {
+        let reading = SensorReading { sensor: 3, temperature: 21.37, humidity: 45.5, altitude: 120.0 };
+        assert_eq!(reading.serialize(), [3, 0x08, 0x59, 0x01, 0xC7, 60]);
+        let reading = SensorReading { sensor: 4, temperature: -5.5, humidity: 0.04, altitude: -255.0 };
+        assert_eq!(reading.serialize(), [4, 0xFD, 0xDA, 0x00, 0x00, 0x80]);
+        assert_eq!(SensorReading::SIZE, 6);
+        let mut out = [0; 6];
+        reading.serialize_to_slice(&mut out);
+        assert_eq!(out, [4, 0xFD, 0xDA, 0x00, 0x00, 0x80]);
+    }
+
+    #[test]
+    fn read_back_rounded()
+    {
+        let reading = SensorReading { sensor: 1, temperature: -12.345, humidity: 99.96, altitude: 3.0 };
+        let (deserialized, len) = SensorReading::deserialize(&reading.serialize()).unwrap();
+        assert_eq!(len, 6);
+        assert_eq!(deserialized, SensorReading { sensor: 1, temperature: -12.35, humidity: 100.0, altitude: 4.0 });
+        // SAFETY: the data was just written by serialize
+        assert_eq!(unsafe { SensorReading::deserialize_trusted(&reading.serialize()) }.unwrap().0, deserialized);
+        assert_eq!(SensorReading::deserialize_exact(&[0, 0x7F, 0xFF, 0xFF, 0xFF, 0x7F]).unwrap().temperature, 327.67);
+        assert_eq!(SensorReading::check_bytes(&[0, 0, 0, 0, 0]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        // The schema describes the integers that are written
+        let Schema::Struct { fields, .. } = SensorReading::describe() else { panic!("SensorReading is a struct") };
+        assert_eq!(fields[1].schema, Schema::Primitive("i16"));
+    }
+
+    #[test]
+    fn saturate_out_of_range()
+    {
+        let reading = SensorReading { sensor: 0, temperature: 0.0, humidity: 7000.0, altitude: 0.0 };
+        assert_eq!(reading.serialize()[3..5], [0xFF, 0xFF]);
+        let reading = SensorReading { sensor: 0, temperature: 0.0, humidity: -3.0, altitude: 0.0 };
+        assert_eq!(reading.serialize()[3..5], [0x00, 0x00]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The value 400 is out of the range of the fixed point field temperature")]
+    fn out_of_range_panics()
+    {
+        SensorReading { sensor: 0, temperature: 400.0, humidity: 0.0, altitude: 0.0 }.serialize();
+    }
+
+    #[test]
+    #[should_panic(expected = "The fixed point field altitude cannot hold NaN")]
+    fn nan_panics()
+    {
+        SensorReading { sensor: 0, temperature: 0.0, humidity: 0.0, altitude: f64::NAN }.serialize();
+    }
+}
@@ -0,0 +1,225 @@
+//! Length prefixed framing of serialized values over byte streams.
+//!
+//! A frame is the length of the payload as a 4 byte big endian number followed by the payload.
+
+use std::io::{Read, Write};
+
+use crate::envelope::Envelope;
+use crate::Serializable;
+
+/// Writes `payload` as a single frame
+pub fn write_frame<W: Write + ?Sized>(writer: &mut W, payload: &[u8]) -> std::io::Result<()>
+{
+    let len: u32 = payload.len().try_into()
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Frame too long"))?;
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    crate::trace::written(payload.len());
+    Ok(())
+}
+
+/// Reads the payload of a single frame, frames longer than `max_len` are rejected before allocating.
+/// Fails with [`std::io::ErrorKind::UnexpectedEof`] if the stream ends before the frame is complete.
+pub fn read_frame<R: Read + ?Sized>(reader: &mut R, max_len: usize) -> std::io::Result<Vec<u8>>
+{
+    read_frame_or_eof(reader, max_len)?
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Stream ended before a frame"))
+}
+
+/// Like [`read_frame`], but returns `None` if the stream ends cleanly before the next frame.
+/// A stream that ends inside a frame still fails with [`std::io::ErrorKind::UnexpectedEof`].
+pub fn read_frame_or_eof<R: Read + ?Sized>(reader: &mut R, max_len: usize) -> std::io::Result<Option<Vec<u8>>>
+{
+    let mut len = [0; 4];
+    let mut filled = 0;
+    while filled < len.len()
+    {
+        match reader.read(&mut len[filled..])
+        {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Stream ended inside a frame")),
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+            Err(e) => return Err(e),
+        }
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > max_len
+    {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame too long"));
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+    crate::trace::read(len);
+    Ok(Some(payload))
+}
+
+/// Writes the serialization of `value` as a single frame
+pub fn write_value<W: Write + ?Sized, S: Serializable>(writer: &mut W, value: &S) -> std::io::Result<()>
+{
+    write_frame(writer, &value.serialize())
+}
+
+/// Reads a single frame and deserializes it, the value must take the whole frame
+pub fn read_value<R: Read + ?Sized, S: Serializable>(reader: &mut R, max_len: usize) -> std::io::Result<S>
+{
+    let payload = read_frame(reader, max_len)?;
+    crate::trace::read_value(S::deserialize_exact(&payload))
+}
+
+/// Writes the serialization of `value` in an [`Envelope`] with the fingerprint of `S` as a single frame
+pub fn write_enveloped<W: Write + ?Sized, S: Serializable>(writer: &mut W, value: &S) -> std::io::Result<()>
+{
+    write_frame(writer, &Envelope::wrap(value))
+}
+
+/// Reads a single frame and opens the [`Envelope`] in it, which must hold an `S` and take the whole frame
+pub fn read_enveloped<R: Read + ?Sized, S: Serializable>(reader: &mut R, max_len: usize) -> std::io::Result<S>
+{
+    let payload = read_frame(reader, max_len)?;
+    crate::trace::read_value(Envelope::<S>::open(&payload).map_err(std::io::Error::from))
+}
+
+/// Splits a stream of bytes into frames without doing any I/O.
+/// Bytes are fed with [`FrameDecoder::push`] as they arrive, in chunks of any size,
+/// and complete frames are taken with [`FrameDecoder::next_frame`].
+#[derive(Debug, Clone)]
+pub struct FrameDecoder
+{
+    buffer: Vec<u8>,
+    max_len: usize,
+}
+
+impl FrameDecoder
+{
+    /// Returns a decoder that rejects frames longer than `max_len`
+    pub fn new(max_len: usize) -> Self
+    {
+        Self { buffer: Vec::new(), max_len }
+    }
+
+    /// Returns the length of the longest accepted frame
+    pub fn max_len(&self) -> usize
+    {
+        self.max_len
+    }
+
+    /// Sets the length of the longest accepted frame
+    pub fn set_max_len(&mut self, max_len: usize)
+    {
+        self.max_len = max_len;
+    }
+
+    /// Appends received bytes to the decoder
+    pub fn push(&mut self, bytes: &[u8])
+    {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the number of received bytes that are not part of a returned frame yet
+    pub fn buffered_len(&self) -> usize
+    {
+        self.buffer.len()
+    }
+
+    /// Returns the payload of the next frame, or `None` if it has not been received completely.
+    /// Fails as soon as the length of a frame longer than the maximum is received.
+    pub fn next_frame(&mut self) -> std::io::Result<Option<Vec<u8>>>
+    {
+        let Some(len) = self.buffer.first_chunk::<4>() else
+        {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(*len) as usize;
+        if len > self.max_len
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Frame too long"));
+        }
+        if self.buffer.len() - 4 < len
+        {
+            return Ok(None);
+        }
+        let payload = self.buffer[4..4 + len].to_vec();
+        self.buffer.drain(..4 + len);
+        Ok(Some(payload))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{read_enveloped, read_frame, read_frame_or_eof, read_value, write_enveloped, write_frame, write_value, FrameDecoder};
+
+    #[test]
+    fn write_and_read_frames()
+    {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, b"first").unwrap();
+        write_value(&mut stream, &"second".to_string()).unwrap();
+        let mut reader = stream.as_slice();
+        assert_eq!(read_frame(&mut reader, 100).unwrap(), b"first");
+        assert_eq!(read_value::<_, String>(&mut reader, 100).unwrap(), "second");
+        assert_eq!(read_frame(&mut reader, 100).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(read_frame_or_eof(&mut stream.as_slice(), 100).unwrap().is_some());
+        assert!(read_frame_or_eof(&mut reader, 100).unwrap().is_none());
+        assert_eq!(read_frame_or_eof(&mut &stream[..2], 100).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_frame_or_eof(&mut &stream[..6], 100).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn write_and_read_enveloped_values()
+    {
+        let mut stream = Vec::new();
+        write_enveloped(&mut stream, &"first".to_string()).unwrap();
+        write_enveloped(&mut stream, &7u32).unwrap();
+        let mut reader = stream.as_slice();
+        assert_eq!(read_enveloped::<_, String>(&mut reader, 100).unwrap(), "first");
+        // The fingerprint tells a u32 from an i32 of the same size
+        let error = read_enveloped::<_, i32>(&mut reader, 100).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(error.get_ref().and_then(|e| e.downcast_ref()), Some(crate::envelope::EnvelopeError::TypeMismatch { .. })));
+        // Frames written without an envelope are rejected
+        let mut plain = Vec::new();
+        write_value(&mut plain, &7u32).unwrap();
+        assert!(matches!(read_enveloped::<_, u32>(&mut plain.as_slice(), 100).unwrap_err().get_ref().and_then(|e| e.downcast_ref()), Some(crate::envelope::EnvelopeError::Truncated)));
+    }
+
+    #[test]
+    fn read_invalid_frames()
+    {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, &[0; 64]).unwrap();
+        assert_eq!(read_frame(&mut stream.as_slice(), 63).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(read_frame(&mut &stream[..40], 64).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+        assert_eq!(read_value::<_, u32>(&mut stream.as_slice(), 64).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decode_frames_split_at_every_byte()
+    {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, b"first").unwrap();
+        write_frame(&mut stream, b"").unwrap();
+        write_frame(&mut stream, b"third").unwrap();
+        let mut decoder = FrameDecoder::new(100);
+        let mut frames = Vec::new();
+        for byte in &stream
+        {
+            assert!(decoder.next_frame().unwrap().is_none());
+            decoder.push(std::slice::from_ref(byte));
+            while let Some(frame) = decoder.next_frame().unwrap()
+            {
+                frames.push(frame);
+            }
+        }
+        assert_eq!(frames, [b"first".to_vec(), Vec::new(), b"third".to_vec()]);
+        assert_eq!(decoder.buffered_len(), 0);
+
+        let mut decoder = FrameDecoder::new(4);
+        decoder.push(&stream[..4]);
+        assert_eq!(decoder.next_frame().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,72 @@
+//! Helpers for fuzzing serializable types, see the `fuzz` directory for the `cargo fuzz` targets.
+//!
+//! Derive [`arbitrary::Arbitrary`] next to [`Serializable`] on your types
+//! and call [`fuzz_roundtrip`] from a fuzz target:
+//!
+//! ```ignore
+//! #![no_main]
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+//!     serializable::fuzz::fuzz_roundtrip::<MyMessage>(data);
+//! });
+//! ```
+
+use arbitrary::{Arbitrary, Unstructured};
+
+use crate::Serializable;
+
+/// Checks the serialization of `T` with the fuzzer input `data`, panicking on any failure.
+///
+/// `data` is first deserialized as raw bytes, which may fail but must not panic nor claim to read
+/// more bytes than given. Then it is used to build a value with [`Arbitrary`], which must
+/// deserialize back to an equal value from exactly the bytes it was serialized to.
+/// Values that are not equal to themselves, like a NaN float, only need to serialize to the same bytes again.
+pub fn fuzz_roundtrip<T: Serializable + for<'a> Arbitrary<'a> + PartialEq>(data: &[u8])
+{
+    if let Ok((_, read)) = T::deserialize(data)
+    {
+        assert!(read <= data.len(), "Deserialization read {read} bytes out of {}", data.len());
+    }
+
+    let value = match T::arbitrary(&mut Unstructured::new(data))
+    {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    let serialized = value.serialize();
+    let (deserialized, read) = match T::deserialize(&serialized)
+    {
+        Ok(ret) => ret,
+        Err(e) => panic!("The serialization of a {} cannot be deserialized: {e}", std::any::type_name::<T>()),
+    };
+    assert_eq!(read, serialized.len(), "Deserialization didn't read the whole serialization");
+    assert!(deserialized == value || deserialized.serialize() == serialized, "Roundtrip changed the value");
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::BTreeMap;
+
+    use super::fuzz_roundtrip;
+
+    #[test]
+    fn roundtrip_builtin_types()
+    {
+        // A few fixed inputs stand in for the fuzzer, the fuzz targets run the real thing
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            vec![0xff; 64],
+            (0..=255).collect(),
+            vec![0, 0, 0, 3, b'a', 0xc3, 0x28, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+        ];
+        for input in &inputs
+        {
+            fuzz_roundtrip::<Vec<u32>>(input);
+            fuzz_roundtrip::<String>(input);
+            fuzz_roundtrip::<[u16; 8]>(input);
+            fuzz_roundtrip::<(u8, Option<i64>, Vec<bool>)>(input);
+            fuzz_roundtrip::<BTreeMap<String, Vec<u8>>>(input);
+            fuzz_roundtrip::<Vec<f64>>(input);
+        }
+    }
+}
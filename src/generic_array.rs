@@ -0,0 +1,114 @@
+//! Serialization of [`GenericArray`], as returned by the RustCrypto traits like `digest` and `aead`.
+//!
+//! A `GenericArray<T, N>` is written exactly like `[T; N]`: the `N` items in order, without a length prefix.
+
+use generic_array::{ArrayLength, GenericArray};
+
+use crate::schema::{Describe, Schema};
+use crate::serializable::{check_items, deserialize_items_trusted, items_size, read_items, serialize_slice_deterministic};
+use crate::Serializable;
+
+impl<T: Serializable, N: ArrayLength<T>> Serializable for GenericArray<T, N>
+{
+    const FIXED_SIZE: Option<usize> = match T::FIXED_SIZE
+    {
+        Some(size) => size.checked_mul(N::USIZE),
+        None => None,
+    };
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        T::serialize_slice(self, out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        items_size(self.iter(), N::USIZE)
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        serialize_slice_deterministic(self, &mut ret);
+        ret
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (items, offset) = T::deserialize_vec(data, N::USIZE)?;
+        let ret = GenericArray::from_exact_iter(items).unwrap_or_else(|| unreachable!("Exactly N items were deserialized"));
+        Ok((ret, offset))
+    }
+
+    fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+        check_items::<T>(data, N::USIZE)
+    }
+
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (items, offset) = unsafe { deserialize_items_trusted::<T>(data, N::USIZE) }?;
+        let ret = GenericArray::from_exact_iter(items).unwrap_or_else(|| unreachable!("Exactly N items were deserialized"));
+        Ok((ret, offset))
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let (items, offset) = read_items::<T>(reader, N::USIZE)?;
+        let ret = GenericArray::from_exact_iter(items).unwrap_or_else(|| unreachable!("Exactly N items were deserialized"));
+        Ok((ret, offset))
+    }
+}
+
+impl<T: Describe, N: ArrayLength<T>> Describe for GenericArray<T, N>
+{
+    fn describe() -> Schema
+    {
+        Schema::Array(Box::new(T::describe()), N::USIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use generic_array::typenum::{U3, U32};
+    use generic_array::GenericArray;
+
+    use crate::schema::{Describe, Schema};
+    use crate::Serializable;
+
+    #[test]
+    fn same_bytes_as_arrays()
+    {
+        let digest: [u8; 32] = std::array::from_fn(|i| (i * 7) as u8);
+        let generic = GenericArray::<u8, U32>::clone_from_slice(&digest);
+        let serialized = generic.serialize();
+        assert_eq!(serialized, digest.serialize());
+        assert_eq!(serialized.len(), 32);
+        assert_eq!(GenericArray::<u8, U32>::deserialize_exact(&serialized).unwrap(), generic);
+        assert_eq!(<[u8; 32]>::deserialize_exact(&serialized).unwrap(), digest);
+        assert_eq!(GenericArray::<u8, U32>::FIXED_SIZE, Some(32));
+        assert_eq!(GenericArray::<u8, U32>::describe(), <[u8; 32]>::describe());
+    }
+
+    #[test]
+    fn exact_length()
+    {
+        let bytes = [0; 32];
+        assert!(GenericArray::<u8, U32>::deserialize(&bytes[..31]).is_err());
+        assert!(GenericArray::<u8, U32>::check_bytes(&bytes[..31]).is_err());
+        assert!(GenericArray::<u8, U32>::deserialize_exact(&[0; 33]).is_err());
+        assert_eq!(GenericArray::<u8, U32>::deserialize(&[0; 33]).unwrap().1, 32);
+    }
+
+    #[test]
+    fn items_with_a_length()
+    {
+        let words = GenericArray::<String, U3>::from(["a".to_string(), "bc".to_string(), String::new()]);
+        let serialized = words.serialize();
+        assert_eq!(serialized, ["a".to_string(), "bc".to_string(), String::new()].serialize());
+        assert_eq!(GenericArray::<String, U3>::deserialize_exact(&serialized).unwrap(), words);
+        // SAFETY: the data was just written by serialize
+        assert_eq!(unsafe { GenericArray::<String, U3>::deserialize_trusted(&serialized) }.unwrap().0, words);
+        assert_eq!(GenericArray::<String, U3>::describe(), Schema::Array(Box::new(Schema::Primitive("String")), 3));
+    }
+}
@@ -0,0 +1,255 @@
+//! Best-effort persistence of [`Instant`]s.
+//!
+//! An `Instant` only has a meaning inside the process that took it, so it is stored relative to an
+//! [`InstantAnchor`], a wall clock time and an `Instant` taken together. Within the process that
+//! captured the anchor, [`InstantAnchor::encode`] and [`InstantAnchor::decode`] are exact inverses.
+//! A deserialized anchor is placed on the timeline of the reading process by comparing its wall clock
+//! time with the current one, so decoded instants are as accurate as the two wall clocks agree,
+//! and they are off by however much the wall clock was adjusted in between.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::schema::{Describe, Schema};
+use crate::{Error, Serializable};
+
+/// A wall clock time and an `Instant` taken at the same moment.
+/// It is serialized as the wall clock time in nanoseconds since the UNIX epoch, as a big endian `i128`;
+/// the `Instant` of a deserialized anchor is the moment of the reading process's timeline with that wall clock time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InstantAnchor
+{
+    system: SystemTime,
+    instant: Instant,
+}
+
+impl InstantAnchor
+{
+    /// Returns an anchor at the current time
+    pub fn now() -> Self
+    {
+        Self { system: SystemTime::now(), instant: Instant::now() }
+    }
+
+    /// Returns an anchor that considers `system` and `instant` the same moment
+    pub fn from_parts(system: SystemTime, instant: Instant) -> Self
+    {
+        Self { system, instant }
+    }
+
+    /// Returns the wall clock time of the anchor
+    pub fn system_time(&self) -> SystemTime
+    {
+        self.system
+    }
+
+    /// Returns the `Instant` of the anchor on the timeline of this process
+    pub fn instant(&self) -> Instant
+    {
+        self.instant
+    }
+
+    /// Returns the nanoseconds from the anchor to `instant`, negative if `instant` is earlier
+    pub fn encode(&self, instant: Instant) -> i128
+    {
+        match instant.checked_duration_since(self.instant)
+        {
+            Some(after) => after.as_nanos() as i128,
+            None => -(self.instant.duration_since(instant).as_nanos() as i128),
+        }
+    }
+
+    /// Returns the `Instant` `nanos` nanoseconds after the anchor, or before it if negative.
+    /// Instants that this process cannot represent, such as those before the start of the
+    /// monotonic clock, are clamped to the closest one it can.
+    pub fn decode(&self, nanos: i128) -> Instant
+    {
+        let duration = nanos_to_duration(nanos).unwrap_or(Duration::MAX);
+        if nanos >= 0
+        {
+            offset_instant(self.instant, duration, Instant::checked_add)
+        }
+        else
+        {
+            offset_instant(self.instant, duration, Instant::checked_sub)
+        }
+    }
+}
+
+/// Applies `op` to `instant` and `duration`, halving the part of `duration` that doesn't fit
+/// until nothing is left, which gets within a nanosecond of the furthest representable instant
+fn offset_instant(mut instant: Instant, mut duration: Duration, op: fn(&Instant, Duration) -> Option<Instant>) -> Instant
+{
+    while !duration.is_zero()
+    {
+        match op(&instant, duration)
+        {
+            Some(ret) => return ret,
+            None => {
+                let half = duration / 2;
+                instant = op(&instant, half).unwrap_or(instant);
+                duration = if half.is_zero() { Duration::ZERO } else { duration - half };
+            },
+        }
+    }
+    instant
+}
+
+/// Returns the length of `nanos` nanoseconds ignoring the sign, or `None` if it doesn't fit a [`Duration`]
+fn nanos_to_duration(nanos: i128) -> Option<Duration>
+{
+    let nanos = nanos.unsigned_abs();
+    let secs = u64::try_from(nanos / 1_000_000_000).ok()?;
+    Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// Returns the nanoseconds from `to` to `from`, negative if `from` is earlier
+fn signed_nanos_between(from: SystemTime, to: SystemTime) -> i128
+{
+    match from.duration_since(to)
+    {
+        Ok(after) => after.as_nanos() as i128,
+        Err(e) => -(e.duration().as_nanos() as i128),
+    }
+}
+
+impl Serializable for InstantAnchor
+{
+    const FIXED_SIZE: Option<usize> = i128::FIXED_SIZE;
+
+    fn serialize(&self) -> Vec<u8> {
+        signed_nanos_between(self.system, SystemTime::UNIX_EPOCH).serialize()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (nanos, read) = i128::deserialize(data)?;
+        let system = match nanos_to_duration(nanos)
+        {
+            Some(duration) if nanos >= 0 => SystemTime::UNIX_EPOCH.checked_add(duration),
+            Some(duration) => SystemTime::UNIX_EPOCH.checked_sub(duration),
+            None => None,
+        };
+        let system = system.ok_or_else(|| Error::InvalidValue { type_name: "InstantAnchor", reason: format!("{nanos} nanoseconds from the UNIX epoch are out of range") })?;
+        // The anchor is as far from now on this process's timeline as it is on the wall clock
+        let now = Self::now();
+        let age = signed_nanos_between(now.system, system);
+        Ok((Self { system, instant: now.decode(-age) }, read))
+    }
+}
+
+impl Describe for InstantAnchor
+{
+    fn describe() -> Schema
+    {
+        i128::describe()
+    }
+}
+
+/// An instant stored as the nanoseconds from an [`InstantAnchor`], for struct fields.
+/// The anchor is needed to convert it from and to an [`Instant`], so it is usually serialized next to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RelativeInstant(pub i128);
+
+impl RelativeInstant
+{
+    /// Returns `instant` relative to `anchor`
+    pub fn new(anchor: &InstantAnchor, instant: Instant) -> Self
+    {
+        Self(anchor.encode(instant))
+    }
+
+    /// Returns the instant on the timeline of `anchor`, see [`InstantAnchor::decode`]
+    pub fn to_instant(self, anchor: &InstantAnchor) -> Instant
+    {
+        anchor.decode(self.0)
+    }
+}
+
+impl Serializable for RelativeInstant
+{
+    const FIXED_SIZE: Option<usize> = i128::FIXED_SIZE;
+
+    fn serialize(&self) -> Vec<u8> {
+        self.0.serialize()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (nanos, read) = i128::deserialize(data)?;
+        Ok((Self(nanos), read))
+    }
+}
+
+impl Describe for RelativeInstant
+{
+    fn describe() -> Schema
+    {
+        i128::describe()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::time::{Duration, Instant};
+
+    use super::{InstantAnchor, RelativeInstant};
+    use crate as serializable;
+    use crate::Serializable;
+
+    /// A profiling span, stored with the anchor its instants are relative to
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Profile
+    {
+        anchor: InstantAnchor,
+        spans: Vec<(String, RelativeInstant, RelativeInstant)>,
+    }
+
+    #[test]
+    fn roundtrip_within_the_process()
+    {
+        let start = Instant::now();
+        let anchor = InstantAnchor::now();
+        let later = anchor.instant() + Duration::from_millis(1500);
+        for instant in [start, anchor.instant(), later]
+        {
+            assert_eq!(anchor.decode(anchor.encode(instant)), instant);
+            assert_eq!(RelativeInstant::new(&anchor, instant).to_instant(&anchor), instant);
+        }
+        assert!(anchor.encode(start) <= 0);
+        assert_eq!(anchor.encode(later), 1_500_000_000);
+    }
+
+    #[test]
+    fn roundtrip_across_anchors()
+    {
+        let anchor = InstantAnchor::now();
+        let first = anchor.instant() - Duration::from_millis(250);
+        let second = anchor.instant() + Duration::from_millis(40);
+        let profile = Profile {
+            anchor,
+            spans: vec![("load".to_string(), RelativeInstant::new(&anchor, first), RelativeInstant::new(&anchor, second))],
+        };
+        let serialized = profile.serialize();
+        std::thread::sleep(Duration::from_millis(20));
+        // The deserialized anchor plays the part of the anchor in another process
+        let deserialized = Profile::deserialize_exact(&serialized).unwrap();
+        assert_eq!(deserialized.anchor.system_time(), anchor.system_time());
+        assert_eq!(deserialized.spans[0].1, profile.spans[0].1);
+        let (_, start, end) = deserialized.spans[0];
+        let (start, end) = (start.to_instant(&deserialized.anchor), end.to_instant(&deserialized.anchor));
+        assert_eq!(end - start, Duration::from_millis(290));
+        // Only the disagreement between the wall clock and the monotonic clock is lost
+        let error = start.max(first) - start.min(first);
+        assert!(error < Duration::from_millis(50), "{error:?}");
+    }
+
+    #[test]
+    fn clamp_unrepresentable_instants()
+    {
+        let anchor = InstantAnchor::now();
+        let earliest = anchor.decode(i128::MIN);
+        assert!(earliest <= anchor.instant());
+        assert!(earliest.checked_sub(Duration::from_secs(1)).is_none());
+        assert!(anchor.decode(i128::MAX) > anchor.instant());
+        assert!(InstantAnchor::deserialize(&i128::MAX.serialize()).is_err());
+    }
+}
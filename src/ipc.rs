@@ -0,0 +1,248 @@
+//! Exchanging framed serialized values with a child process over its standard input and output.
+//!
+//! The parent builds a [`ChildChannel`] from the pipes of the child it spawned,
+//! the child talks back through the [`parent_channel`] built from its own stdin and stdout.
+//! Frames are the ones of [`crate::framing`].
+
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+use std::process::{Child, ChildStdin, ChildStdout};
+
+use crate::framing;
+use crate::net::connection::DEFAULT_MAX_FRAME_LEN;
+use crate::Serializable;
+
+/// Channel to a child process, sending `T` to its stdin and receiving `R` from its stdout
+pub type ChildChannel<T, R> = Channel<T, R, ChildStdin, ChildStdout>;
+
+/// Channel to the parent process, sending `T` to stdout and receiving `R` from stdin
+pub type ParentChannel<T, R> = Channel<T, R, std::io::StdoutLock<'static>, std::io::StdinLock<'static>>;
+
+/// Returns the channel to the parent process, which locks stdin and stdout for as long as it lives.
+/// Nothing else should be printed to stdout while it is in use.
+pub fn parent_channel<T: Serializable, R: Serializable>() -> ParentChannel<T, R>
+{
+    Channel::new(std::io::stdout().lock(), std::io::stdin().lock())
+}
+
+/// A channel sending values of type `T` to `W` and receiving values of type `R` from `Rd`
+#[derive(Debug)]
+pub struct Channel<T, R, W, Rd>
+{
+    sender: Sender<T, W>,
+    receiver: Receiver<R, Rd>,
+}
+
+impl<T: Serializable, R: Serializable> ChildChannel<T, R>
+{
+    /// Takes the stdin and stdout of `child`, which must have been spawned with both piped
+    pub fn from_child(child: &mut Child) -> std::io::Result<Self>
+    {
+        match (child.stdin.take(), child.stdout.take())
+        {
+            (Some(stdin), Some(stdout)) => Ok(Channel::new(stdin, stdout)),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Child stdin and stdout must be piped")),
+        }
+    }
+}
+
+impl<T: Serializable, R: Serializable, W: Write, Rd: Read> Channel<T, R, W, Rd>
+{
+    /// Returns a channel writing to `writer` and reading from `reader`
+    pub fn new(writer: W, reader: Rd) -> Self
+    {
+        Self { sender: Sender::new(writer), receiver: Receiver::new(reader) }
+    }
+
+    /// Sends `value` as a single frame
+    pub fn send(&mut self, value: &T) -> std::io::Result<()>
+    {
+        self.sender.send(value)
+    }
+
+    /// See [`Receiver::recv`]
+    pub fn recv(&mut self) -> std::io::Result<Option<R>>
+    {
+        self.receiver.recv()
+    }
+
+    /// Sets the length of the longest accepted frame, [`DEFAULT_MAX_FRAME_LEN`] by default
+    pub fn set_max_frame_len(&mut self, max_frame_len: usize)
+    {
+        self.receiver.set_max_frame_len(max_frame_len);
+    }
+
+    /// Splits the channel so that sending and receiving can happen on different threads
+    pub fn into_split(self) -> (Sender<T, W>, Receiver<R, Rd>)
+    {
+        (self.sender, self.receiver)
+    }
+}
+
+/// The sending half of a [`Channel`].
+/// Every value is written with a single call, so senders sharing it behind a mutex never interleave frames.
+#[derive(Debug)]
+pub struct Sender<T, W>
+{
+    writer: W,
+    _marker: PhantomData<fn(&T)>,
+}
+
+impl<T: Serializable, W: Write> Sender<T, W>
+{
+    /// Returns a sender writing to `writer`
+    pub fn new(writer: W) -> Self
+    {
+        Self { writer, _marker: PhantomData }
+    }
+
+    /// Sends `value` as a single frame
+    pub fn send(&mut self, value: &T) -> std::io::Result<()>
+    {
+        framing::write_value(&mut self.writer, value)
+    }
+
+    /// Returns the underlying writer, dropping a pipe closes the channel for the other side
+    pub fn into_inner(self) -> W
+    {
+        self.writer
+    }
+}
+
+/// The receiving half of a [`Channel`]
+#[derive(Debug)]
+pub struct Receiver<R, Rd>
+{
+    reader: Rd,
+    max_frame_len: usize,
+    _marker: PhantomData<fn() -> R>,
+}
+
+impl<R: Serializable, Rd: Read> Receiver<R, Rd>
+{
+    /// Returns a receiver reading from `reader`
+    pub fn new(reader: Rd) -> Self
+    {
+        Self { reader, max_frame_len: DEFAULT_MAX_FRAME_LEN, _marker: PhantomData }
+    }
+
+    /// Sets the length of the longest accepted frame, [`DEFAULT_MAX_FRAME_LEN`] by default
+    pub fn set_max_frame_len(&mut self, max_frame_len: usize)
+    {
+        self.max_frame_len = max_frame_len;
+    }
+
+    /// Waits for the next value, returning `Ok(None)` once the other side has closed the channel.
+    /// A channel closed in the middle of a frame fails with [`std::io::ErrorKind::UnexpectedEof`]
+    /// and a frame that is not a valid `R` fails with [`std::io::ErrorKind::InvalidData`].
+    pub fn recv(&mut self) -> std::io::Result<Option<R>>
+    {
+        match framing::read_frame_or_eof(&mut self.reader, self.max_frame_len)?
+        {
+            Some(frame) => R::deserialize_exact(&frame).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the underlying reader
+    pub fn into_inner(self) -> Rd
+    {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::sync::{Arc, Mutex};
+
+    use super::{Channel, ChildChannel};
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, Clone, PartialEq)]
+    struct Job
+    {
+        worker: u8,
+        sequence: u32,
+        argument: String,
+    }
+
+    #[test]
+    fn exchange_over_pipes()
+    {
+        let (child_stdin, to_child) = std::io::pipe().unwrap();
+        let (from_child, child_stdout) = std::io::pipe().unwrap();
+        let child = std::thread::spawn(move || {
+            let mut channel = Channel::<u64, String, _, _>::new(child_stdout, child_stdin);
+            while let Some(line) = channel.recv().unwrap()
+            {
+                channel.send(&(line.len() as u64)).unwrap();
+            }
+        });
+        let mut channel = Channel::<String, u64, _, _>::new(to_child, from_child);
+        for word in ["a", "plugin", "message"]
+        {
+            channel.send(&word.to_string()).unwrap();
+            assert_eq!(channel.recv().unwrap(), Some(word.len() as u64));
+        }
+        let (sender, mut receiver) = channel.into_split();
+        drop(sender);
+        child.join().unwrap();
+        assert_eq!(receiver.recv().unwrap(), None);
+    }
+
+    #[test]
+    fn closed_inside_a_frame()
+    {
+        let mut frame = Vec::new();
+        crate::framing::write_value(&mut frame, &"truncated".to_string()).unwrap();
+        frame.truncate(frame.len() - 1);
+        let mut channel = Channel::<String, String, _, _>::new(Vec::new(), frame.as_slice());
+        assert_eq!(channel.recv().unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let mut frame = Vec::new();
+        crate::framing::write_value(&mut frame, &1u8).unwrap();
+        let mut channel = Channel::<String, String, _, _>::new(Vec::new(), frame.as_slice());
+        assert_eq!(channel.recv().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn concurrent_senders_to_child_process()
+    {
+        // `cat` echoes every frame back unchanged
+        let mut child = std::process::Command::new("cat")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let (sender, mut receiver) = ChildChannel::<Job, Job>::from_child(&mut child).unwrap().into_split();
+        let sender = Arc::new(Mutex::new(sender));
+        let workers: Vec<_> = (0..4).map(|worker| {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                for sequence in 0..50
+                {
+                    let job = Job { worker, sequence, argument: "x".repeat(sequence as usize * 100) };
+                    sender.lock().unwrap().send(&job).unwrap();
+                }
+            })
+        }).collect();
+        let mut next_sequence = [0; 4];
+        for _ in 0..200
+        {
+            let job = receiver.recv().unwrap().unwrap();
+            assert_eq!(job.sequence, next_sequence[job.worker as usize]);
+            assert_eq!(job.argument.len(), job.sequence as usize * 100);
+            next_sequence[job.worker as usize] += 1;
+        }
+        for worker in workers
+        {
+            worker.join().unwrap();
+        }
+        drop(sender);
+        assert_eq!(receiver.recv().unwrap(), None);
+        child.wait().unwrap();
+    }
+}
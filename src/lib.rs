@@ -1,14 +1,95 @@
 pub mod serializable;
+pub mod borrow;
+pub mod schema;
+pub mod debug;
+pub mod trace;
+pub mod testing;
+pub mod message_queue;
+pub mod log;
+pub mod framing;
+pub mod cobs;
+pub mod envelope;
+pub mod collections;
+pub mod fixed_point;
+pub mod instant;
+pub mod net;
+pub mod ipc;
+pub mod persistent;
+pub mod store;
+pub mod patch;
+#[cfg(feature = "bump")]
+pub mod arena;
+#[cfg(feature = "miniz")]
+pub mod compress;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "axum")]
+pub mod axum;
+#[cfg(feature = "embedded-io")]
+pub mod embedded;
+#[cfg(feature = "archive")]
+pub mod archive;
+#[cfg(feature = "arbitrary")]
+pub mod fuzz;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "pyo3")]
+pub mod python;
+#[cfg(feature = "sqlx")]
+pub mod sqlx;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+#[cfg(feature = "bincode-compat")]
+pub mod bincode_compat;
+#[cfg(feature = "generic-array")]
+pub mod generic_array;
 
-pub use crate::serializable::Serializable;
+pub use crate::serializable::{Serializable, FixedSize, ExternallyTagged, DeserializeError, Error};
+pub use crate::borrow::SerializableRef;
 pub use serializable_derive::Serializable;
 
 #[cfg(test)]
 mod tests
 {
-    use super::Serializable;
+    use crate as serializable;
+    use super::{Serializable, FixedSize, ExternallyTagged, DeserializeError, Error};
+
+    /// Global allocator that counts the allocations made by each thread,
+    /// so that tests running in parallel don't interfere with each other
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator
+    {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    /// Returns the result of `f` and the number of allocations it made
+    pub(crate) fn count_allocations<R>(f: impl FnOnce() -> R) -> (R, usize)
+    {
+        let before = ALLOCATIONS.with(|count| count.get());
+        let ret = f();
+        let after = ALLOCATIONS.with(|count| count.get());
+        (ret, after - before)
+    }
     
     #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(describe)]
     pub struct NamedTestStruct
     {
         a: u32,
@@ -85,4 +166,2274 @@ mod tests
         assert_eq!(test_struct, deserialized);
         assert_eq!(serialized.len(), bytes_read);
     }
-}
\ No newline at end of file
+
+    #[derive(Serializable, Debug, PartialEq, Clone, Copy)]
+    #[serializable(encode_as = "String")]
+    pub enum Color
+    {
+        Red,
+        Green,
+        Blue,
+    }
+    impl std::fmt::Display for Color
+    {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Color::Red => write!(f, "Red"),
+                Color::Green => write!(f, "Green"),
+                Color::Blue => write!(f, "Blue"),
+            }
+        }
+    }
+    impl std::str::FromStr for Color
+    {
+        type Err = ();
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "Red" => Ok(Color::Red),
+                "Green" => Ok(Color::Green),
+                "Blue" => Ok(Color::Blue),
+                _ => Err(()),
+            }
+        }
+    }
+    #[test]
+    fn serialize_and_deserialize_string_encoded_enum()
+    {
+        for color in [Color::Red, Color::Green, Color::Blue]
+        {
+            let serialized = color.serialize();
+            assert_eq!(serialized, color.to_string().serialize());
+            let (deserialized, bytes_read) = Color::deserialize(&serialized).unwrap();
+            assert_eq!(color, deserialized);
+            assert_eq!(serialized.len(), bytes_read);
+        }
+        let invalid = "Purple".to_string().serialize();
+        let error = Color::deserialize(&invalid).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(Error::find(&error), Some(&Error::InvalidValue { type_name: "Color", reason: "\"Purple\" is not a valid representation".to_string() }));
+        assert!(error.to_string().contains("while deserializing Color"), "{error}");
+    }
+
+    #[test]
+    fn deserialize_large_vec_with_single_allocation()
+    {
+        let test_vec: Vec<u32> = (0..1_000_000).collect();
+        let serialized = test_vec.serialize();
+        let (deserialized, bytes_read) = Vec::<u32>::deserialize(&serialized).unwrap();
+        assert_eq!(test_vec, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert_eq!(deserialized.capacity(), test_vec.len());
+    }
+
+    #[test]
+    fn deserialize_vec_with_hostile_length()
+    {
+        let mut serialized = u32::MAX.serialize();
+        serialized.extend_from_slice(&[1, 2, 3]);
+        assert!(Vec::<u64>::deserialize(&serialized).is_err());
+        assert!(Vec::<String>::deserialize(&serialized).is_err());
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Marker;
+
+    #[test]
+    fn forged_item_count_fails_before_reading_items()
+    {
+        let forged = [0xFF, 0xFF, 0xFF, 0xFF];
+        // Only the error is allocated, nothing for the items
+        let (result, allocations) = count_allocations(|| Vec::<u8>::deserialize(&forged));
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert!(allocations < 8);
+        let (result, allocations) = count_allocations(|| Vec::<String>::check_bytes(&forged));
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert!(allocations < 8);
+        // Items serialized in zero bytes are bounded by the configurable limit instead of the data
+        let (result, allocations) = count_allocations(|| Vec::<()>::deserialize(&forged));
+        let error = result.unwrap_err();
+        assert!(matches!(Error::find(&error), Some(Error::LimitExceeded { what: "zero sized items", .. })), "{error}");
+        assert_eq!(DeserializeError::offset_of(&error), 4);
+        assert!(allocations < 8);
+        assert!(Vec::<Marker>::check_bytes(&forged).is_err());
+        // SAFETY: a forged count is still checked against the limit
+        assert!(unsafe { Vec::<Marker>::deserialize_trusted(&forged) }.is_err());
+        assert!(Vec::<Marker>::deserialize_from(&mut &forged[..]).is_err());
+    }
+
+    #[test]
+    fn zero_sized_item_limit()
+    {
+        let items = vec![Marker, Marker, Marker, Marker, Marker];
+        let serialized = items.serialize();
+        assert_eq!(serialized, [0, 0, 0, 5]);
+        crate::serializable::set_max_zero_sized_items(4);
+        assert!(Vec::<Marker>::deserialize(&serialized).is_err());
+        // The length of an array is part of its type, so it is not limited
+        assert_eq!(<[Marker; 5]>::deserialize_exact(&[]).unwrap(), [Marker, Marker, Marker, Marker, Marker]);
+        assert_eq!(Vec::<Marker>::deserialize(&serialized[..3].iter().chain(&[4]).copied().collect::<Vec<_>>()).unwrap().0.len(), 4);
+        crate::serializable::set_max_zero_sized_items(crate::serializable::DEFAULT_MAX_ZERO_SIZED_ITEMS);
+        assert_eq!(Vec::<Marker>::deserialize_exact(&serialized).unwrap(), items);
+        assert_eq!(<[(); 70_000]>::deserialize(&[]).unwrap(), ([(); 70_000], 0));
+        assert_eq!(<[(); 70_000]>::check_bytes(&[]).unwrap(), 0);
+        assert_eq!(<[(); 70_000]>::deserialize_from(&mut &[][..]).unwrap(), ([(); 70_000], 0));
+        // SAFETY: the items take no bytes, so any data is valid
+        assert_eq!(unsafe { <[(); 70_000]>::deserialize_trusted(&[]) }.unwrap(), ([(); 70_000], 0));
+    }
+
+    fn serialize_each<T: Serializable>(items: &[T]) -> Vec<u8>
+    {
+        let mut ret = (items.len() as u32).serialize();
+        for item in items
+        {
+            ret.extend(item.serialize());
+        }
+        ret
+    }
+
+    fn assert_bulk_matches_generic<T: Serializable + std::fmt::Debug + PartialEq>(items: Vec<T>)
+    {
+        let serialized = items.serialize();
+        assert_eq!(serialized, serialize_each(&items));
+        let (deserialized, bytes_read) = Vec::<T>::deserialize(&serialized).unwrap();
+        assert_eq!(items, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert!(Vec::<T>::deserialize(&serialized[..serialized.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn serialize_and_deserialize_primitive_vec_in_bulk()
+    {
+        assert_bulk_matches_generic::<u16>(vec![0, 1, 0x1234, u16::MAX]);
+        assert_bulk_matches_generic::<u32>((0..1000).map(|i| i * 4_000_003).collect());
+        assert_bulk_matches_generic::<u64>(vec![0, 1, 0x123456789ABCDEF0, u64::MAX]);
+        assert_bulk_matches_generic::<u128>(vec![0, 1, u128::MAX / 3, u128::MAX]);
+        assert_bulk_matches_generic::<i16>(vec![i16::MIN, -1, 0, i16::MAX]);
+        assert_bulk_matches_generic::<i32>(vec![i32::MIN, -1, 0, i32::MAX]);
+        assert_bulk_matches_generic::<i64>(vec![i64::MIN, -1, 0, i64::MAX]);
+        assert_bulk_matches_generic::<i128>(vec![i128::MIN, -1, 0, i128::MAX]);
+        assert_bulk_matches_generic::<f32>(vec![f32::MIN, -1.5, 0.0, f32::INFINITY]);
+        assert_bulk_matches_generic::<f64>(vec![f64::MIN, -1.5, 0.0, f64::INFINITY]);
+        let test_array: [u32; 4] = [1, 2, 3, 4];
+        assert_eq!(test_array.serialize(), serialize_each(&test_array)[4..]);
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub struct FixedTestStruct
+    {
+        a: u32,
+        b: i16,
+        c: f64,
+    }
+    #[derive(Serializable, Debug, PartialEq)]
+    pub struct FixedUnnamedTestStruct(u8, FixedTestStruct);
+    #[test]
+    fn serialize_fixed_size()
+    {
+        assert_eq!(FixedTestStruct::SIZE, 14);
+        assert_eq!(FixedUnnamedTestStruct::SIZE, 15);
+        assert_eq!(UnitTestStruct::SIZE, 0);
+        let test_struct = FixedUnnamedTestStruct(7, FixedTestStruct { a: 0x12345678, b: -2, c: 1.5 });
+        let (fixed, allocations) = count_allocations(|| test_struct.serialize_fixed::<15>().unwrap());
+        assert_eq!(allocations, 0);
+        assert_eq!(fixed.to_vec(), test_struct.serialize());
+        assert_eq!(0x9ABCu16.serialize_fixed::<2>().unwrap(), [0x9A, 0xBC]);
+        assert_eq!(test_struct.serialize_fixed::<16>().unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        let mut buffer = [0u8; 20];
+        test_struct.serialize_to_slice(&mut buffer[5..]);
+        assert_eq!(buffer[5..], fixed);
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub struct FixedFlags
+    {
+        enabled: bool,
+        mode: char,
+        levels: [[u16; 2]; 3],
+        nothing: (),
+    }
+    #[test]
+    fn fixed_size_bool_char_unit_and_arrays()
+    {
+        assert_eq!(bool::SIZE, 1);
+        assert_eq!(char::SIZE, 4);
+        assert_eq!(<()>::SIZE, 0);
+        assert_eq!(<[u32; 5]>::SIZE, 20);
+        assert_eq!(<[(); 5]>::SIZE, 0);
+        assert_eq!(FixedFlags::SIZE, 1 + 4 + 12);
+        let flags = FixedFlags { enabled: true, mode: '\u{1F980}', levels: [[1, 2], [3, 4], [5, 0xFFFF]], nothing: () };
+        let fixed = flags.serialize_fixed::<17>().unwrap();
+        assert_eq!(fixed.to_vec(), flags.serialize());
+        assert_eq!(fixed[..5], [1, 0, 1, 0xF9, 0x80]);
+        assert_eq!(FixedFlags::deserialize_exact(&fixed).unwrap(), flags);
+        assert_eq!(false.serialize_fixed::<1>().unwrap(), [0]);
+    }
+
+    #[derive(Serializable, Debug)]
+    pub struct Metrics
+    {
+        timestamp: std::time::SystemTime,
+        values: std::collections::HashMap<String, f64>,
+    }
+    /// Ten metrics, three of them not finite
+    fn metrics() -> Metrics
+    {
+        let mut values: std::collections::HashMap<_, _> = (0..7).map(|i| (format!("metric_{i}"), i as f64 * 0.25)).collect();
+        values.insert("nan".to_string(), f64::NAN);
+        values.insert("infinity".to_string(), f64::INFINITY);
+        values.insert("negative_infinity".to_string(), f64::NEG_INFINITY);
+        Metrics {
+            timestamp: std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000),
+            values,
+        }
+    }
+
+    #[test]
+    fn metrics_keep_float_bit_patterns()
+    {
+        // f64 is written as its IEEE 754 bits, so NaN payloads, infinities and the sign of zero are kept as they are
+        let mut metrics = metrics();
+        metrics.values.insert("nan_payload".to_string(), f64::from_bits(0x7FF8_0000_0000_1234));
+        metrics.values.insert("negative_zero".to_string(), -0.0);
+        let deserialized = Metrics::deserialize_exact(&metrics.serialize()).unwrap();
+        assert_eq!(deserialized.timestamp, metrics.timestamp);
+        assert_eq!(deserialized.values.len(), 12);
+        for (key, value) in &metrics.values
+        {
+            assert_eq!(value.to_bits(), deserialized.values[key].to_bits(), "{key}");
+        }
+    }
+
+    #[test]
+    fn deserialize_map_with_duplicate_keys()
+    {
+        let mut serialized = 2u32.serialize();
+        for _ in 0..2
+        {
+            serialized.extend("key".to_string().serialize());
+            serialized.extend(1.0f64.serialize());
+        }
+        assert_eq!(std::collections::HashMap::<String, f64>::deserialize(&serialized).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn deserialize_string_edge_cases()
+    {
+        let serialized = "Hello world".to_string().serialize();
+        let (deserialized, bytes_read) = String::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized, "Hello world");
+        assert_eq!(deserialized.capacity(), deserialized.len());
+        assert_eq!(bytes_read, serialized.len());
+        let (empty, bytes_read) = String::deserialize(&[0, 0, 0, 0, 0xFF]).unwrap();
+        assert_eq!((empty.as_str(), bytes_read), ("", 4));
+        let invalid_utf8 = [0, 0, 0, 2, 0xC3, 0x28];
+        assert_eq!(String::deserialize(&invalid_utf8).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(String::deserialize(&serialized[..serialized.len() - 1]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(String::deserialize(&serialized[..3]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub struct ConfigTree<T>
+    {
+        sections: std::collections::BTreeMap<String, std::collections::BTreeMap<String, T>>,
+    }
+    #[derive(Serializable, Debug, PartialEq)]
+    pub enum GenericTestEnum<T, U>
+    {
+        First(T),
+        Second { value: U },
+    }
+    #[test]
+    fn serialize_and_deserialize_nested_config_tree()
+    {
+        let mut sections = std::collections::BTreeMap::new();
+        for section in ["network", "storage", "logging"]
+        {
+            let mut keys = std::collections::BTreeMap::new();
+            for i in 0..5
+            {
+                let value: Vec<u8> = (0..(i * 25) as u8).collect();
+                keys.insert(format!("{section}.key{i}"), value);
+            }
+            sections.insert(section.to_string(), keys);
+        }
+        let config = ConfigTree { sections };
+        let serialized = config.serialize();
+        let (deserialized, bytes_read) = ConfigTree::<Vec<u8>>::deserialize(&serialized).unwrap();
+        assert_eq!(config, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert_eq!(deserialized.sections["storage"]["storage.key4"].len(), 100);
+
+        let test_enum = GenericTestEnum::<u8, ConfigTree<String>>::Second { value: ConfigTree { sections: Default::default() } };
+        let serialized = test_enum.serialize();
+        let (deserialized, bytes_read) = GenericTestEnum::<u8, ConfigTree<String>>::deserialize(&serialized).unwrap();
+        assert_eq!(test_enum, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+    }
+
+    #[test]
+    fn deserialize_fixed_size_vec_boundary()
+    {
+        assert_eq!(FixedUnnamedTestStruct::FIXED_SIZE, Some(FixedUnnamedTestStruct::SIZE));
+        assert_eq!(<[FixedTestStruct; 3]>::FIXED_SIZE, Some(42));
+        assert_eq!(NamedTestStruct::FIXED_SIZE, None);
+        let test_vec: Vec<FixedUnnamedTestStruct> = (0..100).map(|i| FixedUnnamedTestStruct(i, FixedTestStruct { a: i as u32, b: -(i as i16), c: i as f64 })).collect();
+        let serialized = test_vec.serialize();
+        let (deserialized, bytes_read) = Vec::<FixedUnnamedTestStruct>::deserialize(&serialized).unwrap();
+        assert_eq!(test_vec, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert_eq!(Vec::<FixedUnnamedTestStruct>::deserialize(&serialized[..serialized.len() - 1]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        let arrays = vec![[1u8, 2, 3], [4, 5, 6]];
+        let serialized = arrays.serialize();
+        assert_eq!(Vec::<[u8; 3]>::deserialize(&serialized).unwrap(), (arrays, 10));
+        assert!(Vec::<[u8; 3]>::deserialize(&serialized[..9]).is_err());
+    }
+
+    #[test]
+    fn serialize_and_deserialize_empty_arrays()
+    {
+        let unit_array: [(); 0] = [];
+        assert!(unit_array.serialize().is_empty());
+        assert_eq!(<[(); 0]>::deserialize(&[]).unwrap(), ([], 0));
+        let byte_array: [u8; 0] = [];
+        assert!(byte_array.serialize().is_empty());
+        assert_eq!(<[u8; 0]>::deserialize(&[]).unwrap(), ([], 0));
+        let string_array: [String; 0] = [];
+        assert!(string_array.serialize().is_empty());
+        assert_eq!(<[String; 0]>::deserialize(&[1, 2, 3]).unwrap(), ([], 0));
+        assert_eq!(<[(); 3]>::deserialize(&[]).unwrap(), ([(), (), ()], 0));
+    }
+
+    #[test]
+    fn serialize_and_deserialize_priority_queue()
+    {
+        let mut queue = std::collections::VecDeque::new();
+        for (priority, name) in [(1, "backup"), (3, "index"), (5, "compact"), (8, "replicate")]
+        {
+            queue.push_back((priority, name.to_string()));
+        }
+        queue.push_front((0u32, "health check".to_string()));
+        let serialized = queue.serialize();
+        let (deserialized, bytes_read) = std::collections::VecDeque::<(u32, String)>::deserialize(&serialized).unwrap();
+        assert_eq!(queue, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert_eq!(deserialized.front(), Some(&(0, "health check".to_string())));
+        assert!(deserialized.iter().zip(deserialized.iter().skip(1)).all(|(a, b)| a.0 <= b.0));
+        assert_eq!(serialized, queue.iter().cloned().collect::<Vec<_>>().serialize());
+    }
+
+    #[test]
+    fn deserialize_exact_rejects_trailing_bytes()
+    {
+        let mut serialized = NamedTestStruct { a: 1, b: 2, c: "three".to_string() }.serialize();
+        assert!(NamedTestStruct::deserialize_exact(&serialized).is_ok());
+        serialized.push(0);
+        assert_eq!(NamedTestStruct::deserialize_exact(&serialized).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    fn nullable_map() -> std::collections::HashMap<String, Option<String>>
+    {
+        std::collections::HashMap::from([
+            ("host".to_string(), Some("example.com".to_string())),
+            ("proxy".to_string(), None),
+            ("user".to_string(), Some("admin".to_string())),
+            ("password".to_string(), None),
+        ])
+    }
+
+    #[test]
+    fn nullable_map_keeps_null_entries()
+    {
+        let deserialized = std::collections::HashMap::<String, Option<String>>::deserialize_exact(&nullable_map().serialize()).unwrap();
+        assert_eq!(deserialized.len(), 4);
+        assert_eq!(deserialized.get("proxy"), Some(&None));
+        assert_eq!(deserialized.get("host"), Some(&Some("example.com".to_string())));
+        // A null value is a single 0 tag, a tag other than 0 and 1 is not a value
+        let mut serialized = std::collections::HashMap::from([("k".to_string(), None::<String>)]).serialize();
+        assert_eq!(serialized, [0, 0, 0, 1, 0, 0, 0, 1, b'k', 0]);
+        serialized[9] = 2;
+        let error = std::collections::HashMap::<String, Option<String>>::deserialize(&serialized).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(DeserializeError::offset_of(&error), 9);
+    }
+
+    #[test]
+    fn serialize_and_deserialize_socket_addr_v4()
+    {
+        let addr = std::net::SocketAddrV4::new(std::net::Ipv4Addr::new(192, 168, 1, 10), 8080);
+        let serialized = addr.serialize();
+        assert_eq!(serialized.len(), 6);
+        assert_eq!(serialized, [192, 168, 1, 10, 0x1f, 0x90]);
+        assert_eq!(addr.serialize_fixed::<6>().unwrap().as_slice(), serialized.as_slice());
+        let (deserialized, bytes_read) = std::net::SocketAddrV4::deserialize(&serialized).unwrap();
+        assert_eq!(addr, deserialized);
+        assert_eq!(bytes_read, 6);
+        assert!(std::net::SocketAddrV4::deserialize(&serialized[..5]).is_err());
+        let addrs = vec![addr; 3];
+        assert_eq!(addrs.serialize().len(), 4 + 3 * 6);
+        assert_eq!(Vec::<std::net::SocketAddrV4>::deserialize(&addrs.serialize()).unwrap().0, addrs);
+    }
+
+    /// Claims to have read the length stored in its first byte, as a faulty or hostile impl could
+    #[derive(Debug)]
+    struct LyingLength;
+
+    impl Serializable for LyingLength
+    {
+        fn serialize(&self) -> Vec<u8> {
+            vec![0]
+        }
+
+        fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+            match data.first()
+            {
+                Some(0) => Ok((LyingLength, 1)),
+                Some(1) => Ok((LyingLength, usize::MAX)),
+                Some(_) => Ok((LyingLength, usize::MAX / 2)),
+                None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length")),
+            }
+        }
+    }
+
+    #[test]
+    fn deserialize_vec_with_huge_item_lengths()
+    {
+        assert_eq!(Vec::<LyingLength>::deserialize(&[0, 0, 0, 2, 0, 0]).unwrap().1, 6);
+        // The running total overflows
+        let err = Vec::<LyingLength>::deserialize(&[0, 0, 0, 2, 0, 1]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        // The next item would start past the end of the data
+        let err = Vec::<LyingLength>::deserialize(&[0, 0, 0, 2, 2, 0]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        // The item length fits but adding the length prefix overflows
+        let err = Vec::<LyingLength>::deserialize(&[0, 0, 0, 1, 1]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[derive(Serializable, Debug, Clone, Copy, PartialEq)]
+    #[serializable(assert_no_padding)]
+    #[repr(C)]
+    struct PackedHeader
+    {
+        magic: u32,
+        version: u16,
+        flags: u8,
+        kind: u8,
+        length: u64,
+    }
+
+    #[test]
+    fn serialize_struct_without_padding()
+    {
+        let header = PackedHeader { magic: 0xCAFEBABE, version: 3, flags: 1, kind: 2, length: 1024 };
+        assert_eq!(std::mem::size_of::<PackedHeader>(), PackedHeader::SIZE);
+        let serialized = header.serialize();
+        assert_eq!(serialized.len(), 16);
+        assert_eq!(PackedHeader::deserialize(&serialized).unwrap(), (header, 16));
+    }
+
+    #[derive(Serializable, Debug, Clone, Copy, PartialEq)]
+    #[serializable(assert_no_padding)]
+    #[repr(C, packed)]
+    struct UnalignedHeader
+    {
+        kind: u8,
+        length: u64,
+        version: u16,
+        checksum: u32,
+    }
+
+    #[derive(Serializable, Debug, Clone, Copy, PartialEq)]
+    #[repr(C, packed(2))]
+    struct UnalignedPair(u8, u32);
+
+    #[test]
+    fn serialize_packed_structs()
+    {
+        let header = UnalignedHeader { kind: 7, length: 0x0102030405060708, version: 2, checksum: 0xDEADBEEF };
+        let serialized = header.serialize();
+        assert_eq!(serialized, [7, 1, 2, 3, 4, 5, 6, 7, 8, 0, 2, 0xDE, 0xAD, 0xBE, 0xEF]);
+        assert_eq!(serialized, header.serialize_deterministic());
+        assert_eq!(UnalignedHeader::deserialize(&serialized).unwrap(), (header, 15));
+        let mut out = [0; UnalignedHeader::SIZE];
+        header.serialize_to_slice(&mut out);
+        assert_eq!(out[..], serialized[..]);
+        let headers = vec![header; 3];
+        assert_eq!(Vec::<UnalignedHeader>::deserialize_exact(&headers.serialize()).unwrap(), headers);
+        let pair = UnalignedPair(1, 0x02030405);
+        assert_eq!(pair.serialize(), [1, 2, 3, 4, 5]);
+        assert_eq!(UnalignedPair::deserialize(&pair.serialize()).unwrap(), (pair, 5));
+    }
+
+    #[test]
+    fn deserialize_option_discriminants()
+    {
+        let results: Vec<_> = (0..=u8::MAX).map(|discriminant| Option::<u8>::deserialize(&[discriminant, 42])).collect();
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 2);
+        assert_eq!(results[0].as_ref().unwrap(), &(None, 1));
+        assert_eq!(results[1].as_ref().unwrap(), &(Some(42), 2));
+        for result in &results[2..]
+        {
+            assert_eq!(result.as_ref().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn deserialize_truncated_option()
+    {
+        assert_eq!(Option::<u32>::deserialize(&[]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(Option::<u32>::deserialize(&[1]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(Option::<u32>::deserialize(&[1, 0, 0, 0]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(Option::<String>::deserialize(&[1]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn serialize_and_deserialize_ipv4_addr()
+    {
+        let addr = std::net::Ipv4Addr::new(127, 0, 0, 1);
+        assert_eq!(addr.serialize(), [127, 0, 0, 1]);
+        assert_eq!(addr.serialize(), addr.octets());
+        assert_eq!(addr.serialize(), u32::from(addr).serialize());
+        assert_eq!(std::net::Ipv4Addr::deserialize(&[192, 168, 0, 1, 99]).unwrap(), (std::net::Ipv4Addr::new(192, 168, 0, 1), 4));
+        assert!(std::net::Ipv4Addr::deserialize(&[10, 0, 0]).is_err());
+        let socket_addr = std::net::SocketAddrV4::new(addr, 80);
+        assert_eq!(&socket_addr.serialize()[..4], addr.serialize().as_slice());
+    }
+
+    #[test]
+    fn serialize_and_deserialize_byte_vec_in_bulk()
+    {
+        let bytes: Vec<u8> = (0..10_000).map(|i| (i % 251) as u8).collect();
+        let (serialized, allocations) = count_allocations(|| bytes.serialize());
+        assert_eq!(allocations, 1);
+        assert_eq!(&serialized[..4], &10_000u32.to_be_bytes());
+        assert_eq!(&serialized[4..], bytes.as_slice());
+        let ((deserialized, bytes_read), allocations) = count_allocations(|| Vec::<u8>::deserialize(&serialized).unwrap());
+        assert_eq!(allocations, 1);
+        assert_eq!(deserialized, bytes);
+        assert_eq!(bytes_read, serialized.len());
+        assert!(Vec::<u8>::deserialize(&serialized[..serialized.len() - 1]).is_err());
+    }
+
+    /// Last sequence number received on every connection of a reliable UDP protocol
+    #[derive(Serializable, Debug, PartialEq)]
+    struct SeqTable
+    {
+        sequences: std::collections::HashMap<u32, u64>,
+        last_seen: std::time::SystemTime,
+    }
+
+    fn seq_table(connections: u32) -> SeqTable
+    {
+        SeqTable {
+            sequences: (0..connections).map(|connection| (connection * 7919, connection as u64 * 1_000_003)).collect(),
+            last_seen: std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000),
+        }
+    }
+
+    #[test]
+    fn seq_table_layout()
+    {
+        let table = seq_table(1000);
+        let serialized = table.serialize();
+        // The entry count, the fixed size entries, then the seconds of last_seen
+        assert_eq!(serialized.len(), 4 + 1000 * (4 + 8) + 8);
+        assert_eq!(serialized[..4], 1000u32.to_be_bytes());
+        assert_eq!(serialized[serialized.len() - 8..], 1_700_000_000u64.to_be_bytes());
+        let deserialized = SeqTable::deserialize_exact(&serialized).unwrap();
+        assert_eq!(deserialized.sequences[&(999 * 7919)], 999 * 1_000_003);
+        assert_eq!(deserialized.last_seen, table.last_seen);
+    }
+
+    /// A user record nested two levels deep, used to check where failures are reported
+    #[derive(Serializable, Debug, PartialEq)]
+    struct UserRecord
+    {
+        id: u32,
+        name: String,
+        email: Option<String>,
+    }
+
+    #[test]
+    fn report_offset_of_nested_failure()
+    {
+        let users = vec![
+            UserRecord { id: 1, name: "alice".to_string(), email: None },
+            UserRecord { id: 2, name: "bob".to_string(), email: Some("bob@example.com".to_string()) },
+        ];
+        let mut serialized = users.serialize();
+        // vec length, first record, id and name of the second record, option tag, string length
+        let email_offset = 4 + (4 + 4 + 5 + 1) + (4 + 4 + 3) + 1 + 4;
+        assert_eq!(&serialized[email_offset..email_offset + 3], b"bob");
+        serialized[email_offset + 1] = 0xff;
+        let error = Vec::<UserRecord>::deserialize(&serialized).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        let deserialize_error = DeserializeError::find(&error).unwrap();
+        assert_eq!(deserialize_error.offset(), email_offset + 1);
+        assert_eq!(DeserializeError::offset_of(&error), email_offset + 1);
+        assert_eq!(error.to_string(), format!("while deserializing [1].email at offset {}: Invalid utf8 string format", email_offset + 1));
+    }
+
+    #[test]
+    fn report_offset_of_truncated_input()
+    {
+        let serialized = vec![(1u16, vec![2u32]), (3, vec![4])].serialize();
+        let error = Vec::<(u16, Vec<u32>)>::deserialize(&serialized[..serialized.len() - 1]).unwrap_err();
+        // vec length, first tuple, the u16 and the length of the inner vec of the second one
+        assert_eq!(DeserializeError::offset_of(&error), 4 + 10 + 2 + 4);
+        let error = u32::deserialize(&[0, 0]).unwrap_err();
+        assert!(DeserializeError::find(&error).is_none());
+        assert_eq!(DeserializeError::offset_of(&error), 0);
+    }
+
+    #[test]
+    fn report_type_and_field_of_failure()
+    {
+        let serialized = NamedTestStruct { a: 0x12345678, b: 0x9abc, c: "Hello World".to_string() }.serialize();
+        let error = NamedTestStruct::deserialize(&serialized[..16]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "while deserializing NamedTestStruct.c at offset 6: Unexpected end of data, 15 bytes needed but 10 left");
+        let deserialize_error = DeserializeError::find(&error).unwrap();
+        assert_eq!(deserialize_error.type_name(), Some("NamedTestStruct"));
+        assert_eq!(deserialize_error.path(), "c");
+        assert_eq!(Error::find(&error), Some(&Error::UnexpectedEof { needed: 15, available: 10 }));
+
+        // The outermost derived type names the path through the nested ones
+        let error = Frame::deserialize(&[2, 0, 0, 0, 1, 0, 0, 0, 1, 0xff, 0, 0, 0]).unwrap_err();
+        assert_eq!(error.to_string(), "while deserializing Frame.0[0].sensor at offset 9: Invalid utf8 string format");
+        assert_eq!(Error::find(&error), Some(&Error::InvalidUtf8));
+        let error = Frame::deserialize(&[7]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 7, type_name: "Frame" }));
+
+        let error = u8::deserialize_exact(&[1, 2]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::TrailingBytes));
+        assert_eq!(DeserializeError::offset_of(&error), 1);
+        assert_eq!(error.to_string(), "Trailing bytes after the serialized object at offset 1");
+    }
+
+    #[test]
+    fn report_typed_errors_of_std_types()
+    {
+        let error = bool::deserialize(&[2]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 2, type_name: "bool" }));
+        let error = bool::deserialize(&[]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::UnexpectedEof { needed: 1, available: 0 }));
+        let error = Option::<u8>::deserialize(&[3, 0]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 3, type_name: "Option" }));
+        let error = Option::<u8>::check_bytes(&[3, 0]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 3, type_name: "Option" }));
+        let error = Option::<u8>::deserialize_from(&mut &[3u8, 0][..]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 3, type_name: "Option" }));
+        // The item of a vector keeps the reason behind its offset and path
+        let error = Vec::<Option<u8>>::deserialize(&[0, 0, 0, 2, 0, 4]).unwrap_err();
+        assert_eq!(DeserializeError::offset_of(&error), 5);
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 4, type_name: "Option" }));
+        let error = std::net::IpAddr::deserialize(&[2, 127, 0, 0, 1]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 2, type_name: "IpAddr" }));
+        let error = std::net::IpAddr::deserialize(&[1, 0, 0, 0, 0]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::UnexpectedEof { needed: 17, available: 5 }));
+        let error = std::net::SocketAddr::deserialize(&[9]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 9, type_name: "SocketAddr" }));
+        let error = Result::<u8, u8>::deserialize(&[2, 0]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 2, type_name: "Result" }));
+        let error = Vec::<u32>::deserialize(&[0, 0, 0, 2, 0, 0, 0, 1]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::UnexpectedEof { needed: 8, available: 4 }));
+        let error = std::collections::BTreeSet::<u8>::deserialize(&[0, 0, 0, 2, 1, 1]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::DuplicateItem));
+        assert_eq!(DeserializeError::offset_of(&error), 5);
+        let error = std::time::SystemTime::deserialize(&u64::MAX.serialize()).unwrap_err();
+        assert!(matches!(Error::find(&error), Some(Error::InvalidValue { type_name: "SystemTime", .. })), "{error}");
+    }
+
+    #[test]
+    fn explain_named_struct()
+    {
+        use crate::schema::Describe;
+
+        let test_struct = NamedTestStruct { a: 0x12345678, b: 0x9ABC, c: "Hello world".to_string() };
+        let serialized = test_struct.serialize();
+        let expected = "\
+No failure, the data is valid
+0000..0004  12 34 56 78                 a = 305419896
+0004..0006  9a bc                       b = 39612
+0006..0015  00 00 00 0b 48 65 6c 6c ..  c = \"Hello world\"
+";
+        assert_eq!(NamedTestStruct::explain_failure(&serialized), expected);
+
+        let expected = "\
+Deserialization failed: while deserializing NamedTestStruct.c at offset 6: Unexpected end of data, 15 bytes needed but 10 left
+0000..0004  12 34 56 78                 a = 305419896
+0004..0006  9a bc                       b = 39612
+0006  !! parsing stopped: Unexpected end of data, 15 bytes needed but 10 left
+0006..000e  00 00 00 0b 48 65 6c 6c     ?? = unparsed
+000e..0010  6f 20                       ?? = unparsed
+";
+        assert_eq!(NamedTestStruct::explain_failure(&serialized[..16]), expected);
+    }
+
+    /// Membership of a cluster, the addresses of every node by node name
+    #[derive(Serializable, Debug, PartialEq)]
+    struct ClusterMap
+    {
+        cluster_id: u64,
+        version: u32,
+        nodes: std::collections::BTreeMap<String, Vec<std::net::SocketAddr>>,
+    }
+
+    /// Five nodes with an IPv4 address and one or two IPv6 addresses each
+    fn cluster_map() -> ClusterMap
+    {
+        let nodes = (0..5u8).map(|node| {
+            let addresses = (0..2 + node % 2).map(|i| {
+                match i
+                {
+                    0 => std::net::SocketAddr::from(([10, 0, 0, node], 7000)),
+                    _ => std::net::SocketAddr::from((std::net::Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, node as u16, i as u16), 7000 + i as u16)),
+                }
+            }).collect();
+            (format!("node-{node}"), addresses)
+        }).collect();
+        ClusterMap { cluster_id: 0xC1C1_0001, version: 42, nodes }
+    }
+
+    #[test]
+    fn cluster_map_checks_address_families()
+    {
+        let deserialized = ClusterMap::deserialize_exact(&cluster_map().serialize()).unwrap();
+        assert_eq!(deserialized.nodes["node-3"].len(), 3);
+        assert!(deserialized.nodes["node-3"][0].is_ipv4() && deserialized.nodes["node-3"][2].is_ipv6());
+        let single = ClusterMap {
+            cluster_id: 1,
+            version: 1,
+            nodes: std::collections::BTreeMap::from([("a".to_string(), vec![std::net::SocketAddr::from(([10, 0, 0, 1], 7000))])]),
+        };
+        let mut serialized = single.serialize();
+        // The id, the version, the node count, the node name and the address count come before the family
+        let family = 8 + 4 + 4 + 5 + 4;
+        assert_eq!(serialized[family], 0);
+        // An IPv6 address doesn't fit in the bytes of an IPv4 one
+        serialized[family] = 1;
+        assert_eq!(DeserializeError::offset_of(&ClusterMap::deserialize(&serialized).unwrap_err()), family);
+        serialized[family] = 2;
+        assert_eq!(DeserializeError::offset_of(&ClusterMap::deserialize(&serialized).unwrap_err()), family);
+    }
+
+    #[test]
+    fn serialize_hash_map_deterministically()
+    {
+        // Every map gets its own random hasher, so the iteration orders differ between them
+        let maps: Vec<std::collections::HashMap<String, u32>> = (0..20).map(|_| (0..100).map(|i| (format!("key {i}"), i)).collect()).collect();
+        let serialized = maps[0].serialize_deterministic();
+        assert!(maps.iter().all(|map| map.serialize_deterministic() == serialized));
+        assert_eq!(maps[0].serialize_deterministic(), serialized);
+        let (deserialized, bytes_read) = std::collections::HashMap::<String, u32>::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized, maps[0]);
+        assert_eq!(bytes_read, serialized.len());
+        assert_eq!(serialized.len(), maps[0].serialize().len());
+    }
+
+    #[test]
+    fn serialize_nested_hash_map_deterministically()
+    {
+        let tables: Vec<Option<SeqTable>> = (0..10).map(|_| Some(SeqTable {
+            sequences: (0..100).map(|connection| (connection, connection as u64 * 3)).collect(),
+            last_seen: std::time::SystemTime::UNIX_EPOCH,
+        })).collect();
+        let serialized = tables[0].serialize_deterministic();
+        assert!(tables.iter().all(|table| table.serialize_deterministic() == serialized));
+        assert_eq!(Option::<SeqTable>::deserialize_exact(&serialized).unwrap(), tables[0]);
+        // Keys are sorted by their big endian bytes, which is numeric order for unsigned keys
+        assert_eq!(&serialized[5..9], &0u32.to_be_bytes());
+        assert_eq!(&serialized[17..21], &1u32.to_be_bytes());
+    }
+
+    /// Next hops of an IPv4 routing table by prefix length
+    #[derive(Serializable, Debug, PartialEq)]
+    struct RoutingTable
+    {
+        created_at: std::time::SystemTime,
+        routes: std::collections::HashMap<u8, Vec<std::net::SocketAddr>>,
+    }
+
+    /// Ten prefix lengths with one to three next hops each
+    fn routing_table() -> RoutingTable
+    {
+        let routes = [0u8, 8, 12, 16, 20, 24, 26, 28, 30, 32].into_iter().map(|prefix_len| {
+            let next_hops = (0..1 + prefix_len % 3).map(|i| std::net::SocketAddr::from(([192, 168, prefix_len, i + 1], 179))).collect();
+            (prefix_len, next_hops)
+        }).collect();
+        RoutingTable {
+            created_at: std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_650_000_000),
+            routes,
+        }
+    }
+
+    #[test]
+    fn routing_tables_serialize_deterministically()
+    {
+        // Every table gets its own random hasher
+        let tables: Vec<RoutingTable> = (0..10).map(|_| routing_table()).collect();
+        let serialized = tables[0].serialize_deterministic();
+        assert!(tables.iter().all(|table| table.serialize_deterministic() == serialized));
+        // After the timestamp and the count, the prefix lengths start from the shortest
+        assert_eq!(serialized[8..13], [0, 0, 0, 10, 0]);
+        assert_eq!(RoutingTable::deserialize_exact(&serialized).unwrap(), tables[0]);
+    }
+
+    /// Times at which events of a type happened, oldest first
+    #[derive(Serializable, Debug, PartialEq)]
+    struct TimestampLog
+    {
+        event_type: u8,
+        timestamps: std::collections::VecDeque<std::time::SystemTime>,
+    }
+
+    /// A hundred events a minute apart
+    fn timestamp_log() -> TimestampLog
+    {
+        let start = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        TimestampLog {
+            event_type: 3,
+            timestamps: (0..100).map(|i| start + std::time::Duration::from_secs(i * 60)).collect(),
+        }
+    }
+
+    #[test]
+    fn timestamp_log_keeps_the_oldest_first()
+    {
+        let log = timestamp_log();
+        let serialized = log.serialize();
+        assert_eq!(serialized.len(), 1 + 4 + 100 * 8);
+        assert_eq!(TimestampLog::deserialize_exact(&serialized).unwrap().timestamps.front(), log.timestamps.front());
+        // Events pushed at the front of a deque that wrapped around its buffer are still written first
+        let mut wrapped = TimestampLog { event_type: 1, timestamps: std::collections::VecDeque::with_capacity(4) };
+        let oldest = log.timestamps[0];
+        wrapped.timestamps.extend([log.timestamps[2], log.timestamps[3]]);
+        wrapped.timestamps.push_front(log.timestamps[1]);
+        wrapped.timestamps.push_front(oldest);
+        let serialized = wrapped.serialize();
+        assert_eq!(serialized[5..13], 1_600_000_000u64.to_be_bytes());
+        assert_eq!(TimestampLog::deserialize_exact(&serialized).unwrap(), wrapped);
+    }
+
+    /// Tags of every category of a search index
+    #[derive(Serializable, Debug, PartialEq)]
+    struct TagIndex
+    {
+        index_version: u32,
+        categories: std::collections::HashMap<String, std::collections::HashSet<String>>,
+    }
+
+    /// Five categories of ten tags
+    fn tag_index() -> TagIndex
+    {
+        let categories = ["color", "size", "material", "brand", "season"].into_iter().map(|category| {
+            (category.to_string(), (0..10).map(|i| format!("{category}-{i}")).collect())
+        }).collect();
+        TagIndex { index_version: 7, categories }
+    }
+
+    #[test]
+    fn tag_index_rejects_duplicate_tags()
+    {
+        let sizes = ["s".to_string(), "m".to_string()].into();
+        let index = TagIndex { index_version: 1, categories: std::collections::HashMap::from([("size".to_string(), sizes)]) };
+        let mut serialized = index.serialize_deterministic();
+        // The version, the category count, the category and the tag count come before "m", then "s"
+        let second_tag = 4 + 4 + 8 + 4 + 5;
+        assert_eq!(serialized[second_tag..], [0, 0, 0, 1, b's']);
+        serialized[second_tag + 4] = b'm';
+        let error = TagIndex::deserialize(&serialized).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(DeserializeError::offset_of(&error), second_tag);
+    }
+
+    #[test]
+    fn reject_duplicate_set_items()
+    {
+        let mut serialized = 2u32.serialize();
+        serialized.extend("a".to_string().serialize());
+        serialized.extend("a".to_string().serialize());
+        let error = std::collections::HashSet::<String>::deserialize(&serialized).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(DeserializeError::offset_of(&error), 9);
+        assert!(std::collections::BTreeSet::<String>::deserialize(&serialized).is_err());
+        let set = std::collections::BTreeSet::from([3u16, 1, 2]);
+        assert_eq!(set.serialize(), [0, 0, 0, 3, 0, 1, 0, 2, 0, 3]);
+        assert_eq!(std::collections::BTreeSet::<u16>::deserialize_exact(&set.serialize()).unwrap(), set);
+    }
+
+    /// Permissions granted to a user, with the groups they belong to
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Grants
+    {
+        user: String,
+        permissions: std::collections::BTreeSet<String>,
+        groups: std::collections::HashSet<u32>,
+    }
+
+    #[test]
+    fn serialize_and_deserialize_sets_in_struct()
+    {
+        let grants = Grants {
+            user: "root".to_string(),
+            permissions: ["write", "read", "admin"].into_iter().map(String::from).collect(),
+            groups: std::collections::HashSet::from([0, 10, 27]),
+        };
+        let serialized = grants.serialize();
+        assert_eq!(Grants::deserialize_exact(&serialized).unwrap(), grants);
+        // The permissions are written in ascending order
+        assert_eq!(serialized[8..17], [0, 0, 0, 3, 0, 0, 0, 5, b'a']);
+        let empty = Grants { user: String::new(), permissions: Default::default(), groups: Default::default() };
+        assert_eq!(empty.serialize(), [0; 12]);
+        assert_eq!(Grants::deserialize_exact(&[0; 12]).unwrap(), empty);
+        // A set that claims more items than there are
+        for len in [serialized.len() - 1, 16]
+        {
+            assert_eq!(Grants::deserialize(&serialized[..len]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+
+    /// Payloads of an append-only log, keyed by their offset
+    #[derive(Serializable, Debug, PartialEq)]
+    struct LogSegment
+    {
+        segment_id: u64,
+        entries: std::collections::BTreeMap<u64, Vec<u8>>,
+    }
+
+    /// A hundred entries with payloads of growing length, the first one empty
+    fn log_segment() -> LogSegment
+    {
+        let mut offset = 4096;
+        let entries = (0..100u64).map(|i| {
+            let payload: Vec<u8> = (0..i * 3).map(|byte| (byte * 7 + i) as u8).collect();
+            // Every entry is stored after an 8 bytes header
+            let entry = (offset, payload);
+            offset += 8 + i * 3;
+            entry
+        }).collect();
+        LogSegment { segment_id: 12, entries }
+    }
+
+    #[test]
+    fn log_segment_payloads_are_exact()
+    {
+        let segment = log_segment();
+        let deserialized = LogSegment::deserialize_exact(&segment.serialize()).unwrap();
+        assert!(deserialized.entries[&4096].is_empty());
+        for (offset, payload) in &segment.entries
+        {
+            assert_eq!(&deserialized.entries[offset], payload);
+        }
+        // A payload that claims one byte more than the segment holds
+        let single = LogSegment { segment_id: 1, entries: std::collections::BTreeMap::from([(0, vec![1, 2, 3])]) };
+        let mut serialized = single.serialize();
+        let payload_len = 8 + 4 + 8;
+        assert_eq!(serialized[payload_len..payload_len + 4], [0, 0, 0, 3]);
+        serialized[payload_len + 3] = 4;
+        assert_eq!(LogSegment::deserialize(&serialized).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    /// Last modification time of the files below a directory, keyed by relative path
+    #[derive(Serializable, Debug, PartialEq)]
+    struct FileMetadataCache
+    {
+        root: String,
+        entries: std::collections::HashMap<String, std::time::SystemTime>,
+    }
+
+    /// Twenty files modified a minute and a half apart
+    fn file_metadata_cache() -> FileMetadataCache
+    {
+        let start = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let entries = (0..20u64).map(|i| {
+            (format!("src/module_{i}.rs"), start + std::time::Duration::from_secs(i * 90))
+        }).collect();
+        FileMetadataCache { root: "/home/user/project".to_string(), entries }
+    }
+
+    #[test]
+    fn file_metadata_cache_keeps_whole_seconds()
+    {
+        // SystemTime is written as whole seconds since the epoch, the fraction of a second is dropped
+        let start = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let entries = std::collections::HashMap::from([("a.rs".to_string(), start + std::time::Duration::from_millis(1500))]);
+        let cache = FileMetadataCache { root: "/".to_string(), entries };
+        let deserialized = FileMetadataCache::deserialize_exact(&cache.serialize()).unwrap();
+        assert_eq!(deserialized.root, "/");
+        assert_eq!(deserialized.entries["a.rs"], start + std::time::Duration::from_secs(1));
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    enum Body
+    {
+        Ping,
+        Data { channel: u16, payload: Vec<u8> },
+        Close(u32),
+    }
+
+    /// A message whose header says which variant the body is
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Msg
+    {
+        kind: u8,
+        sequence: u16,
+        #[serializable(tag_field = "kind")]
+        body: Body,
+    }
+
+    #[test]
+    fn serialize_externally_tagged_fields()
+    {
+        let body = Body::Data { channel: 5, payload: vec![0xAA, 0xBB] };
+        let data = Msg { kind: body.tag(), sequence: 1, body };
+        let serialized = data.serialize();
+        assert_eq!(serialized, [1, 0, 1, 0, 5, 0, 0, 0, 2, 0xAA, 0xBB]);
+        assert_eq!(serialized, data.serialize_deterministic());
+        assert_eq!(Msg::deserialize_exact(&serialized).unwrap(), data);
+        let close = Msg { kind: 2, sequence: 0x0102, body: Body::Close(7) };
+        assert_eq!(close.serialize(), [2, 1, 2, 0, 0, 0, 7]);
+        assert_eq!(Msg::deserialize_exact(&close.serialize()).unwrap(), close);
+        // A header that says ping has no body, so the rest is trailing
+        assert_eq!(Msg::deserialize(&[0, 0, 3, 0, 0, 0, 7]).unwrap(), (Msg { kind: 0, sequence: 3, body: Body::Ping }, 3));
+        let error = Msg::deserialize(&[3, 0, 1, 0]).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(DeserializeError::offset_of(&error), 3);
+    }
+
+    /// A message whose header has room for more variants than an enum can have
+    #[derive(Serializable, Debug, PartialEq)]
+    struct WideMsg
+    {
+        kind: u16,
+        #[serializable(tag_field = "kind")]
+        body: Body,
+    }
+
+    #[test]
+    fn report_invalid_tag_fields()
+    {
+        let error = WideMsg::deserialize(&[0, 3, 0, 0, 0, 7]).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::InvalidVariant { found: 3, type_name: "Body" }));
+        assert_eq!(DeserializeError::offset_of(&error), 2);
+        let error = WideMsg::deserialize(&[1, 2, 0, 0, 0, 7]).unwrap_err();
+        assert!(matches!(Error::find(&error), Some(Error::InvalidValue { reason, .. }) if reason == "the tag field kind is not a variant index"), "{error}");
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "The tag field kind does not match the variant of body"))]
+    fn tag_fields_are_written_from_the_variant()
+    {
+        // A kind that doesn't match the body fails a debug assertion,
+        // otherwise it is replaced by the tag of the body, so the encoding reads back
+        let mismatched = Msg { kind: 0, sequence: 1, body: Body::Close(7) };
+        let serialized = mismatched.serialize();
+        assert_eq!(serialized, [2, 0, 1, 0, 0, 0, 7]);
+        assert_eq!(mismatched.serialized_size(), serialized.len());
+        assert_eq!(Msg::deserialize_exact(&serialized).unwrap(), Msg { kind: 2, sequence: 1, body: Body::Close(7) });
+        let mut written = Vec::new();
+        mismatched.serialize_into(&mut written).unwrap();
+        assert_eq!(written, serialized);
+    }
+
+    /// Descriptions of the error codes of a protocol
+    #[derive(Serializable, Debug, PartialEq)]
+    struct ErrorCodeTable
+    {
+        protocol_version: u16,
+        codes: std::collections::BTreeMap<u16, String>,
+    }
+
+    /// Fifty codes from 400, with a description that names them
+    fn error_code_table() -> ErrorCodeTable
+    {
+        let codes = (0..50u16).map(|i| (400 + i * 3, format!("Error {} of the protocol", 400 + i * 3))).collect();
+        ErrorCodeTable { protocol_version: 2, codes }
+    }
+
+    #[test]
+    fn error_code_table_rejects_duplicate_codes()
+    {
+        let codes = std::collections::BTreeMap::from([(404, "Not found".to_string()), (400, "Bad request".to_string())]);
+        let mut serialized = ErrorCodeTable { protocol_version: 2, codes }.serialize();
+        // The codes are written in ascending order after the version and the count
+        assert_eq!(serialized[6..8], 400u16.to_be_bytes());
+        let second = 6 + 2 + 4 + "Bad request".len();
+        assert_eq!(serialized[second..second + 2], 404u16.to_be_bytes());
+        serialized[second..second + 2].copy_from_slice(&400u16.to_be_bytes());
+        let error = ErrorCodeTable::deserialize(&serialized).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(Error::find(&error), Some(&Error::DuplicateKey));
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(impl_conversions)]
+    struct Greeting
+    {
+        id: u16,
+        text: String,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(impl_conversions)]
+    enum Reply<T>
+    {
+        Empty,
+        Value(T),
+    }
+
+    /// Without the attribute the type is free to define its own conversions
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Checksummed(u8);
+
+    impl TryFrom<&[u8]> for Checksummed
+    {
+        type Error = &'static str;
+
+        fn try_from(bytes: &[u8]) -> Result<Self, Self::Error>
+        {
+            Ok(Checksummed(bytes.iter().fold(0, |sum, byte| sum ^ byte)))
+        }
+    }
+
+    #[test]
+    fn convert_to_and_from_bytes()
+    {
+        let greeting = Greeting { id: 1, text: "hi".to_string() };
+        let bytes: Vec<u8> = (&greeting).into();
+        assert_eq!(bytes, [0, 1, 0, 0, 0, 2, b'h', b'i']);
+        let converted: Greeting = bytes.as_slice().try_into().unwrap();
+        assert_eq!(converted, greeting);
+        assert_eq!(Vec::from(greeting), bytes);
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        let error = Greeting::try_from(trailing.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(Greeting::try_from(&bytes[..7]).is_err());
+
+        let reply: Vec<u8> = Reply::Value(7u32).into();
+        assert_eq!(reply, [1, 0, 0, 0, 7]);
+        assert_eq!(Reply::<u32>::try_from(reply.as_slice()).unwrap(), Reply::Value(7));
+        assert_eq!(Reply::<u32>::try_from(&[0u8][..]).unwrap(), Reply::Empty);
+        assert_eq!(Checksummed::try_from(&[1u8, 3][..]), Ok(Checksummed(2)));
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(indexed)]
+    struct ConfigV1
+    {
+        #[serializable(field_index = 1)]
+        name: String,
+        #[serializable(field_index = 2)]
+        port: u16,
+    }
+
+    /// The next version of `ConfigV1`, with a new field and the fields in another order
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(indexed)]
+    struct ConfigV2
+    {
+        #[serializable(field_index = 2)]
+        port: u16,
+        #[serializable(field_index = 1)]
+        name: String,
+        #[serializable(field_index = 3)]
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn serialize_indexed_fields()
+    {
+        let v1 = ConfigV1 { name: "db".to_string(), port: 5432 };
+        let serialized = v1.serialize();
+        assert_eq!(serialized, [
+            0, 2,
+            0, 1, 0, 0, 0, 6, 0, 0, 0, 2, b'd', b'b',
+            0, 2, 0, 0, 0, 2, 0x15, 0x38,
+        ]);
+        assert_eq!(ConfigV1::deserialize(&serialized).unwrap(), (ConfigV1 { name: "db".to_string(), port: 5432 }, serialized.len()));
+        assert_eq!(ConfigV1::FIXED_SIZE, None);
+        // A newer reader fills the missing field with its default value
+        let v2 = ConfigV2::deserialize_exact(&serialized).unwrap();
+        assert_eq!(v2, ConfigV2 { port: 5432, name: "db".to_string(), tags: Vec::new() });
+        // An older reader skips the unknown field
+        let v2 = ConfigV2 { port: 80, name: "web".to_string(), tags: vec!["a".to_string(), "b".to_string()] };
+        let serialized = v2.serialize();
+        assert_eq!(ConfigV1::deserialize_exact(&serialized).unwrap(), ConfigV1 { name: "web".to_string(), port: 80 });
+        assert_eq!(ConfigV2::deserialize_exact(&serialized).unwrap(), v2);
+        assert_eq!(v2.serialize_deterministic(), serialized);
+    }
+
+    #[test]
+    fn reject_invalid_indexed_fields()
+    {
+        // The same field twice
+        let mut duplicate = vec![0, 2];
+        duplicate.extend([0, 2, 0, 0, 0, 2, 0, 80]);
+        duplicate.extend([0, 2, 0, 0, 0, 2, 0, 81]);
+        let error = ConfigV1::deserialize(&duplicate).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(DeserializeError::offset_of(&error), 10);
+        // The length is longer than the value, the byte after it is left over
+        let error = ConfigV1::deserialize(&[0, 1, 0, 2, 0, 0, 0, 3, 0, 80, 0]).unwrap_err();
+        assert_eq!(DeserializeError::offset_of(&error), 10);
+        // The length goes past the end of the data
+        let error = ConfigV1::deserialize(&[0, 1, 0, 2, 0, 0, 0, 3, 0, 80]).unwrap_err();
+        assert_eq!(DeserializeError::offset_of(&error), 8);
+        assert!(ConfigV1::deserialize(&[0, 1, 0, 2, 0]).is_err());
+    }
+
+    /// Asserts that `check_bytes` accepts and rejects every truncation and some corruptions of every byte
+    /// of `serialized` like `deserialize` does, with the same length read or the same offset of the failure
+    fn assert_check_bytes_agrees<T: Serializable>(serialized: &[u8])
+    {
+        let mut inputs: Vec<Vec<u8>> = (0..=serialized.len()).map(|len| serialized[..len].to_vec()).collect();
+        for i in 0..serialized.len()
+        {
+            for byte in [0, 1, 2, 0x80, 0xff]
+            {
+                let mut corrupted = serialized.to_vec();
+                corrupted[i] = byte;
+                inputs.push(corrupted);
+            }
+        }
+        for input in inputs
+        {
+            let expected = T::deserialize(&input).map(|(_, len)| len).map_err(|e| DeserializeError::offset_of(&e));
+            let checked = T::check_bytes(&input).map_err(|e| DeserializeError::offset_of(&e));
+            assert_eq!(checked, expected, "{} {input:?}", std::any::type_name::<T>());
+        }
+    }
+
+    #[test]
+    fn check_bytes_like_deserialize()
+    {
+        assert_check_bytes_agrees::<NamedTestStruct>(&NamedTestStruct { a: 1, b: 2, c: "héllo".to_string() }.serialize());
+        assert_check_bytes_agrees::<UnnamedTestStruct>(&UnnamedTestStruct(1, 2, "abc".to_string()).serialize());
+        assert_check_bytes_agrees::<UnitTestStruct>(&UnitTestStruct.serialize());
+        for test_enum in [TestEnum::A(1), TestEnum::C("abc".to_string()), TestEnum::D, TestEnum::E { f: 1, g: 2, h: "x".to_string() }]
+        {
+            assert_check_bytes_agrees::<TestEnum>(&test_enum.serialize());
+        }
+        assert_check_bytes_agrees::<TestStructWithVec>(&TestStructWithVec { a: 1, b: 2, c: vec![3, 4, 5] }.serialize());
+        let users = vec![
+            UserRecord { id: 1, name: "alice".to_string(), email: None },
+            UserRecord { id: 2, name: "bob".to_string(), email: Some("bob@example.com".to_string()) },
+        ];
+        assert_check_bytes_agrees::<Vec<UserRecord>>(&users.serialize());
+        assert_check_bytes_agrees::<std::collections::VecDeque<String>>(&vec!["a".to_string(), "bc".to_string()].serialize());
+        assert_check_bytes_agrees::<GenericTestEnum<[u16; 3], Option<String>>>(&GenericTestEnum::<[u16; 3], Option<String>>::First([1, 2, 3]).serialize());
+        assert_check_bytes_agrees::<GenericTestEnum<u8, Option<String>>>(&GenericTestEnum::<u8, _>::Second { value: Some("v".to_string()) }.serialize());
+        assert_check_bytes_agrees::<(bool, String, [bool; 2])>(&(true, "ab".to_string(), [false, true]).serialize());
+        assert_check_bytes_agrees::<Vec<bool>>(&vec![true, false, true].serialize());
+        assert_check_bytes_agrees::<Color>(&Color::Green.serialize());
+        assert_check_bytes_agrees::<Msg>(&Msg { kind: 2, sequence: 1, body: Body::Close(7) }.serialize());
+        assert_check_bytes_agrees::<ConfigV1>(&ConfigV1 { name: "db".to_string(), port: 5432 }.serialize());
+        assert_check_bytes_agrees::<std::collections::BTreeMap<u8, String>>(&std::collections::BTreeMap::from([(1, "a".to_string())]).serialize());
+    }
+
+    #[test]
+    fn check_bytes_without_allocating()
+    {
+        let strings: Vec<String> = (0..100).map(|i| format!("string {i}")).collect();
+        let serialized = strings.serialize();
+        let (checked, allocations) = count_allocations(|| Vec::<String>::check_bytes(&serialized));
+        assert_eq!(checked.unwrap(), serialized.len());
+        assert_eq!(allocations, 0);
+        let serialized = "a longer string".to_string().serialize();
+        let (checked, allocations) = count_allocations(|| String::check_bytes(&serialized));
+        assert_eq!(checked.unwrap(), serialized.len());
+        assert_eq!(allocations, 0);
+        let record = UserRecord { id: 2, name: "bob".to_string(), email: Some("bob@example.com".to_string()) };
+        let serialized = vec![Some(record)].serialize();
+        let (checked, allocations) = count_allocations(|| Vec::<Option<UserRecord>>::check_bytes(&serialized));
+        assert_eq!(checked.unwrap(), serialized.len());
+        assert_eq!(allocations, 0);
+        let (_, allocations) = count_allocations(|| Vec::<Option<UserRecord>>::deserialize(&serialized).unwrap());
+        assert!(allocations > 0);
+    }
+
+    /// Checks that `deserialize_trusted` reads the serialization of `value` and every truncation of it
+    /// like `deserialize`, truncated data is still rejected
+    fn assert_trusted_agrees<T: Serializable + PartialEq + std::fmt::Debug>(value: &T)
+    {
+        let serialized = value.serialize();
+        for len in 0..=serialized.len()
+        {
+            let input = &serialized[..len];
+            let expected = T::deserialize(input).map_err(|e| DeserializeError::offset_of(&e));
+            // SAFETY: the input is a prefix of the output of serialize
+            let trusted = unsafe { T::deserialize_trusted(input) }.map_err(|e| DeserializeError::offset_of(&e));
+            assert_eq!(trusted, expected, "{} {input:?}", std::any::type_name::<T>());
+        }
+        assert_eq!(unsafe { T::deserialize_trusted(&serialized) }.unwrap(), (T::deserialize(&serialized).unwrap().0, serialized.len()));
+    }
+
+    #[test]
+    fn deserialize_trusted_like_deserialize()
+    {
+        assert_trusted_agrees(&NamedTestStruct { a: 1, b: 2, c: "héllo".to_string() });
+        assert_trusted_agrees(&UnnamedTestStruct(1, 2, "abc".to_string()));
+        assert_trusted_agrees(&UnitTestStruct);
+        for test_enum in [TestEnum::A(1), TestEnum::C("abc".to_string()), TestEnum::D, TestEnum::E { f: 1, g: 2, h: "x".to_string() }]
+        {
+            assert_trusted_agrees(&test_enum);
+        }
+        assert_trusted_agrees(&vec![
+            UserRecord { id: 1, name: "alice".to_string(), email: None },
+            UserRecord { id: 2, name: "bob".to_string(), email: Some("bob@example.com".to_string()) },
+        ]);
+        assert_trusted_agrees(&std::collections::VecDeque::from(["a".to_string(), "bc".to_string()]));
+        assert_trusted_agrees(&GenericTestEnum::<[u16; 3], Option<String>>::First([1, 2, 3]));
+        assert_trusted_agrees(&GenericTestEnum::<u8, Option<String>>::Second { value: Some("v".to_string()) });
+        assert_trusted_agrees(&(true, "ab".to_string(), [false, true]));
+        assert_trusted_agrees(&vec![true, false, true]);
+        assert_trusted_agrees(&Color::Green);
+        assert_trusted_agrees(&Msg { kind: 2, sequence: 1, body: Body::Close(7) });
+        assert_trusted_agrees(&ConfigV1 { name: "db".to_string(), port: 5432 });
+        assert_trusted_agrees(&std::collections::BTreeMap::from([(1u8, "a".to_string()), (2, "bc".to_string())]));
+        assert_trusted_agrees(&std::collections::HashMap::<String, u16>::from([("a".to_string(), 1)]));
+        assert_trusted_agrees(&std::collections::BTreeSet::from(["x".to_string(), "yz".to_string()]));
+        assert_trusted_agrees(&std::collections::HashSet::<u32>::from([7]));
+        assert_trusted_agrees(&vec![Some("ünïcödé".to_string()), None]);
+    }
+
+    /// Topics that every subscriber of a publish-subscribe server follows
+    #[derive(Serializable, Debug, PartialEq)]
+    struct SubscriptionTable
+    {
+        server_id: u64,
+        subscriptions: std::collections::HashMap<u64, std::collections::BTreeSet<u32>>,
+    }
+
+    /// A hundred subscribers following five of forty topics each
+    fn subscription_table() -> SubscriptionTable
+    {
+        let subscriptions = (0..100u64).map(|subscriber| {
+            (subscriber * 31 + 7, (0..5).map(|i| (subscriber as u32 + i * 13) % 40).collect())
+        }).collect();
+        SubscriptionTable { server_id: 0xABCDEF, subscriptions }
+    }
+
+    #[test]
+    fn subscription_tables_serialize_deterministically()
+    {
+        let tables: Vec<SubscriptionTable> = (0..10).map(|_| subscription_table()).collect();
+        let serialized = tables[0].serialize_deterministic();
+        assert!(tables.iter().all(|table| table.serialize_deterministic() == serialized));
+        // The first subscriber is 7, its topics are written in ascending order
+        assert_eq!(serialized[12..20], 7u64.to_be_bytes());
+        let topics: Vec<u8> = [5u32, 0, 12, 13, 26, 39].iter().flat_map(|topic| topic.to_be_bytes()).collect();
+        assert_eq!(serialized[20..44], topics);
+        assert_eq!(SubscriptionTable::deserialize_exact(&serialized).unwrap(), tables[0]);
+    }
+
+    /// Backends of every virtual host, keyed by host name and port
+    #[derive(Serializable, Debug, PartialEq)]
+    struct VirtualHostMap(std::collections::BTreeMap<(String, u16), Vec<std::net::SocketAddr>>);
+
+    /// Three virtual hosts with an IPv4 and an IPv6 backend each
+    fn virtual_host_map() -> VirtualHostMap
+    {
+        let hosts = [("example.com", 443), ("example.com", 80), ("api.example.com", 443)];
+        VirtualHostMap(hosts.iter().enumerate().map(|(i, (host, port))| {
+            let backends = vec![
+                std::net::SocketAddr::from(([10, 0, i as u8, 1], 8080)),
+                std::net::SocketAddr::from((std::net::Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, i as u16, 2), 8080)),
+            ];
+            ((host.to_string(), *port), backends)
+        }).collect())
+    }
+
+    #[test]
+    fn virtual_hosts_are_sorted_by_host_then_port()
+    {
+        let serialized = virtual_host_map().serialize();
+        // The first key is the shortest host name
+        assert_eq!(serialized[4..8], ("api.example.com".len() as u32).to_be_bytes());
+        let deserialized = VirtualHostMap::deserialize_exact(&serialized).unwrap();
+        let keys: Vec<_> = deserialized.0.keys().map(|(host, port)| (host.as_str(), *port)).collect();
+        assert_eq!(keys, [("api.example.com", 443), ("example.com", 80), ("example.com", 443)]);
+        assert_eq!(deserialized.0[&("example.com".to_string(), 80)][0], std::net::SocketAddr::from(([10, 0, 1, 1], 8080)));
+    }
+
+    /// Coordinates of named locations, as latitude and longitude in degrees
+    #[derive(Serializable, Debug, PartialEq)]
+    struct CoordinateTable
+    {
+        region: String,
+        locations: std::collections::HashMap<String, (f64, f64)>,
+    }
+
+    /// Ten Italian cities
+    fn coordinate_table() -> CoordinateTable
+    {
+        let locations = [
+            ("Rome", (41.9028, 12.4964)),
+            ("Milan", (45.4642, 9.19)),
+            ("Naples", (40.8518, 14.2681)),
+            ("Turin", (45.0703, 7.6869)),
+            ("Palermo", (38.1157, 13.3615)),
+            ("Genoa", (44.4056, 8.9463)),
+            ("Bologna", (44.4949, 11.3426)),
+            ("Florence", (43.7696, 11.2558)),
+            ("Bari", (41.1171, 16.8719)),
+            ("Catania", (37.5079, 15.083)),
+        ];
+        CoordinateTable {
+            region: "Italy".to_string(),
+            locations: locations.iter().map(|(name, coordinates)| (name.to_string(), *coordinates)).collect(),
+        }
+    }
+
+    #[test]
+    fn coordinates_are_read_back_bit_for_bit()
+    {
+        let mut table = coordinate_table();
+        table.locations.insert("Null Island".to_string(), (-0.0, 0.0));
+        table.locations.insert("Edge".to_string(), (f64::MIN_POSITIVE / 2.0, -f64::MAX));
+        let deserialized = CoordinateTable::deserialize_exact(&table.serialize()).unwrap();
+        for (name, (lat, lon)) in &table.locations
+        {
+            let (read_lat, read_lon) = deserialized.locations[name];
+            assert_eq!((read_lat.to_bits(), read_lon.to_bits()), (lat.to_bits(), lon.to_bits()), "{name}");
+        }
+    }
+
+    /// Changes of every version of a project, as the line of the change and its text
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Changelog
+    {
+        project: String,
+        changes: std::collections::BTreeMap<String, Vec<(u32, String)>>,
+    }
+
+    /// Three versions of five changes
+    fn changelog() -> Changelog
+    {
+        let changes = ["0.1.0", "0.2.0", "1.0.0"].iter().enumerate().map(|(v, version)| {
+            let entries = (0..5).map(|i| (v as u32 * 100 + i * 3, format!("Change {i} of {version}"))).collect();
+            (version.to_string(), entries)
+        }).collect();
+        Changelog { project: "serializable".to_string(), changes }
+    }
+
+    #[test]
+    fn changelog_entries_are_pairs_without_framing()
+    {
+        let changes = std::collections::BTreeMap::from([("1.0".to_string(), vec![(7, "x".to_string())])]);
+        let changelog = Changelog { project: String::new(), changes };
+        // The project, the version count, the version, the entry count, then the line and the text of the entry
+        assert_eq!(changelog.serialize(), [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 3, b'1', b'.', b'0', 0, 0, 0, 1, 0, 0, 0, 7, 0, 0, 0, 1, b'x']);
+        let deserialized = Changelog::deserialize_exact(&self::changelog().serialize()).unwrap();
+        assert_eq!(deserialized.changes["1.0.0"][4], (212, "Change 4 of 1.0.0".to_string()));
+        let versions: Vec<_> = deserialized.changes.keys().map(String::as_str).collect();
+        assert_eq!(versions, ["0.1.0", "0.2.0", "1.0.0"]);
+    }
+
+    /// Checks the round trip of `value` and the edge cases that every encoding shares: truncated inputs
+    /// and a trailing byte are rejected, and `check_bytes` and `serialized_size` agree with `serialize`.
+    /// The values are compared by their deterministic serialization, which also works for floats like NaN.
+    fn assert_roundtrip<T: Serializable + std::fmt::Debug>(value: &T)
+    {
+        let serialized = value.serialize();
+        let (deserialized, bytes_read) = T::deserialize(&serialized).unwrap();
+        assert_eq!(bytes_read, serialized.len(), "{value:?}");
+        assert_eq!(deserialized.serialize_deterministic(), value.serialize_deterministic(), "{value:?}");
+        assert_eq!(T::check_bytes(&serialized).unwrap(), serialized.len());
+        assert_eq!(value.serialized_size(), serialized.len());
+        // A sample of the truncated inputs, always with the empty one and the one missing only the last byte
+        let step = (serialized.len() / 64).max(1);
+        for len in (0..serialized.len()).step_by(step).chain([serialized.len() - 1])
+        {
+            let error = T::deserialize(&serialized[..len]).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{} truncated to {len} bytes", std::any::type_name::<T>());
+        }
+        let mut trailing = serialized;
+        trailing.push(0);
+        assert_eq!(T::deserialize_exact(&trailing).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn serialize_and_deserialize_compositions()
+    {
+        let epoch = std::time::SystemTime::UNIX_EPOCH;
+        assert_roundtrip(&metrics());
+        assert_roundtrip(&Metrics { timestamp: epoch, values: Default::default() });
+        assert_roundtrip(&nullable_map());
+        assert_roundtrip(&seq_table(1000));
+        assert_roundtrip(&seq_table(0));
+        assert_roundtrip(&cluster_map());
+        assert_roundtrip(&ClusterMap { cluster_id: 0, version: 0, nodes: [("empty".to_string(), Vec::new())].into() });
+        assert_roundtrip(&routing_table());
+        assert_roundtrip(&RoutingTable { created_at: epoch, routes: Default::default() });
+        assert_roundtrip(&timestamp_log());
+        assert_roundtrip(&TimestampLog { event_type: 0, timestamps: Default::default() });
+        assert_roundtrip(&tag_index());
+        assert_roundtrip(&TagIndex { index_version: 0, categories: [(String::new(), Default::default())].into() });
+        assert_roundtrip(&log_segment());
+        assert_roundtrip(&LogSegment { segment_id: 0, entries: Default::default() });
+        assert_roundtrip(&file_metadata_cache());
+        assert_roundtrip(&FileMetadataCache { root: String::new(), entries: Default::default() });
+        assert_roundtrip(&error_code_table());
+        assert_roundtrip(&ErrorCodeTable { protocol_version: 0, codes: [(0, String::new())].into() });
+        assert_roundtrip(&subscription_table());
+        assert_roundtrip(&SubscriptionTable { server_id: 0, subscriptions: [(0, Default::default())].into() });
+        assert_roundtrip(&virtual_host_map());
+        assert_roundtrip(&VirtualHostMap(Default::default()));
+        assert_roundtrip(&coordinate_table());
+        assert_roundtrip(&CoordinateTable { region: String::new(), locations: Default::default() });
+        assert_roundtrip(&changelog());
+        assert_roundtrip(&Changelog { project: String::new(), changes: [(String::new(), Vec::new())].into() });
+    }
+
+    /// A value that claims to be longer than the data it was read from, like a buggy hand written impl
+    #[derive(Debug, PartialEq)]
+    struct Overlong(u8);
+
+    impl Serializable for Overlong
+    {
+        fn serialize(&self) -> Vec<u8>
+        {
+            vec![self.0]
+        }
+
+        fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)>
+        {
+            let (value, _) = u8::deserialize(data)?;
+            Ok((Overlong(value), 100))
+        }
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Packet
+    {
+        kind: u8,
+        payload: Overlong,
+        checksum: u32,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Span(u32, String, u16);
+
+    #[derive(Serializable, Debug, PartialEq)]
+    enum Command
+    {
+        Move { x: i32, y: i32 },
+        Say(String, u8),
+        Stop,
+    }
+
+    #[test]
+    fn truncated_input_is_an_error()
+    {
+        let span = Span(7, "abc".to_string(), 9).serialize();
+        let moved = Command::Move { x: -1, y: 2 }.serialize();
+        let said = Command::Say("hi".to_string(), 3).serialize();
+        for len in 0..span.len()
+        {
+            let kind = Span::deserialize(&span[..len]).unwrap_err().kind();
+            assert!(matches!(kind, std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof));
+        }
+        for serialized in [moved, said]
+        {
+            for len in 0..serialized.len()
+            {
+                let kind = Command::deserialize(&serialized[..len]).unwrap_err().kind();
+                assert!(matches!(kind, std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof));
+            }
+        }
+        assert_eq!(Command::deserialize_exact(&Command::Stop.serialize()).unwrap(), Command::Stop);
+    }
+
+    #[test]
+    fn overlong_field_is_an_error()
+    {
+        let serialized = Packet { kind: 1, payload: Overlong(2), checksum: 3 }.serialize();
+        assert_eq!(Packet::deserialize(&serialized).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(<(Overlong, u8)>::deserialize(&[1, 2]).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        let mut map = 2u32.serialize();
+        map.extend([1, 2, 3, 4]);
+        assert!(std::collections::BTreeMap::<u8, Overlong>::deserialize(&map).is_err());
+    }
+
+    /// A key of a keyboard layout, with the characters it types
+    #[derive(Serializable, Debug, PartialEq)]
+    struct KeyMapping
+    {
+        scancode: u16,
+        base: char,
+        shifted: Option<char>,
+        dead_keys: Vec<(char, char)>,
+    }
+
+    #[test]
+    fn serialize_and_deserialize_chars()
+    {
+        assert_eq!('A'.serialize(), [0, 0, 0, 0x41]);
+        assert_eq!('€'.serialize(), [0, 0, 0x20, 0xAC]);
+        assert_eq!('🦀'.serialize(), 0x1F980u32.to_be_bytes());
+        let mapping = KeyMapping { scancode: 0x1A, base: 'è', shifted: Some('é'), dead_keys: vec![('^', 'ê'), ('`', 'è')] };
+        let serialized = mapping.serialize();
+        assert_eq!(KeyMapping::deserialize_exact(&serialized).unwrap(), mapping);
+        assert_eq!(serialized.len(), 2 + 4 + 5 + 4 + 2 * 8);
+        // Surrogates and values past the last code point are not chars
+        for invalid in [0xD800u32, 0xDFFF, 0x110000, u32::MAX]
+        {
+            let error = char::deserialize(&invalid.serialize()).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+            assert_eq!(Error::find(&error), Some(&Error::InvalidChar { found: invalid }));
+        }
+        assert!(char::deserialize(&[0, 0, 0x41]).is_err());
+        assert_eq!(<char as crate::schema::Describe>::describe(), crate::schema::Schema::Primitive("char"));
+    }
+
+    thread_local! {
+        static LIVE_TOKENS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    /// A value that counts how many of its kind are alive, fails to deserialize from 0xFF and panics on 0xFE
+    #[derive(Debug, PartialEq)]
+    struct Token(u8);
+
+    impl Serializable for Token
+    {
+        fn serialize(&self) -> Vec<u8>
+        {
+            vec![self.0]
+        }
+
+        fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)>
+        {
+            match u8::deserialize(data)?
+            {
+                (0xFF, _) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid token")),
+                (0xFE, _) => panic!("Token 0xFE"),
+                (value, read) => {
+                    LIVE_TOKENS.with(|live| live.set(live.get() + 1));
+                    Ok((Token(value), read))
+                },
+            }
+        }
+    }
+
+    impl Drop for Token
+    {
+        fn drop(&mut self)
+        {
+            LIVE_TOKENS.with(|live| live.set(live.get() - 1));
+        }
+    }
+
+    #[test]
+    fn deserialize_arrays_of_owned_items()
+    {
+        let names = ["alpha".to_string(), String::new(), "gamma".to_string(), "delta".to_string()];
+        let serialized = names.serialize();
+        assert_eq!(<[String; 4]>::deserialize_exact(&serialized).unwrap(), names);
+        // SAFETY: the data was just written by serialize
+        assert_eq!(unsafe { <[String; 4]>::deserialize_trusted(&serialized) }.unwrap().0, names);
+        assert!(<[String; 4]>::deserialize(&serialized[..serialized.len() - 1]).is_err());
+
+        // The items read before the one that fails are dropped exactly once
+        let error = <[Token; 4]>::deserialize(&[1, 2, 0xFF, 4]).unwrap_err();
+        assert_eq!(DeserializeError::offset_of(&error), 2);
+        assert_eq!(LIVE_TOKENS.with(std::cell::Cell::get), 0);
+        let tokens = <[Token; 4]>::deserialize_exact(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(LIVE_TOKENS.with(std::cell::Cell::get), 4);
+        drop(tokens);
+        assert_eq!(LIVE_TOKENS.with(std::cell::Cell::get), 0);
+        assert!(<[[Token; 2]; 2]>::deserialize(&[1, 2, 3, 0xFF]).is_err());
+        assert_eq!(LIVE_TOKENS.with(std::cell::Cell::get), 0);
+    }
+
+    #[test]
+    fn deserialize_arrays_that_panic_midway()
+    {
+        // The items read before the one that panics are dropped while unwinding, nothing is left uninitialized
+        assert!(std::panic::catch_unwind(|| <[Token; 3]>::deserialize(&[1, 0xFE, 3])).is_err());
+        assert_eq!(LIVE_TOKENS.with(std::cell::Cell::get), 0);
+        assert!(std::panic::catch_unwind(|| <[Token; 3]>::deserialize_from(&mut &[1, 2, 0xFE][..])).is_err());
+        assert_eq!(LIVE_TOKENS.with(std::cell::Cell::get), 0);
+        assert!(std::panic::catch_unwind(|| <[Option<Token>; 2]>::deserialize(&[1, 7, 1, 0xFE])).is_err());
+        assert_eq!(LIVE_TOKENS.with(std::cell::Cell::get), 0);
+    }
+
+    /// A value of a settings file, of one of a few types
+    #[derive(Serializable, Debug, PartialEq)]
+    enum SettingValue
+    {
+        Flag(bool),
+        Number(i64),
+        Text(String),
+        List(Vec<String>),
+    }
+
+    /// Settings of every profile, with the counters of the last session
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Profiles
+    {
+        settings: std::collections::BTreeMap<String, std::collections::HashMap<String, SettingValue>>,
+        counters: std::collections::HashMap<String, u64>,
+        history: std::collections::HashMap<u16, (String, Option<f32>)>,
+    }
+
+    #[test]
+    fn serialize_and_deserialize_maps_of_mixed_values()
+    {
+        let default = std::collections::HashMap::from([
+            ("dark_mode".to_string(), SettingValue::Flag(true)),
+            ("font_size".to_string(), SettingValue::Number(14)),
+            ("theme".to_string(), SettingValue::Text("solarized".to_string())),
+            ("plugins".to_string(), SettingValue::List(vec!["git".to_string(), "lsp".to_string()])),
+        ]);
+        let profiles = Profiles {
+            settings: std::collections::BTreeMap::from([("default".to_string(), default), ("empty".to_string(), Default::default())]),
+            counters: std::collections::HashMap::from([("opened".to_string(), 41), ("saved".to_string(), u64::MAX)]),
+            history: std::collections::HashMap::from([(1, ("a.txt".to_string(), Some(0.5))), (2, ("b.txt".to_string(), None))]),
+        };
+        let serialized = profiles.serialize();
+        assert_eq!(Profiles::deserialize_exact(&serialized).unwrap(), profiles);
+        assert_eq!(profiles.serialize_deterministic(), Profiles::deserialize_exact(&serialized).unwrap().serialize_deterministic());
+
+        let empty = Profiles { settings: Default::default(), counters: Default::default(), history: Default::default() };
+        assert_eq!(empty.serialize(), [0; 12]);
+        assert_eq!(Profiles::deserialize_exact(&[0; 12]).unwrap(), empty);
+        // A count far larger than the data fails without reserving memory for it
+        assert!(std::collections::HashMap::<String, u64>::deserialize(&u32::MAX.serialize()).is_err());
+
+        let mut duplicate = 2u32.serialize();
+        duplicate.extend([0, 1, 5, 0, 1, 6]);
+        let error = std::collections::BTreeMap::<u16, u8>::deserialize(&duplicate).unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::DuplicateKey));
+        assert_eq!(DeserializeError::offset_of(&error), 7);
+    }
+
+    /// The widest tuple with an impl
+    type ReportCells = (u8, i16, u32, i64, f32, f64, bool, char, String, Option<u8>, Vec<u16>, (u8, u8));
+
+    /// A row of a report, with tuples of every width as columns
+    #[derive(Serializable, Debug, PartialEq)]
+    struct ReportRow
+    {
+        id: (u32,),
+        owner: (u32, String),
+        cells: ReportCells,
+    }
+
+    #[test]
+    fn serialize_and_deserialize_tuples()
+    {
+        assert_eq!((7u8,).serialize(), [7]);
+        assert_eq!((1u16, "ab".to_string()).serialize(), [0, 1, 0, 0, 0, 2, b'a', b'b']);
+        assert_eq!(<(u8, u16, u32)>::FIXED_SIZE, Some(7));
+        assert_eq!(<(u8, String)>::FIXED_SIZE, None);
+        let row = ReportRow {
+            id: (9,),
+            owner: (3, "ops".to_string()),
+            cells: (1, -2, 3, -4, 5.5, -6.25, true, 'x', "nine".to_string(), Some(10), vec![11, 11], (12, 12)),
+        };
+        let serialized = row.serialize();
+        assert_eq!(ReportRow::deserialize_exact(&serialized).unwrap(), row);
+        assert_eq!(serialized.len(), 4 + 4 + 7 + (1 + 2 + 4 + 8 + 4 + 8 + 1 + 4 + 8 + 2 + 8 + 2));
+        // The components are written left to right, so a broken one is found at its offset
+        let mut broken = serialized.clone();
+        broken[15 + 1 + 2 + 4 + 8 + 4 + 8] = 2;
+        let error = ReportRow::deserialize(&broken).unwrap_err();
+        assert_eq!(DeserializeError::offset_of(&error), 42);
+        assert!(ReportRow::deserialize(&serialized[..serialized.len() - 1]).is_err());
+    }
+
+    /// A message of a peer exchange protocol
+    #[derive(Serializable, Debug, PartialEq)]
+    enum PeerMessage
+    {
+        Peers(std::collections::HashSet<std::net::SocketAddr>),
+        Banned { reason: String, addresses: std::collections::BTreeSet<std::net::IpAddr> },
+        Ping,
+    }
+
+    #[test]
+    fn serialize_and_deserialize_sets_in_enum()
+    {
+        let peers: std::collections::HashSet<std::net::SocketAddr> = (1..=20).map(|i| std::net::SocketAddr::from(([10, 0, 0, i], 4000 + i as u16))).collect();
+        let banned = std::collections::BTreeSet::from([std::net::IpAddr::from([192, 168, 1, 1]), std::net::IpAddr::from([10, 0, 0, 1])]);
+        let messages = [
+            PeerMessage::Peers(peers.clone()),
+            PeerMessage::Peers(Default::default()),
+            PeerMessage::Banned { reason: "spam".to_string(), addresses: banned },
+            PeerMessage::Banned { reason: String::new(), addresses: Default::default() },
+            PeerMessage::Ping,
+        ];
+        for message in &messages
+        {
+            assert_eq!(&PeerMessage::deserialize_exact(&message.serialize()).unwrap(), message);
+        }
+        // The order of a HashSet depends on its hasher, the deterministic serialization doesn't
+        let reordered = PeerMessage::Peers(peers.iter().copied().collect::<Vec<_>>().into_iter().rev().collect());
+        assert_eq!(reordered.serialize_deterministic(), messages[0].serialize_deterministic());
+        assert_eq!(messages[1].serialize(), [0, 0, 0, 0, 0]);
+        // The BTreeSet is written in ascending order, 10.0.0.1 first
+        assert_eq!(messages[2].serialize()[13..18], [0, 10, 0, 0, 1]);
+
+        let mut duplicate = vec![0, 0, 0, 0, 2];
+        duplicate.extend(std::net::SocketAddr::from(([10, 0, 0, 1], 80)).serialize());
+        duplicate.extend(std::net::SocketAddr::from(([10, 0, 0, 1], 80)).serialize());
+        assert_eq!(PeerMessage::deserialize(&duplicate).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    /// A reply that may carry a value, used with `()` when only the status matters
+    #[derive(Serializable, Debug, PartialEq)]
+    struct StatusReply<T>
+    {
+        status: u16,
+        value: Option<T>,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    enum Ack
+    {
+        Done(()),
+        Retry(u8, ()),
+    }
+
+    #[test]
+    fn serialize_and_deserialize_unit_values()
+    {
+        assert!(().serialize().is_empty());
+        assert_eq!(<()>::deserialize(&[1, 2]).unwrap(), ((), 0));
+        assert_eq!(<()>::FIXED_SIZE, Some(0));
+        assert_eq!(Some(()).serialize(), [1]);
+        assert_eq!(Option::<()>::deserialize_exact(&[0]).unwrap(), None);
+        assert_eq!(vec![(); 3].serialize(), [0, 0, 0, 3]);
+        assert_eq!(Vec::<()>::deserialize_exact(&[0, 0, 0, 3]).unwrap(), vec![(); 3]);
+
+        let reply = StatusReply::<()> { status: 204, value: Some(()) };
+        assert_eq!(reply.serialize(), [0, 204, 1]);
+        assert_eq!(StatusReply::<()>::deserialize_exact(&[0, 204, 1]).unwrap(), reply);
+        assert_eq!(Ack::Done(()).serialize(), [0]);
+        assert_eq!(Ack::deserialize_exact(&[1, 5]).unwrap(), Ack::Retry(5, ()));
+    }
+
+    /// The waypoints of a route, with the optional sensor sample taken at the end
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Track
+    {
+        positions: Vec<(u32, u32)>,
+        labels: Vec<(u16, String, bool)>,
+        sample: Option<(u8, Vec<u16>)>,
+    }
+
+    #[test]
+    fn serialize_and_deserialize_tuple_fields()
+    {
+        let pair = (0x01020304u32, 0x05060708u32);
+        assert_eq!(pair.serialize(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        let triple = (7u16, "gate".to_string(), true);
+        let serialized = triple.serialize();
+        assert_eq!(<(u16, String, bool)>::deserialize(&serialized).unwrap(), (triple.clone(), 2 + 8 + 1));
+        let sample = Some((3u8, vec![10u16, 20]));
+        assert_eq!(sample.serialize(), [1, 3, 0, 0, 0, 2, 0, 10, 0, 20]);
+        assert_eq!(Option::<(u8, Vec<u16>)>::deserialize_exact(&sample.serialize()).unwrap(), sample);
+
+        let track = Track { positions: vec![pair, (9, 10)], labels: vec![triple], sample };
+        let serialized = track.serialize();
+        let (deserialized, bytes_read) = Track::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized, track);
+        assert_eq!(bytes_read, (4 + 16) + (4 + 11) + 10);
+    }
+
+    /// A reader that hands out at most one byte per call, like a slow socket
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_>
+    {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize>
+        {
+            match (self.0.split_first(), buf.first_mut())
+            {
+                (Some((&byte, rest)), Some(out)) => {
+                    *out = byte;
+                    self.0 = rest;
+                    Ok(1)
+                },
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn serialize_into_and_deserialize_from_streams()
+    {
+        let track = (vec![(1u32, 2u32), (3, 4)], "end".to_string());
+        let mut stream = Vec::new();
+        track.serialize_into(&mut stream).unwrap();
+        (7u16, true).serialize_into(&mut stream).unwrap();
+        assert_eq!(stream[..stream.len() - 3], track.serialize());
+
+        let mut cursor = std::io::Cursor::new(&stream);
+        let (read, len) = <(Vec<(u32, u32)>, String)>::deserialize_from(&mut cursor).unwrap();
+        assert_eq!((read, len), (track.clone(), stream.len() - 3));
+        // Nothing after the value is consumed
+        assert_eq!(cursor.position() as usize, len);
+        assert_eq!(<(u16, bool)>::deserialize_from(&mut cursor).unwrap(), ((7, true), 3));
+        assert_eq!(u8::deserialize_from(&mut cursor).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let mut trickle = Trickle(&stream);
+        assert_eq!(<(Vec<(u32, u32)>, String)>::deserialize_from(&mut trickle).unwrap().0, track);
+        assert_eq!(<(u16, bool)>::deserialize_from(&mut trickle).unwrap().0, (7, true));
+        let error = String::deserialize_from(&mut Trickle(&[0, 0, 0, 5, b'a'])).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn forged_length_prefix_is_an_error()
+    {
+        for data in [&[0xFF, 0xFF, 0xFF, 0xFF][..], &[0xFF, 0xFF, 0xFF, 0xFF, b'a', b'b'], &[0xFF, 0xFF, 0xFF, 0xFC, 0]]
+        {
+            assert_eq!(String::deserialize(data).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+            assert_eq!(String::check_bytes(data).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+            // SAFETY: a forged length is still checked against the data
+            assert!(unsafe { String::deserialize_trusted(data) }.is_err());
+            assert!(Vec::<u8>::deserialize(data).is_err());
+            assert!(<(String, u8)>::deserialize(data).is_err());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "The length 4294967296 does not fit in the u32 length prefix")]
+    fn length_past_u32_panics()
+    {
+        crate::serializable::length_prefix(u32::MAX as usize + 1);
+    }
+
+    #[test]
+    fn checked_length_prefix()
+    {
+        assert_eq!(crate::serializable::try_length_prefix(u32::MAX as usize).unwrap(), [0xFF; 4]);
+        let error = crate::serializable::try_length_prefix(u32::MAX as usize + 1).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "The length 4294967296 does not fit in the u32 length prefix");
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Reading
+    {
+        sensor: String,
+        #[serializable(fixed_point(scale = 100, repr = "i16"))]
+        celsius: f32,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    enum Frame
+    {
+        Ping,
+        Data { id: u16, payload: Vec<u8> },
+        Readings(Vec<Reading>, Option<char>),
+    }
+
+    #[test]
+    fn deserialize_from_reads_fields_in_order()
+    {
+        let frames = vec![
+            Frame::Data { id: 3, payload: vec![1, 2, 3] },
+            Frame::Ping,
+            Frame::Readings(vec![Reading { sensor: "hall".to_string(), celsius: 21.5 }], Some('c')),
+        ];
+        let mut stream = Vec::new();
+        for frame in &frames
+        {
+            frame.serialize_into(&mut stream).unwrap();
+        }
+        let mut trickle = Trickle(&stream);
+        for frame in &frames
+        {
+            let (read, len) = Frame::deserialize_from(&mut trickle).unwrap();
+            assert_eq!(&read, frame);
+            assert_eq!(len, frame.serialize().len());
+        }
+        assert!(trickle.0.is_empty());
+
+        let mut map = std::collections::BTreeMap::new();
+        map.insert(1u8, vec!["a".to_string()]);
+        map.insert(2, Vec::new());
+        let value = ([Some(1u64), None], map, std::collections::HashSet::from([7u16, 9]));
+        let serialized = value.serialize();
+        assert_eq!(<_>::deserialize_from(&mut Trickle(&serialized)).unwrap(), (value, serialized.len()));
+    }
+
+    #[test]
+    fn deserialize_from_stops_at_invalid_data()
+    {
+        // The unknown variant fails before anything after it is read
+        let mut trickle = Trickle(&[7, 0, 0, 0, 0]);
+        let error = Frame::deserialize_from(&mut trickle).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid variant index 7 for Frame");
+        assert_eq!(trickle.0.len(), 4);
+        // Only the bytes of the string are read before its encoding is checked
+        let mut trickle = Trickle(&[0, 0, 0, 2, 0xC3, 0x28, 0, 0]);
+        assert_eq!(String::deserialize_from(&mut trickle).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(trickle.0.len(), 2);
+        // A forged length fails at the end of the stream without allocating for it
+        let error = Vec::<u64>::deserialize_from(&mut Trickle(&[0xFF, 0xFF, 0xFF, 0xFF, 1])).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+        let error = Frame::deserialize_from(&mut Trickle(&[1, 0, 3, 0, 0, 0, 2, 1])).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn serialized_size_hint()
+    {
+        assert_eq!(7u32.serialized_size_hint(), Some(4));
+        assert_eq!((1u8, 'x', [0i16; 3]).serialized_size_hint(), Some(1 + 4 + 6));
+        assert_eq!(Reading { sensor: String::new(), celsius: 0.0 }.serialized_size_hint(), None);
+        assert_eq!("fixed".to_string().serialized_size_hint(), None);
+        assert_eq!(Frame::Ping.serialized_size_hint(), None);
+    }
+
+    /// A type that only implements the required methods, like the impls written before `serialize_append`
+    #[derive(Debug, PartialEq)]
+    struct Celsius(i16);
+
+    impl Serializable for Celsius
+    {
+        fn serialize(&self) -> Vec<u8> {
+            self.0.serialize()
+        }
+
+        fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+            let (value, len) = i16::deserialize(data)?;
+            Ok((Celsius(value), len))
+        }
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Leaf
+    {
+        id: u32,
+        name: String,
+        tags: Vec<u16>,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    enum Node
+    {
+        Leaf(Leaf),
+        Branch(Vec<Leaf>, Option<(String, [u8; 4])>),
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Tree
+    {
+        nodes: Vec<Node>,
+        sizes: std::collections::BTreeMap<String, u64>,
+    }
+
+    fn tree() -> Tree
+    {
+        let leaf = |id: u32| Leaf { id, name: format!("leaf {id}"), tags: vec![id as u16; 3] };
+        let nodes = vec![
+            Node::Branch((0..100).map(leaf).collect(), Some(("inner".to_string(), [1, 2, 3, 4]))),
+            Node::Leaf(leaf(100)),
+            Node::Branch(Vec::new(), None),
+        ];
+        Tree { nodes, sizes: [("a".to_string(), 1), ("b".to_string(), 2)].into() }
+    }
+
+    #[test]
+    fn serialize_append_writes_nested_values_in_place()
+    {
+        let tree = tree();
+        let serialized = tree.serialize();
+        let (out, allocations) = count_allocations(|| {
+            let mut out = Vec::with_capacity(serialized.len());
+            tree.serialize_append(&mut out);
+            out
+        });
+        // Only the buffer itself is allocated
+        assert_eq!(allocations, 1);
+        assert_eq!(out, serialized);
+        assert_eq!(Tree::deserialize_exact(&out).unwrap(), tree);
+
+        // The bytes already in the buffer are kept
+        let mut out = vec![0xAA];
+        (7u8, "x".to_string()).serialize_append(&mut out);
+        assert_eq!(out, [0xAA, 7, 0, 0, 0, 1, b'x']);
+        // Types that only implement serialize are appended with it
+        let readings = vec![Some(Celsius(-3)), None];
+        let mut out = vec![0xAA];
+        readings.serialize_append(&mut out);
+        assert_eq!(out[1..], readings.serialize());
+        assert_eq!(Vec::<Option<Celsius>>::deserialize_exact(&out[1..]).unwrap(), readings);
+    }
+
+    fn assert_serialized_size<T: Serializable>(value: &T)
+    {
+        assert_eq!(value.serialized_size(), value.serialize().len());
+    }
+
+    #[test]
+    fn serialized_size_matches_serialize()
+    {
+        assert_serialized_size(&NamedTestStruct { a: 1, b: 2, c: "three".to_string() });
+        assert_serialized_size(&UnnamedTestStruct(1, 2, String::new()));
+        assert_serialized_size(&UnitTestStruct);
+        for value in [TestEnum::A(1), TestEnum::C("c".to_string()), TestEnum::D, TestEnum::E { f: 1, g: 2, h: "h".to_string() }]
+        {
+            assert_serialized_size(&value);
+        }
+        assert_serialized_size(&TestStructWithVec { a: 1, b: 2, c: vec![3; 10] });
+        assert_serialized_size(&Color::Green);
+        assert_serialized_size(&FixedUnnamedTestStruct(1, FixedTestStruct { a: 1, b: 2, c: 3.0 }));
+        assert_serialized_size(&UnalignedHeader { kind: 1, length: 2, version: 3, checksum: 4 });
+        assert_serialized_size(&UnalignedPair(1, 2));
+        assert_serialized_size(&Msg { kind: 1, sequence: 2, body: Body::Data { channel: 3, payload: vec![4, 5] } });
+        assert_serialized_size(&Msg { kind: 0, sequence: 2, body: Body::Ping });
+        assert_serialized_size(&ConfigV2 { port: 80, name: "web".to_string(), tags: vec!["a".to_string()] });
+        assert_serialized_size(&PeerMessage::Banned {
+            reason: "spam".to_string(),
+            addresses: [std::net::IpAddr::from([10, 0, 0, 1]), std::net::IpAddr::from([0u16; 8])].into(),
+        });
+        assert_serialized_size(&PeerMessage::Peers([std::net::SocketAddr::from(([0u16; 8], 80))].into()));
+        assert_serialized_size(&StatusReply { status: 200, value: Some(()) });
+        assert_serialized_size(&Ack::Retry(3, ()));
+        assert_serialized_size(&Frame::Readings(vec![Reading { sensor: "hall".to_string(), celsius: 21.5 }], Some('c')));
+        let tree = tree();
+        assert_serialized_size(&tree);
+        // Derived types and the provided impls don't serialize to find their size
+        let (size, allocations) = count_allocations(|| tree.serialized_size());
+        assert_eq!((size, allocations), (tree.serialize().len(), 0));
+        assert_serialized_size(&vec![Some(Celsius(-3)), None]);
+        assert_serialized_size(&std::collections::HashMap::from([("a".to_string(), vec![1u8, 2]), ("bc".to_string(), Vec::new())]));
+        assert_serialized_size(&std::collections::VecDeque::from(["x".to_string(), "yz".to_string()]));
+        assert_serialized_size(&(std::time::SystemTime::now(), 'x', [Some(1u16), None], std::net::Ipv4Addr::LOCALHOST));
+    }
+
+    /// The response of a remote call, which fails with a code and a message
+    #[derive(Serializable, Debug, PartialEq)]
+    struct RpcResponse
+    {
+        id: u32,
+        result: Result<Vec<String>, (u16, String)>,
+    }
+
+    #[test]
+    fn serialize_and_deserialize_results()
+    {
+        assert_eq!(Ok::<u16, String>(7).serialize(), [0, 0, 7]);
+        assert_eq!(Err::<u16, String>("no".to_string()).serialize(), [1, 0, 0, 0, 2, b'n', b'o']);
+        let responses = [
+            RpcResponse { id: 1, result: Ok(vec!["a".to_string(), "b".to_string()]) },
+            RpcResponse { id: 2, result: Err((404, "not found".to_string())) },
+        ];
+        for response in &responses
+        {
+            let serialized = response.serialize();
+            assert_eq!(&RpcResponse::deserialize_exact(&serialized).unwrap(), response);
+            assert_eq!(RpcResponse::check_bytes(&serialized).unwrap(), serialized.len());
+            // SAFETY: the data was just written by serialize
+            assert_eq!(&unsafe { RpcResponse::deserialize_trusted(&serialized) }.unwrap().0, response);
+            assert_eq!(&RpcResponse::deserialize_from(&mut Trickle(&serialized)).unwrap().0, response);
+            assert_eq!(response.serialized_size(), serialized.len());
+        }
+        let error = Result::<u8, u8>::deserialize(&[2, 0]).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid variant index 2 for Result");
+        assert!(Result::<u8, u8>::check_bytes(&[2, 0]).is_err());
+        assert!(Result::<u8, u8>::deserialize(&[]).is_err());
+        let error = Result::<u8, u16>::deserialize(&[1, 0]).unwrap_err();
+        assert_eq!(DeserializeError::offset_of(&error), 1);
+    }
+
+    #[test]
+    fn write_to_and_read_from_cursors()
+    {
+        let responses = [
+            RpcResponse { id: 1, result: Ok(vec!["a".to_string()]) },
+            RpcResponse { id: 2, result: Err((500, "failed".to_string())) },
+        ];
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        let written: usize = responses.iter().map(|response| response.write_to(&mut cursor).unwrap()).sum();
+        assert_eq!(written, cursor.get_ref().len());
+        cursor.set_position(0);
+        assert_eq!(RpcResponse::read_from(&mut cursor).unwrap(), responses[0]);
+        assert_eq!(RpcResponse::read_from(&mut cursor).unwrap(), responses[1]);
+        assert_eq!(RpcResponse::read_from(&mut cursor).unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    /// An arithmetic expression, a tree of boxed nodes
+    #[derive(Serializable, Debug, PartialEq)]
+    enum Expr
+    {
+        Number(i64),
+        Add(Box<Expr>, Box<Expr>),
+        Negate { operand: Box<Expr> },
+    }
+
+    #[test]
+    fn serialize_and_deserialize_recursive_types()
+    {
+        let expr = Expr::Add(Box::new(Expr::Number(2)), Box::new(Expr::Negate { operand: Box::new(Expr::Number(3)) }));
+        let serialized = expr.serialize();
+        assert_eq!(serialized, [1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 2, 0, 0, 0, 0, 0, 0, 0, 0, 3]);
+        assert_eq!(Expr::deserialize_exact(&serialized).unwrap(), expr);
+        assert_eq!(Expr::deserialize_from(&mut Trickle(&serialized)).unwrap(), (expr, serialized.len()));
+
+        let list = ListNode { value: 1, next: Some(Box::new(ListNode { value: 2, next: None })) };
+        assert_eq!(list.serialize(), [0, 1, 1, 0, 2, 0]);
+        assert_eq!(ListNode::deserialize_exact(&list.serialize()).unwrap(), list);
+        assert!(ListNode::deserialize(&[0, 1, 1, 0, 2]).is_err());
+    }
+
+    /// A singly linked list
+    #[derive(Serializable, Debug, PartialEq)]
+    struct ListNode
+    {
+        value: u16,
+        next: Option<Box<Self>>,
+    }
+
+    /// A directory tree, where the two types refer to each other
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Directory
+    {
+        name: String,
+        entries: Vec<Entry>,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    enum Entry
+    {
+        File { name: String, size: u32 },
+        Directory(Box<Directory>),
+    }
+
+    #[test]
+    fn serialize_and_deserialize_mutually_recursive_types()
+    {
+        let nested = Directory { name: "b".to_string(), entries: vec![Entry::File { name: "c".to_string(), size: 7 }] };
+        let root = Directory { name: "a".to_string(), entries: vec![Entry::Directory(Box::new(nested)), Entry::File { name: "d".to_string(), size: 0 }] };
+        let serialized = root.serialize();
+        assert_eq!(serialized[..10], [0, 0, 0, 1, b'a', 0, 0, 0, 2, 1]);
+        assert_eq!(Directory::deserialize_exact(&serialized).unwrap(), root);
+        assert_eq!(Directory::check_bytes(&serialized).unwrap(), serialized.len());
+        let error = Directory::deserialize(&serialized[..serialized.len() - 1]).unwrap_err();
+        assert_eq!(DeserializeError::find(&error).unwrap().path(), "entries[1].size");
+    }
+
+    #[test]
+    fn serialize_and_deserialize_shared_pointers()
+    {
+        let name = std::rc::Rc::new("shared".to_string());
+        let names = vec![name.clone(), name];
+        let serialized = names.serialize();
+        assert_eq!(serialized, vec!["shared".to_string(), "shared".to_string()].serialize());
+        assert_eq!(Vec::<std::rc::Rc<String>>::deserialize_exact(&serialized).unwrap(), names);
+        let counter = std::sync::Arc::new(7u32);
+        assert_eq!(counter.serialize(), [0, 0, 0, 7]);
+        assert_eq!(std::sync::Arc::<u32>::deserialize_exact(&[0, 0, 0, 7]).unwrap(), counter);
+        assert_eq!(<Box<u32> as FixedSize>::SIZE, 4);
+        assert_eq!(Box::new([1u8, 2]).serialize_fixed::<2>().unwrap(), [1, 2]);
+        assert_eq!(<std::sync::Arc<(u8, u16)> as crate::schema::Describe>::describe(), <(u8, u16) as crate::schema::Describe>::describe());
+    }
+}
@@ -0,0 +1,369 @@
+//! An append-only log of serialized records stored in a file.
+//!
+//! Every record is its length as a 4 byte big endian number, the CRC-32 of the payload
+//! as a 4 byte big endian number and the payload, the serialization of the value.
+//! A crash in the middle of an append leaves a torn record at the end of the file,
+//! which [`LogReader`] treats as the end of the log and [`LogWriter`] removes when reopening.
+//! A corrupt record followed by more data is not left by a crash, so [`LogWriter`] refuses to open the log.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use crate::Serializable;
+
+/// Length of the header of every record
+const RECORD_HEADER_LEN: u64 = 8;
+
+/// Appends records to a log file
+pub struct LogWriter<T: Serializable>
+{
+    file: BufWriter<File>,
+    len: u64,
+    discarded_len: u64,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Serializable> LogWriter<T>
+{
+    /// Opens the log stored at `path`, creating an empty one if the file does not exist.
+    /// A torn record left at the end of the file by an interrupted append is truncated, see [`LogWriter::discarded_len`].
+    /// Fails with [`std::io::ErrorKind::InvalidData`], leaving the file untouched, if a corrupt record is followed by more data.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self>
+    {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let mut scanner = RecordScanner::new(file)?;
+        while scanner.next_record()?.is_some() {}
+        let len = scanner.valid_len;
+        if scanner.corrupt_tail && !scanner.torn
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Corrupt log record at offset {len} followed by more data")));
+        }
+        let mut file = scanner.into_inner();
+        let discarded_len = file.seek(SeekFrom::End(0))? - len;
+        if discarded_len > 0
+        {
+            file.set_len(len)?;
+            file.sync_data()?;
+        }
+        file.seek(SeekFrom::Start(len))?;
+        Ok(Self { file: BufWriter::new(file), len, discarded_len, _phantom: PhantomData })
+    }
+
+    /// Appends a record, returning its offset in the file.
+    /// The record is buffered until [`LogWriter::flush`] or [`LogWriter::sync`] is called or the buffer fills up.
+    pub fn append(&mut self, value: &T) -> std::io::Result<u64>
+    {
+        self.append_serialized(&value.serialize())
+    }
+
+    /// Appends a record whose payload is already serialized, which must be the serialization of a `T`.
+    /// It lets values be written from a borrowed form with the same layout, as [`crate::store::KvStore`] does.
+    pub(crate) fn append_serialized(&mut self, payload: &[u8]) -> std::io::Result<u64>
+    {
+        let len: u32 = payload.len().try_into()
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Record too long"))?;
+        self.file.write_all(&len.to_be_bytes())?;
+        self.file.write_all(&crc32(payload).to_be_bytes())?;
+        self.file.write_all(payload)?;
+        let offset = self.len;
+        self.len += RECORD_HEADER_LEN + payload.len() as u64;
+        Ok(offset)
+    }
+
+    /// Writes the buffered records to the file
+    pub fn flush(&mut self) -> std::io::Result<()>
+    {
+        self.file.flush()
+    }
+
+    /// Writes the buffered records to the file and waits until they are stored on the disk
+    pub fn sync(&mut self) -> std::io::Result<()>
+    {
+        self.file.flush()?;
+        self.file.get_ref().sync_data()
+    }
+
+    /// Returns the length in bytes of the log, including buffered records
+    pub fn len(&self) -> u64
+    {
+        self.len
+    }
+
+    /// Returns true if the log has no records
+    pub fn is_empty(&self) -> bool
+    {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes of the torn record that was truncated when opening the log
+    pub fn discarded_len(&self) -> u64
+    {
+        self.discarded_len
+    }
+}
+
+/// Iterates the records of a log file, from the oldest to the newest.
+///
+/// Iteration stops at the end of the file or at the first record that is torn or fails its checksum,
+/// and [`LogReader::valid_len`] tells where the valid data ends.
+/// A record that is intact but cannot be deserialized as a `T` is returned as an error
+/// and iteration continues with the next one.
+pub struct LogReader<T: Serializable>
+{
+    scanner: RecordScanner,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Serializable> LogReader<T>
+{
+    /// Opens the log stored at `path` for reading
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self>
+    {
+        Ok(Self { scanner: RecordScanner::new(File::open(path)?)?, _phantom: PhantomData })
+    }
+
+    /// Returns the length of the valid records returned so far, which is the length of the whole
+    /// valid log once the iteration is over
+    pub fn valid_len(&self) -> u64
+    {
+        self.scanner.valid_len
+    }
+
+    /// Returns true if the iteration stopped at a torn or corrupt record instead of the end of the file
+    pub fn has_corrupt_tail(&self) -> bool
+    {
+        self.scanner.corrupt_tail
+    }
+}
+
+impl<T: Serializable> Iterator for LogReader<T>
+{
+    type Item = std::io::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item>
+    {
+        match self.scanner.next_record()
+        {
+            Ok(Some(payload)) => Some(T::deserialize_exact(&payload)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Reads the payloads of the records of a log file, checking their length and checksum
+struct RecordScanner
+{
+    reader: BufReader<File>,
+    file_len: u64,
+    valid_len: u64,
+    corrupt_tail: bool,
+    /// The record where the scan stopped runs to the end of the file, as an interrupted append leaves it
+    torn: bool,
+    done: bool,
+}
+
+impl RecordScanner
+{
+    fn new(mut file: File) -> std::io::Result<Self>
+    {
+        let file_len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(Self { reader: BufReader::new(file), file_len, valid_len: 0, corrupt_tail: false, torn: false, done: false })
+    }
+
+    /// Returns the payload of the next valid record, or `None` at the end of the valid data
+    fn next_record(&mut self) -> std::io::Result<Option<Vec<u8>>>
+    {
+        if self.done
+        {
+            return Ok(None);
+        }
+        let remaining = self.file_len - self.valid_len;
+        if remaining == 0
+        {
+            self.done = true;
+            return Ok(None);
+        }
+        let mut header = [0; RECORD_HEADER_LEN as usize];
+        if remaining < RECORD_HEADER_LEN
+        {
+            return Ok(self.stop_at_corruption(true));
+        }
+        self.reader.read_exact(&mut header)?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let checksum = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        if remaining - RECORD_HEADER_LEN < len
+        {
+            return Ok(self.stop_at_corruption(true));
+        }
+        let mut payload = vec![0; len as usize];
+        self.reader.read_exact(&mut payload)?;
+        if crc32(&payload) != checksum
+        {
+            return Ok(self.stop_at_corruption(remaining - RECORD_HEADER_LEN == len));
+        }
+        self.valid_len += RECORD_HEADER_LEN + len;
+        Ok(Some(payload))
+    }
+
+    /// Stops the scan at an invalid record, `torn` if the record runs to the end of the file
+    fn stop_at_corruption(&mut self, torn: bool) -> Option<Vec<u8>>
+    {
+        self.done = true;
+        self.corrupt_tail = true;
+        self.torn = torn;
+        None
+    }
+
+    fn into_inner(self) -> File
+    {
+        self.reader.into_inner()
+    }
+}
+
+/// Lookup table of the CRC-32 used by zlib and Ethernet, with the reflected polynomial 0xEDB88320
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256
+    {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8
+        {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xEDB88320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub(crate) fn crc32(data: &[u8]) -> u32
+{
+    !data.iter().fold(!0, |crc, &byte| CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{crc32, LogReader, LogWriter};
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, Clone, PartialEq)]
+    enum Event
+    {
+        Deposit(u64),
+        Withdraw(u64),
+        Rename(String),
+    }
+
+    fn events() -> Vec<Event>
+    {
+        vec![Event::Deposit(100), Event::Rename("savings".to_string()), Event::Withdraw(30), Event::Deposit(5)]
+    }
+
+    #[test]
+    fn crc32_check_value()
+    {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn append_and_iterate()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = LogWriter::<Event>::open(file.path()).unwrap();
+        assert!(writer.is_empty());
+        let offsets: Vec<u64> = events().iter().map(|event| writer.append(event).unwrap()).collect();
+        writer.sync().unwrap();
+        assert_eq!(offsets[0], 0);
+        assert_eq!(offsets[1], 8 + 9);
+        let mut reader = LogReader::<Event>::open(file.path()).unwrap();
+        let read: Vec<Event> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(read, events());
+        assert_eq!(reader.valid_len(), writer.len());
+        assert!(!reader.has_corrupt_tail());
+    }
+
+    #[test]
+    fn recover_from_torn_record()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = LogWriter::<Event>::open(file.path()).unwrap();
+        for event in events()
+        {
+            writer.append(&event).unwrap();
+        }
+        writer.flush().unwrap();
+        let full_len = writer.len();
+        drop(writer);
+
+        // Simulate a crash in the middle of the last record
+        let torn_len = full_len - 3;
+        std::fs::OpenOptions::new().write(true).open(file.path()).unwrap().set_len(torn_len).unwrap();
+        let mut reader = LogReader::<Event>::open(file.path()).unwrap();
+        assert_eq!(reader.by_ref().count(), 3);
+        assert!(reader.has_corrupt_tail());
+        let valid_len = reader.valid_len();
+        assert_eq!(valid_len, full_len - 8 - 9);
+
+        let mut writer = LogWriter::<Event>::open(file.path()).unwrap();
+        assert_eq!(writer.discarded_len(), torn_len - valid_len);
+        assert_eq!(writer.append(&Event::Withdraw(1)).unwrap(), valid_len);
+        writer.flush().unwrap();
+        let read: Vec<Event> = LogReader::<Event>::open(file.path()).unwrap().map(Result::unwrap).collect();
+        assert_eq!(read, [&events()[..3], &[Event::Withdraw(1)]].concat());
+    }
+
+    #[test]
+    fn stop_at_corrupt_record()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = LogWriter::<Event>::open(file.path()).unwrap();
+        for event in events()
+        {
+            writer.append(&event).unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut data = std::fs::read(file.path()).unwrap();
+        let second_payload = 8 + 9 + 8;
+        data[second_payload + 3] ^= 0xFF;
+        std::fs::write(file.path(), &data).unwrap();
+        let mut reader = LogReader::<Event>::open(file.path()).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), Event::Deposit(100));
+        assert!(reader.next().is_none());
+        assert!(reader.has_corrupt_tail());
+        assert_eq!(reader.valid_len(), 8 + 9);
+        // The records after the corrupt one are not truncated
+        assert_eq!(LogWriter::<Event>::open(file.path()).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(file.path()).unwrap(), data);
+
+        // A corrupt last record is torn, so it is truncated
+        let last_payload = data.len() - 9;
+        data[second_payload + 3] ^= 0xFF;
+        data[last_payload] ^= 0xFF;
+        std::fs::write(file.path(), &data).unwrap();
+        let writer = LogWriter::<Event>::open(file.path()).unwrap();
+        assert_eq!(writer.discarded_len(), 8 + 9);
+        drop(writer);
+
+        // A record that is intact but of another type is an error and iteration goes on
+        let mut writer = LogWriter::<u8>::open(file.path()).unwrap();
+        writer.append(&7).unwrap();
+        writer.flush().unwrap();
+        let mut reader = LogReader::<Event>::open(file.path()).unwrap();
+        assert_eq!(reader.by_ref().take(3).map(Result::unwrap).collect::<Vec<_>>(), events()[..3]);
+        assert_eq!(reader.next().unwrap().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert!(reader.next().is_none());
+        assert!(!reader.has_corrupt_tail());
+    }
+}
@@ -0,0 +1,228 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use crate::persistent::write_atomic;
+use crate::serializable::try_length_prefix;
+use crate::{Error, Serializable};
+
+/// Length of the header that holds the offset of the first message that was not popped yet
+const HEADER_LEN: u64 = 8;
+
+/// Number of bytes of popped messages after which the file is compacted,
+/// if they are also at least as many as the bytes of the messages still in the queue
+const COMPACT_AFTER: u64 = 64 * 1024;
+
+/// A persistent FIFO queue of messages stored in a file.
+///
+/// The file starts with the 8 byte big endian offset of the first message that was not popped yet,
+/// followed by the messages, each stored as a 4 byte big endian length followed by its serialization.
+/// Popping a message only moves the offset, the popped messages are removed once they take enough space
+/// by writing the rest of the queue to a new file that replaces the old one, so that a crash leaves
+/// either the old or the new queue.
+pub struct MessageQueue<T: Serializable>
+{
+    path: PathBuf,
+    file: File,
+    head: u64,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: Serializable> MessageQueue<T>
+{
+    /// Opens the queue stored at `path`, creating an empty one if the file does not exist
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self>
+    {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        let len = file.metadata()?.len();
+        let head = if len == 0
+        {
+            file.write_all(&HEADER_LEN.to_be_bytes())?;
+            file.flush()?;
+            HEADER_LEN
+        }
+        else if len < HEADER_LEN
+        {
+            return Err(Error::UnexpectedEof { needed: HEADER_LEN as usize, available: len as usize }.into());
+        }
+        else
+        {
+            let mut header = [0; HEADER_LEN as usize];
+            file.read_exact(&mut header)?;
+            u64::from_be_bytes(header)
+        };
+        if head < HEADER_LEN || head > len.max(HEADER_LEN)
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid queue head"));
+        }
+        Ok(Self { path, file, head, _phantom: PhantomData })
+    }
+
+    /// Appends a message at the end of the queue
+    pub fn push(&mut self, msg: &T) -> std::io::Result<()>
+    {
+        let serialized = msg.serialize();
+        let mut record = Vec::with_capacity(serialized.len() + 4);
+        record.extend_from_slice(&try_length_prefix(serialized.len())?);
+        record.extend_from_slice(&serialized);
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_all(&record)?;
+        self.file.flush()
+    }
+
+    /// Removes and returns the first message of the queue, or `None` if the queue is empty.
+    /// A message that is stored intact but is not a valid `T` is removed too and returned as an error,
+    /// so that the next call returns the message after it.
+    pub fn pop(&mut self) -> std::io::Result<Option<T>>
+    {
+        let end = self.file.metadata()?.len();
+        if self.head == end
+        {
+            return Ok(None);
+        }
+        let available = (end - self.head) as usize;
+        if available < 4
+        {
+            return Err(Error::UnexpectedEof { needed: 4, available }.into());
+        }
+        self.file.seek(SeekFrom::Start(self.head))?;
+        let mut len = [0; 4];
+        self.file.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as u64;
+        let next = match self.head.checked_add(4).and_then(|start| start.checked_add(len))
+        {
+            Some(next) if next <= end => next,
+            _ => return Err(Error::UnexpectedEof { needed: 4 + len as usize, available }.into()),
+        };
+        let mut record = Vec::new();
+        (&mut self.file).take(len).read_to_end(&mut record)?;
+        let msg = T::deserialize_exact(&record);
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&next.to_be_bytes())?;
+        self.file.flush()?;
+        self.head = next;
+        let popped = self.head - HEADER_LEN;
+        if popped >= COMPACT_AFTER && popped >= end - self.head
+        {
+            self.compact()?;
+        }
+        msg.map(Some)
+    }
+
+    /// Replaces the file with one that only holds the messages that were not popped yet
+    fn compact(&mut self) -> std::io::Result<()>
+    {
+        let mut data = HEADER_LEN.to_be_bytes().to_vec();
+        self.file.seek(SeekFrom::Start(self.head))?;
+        self.file.read_to_end(&mut data)?;
+        write_atomic(&self.path, &data)?;
+        self.file = OpenOptions::new().read(true).write(true).open(&self.path)?;
+        self.head = HEADER_LEN;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{MessageQueue, COMPACT_AFTER, HEADER_LEN};
+    use crate::{Error, Serializable};
+
+    #[test]
+    fn push_and_pop_in_order()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut queue = MessageQueue::<String>::open(file.path()).unwrap();
+        queue.push(&"first".to_string()).unwrap();
+        queue.push(&"second".to_string()).unwrap();
+        assert_eq!(queue.pop().unwrap(), Some("first".to_string()));
+        queue.push(&"third".to_string()).unwrap();
+        assert_eq!(queue.pop().unwrap(), Some("second".to_string()));
+        assert_eq!(queue.pop().unwrap(), Some("third".to_string()));
+        assert_eq!(queue.pop().unwrap(), None);
+    }
+
+    #[test]
+    fn messages_persist_across_reopen()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        {
+            let mut queue = MessageQueue::<Vec<u32>>::open(file.path()).unwrap();
+            queue.push(&vec![1, 2, 3]).unwrap();
+            queue.push(&vec![4, 5]).unwrap();
+            assert_eq!(queue.pop().unwrap(), Some(vec![1, 2, 3]));
+        }
+        let mut queue = MessageQueue::<Vec<u32>>::open(file.path()).unwrap();
+        assert_eq!(queue.pop().unwrap(), Some(vec![4, 5]));
+        assert_eq!(queue.pop().unwrap(), None);
+    }
+
+    #[test]
+    fn pop_only_moves_the_head_until_compaction()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue.bin");
+        let mut queue = MessageQueue::<Vec<u8>>::open(&path).unwrap();
+        let message = vec![7; 1000];
+        let record_len = 4 + message.serialize().len() as u64;
+        for _ in 0..100
+        {
+            queue.push(&message).unwrap();
+        }
+        let full_len = std::fs::metadata(&path).unwrap().len();
+        assert_eq!(full_len, HEADER_LEN + 100 * record_len);
+        assert_eq!(queue.pop().unwrap(), Some(message.clone()));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), full_len);
+
+        // Once the popped messages take more than the threshold and the rest of the queue, they are removed
+        let mut popped = 1;
+        while popped * record_len < COMPACT_AFTER || popped < 50
+        {
+            assert_eq!(queue.pop().unwrap(), Some(message.clone()));
+            popped += 1;
+        }
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), HEADER_LEN + (100 - popped) * record_len);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        let mut queue = MessageQueue::<Vec<u8>>::open(&path).unwrap();
+        for _ in popped..100
+        {
+            assert_eq!(queue.pop().unwrap(), Some(message.clone()));
+        }
+        assert_eq!(queue.pop().unwrap(), None);
+    }
+
+    #[test]
+    fn corrupt_records_are_errors()
+    {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut queue = MessageQueue::<u16>::open(file.path()).unwrap();
+        queue.push(&1).unwrap();
+        // A record with a trailing byte
+        let mut data = std::fs::read(file.path()).unwrap();
+        data.extend([0, 0, 0, 3, 0, 2, 9]);
+        std::fs::write(file.path(), &data).unwrap();
+        queue.push(&3).unwrap();
+        let mut data = std::fs::read(file.path()).unwrap();
+        let mut queue = MessageQueue::<u16>::open(file.path()).unwrap();
+        assert_eq!(queue.pop().unwrap(), Some(1));
+        assert_eq!(queue.pop().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        // The invalid message is skipped
+        assert_eq!(queue.pop().unwrap(), Some(3));
+        assert_eq!(queue.pop().unwrap(), None);
+        // A length past the end of the file
+        data.extend([0xFF, 0xFF, 0xFF, 0xFF, 0]);
+        let head = data.len() as u64 - 5;
+        data[..8].copy_from_slice(&head.to_be_bytes());
+        std::fs::write(file.path(), &data).unwrap();
+        let mut queue = MessageQueue::<u16>::open(file.path()).unwrap();
+        let error = queue.pop().unwrap_err();
+        assert_eq!(Error::find(&error), Some(&Error::UnexpectedEof { needed: 4 + u32::MAX as usize, available: 5 }));
+        // A head past the end of the file
+        let head = data.len() as u64 + 1;
+        data[..8].copy_from_slice(&head.to_be_bytes());
+        std::fs::write(file.path(), &data).unwrap();
+        assert!(MessageQueue::<u16>::open(file.path()).is_err());
+    }
+}
@@ -0,0 +1,345 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, SocketAddrV4, UdpSocket};
+
+use crate as serializable;
+use crate::{framing, Serializable};
+
+pub mod connection;
+#[cfg(feature = "proxy-protocol")]
+pub mod proxy;
+#[cfg(feature = "stun")]
+pub mod stun;
+pub mod udp;
+#[cfg(unix)]
+pub mod unix;
+
+/// Largest payload that fits in a UDP datagram
+const MAX_DATAGRAM_LEN: usize = 65535;
+
+/// A network prefix such as `192.168.0.0/24` or `2001:db8::/32`.
+/// It is serialized as the [`IpAddr`] followed by the prefix length in a single byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CidrAddr
+{
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl CidrAddr
+{
+    /// Returns a new prefix, or `None` if `prefix_len` is longer than the address
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self>
+    {
+        if prefix_len <= Self::max_prefix_len(&addr)
+        {
+            Some(Self { addr, prefix_len })
+        }
+        else
+        {
+            None
+        }
+    }
+
+    /// Returns the address of the prefix
+    pub fn addr(&self) -> IpAddr
+    {
+        self.addr
+    }
+
+    /// Returns the number of leading bits of the address that belong to the prefix
+    pub fn prefix_len(&self) -> u8
+    {
+        self.prefix_len
+    }
+
+    /// Returns true if `addr` belongs to this network, addresses of the other IP version never do
+    pub fn contains(&self, addr: IpAddr) -> bool
+    {
+        match (self.addr, addr)
+        {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            },
+            _ => false,
+        }
+    }
+
+    fn max_prefix_len(addr: &IpAddr) -> u8
+    {
+        match addr
+        {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+}
+
+impl Serializable for CidrAddr
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = self.addr.serialize();
+        ret.push(self.prefix_len);
+        ret
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (addr, offset) = IpAddr::deserialize(data)?;
+        let (prefix_len, len) = u8::deserialize(&data[offset..])?;
+        match Self::new(addr, prefix_len)
+        {
+            Some(ret) => Ok((ret, offset + len)),
+            None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid prefix length")),
+        }
+    }
+}
+
+/// A socket address for protocols that only use IPv4.
+/// Unlike a [`SocketAddr`] it has no address type byte, it is serialized in 6 bytes,
+/// the address followed by the port.
+#[derive(Serializable, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ipv4SocketAddr(pub SocketAddrV4);
+
+impl From<SocketAddrV4> for Ipv4SocketAddr
+{
+    fn from(addr: SocketAddrV4) -> Self
+    {
+        Self(addr)
+    }
+}
+
+impl TryFrom<SocketAddr> for Ipv4SocketAddr
+{
+    type Error = std::io::Error;
+
+    /// Fails if `addr` is an IPv6 address
+    fn try_from(addr: SocketAddr) -> std::io::Result<Self>
+    {
+        match addr
+        {
+            SocketAddr::V4(addr) => Ok(Self(addr)),
+            SocketAddr::V6(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not an IPv4 address")),
+        }
+    }
+}
+
+impl From<Ipv4SocketAddr> for SocketAddr
+{
+    fn from(addr: Ipv4SocketAddr) -> Self
+    {
+        SocketAddr::V4(addr.0)
+    }
+}
+
+/// A UDP datagram together with the metadata of its delivery
+#[derive(Serializable, Debug, Clone, PartialEq, Eq)]
+pub struct Datagram
+{
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub ttl: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Datagram
+{
+    /// Waits for a datagram on `socket`.
+    /// The destination is the local address of the socket and, since the TTL of
+    /// received packets is not exposed by the standard library, the TTL is the one set on the socket.
+    pub fn receive(socket: &UdpSocket) -> std::io::Result<Datagram>
+    {
+        let mut buffer = vec![0; MAX_DATAGRAM_LEN];
+        let (len, src) = socket.recv_from(&mut buffer)?;
+        buffer.truncate(len);
+        Ok(Datagram {
+            src,
+            dst: socket.local_addr()?,
+            ttl: socket.ttl()?.min(u8::MAX as u32) as u8,
+            payload: buffer,
+        })
+    }
+
+    /// Sends the payload to the destination through `socket`, returning the number of bytes sent.
+    /// The source and the TTL are the ones of the socket.
+    pub fn send(&self, socket: &UdpSocket) -> std::io::Result<usize>
+    {
+        socket.send_to(&self.payload, self.dst)
+    }
+}
+
+/// Sends and receives framed serialized values over any byte stream,
+/// such as a `TcpStream` or a `UnixStream`
+pub trait SerializableStreamExt
+{
+    /// Writes the serialization of `value` as a single length prefixed frame
+    fn send_value<S: Serializable>(&mut self, value: &S) -> std::io::Result<()>;
+    /// Reads a single frame and deserializes it, frames longer than `max_len` are rejected before allocating
+    fn recv_value<S: Serializable>(&mut self, max_len: usize) -> std::io::Result<S>;
+}
+
+impl<T: Read + Write + ?Sized> SerializableStreamExt for T
+{
+    fn send_value<S: Serializable>(&mut self, value: &S) -> std::io::Result<()>
+    {
+        framing::write_value(self, value)
+    }
+
+    fn recv_value<S: Serializable>(&mut self, max_len: usize) -> std::io::Result<S>
+    {
+        framing::read_value(self, max_len)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+
+    use super::{CidrAddr, Datagram, Ipv4SocketAddr, SerializableStreamExt};
+    use crate as serializable;
+    use crate::{FixedSize, Serializable};
+
+    #[test]
+    fn serialize_and_deserialize_ipv4_cidr()
+    {
+        let cidr = CidrAddr::new(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)), 24).unwrap();
+        let serialized = cidr.serialize();
+        assert_eq!(serialized, [0, 192, 168, 0, 0, 24]);
+        let (deserialized, bytes_read) = CidrAddr::deserialize(&serialized).unwrap();
+        assert_eq!(cidr, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert!(cidr.contains(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 42))));
+        assert!(!cidr.contains(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 42))));
+        assert!(!cidr.contains(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn serialize_and_deserialize_ipv6_cidr()
+    {
+        let cidr = CidrAddr::new(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0)), 32).unwrap();
+        let serialized = cidr.serialize();
+        assert_eq!(serialized.len(), 18);
+        let (deserialized, bytes_read) = CidrAddr::deserialize(&serialized).unwrap();
+        assert_eq!(cidr, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert!(cidr.contains(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0xffff, 0, 0, 0, 0, 1))));
+        assert!(!cidr.contains(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb9, 0, 0, 0, 0, 0, 1))));
+    }
+
+    #[test]
+    fn cidr_edge_prefix_lengths()
+    {
+        let everything = CidrAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0).unwrap();
+        assert!(everything.contains(IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8))));
+        let host = CidrAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 128).unwrap();
+        assert!(host.contains(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+        assert!(CidrAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 33).is_none());
+        assert!(CidrAddr::deserialize(&[0, 10, 0, 0, 0, 33]).is_err());
+        assert!(CidrAddr::deserialize(&[0, 10, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn serialize_ipv4_socket_addr_without_type()
+    {
+        let addr = Ipv4SocketAddr(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 80));
+        let serialized = addr.serialize();
+        assert_eq!(serialized.len(), 6);
+        assert_eq!(serialized, [127, 0, 0, 1, 0, 80]);
+        assert_eq!(Ipv4SocketAddr::SIZE, 6);
+        assert_eq!(Ipv4SocketAddr::deserialize(&serialized).unwrap(), (addr, 6));
+        assert!(Ipv4SocketAddr::deserialize(&serialized[..5]).is_err());
+        assert_eq!(SocketAddr::from(addr).serialize().len(), 7);
+        assert_eq!(Ipv4SocketAddr::try_from(SocketAddr::from(addr)).unwrap(), addr);
+        assert!(Ipv4SocketAddr::try_from(SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 80)).is_err());
+    }
+
+    #[test]
+    fn send_and_receive_datagram()
+    {
+        let sender = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let receiver = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        let datagram = Datagram {
+            src: sender.local_addr().unwrap(),
+            dst: receiver.local_addr().unwrap(),
+            ttl: 64,
+            payload: b"Hello world".to_vec(),
+        };
+        assert_eq!(datagram.send(&sender).unwrap(), datagram.payload.len());
+        let received = Datagram::receive(&receiver).unwrap();
+        assert_eq!(received.src, datagram.src);
+        assert_eq!(received.dst, datagram.dst);
+        assert_eq!(received.payload, datagram.payload);
+
+        let serialized = received.serialize();
+        let (deserialized, bytes_read) = Datagram::deserialize(&serialized).unwrap();
+        assert_eq!(received, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub enum Request
+    {
+        Get { key: String },
+        Put { key: String, value: Vec<u8> },
+    }
+
+    #[test]
+    fn exchange_values_over_tcp()
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for _ in 0..2
+            {
+                let request: Request = stream.recv_value(1024).unwrap();
+                let response = match request
+                {
+                    Request::Get { key } => Some(format!("value of {key}")),
+                    Request::Put { .. } => None,
+                };
+                stream.send_value(&response).unwrap();
+            }
+            stream.recv_value::<Vec<u8>>(16).unwrap_err().kind()
+        });
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        stream.send_value(&Request::Put { key: "a".to_string(), value: vec![1, 2, 3] }).unwrap();
+        assert_eq!(stream.recv_value::<Option<String>>(1024).unwrap(), None);
+        stream.send_value(&Request::Get { key: "a".to_string() }).unwrap();
+        assert_eq!(stream.recv_value::<Option<String>>(1024).unwrap(), Some("value of a".to_string()));
+        stream.send_value(&vec![0u8; 100]).unwrap();
+        assert_eq!(server.join().unwrap(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn write_to_and_read_from_tcp_streams()
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut requests = Vec::new();
+            while let Ok(request) = Request::read_from(&mut stream)
+            {
+                requests.push(request);
+            }
+            requests
+        });
+        let requests = vec![
+            Request::Put { key: "a".to_string(), value: vec![7; 5000] },
+            Request::Get { key: "a".to_string() },
+        ];
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        for request in &requests
+        {
+            assert_eq!(request.write_to(&mut stream).unwrap(), request.serialize().len());
+        }
+        drop(stream);
+        assert_eq!(server.join().unwrap(), requests);
+    }
+}
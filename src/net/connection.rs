@@ -0,0 +1,370 @@
+//! A typed duplex session over any byte stream.
+//!
+//! Values are exchanged as length prefixed frames, see [`crate::framing`].
+
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+use crate::framing::{self, FrameDecoder};
+use crate::schema::Describe;
+use crate::Serializable;
+
+/// Longest frame accepted by a new [`Connection`]
+pub const DEFAULT_MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Size of the buffer used for each read from the stream
+const READ_BUFFER_LEN: usize = 8 * 1024;
+
+/// A session that sends values of type `T` and receives values of type `R` over `io`,
+/// which can be a `TcpStream`, a `UnixStream` or anything else that implements [`Read`] and [`Write`].
+/// Received bytes are buffered, so frames may be split across reads in any way.
+#[derive(Debug)]
+pub struct Connection<T, R, IO>
+{
+    io: IO,
+    decoder: FrameDecoder,
+    _marker: PhantomData<fn(&T) -> R>,
+}
+
+impl<T: Serializable, R: Serializable, IO: Read + Write> Connection<T, R, IO>
+{
+    /// Wraps `io` accepting frames up to [`DEFAULT_MAX_FRAME_LEN`] bytes long
+    pub fn new(io: IO) -> Self
+    {
+        Self::with_max_frame_len(io, DEFAULT_MAX_FRAME_LEN)
+    }
+
+    /// Wraps `io` accepting frames up to `max_frame_len` bytes long
+    pub fn with_max_frame_len(io: IO, max_frame_len: usize) -> Self
+    {
+        Self { io, decoder: FrameDecoder::new(max_frame_len), _marker: PhantomData }
+    }
+
+    /// Returns the length of the longest accepted frame
+    pub fn max_frame_len(&self) -> usize
+    {
+        self.decoder.max_len()
+    }
+
+    /// Sets the length of the longest accepted frame
+    pub fn set_max_frame_len(&mut self, max_frame_len: usize)
+    {
+        self.decoder.set_max_len(max_frame_len);
+    }
+
+    /// Sends `value` as a single frame
+    pub fn send(&mut self, value: &T) -> std::io::Result<()>
+    {
+        framing::write_value(&mut self.io, value)
+    }
+
+    /// Waits until a whole frame is received and deserializes it.
+    /// Fails with [`std::io::ErrorKind::UnexpectedEof`] if the stream ends first.
+    /// On non-blocking streams it fails with [`std::io::ErrorKind::WouldBlock`] when no whole frame
+    /// has arrived yet, the bytes received so far are kept for the next call.
+    pub fn recv(&mut self) -> std::io::Result<R>
+    {
+        let frame = self.recv_frame()?;
+        crate::trace::read(frame.len());
+        crate::trace::read_value(R::deserialize_exact(&frame))
+    }
+
+    /// Returns the next value if a whole frame has been received, otherwise `Ok(None)`.
+    /// At most one read is made on the stream, so this only blocks if the stream is blocking
+    /// and no frame is buffered yet. On non-blocking streams [`std::io::ErrorKind::WouldBlock`]
+    /// is reported as `Ok(None)`.
+    pub fn try_recv(&mut self) -> std::io::Result<Option<R>>
+    {
+        match self.try_recv_frame()?
+        {
+            Some(frame) => {
+                crate::trace::read(frame.len());
+                crate::trace::read_value(R::deserialize_exact(&frame)).map(Some)
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Returns a reference to the underlying stream
+    pub fn get_ref(&self) -> &IO
+    {
+        &self.io
+    }
+
+    /// Returns a mutable reference to the underlying stream,
+    /// reading from it directly may corrupt the framing
+    pub fn get_mut(&mut self) -> &mut IO
+    {
+        &mut self.io
+    }
+
+    /// Returns the underlying stream, any buffered bytes are lost
+    pub fn into_inner(self) -> IO
+    {
+        self.io
+    }
+
+    /// Reads until a whole frame is buffered, [`std::io::ErrorKind::WouldBlock`] is returned as is
+    fn recv_frame(&mut self) -> std::io::Result<Vec<u8>>
+    {
+        loop
+        {
+            if let Some(frame) = self.read_frame()?
+            {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Like [`Connection::read_frame`] but reports [`std::io::ErrorKind::WouldBlock`] as `Ok(None)`
+    fn try_recv_frame(&mut self) -> std::io::Result<Option<Vec<u8>>>
+    {
+        match self.read_frame()
+        {
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(None),
+            result => result,
+        }
+    }
+
+    /// Returns the next buffered frame, making at most one read on the stream if there is none
+    fn read_frame(&mut self) -> std::io::Result<Option<Vec<u8>>>
+    {
+        if let Some(frame) = self.decoder.next_frame()?
+        {
+            return Ok(Some(frame));
+        }
+        let mut buffer = [0; READ_BUFFER_LEN];
+        let len = match self.io.read(&mut buffer)
+        {
+            Ok(0) => return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Connection closed")),
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        self.decoder.push(&buffer[..len]);
+        self.decoder.next_frame()
+    }
+}
+
+impl<T: Describe, R: Describe, IO: Read + Write> Connection<T, R, IO>
+{
+    /// Checks that the other side agrees on the wire layout of the exchanged types
+    /// by sending the schema hashes of `T` and `R` and comparing them with the ones received.
+    /// Both sides must call it before sending anything else, a mismatch fails with [`std::io::ErrorKind::InvalidData`].
+    /// It waits for the hashes of the other side, so on non-blocking streams it fails with
+    /// [`std::io::ErrorKind::WouldBlock`] if they have not arrived yet, call it while the stream is blocking.
+    pub fn handshake(&mut self) -> std::io::Result<()>
+    {
+        framing::write_value(&mut self.io, &(T::schema_hash(), R::schema_hash()))?;
+        let frame = self.recv_frame()?;
+        let (peer_sent, peer_received) = <(u64, u64)>::deserialize_exact(&frame)?;
+        if peer_sent != R::schema_hash() || peer_received != T::schema_hash()
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "The other side uses a different schema"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::io::{Read, Write};
+    use std::rc::Rc;
+
+    use super::Connection;
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(describe)]
+    struct Request
+    {
+        id: u32,
+        path: String,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    #[serializable(describe)]
+    enum Response
+    {
+        Found(u32, Vec<u8>),
+        NotFound(u32),
+    }
+
+    /// One end of an in-memory pipe that delivers at most `chunk_len` bytes per read
+    struct Pipe
+    {
+        incoming: Rc<RefCell<VecDeque<u8>>>,
+        outgoing: Rc<RefCell<VecDeque<u8>>>,
+        chunk_len: usize,
+    }
+
+    fn pipe(chunk_len: usize) -> (Pipe, Pipe)
+    {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        (
+            Pipe { incoming: a.clone(), outgoing: b.clone(), chunk_len },
+            Pipe { incoming: b, outgoing: a, chunk_len },
+        )
+    }
+
+    impl Read for Pipe
+    {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize>
+        {
+            let mut incoming = self.incoming.borrow_mut();
+            if incoming.is_empty()
+            {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            let len = buf.len().min(self.chunk_len).min(incoming.len());
+            for (dst, src) in buf.iter_mut().zip(incoming.drain(..len))
+            {
+                *dst = src;
+            }
+            Ok(len)
+        }
+    }
+
+    impl Write for Pipe
+    {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>
+        {
+            self.outgoing.borrow_mut().extend(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()>
+        {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn request_response_exchange()
+    {
+        let (client, server) = pipe(usize::MAX);
+        let mut client = Connection::<Request, Response, _>::new(client);
+        let mut server = Connection::<Response, Request, _>::new(server);
+        assert!(server.try_recv().unwrap().is_none());
+        assert_eq!(server.recv().unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+        for id in 0..3
+        {
+            client.send(&Request { id, path: format!("/file/{id}") }).unwrap();
+        }
+        for _ in 0..3
+        {
+            let request = server.recv().unwrap();
+            let response = if request.id % 2 == 0
+            {
+                Response::Found(request.id, request.path.into_bytes())
+            }
+            else
+            {
+                Response::NotFound(request.id)
+            };
+            server.send(&response).unwrap();
+        }
+        assert_eq!(client.recv().unwrap(), Response::Found(0, b"/file/0".to_vec()));
+        assert_eq!(client.recv().unwrap(), Response::NotFound(1));
+        assert_eq!(client.recv().unwrap(), Response::Found(2, b"/file/2".to_vec()));
+        assert!(client.try_recv().unwrap().is_none());
+    }
+
+    #[test]
+    fn fragmented_delivery()
+    {
+        for chunk_len in [1, 3, 7]
+        {
+            let (client, server) = pipe(chunk_len);
+            let mut client = Connection::<Request, Response, _>::new(client);
+            let mut server = Connection::<Response, Request, _>::new(server);
+            let requests: Vec<Request> = (0..5).map(|id| Request { id, path: "/".repeat(id as usize) }).collect();
+            for request in &requests
+            {
+                client.send(request).unwrap();
+            }
+            let mut received = Vec::new();
+            let mut polls = 0;
+            while received.len() < requests.len()
+            {
+                if let Some(request) = server.try_recv().unwrap()
+                {
+                    received.push(request);
+                }
+                polls += 1;
+            }
+            assert_eq!(received, requests);
+            assert!(polls > requests.len());
+        }
+
+        // The bytes of a partial frame are kept when recv would block
+        let (client, server) = pipe(usize::MAX);
+        let mut client = Connection::<Request, Response, _>::new(client);
+        let mut server = Connection::<Response, Request, _>::new(server);
+        let request = Request { id: 9, path: "/a".to_string() };
+        let frame_len = 4 + request.serialize().len();
+        client.send(&request).unwrap();
+        let rest: Vec<u8> = client.get_mut().outgoing.borrow_mut().drain(frame_len / 2..).collect();
+        assert_eq!(server.recv().unwrap_err().kind(), std::io::ErrorKind::WouldBlock);
+        client.get_mut().outgoing.borrow_mut().extend(rest);
+        assert_eq!(server.recv().unwrap(), request);
+    }
+
+    #[test]
+    fn oversized_and_truncated_frames()
+    {
+        let (client, server) = pipe(usize::MAX);
+        let mut client = Connection::<Vec<u8>, Vec<u8>, _>::new(client);
+        let mut server = Connection::<Vec<u8>, Vec<u8>, _>::with_max_frame_len(server, 16);
+        client.send(&vec![0; 32]).unwrap();
+        assert_eq!(server.recv().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        let mut closed = Connection::<u32, u32, _>::new(std::io::Cursor::new(vec![0u8, 0, 0, 4, 1]));
+        assert_eq!(closed.recv().unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn connection_over_unix_stream()
+    {
+        let (a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+        let mut a = Connection::<String, u64, _>::new(a);
+        let mut b = Connection::<u64, String, _>::new(b);
+        a.send(&"ping".to_string()).unwrap();
+        assert_eq!(b.recv().unwrap(), "ping");
+        b.send(&42).unwrap();
+        assert_eq!(a.recv().unwrap(), 42);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn handshake_compares_schemas()
+    {
+        let (a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut server = Connection::<Response, Request, _>::new(b);
+            server.handshake().unwrap();
+            let request = server.recv().unwrap();
+            server.send(&Response::NotFound(request.id)).unwrap();
+        });
+        let mut client = Connection::<Request, Response, _>::new(a);
+        client.handshake().unwrap();
+        client.send(&Request { id: 1, path: "/".to_string() }).unwrap();
+        assert_eq!(client.recv().unwrap(), Response::NotFound(1));
+        server.join().unwrap();
+
+        let (a, b) = std::os::unix::net::UnixStream::pair().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut server = Connection::<Response, (u32, u32), _>::new(b);
+            server.handshake().unwrap_err().kind()
+        });
+        let mut client = Connection::<Request, Response, _>::new(a);
+        assert_eq!(client.handshake().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(server.join().unwrap(), std::io::ErrorKind::InvalidData);
+    }
+}
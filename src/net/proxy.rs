@@ -0,0 +1,194 @@
+//! Addresses of proxied connections in the binary PROXY protocol version 2 header,
+//! as sent by HAProxy, nginx and most load balancers in front of a TCP service.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::serializable::error_at_offset;
+use crate::{Error, Serializable};
+
+/// Signature that starts every PROXY protocol version 2 header
+pub const SIGNATURE: [u8; 12] = [0x0D, 0x0A, 0x0D, 0x0A, 0x00, 0x0D, 0x0A, 0x51, 0x55, 0x49, 0x54, 0x0A];
+
+/// Version 2 in the high nibble, `PROXY` command in the low nibble
+const VERSION_COMMAND_PROXY: u8 = 0x21;
+/// `AF_INET` in the high nibble, `STREAM` in the low nibble
+const TCP_OVER_IPV4: u8 = 0x11;
+/// `AF_INET6` in the high nibble, `STREAM` in the low nibble
+const TCP_OVER_IPV6: u8 = 0x21;
+/// Length of the fixed part of the header
+const HEADER_LEN: usize = 16;
+
+/// The source and destination of a proxied TCP connection.
+/// It is serialized as a PROXY protocol version 2 header with the `PROXY` command:
+/// the signature, the version and command, the address family and protocol, the length
+/// of the address block and the address block itself. Type-length-value extensions
+/// following the addresses are skipped when deserializing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProxyProtocolV2Addr
+{
+    source: SocketAddr,
+    destination: SocketAddr,
+}
+
+impl ProxyProtocolV2Addr
+{
+    /// Returns the header of a connection, or `None` if the addresses are of different IP versions
+    pub fn new(source: SocketAddr, destination: SocketAddr) -> Option<Self>
+    {
+        if source.is_ipv4() == destination.is_ipv4()
+        {
+            Some(Self { source, destination })
+        }
+        else
+        {
+            None
+        }
+    }
+
+    /// Returns the address of the client that opened the connection
+    pub fn source(&self) -> SocketAddr
+    {
+        self.source
+    }
+
+    /// Returns the address the client connected to
+    pub fn destination(&self) -> SocketAddr
+    {
+        self.destination
+    }
+}
+
+impl Serializable for ProxyProtocolV2Addr
+{
+    fn serialize(&self) -> Vec<u8> {
+        let (family, addresses): (u8, Vec<u8>) = match (self.source.ip(), self.destination.ip())
+        {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => (TCP_OVER_IPV4, [source.octets(), destination.octets()].concat()),
+            (IpAddr::V6(source), IpAddr::V6(destination)) => (TCP_OVER_IPV6, [source.octets(), destination.octets()].concat()),
+            _ => unreachable!("Addresses of different IP versions are rejected by the constructor"),
+        };
+        let len = addresses.len() + 4;
+        let mut ret = Vec::with_capacity(HEADER_LEN + len);
+        ret.extend_from_slice(&SIGNATURE);
+        ret.push(VERSION_COMMAND_PROXY);
+        ret.push(family);
+        ret.extend_from_slice(&(len as u16).to_be_bytes());
+        ret.extend_from_slice(&addresses);
+        ret.extend_from_slice(&self.source.port().to_be_bytes());
+        ret.extend_from_slice(&self.destination.port().to_be_bytes());
+        ret
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let header = match data.first_chunk::<HEADER_LEN>()
+        {
+            Some(header) => header,
+            None => return Err(Error::UnexpectedEof { needed: HEADER_LEN, available: data.len() }.into()),
+        };
+        if header[..12] != SIGNATURE
+        {
+            return Err(invalid("no PROXY protocol signature".to_string()));
+        }
+        if header[12] != VERSION_COMMAND_PROXY
+        {
+            return Err(error_at_offset(invalid(format!("unsupported version and command {:#04x}", header[12])), 12));
+        }
+        let len = u16::from_be_bytes([header[14], header[15]]) as usize;
+        let block = match data.get(HEADER_LEN..HEADER_LEN + len)
+        {
+            Some(block) => block,
+            None => return Err(Error::UnexpectedEof { needed: HEADER_LEN + len, available: data.len() }.into()),
+        };
+        let ip_len = match header[13]
+        {
+            TCP_OVER_IPV4 => 4,
+            TCP_OVER_IPV6 => 16,
+            family => return Err(error_at_offset(invalid(format!("unsupported address family {family:#04x}")), 13)),
+        };
+        if block.len() < ip_len * 2 + 4
+        {
+            return Err(error_at_offset(invalid(format!("{} address bytes are too few for the address family", block.len())), 14));
+        }
+        let ip = |octets: &[u8]| -> IpAddr {
+            match octets.len()
+            {
+                4 => IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(octets).expect("Slice has 4 bytes"))),
+                _ => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(octets).expect("Slice has 16 bytes"))),
+            }
+        };
+        let ports = &block[ip_len * 2..];
+        let source = SocketAddr::new(ip(&block[..ip_len]), u16::from_be_bytes([ports[0], ports[1]]));
+        let destination = SocketAddr::new(ip(&block[ip_len..ip_len * 2]), u16::from_be_bytes([ports[2], ports[3]]));
+        Ok((Self { source, destination }, HEADER_LEN + len))
+    }
+}
+
+fn invalid(reason: String) -> std::io::Error
+{
+    Error::InvalidValue { type_name: "ProxyProtocolV2Addr", reason }.into()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::net::SocketAddr;
+
+    use super::{ProxyProtocolV2Addr, SIGNATURE};
+    use crate::Serializable;
+
+    #[test]
+    fn serialize_known_ipv4_header()
+    {
+        let addr = ProxyProtocolV2Addr::new("127.0.0.1:56324".parse().unwrap(), "127.0.0.1:443".parse().unwrap()).unwrap();
+        let mut expected = SIGNATURE.to_vec();
+        expected.extend_from_slice(&[0x21, 0x11, 0x00, 0x0C]);
+        expected.extend_from_slice(&[0x7F, 0x00, 0x00, 0x01, 0x7F, 0x00, 0x00, 0x01]);
+        expected.extend_from_slice(&[0xDC, 0x04, 0x01, 0xBB]);
+        assert_eq!(addr.serialize(), expected);
+        assert_eq!(ProxyProtocolV2Addr::deserialize(&expected).unwrap(), (addr, 28));
+    }
+
+    #[test]
+    fn serialize_known_ipv6_header()
+    {
+        let source: SocketAddr = "[2001:db8::1]:1234".parse().unwrap();
+        let destination: SocketAddr = "[2001:db8::2]:80".parse().unwrap();
+        let addr = ProxyProtocolV2Addr::new(source, destination).unwrap();
+        let mut expected = SIGNATURE.to_vec();
+        expected.extend_from_slice(&[0x21, 0x21, 0x00, 0x24]);
+        expected.extend_from_slice(&[0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x01]);
+        expected.extend_from_slice(&[0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02]);
+        expected.extend_from_slice(&[0x04, 0xD2, 0x00, 0x50]);
+        assert_eq!(addr.serialize(), expected);
+        assert_eq!(ProxyProtocolV2Addr::deserialize(&expected).unwrap(), (addr, 52));
+    }
+
+    #[test]
+    fn deserialize_header_with_extensions()
+    {
+        // HAProxy appends a PP2_TYPE_ALPN extension announcing "h2"
+        let mut header = SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x21, 0x11, 0x00, 0x11]);
+        header.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0x30, 0x39, 0x00, 0x50]);
+        header.extend_from_slice(&[0x01, 0x00, 0x02, b'h', b'2']);
+        header.extend_from_slice(b"GET /");
+        let (addr, len) = ProxyProtocolV2Addr::deserialize(&header).unwrap();
+        assert_eq!(addr.source(), "10.0.0.1:12345".parse().unwrap());
+        assert_eq!(addr.destination(), "10.0.0.2:80".parse().unwrap());
+        assert_eq!(&header[len..], b"GET /");
+    }
+
+    #[test]
+    fn reject_invalid_headers()
+    {
+        assert!(ProxyProtocolV2Addr::new("127.0.0.1:1".parse().unwrap(), "[::1]:1".parse().unwrap()).is_none());
+        let valid = ProxyProtocolV2Addr::new("127.0.0.1:1".parse().unwrap(), "127.0.0.1:2".parse().unwrap()).unwrap().serialize();
+        for (index, value) in [(0, 0x0E), (12, 0x20), (12, 0x11), (13, 0x12), (15, 0x08)]
+        {
+            let mut invalid = valid.clone();
+            invalid[index] = value;
+            assert_eq!(ProxyProtocolV2Addr::deserialize(&invalid).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        }
+        assert!(ProxyProtocolV2Addr::deserialize(&valid[..27]).is_err());
+    }
+}
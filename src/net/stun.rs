@@ -0,0 +1,158 @@
+//! Addresses in the XOR-MAPPED-ADDRESS attribute of STUN (RFC 5389),
+//! the public address a STUN server reports back to a client behind a NAT.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::serializable::error_at_offset;
+use crate::{Error, Serializable};
+
+/// Constant that every STUN message carries and that the addresses are XOR-ed with
+pub const MAGIC_COOKIE: u32 = 0x2112A442;
+
+/// Address family of IPv4 addresses
+const FAMILY_IPV4: u8 = 0x01;
+/// Address family of IPv6 addresses
+const FAMILY_IPV6: u8 = 0x02;
+
+/// A socket address encoded as the value of a STUN XOR-MAPPED-ADDRESS attribute:
+/// a zero byte, the address family, the port XOR-ed with the 16 most significant bits
+/// of the [`MAGIC_COOKIE`] and the address XOR-ed with the cookie.
+///
+/// IPv6 addresses are XOR-ed with the cookie followed by the 12 byte transaction ID of the message,
+/// which [`Serializable`] takes as all zeros. Use [`StunAddr::serialize_with_transaction_id`]
+/// and [`StunAddr::deserialize_with_transaction_id`] for the addresses of real messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StunAddr(pub SocketAddr);
+
+impl StunAddr
+{
+    /// Returns the attribute value for a message with the given transaction ID
+    pub fn serialize_with_transaction_id(&self, transaction_id: &[u8; 12]) -> Vec<u8>
+    {
+        let mask = Self::mask(transaction_id);
+        let mut ret = vec![0];
+        match self.0.ip()
+        {
+            IpAddr::V4(_) => ret.push(FAMILY_IPV4),
+            IpAddr::V6(_) => ret.push(FAMILY_IPV6),
+        }
+        ret.extend_from_slice(&(self.0.port() ^ (MAGIC_COOKIE >> 16) as u16).to_be_bytes());
+        let octets = match self.0.ip()
+        {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        ret.extend(octets.iter().zip(mask).map(|(octet, mask)| octet ^ mask));
+        ret
+    }
+
+    /// Returns the address in an attribute value of a message with the given transaction ID
+    /// and the number of bytes read
+    pub fn deserialize_with_transaction_id(data: &[u8], transaction_id: &[u8; 12]) -> std::io::Result<(Self,usize)>
+    {
+        let len = match data.get(1)
+        {
+            Some(&FAMILY_IPV4) => 4,
+            Some(&FAMILY_IPV6) => 16,
+            Some(&family) => {
+                let error = Error::InvalidValue { type_name: "StunAddr", reason: format!("unknown address family {family:#04x}") };
+                return Err(error_at_offset(error.into(), 1));
+            },
+            None => return Err(Error::UnexpectedEof { needed: 2, available: data.len() }.into()),
+        };
+        let (port, octets) = match data.get(2..4 + len)
+        {
+            Some(rest) => rest.split_at(2),
+            None => return Err(Error::UnexpectedEof { needed: 4 + len, available: data.len() }.into()),
+        };
+        let port = u16::from_be_bytes([port[0], port[1]]) ^ (MAGIC_COOKIE >> 16) as u16;
+        let mask = Self::mask(transaction_id);
+        let mut ip = [0; 16];
+        for (i, octet) in octets.iter().enumerate()
+        {
+            ip[i] = octet ^ mask[i];
+        }
+        let ip = match len
+        {
+            4 => IpAddr::V4(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])),
+            _ => IpAddr::V6(Ipv6Addr::from(ip)),
+        };
+        Ok((Self(SocketAddr::new(ip, port)), 4 + len))
+    }
+
+    /// Returns the bytes the address is XOR-ed with, IPv4 addresses only use the first 4
+    fn mask(transaction_id: &[u8; 12]) -> [u8; 16]
+    {
+        let mut ret = [0; 16];
+        ret[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        ret[4..].copy_from_slice(transaction_id);
+        ret
+    }
+}
+
+impl Serializable for StunAddr
+{
+    fn serialize(&self) -> Vec<u8> {
+        self.serialize_with_transaction_id(&[0; 12])
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        Self::deserialize_with_transaction_id(data, &[0; 12])
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::net::SocketAddr;
+
+    use super::StunAddr;
+    use crate::Serializable;
+
+    /// Transaction ID of the sample response messages of RFC 5769
+    const TRANSACTION_ID: [u8; 12] = [0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae];
+
+    #[test]
+    fn encode_ipv4_sample_response()
+    {
+        // XOR-MAPPED-ADDRESS of the IPv4 response in RFC 5769 section 2.2
+        let expected = [0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43];
+        let addr = StunAddr("192.0.2.1:32853".parse().unwrap());
+        assert_eq!(addr.serialize_with_transaction_id(&TRANSACTION_ID), expected);
+        // IPv4 addresses don't depend on the transaction ID
+        assert_eq!(addr.serialize(), expected);
+        let (deserialized, bytes_read) = StunAddr::deserialize(&expected).unwrap();
+        assert_eq!(deserialized, addr);
+        assert_eq!(bytes_read, expected.len());
+    }
+
+    #[test]
+    fn encode_ipv6_sample_response()
+    {
+        // XOR-MAPPED-ADDRESS of the IPv6 response in RFC 5769 section 2.3
+        let expected = [
+            0x00, 0x02, 0xa1, 0x47,
+            0x01, 0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79, 0xbc, 0x25, 0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9,
+        ];
+        let addr = StunAddr("[2001:db8:1234:5678:11:2233:4455:6677]:32853".parse().unwrap());
+        assert_eq!(addr.serialize_with_transaction_id(&TRANSACTION_ID), expected);
+        let (deserialized, bytes_read) = StunAddr::deserialize_with_transaction_id(&expected, &TRANSACTION_ID).unwrap();
+        assert_eq!(deserialized, addr);
+        assert_eq!(bytes_read, expected.len());
+
+        let serialized = addr.serialize();
+        assert_ne!(serialized, expected);
+        assert_eq!(StunAddr::deserialize_exact(&serialized).unwrap(), addr);
+    }
+
+    #[test]
+    fn reject_invalid_attributes()
+    {
+        assert!(StunAddr::deserialize(&[0x00, 0x03, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]).is_err());
+        assert!(StunAddr::deserialize(&[0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6]).is_err());
+        assert!(StunAddr::deserialize(&[0x00, 0x02, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43]).is_err());
+        assert!(StunAddr::deserialize(&[0x00]).is_err());
+        let addr: SocketAddr = "10.0.0.1:3478".parse().unwrap();
+        assert_eq!(StunAddr::deserialize(&StunAddr(addr).serialize()).unwrap().0, StunAddr(addr));
+    }
+}
@@ -0,0 +1,103 @@
+//! Helpers to exchange serialized values as single UDP datagrams.
+//!
+//! A value is never split across datagrams, values whose serialization
+//! doesn't fit are rejected instead of being truncated.
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use crate::Serializable;
+
+/// Largest payload of a UDP datagram over IPv4
+pub const MAX_DATAGRAM_LEN: usize = 65507;
+
+/// Sends the serialization of `value` to `addr` as a single datagram,
+/// returning the number of bytes sent
+pub fn send_to_value<S: Serializable, A: ToSocketAddrs>(socket: &UdpSocket, value: &S, addr: A) -> std::io::Result<usize>
+{
+    send_to_value_limited(socket, value, addr, MAX_DATAGRAM_LEN)
+}
+
+/// Like [`send_to_value`], but fails if the serialization is longer than `max_len` bytes,
+/// which is useful to stay below the MTU of the path
+pub fn send_to_value_limited<S: Serializable, A: ToSocketAddrs>(socket: &UdpSocket, value: &S, addr: A, max_len: usize) -> std::io::Result<usize>
+{
+    let serialized = value.serialize();
+    if serialized.len() > max_len
+    {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Serialized value does not fit in a datagram"));
+    }
+    let sent = socket.send_to(&serialized, addr)?;
+    crate::trace::written(sent);
+    Ok(sent)
+}
+
+/// Receives a single datagram of at most `buf_size` bytes and deserializes it,
+/// returning the value and the address of the sender.
+/// Longer datagrams and datagrams with bytes after the value are rejected.
+pub fn recv_value_from<S: Serializable>(socket: &UdpSocket, buf_size: usize) -> std::io::Result<(S, SocketAddr)>
+{
+    // One more byte than allowed, so that a datagram truncated by the OS can be detected
+    let mut buffer = vec![0; buf_size + 1];
+    let (len, addr) = socket.recv_from(&mut buffer)?;
+    if len > buf_size
+    {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Datagram too long"));
+    }
+    crate::trace::read(len);
+    Ok((crate::trace::read_value(S::deserialize_exact(&buffer[..len]))?, addr))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::net::UdpSocket;
+
+    use super::{recv_value_from, send_to_value, send_to_value_limited};
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub struct Announcement
+    {
+        name: String,
+        port: u16,
+        services: Vec<String>,
+    }
+
+    fn socket_pair() -> (UdpSocket, UdpSocket)
+    {
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let receiver = UdpSocket::bind("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+        (sender, receiver)
+    }
+
+    #[test]
+    fn send_and_receive_value()
+    {
+        let (sender, receiver) = socket_pair();
+        let announcement = Announcement { name: "node-1".to_string(), port: 8080, services: vec!["http".to_string(), "metrics".to_string()] };
+        let sent = send_to_value(&sender, &announcement, receiver.local_addr().unwrap()).unwrap();
+        assert_eq!(sent, announcement.serialize().len());
+        let (received, addr) = recv_value_from::<Announcement>(&receiver, 1500).unwrap();
+        assert_eq!(received, announcement);
+        assert_eq!(addr, sender.local_addr().unwrap());
+    }
+
+    #[test]
+    fn reject_values_that_do_not_fit()
+    {
+        let (sender, receiver) = socket_pair();
+        let announcement = Announcement { name: "x".repeat(2000), port: 1, services: Vec::new() };
+        let error = send_to_value_limited(&sender, &announcement, receiver.local_addr().unwrap(), 1500).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        send_to_value(&sender, &announcement, receiver.local_addr().unwrap()).unwrap();
+        assert_eq!(recv_value_from::<Announcement>(&receiver, 1500).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        let mut trailing = 7u32.serialize();
+        trailing.push(0);
+        sender.send_to(&trailing, receiver.local_addr().unwrap()).unwrap();
+        assert_eq!(recv_value_from::<u32>(&receiver, 1500).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,137 @@
+//! Helpers to exchange serialized values as single Unix datagrams,
+//! mirroring the [`udp`](super::udp) helpers.
+//!
+//! Unix streams need no special support:
+//! [`SerializableStreamExt`](super::SerializableStreamExt) works with any `Read + Write` stream.
+
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::Path;
+
+use crate::Serializable;
+
+/// Default limit on the length of a serialized value sent as a datagram
+pub const MAX_DATAGRAM_LEN: usize = super::udp::MAX_DATAGRAM_LEN;
+
+fn serialize_limited<S: Serializable>(value: &S, max_len: usize) -> std::io::Result<Vec<u8>>
+{
+    let serialized = value.serialize();
+    if serialized.len() > max_len
+    {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Serialized value does not fit in a datagram"));
+    }
+    Ok(serialized)
+}
+
+fn check_received_len(len: usize, buf_size: usize) -> std::io::Result<()>
+{
+    if len > buf_size
+    {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Datagram too long"));
+    }
+    Ok(())
+}
+
+/// Sends the serialization of `value` to the socket bound at `path` as a single datagram,
+/// returning the number of bytes sent
+pub fn send_to_value<S: Serializable, P: AsRef<Path>>(socket: &UnixDatagram, value: &S, path: P) -> std::io::Result<usize>
+{
+    send_to_value_limited(socket, value, path, MAX_DATAGRAM_LEN)
+}
+
+/// Like [`send_to_value`], but fails if the serialization is longer than `max_len` bytes
+pub fn send_to_value_limited<S: Serializable, P: AsRef<Path>>(socket: &UnixDatagram, value: &S, path: P, max_len: usize) -> std::io::Result<usize>
+{
+    let sent = socket.send_to(&serialize_limited(value, max_len)?, path)?;
+    crate::trace::written(sent);
+    Ok(sent)
+}
+
+/// Sends the serialization of `value` as a single datagram to the peer of a connected socket,
+/// failing if it is longer than `max_len` bytes
+pub fn send_value<S: Serializable>(socket: &UnixDatagram, value: &S, max_len: usize) -> std::io::Result<usize>
+{
+    let sent = socket.send(&serialize_limited(value, max_len)?)?;
+    crate::trace::written(sent);
+    Ok(sent)
+}
+
+/// Receives a single datagram of at most `buf_size` bytes and deserializes it,
+/// returning the value and the address of the sender.
+/// Longer datagrams and datagrams with bytes after the value are rejected.
+pub fn recv_value_from<S: Serializable>(socket: &UnixDatagram, buf_size: usize) -> std::io::Result<(S, SocketAddr)>
+{
+    // One more byte than allowed, so that a datagram truncated by the OS can be detected
+    let mut buffer = vec![0; buf_size + 1];
+    let (len, addr) = socket.recv_from(&mut buffer)?;
+    check_received_len(len, buf_size)?;
+    crate::trace::read(len);
+    Ok((crate::trace::read_value(S::deserialize_exact(&buffer[..len]))?, addr))
+}
+
+/// Like [`recv_value_from`], for connected sockets
+pub fn recv_value<S: Serializable>(socket: &UnixDatagram, buf_size: usize) -> std::io::Result<S>
+{
+    let mut buffer = vec![0; buf_size + 1];
+    let len = socket.recv(&mut buffer)?;
+    check_received_len(len, buf_size)?;
+    crate::trace::read(len);
+    crate::trace::read_value(S::deserialize_exact(&buffer[..len]))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::os::unix::net::{UnixDatagram, UnixStream};
+
+    use super::{recv_value, recv_value_from, send_to_value, send_value};
+    use crate as serializable;
+    use crate::net::SerializableStreamExt;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub struct Heartbeat
+    {
+        pid: u32,
+        uptime: u64,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub enum Command
+    {
+        Reload { config: String },
+        Shutdown,
+    }
+
+    #[test]
+    fn exchange_values_over_unix_stream()
+    {
+        let (mut parent, mut child) = UnixStream::pair().unwrap();
+        parent.send_value(&Command::Reload { config: "/etc/app.conf".to_string() }).unwrap();
+        parent.send_value(&Command::Shutdown).unwrap();
+        assert_eq!(child.recv_value::<Command>(1024).unwrap(), Command::Reload { config: "/etc/app.conf".to_string() });
+        assert_eq!(child.recv_value::<Command>(1024).unwrap(), Command::Shutdown);
+        child.send_value(&Heartbeat { pid: 42, uptime: 3600 }).unwrap();
+        assert_eq!(parent.recv_value::<Heartbeat>(1024).unwrap(), Heartbeat { pid: 42, uptime: 3600 });
+    }
+
+    #[test]
+    fn exchange_values_over_unix_datagrams()
+    {
+        let (parent, child) = UnixDatagram::pair().unwrap();
+        send_value(&parent, &Heartbeat { pid: 7, uptime: 1 }, 64).unwrap();
+        assert_eq!(recv_value::<Heartbeat>(&child, 64).unwrap(), Heartbeat { pid: 7, uptime: 1 });
+        let command = Command::Reload { config: "x".repeat(100) };
+        assert_eq!(send_value(&parent, &command, 64).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        send_value(&parent, &command, 1024).unwrap();
+        assert_eq!(recv_value::<Command>(&child, 64).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("server.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+        let client = UnixDatagram::bind(dir.path().join("client.sock")).unwrap();
+        send_to_value(&client, &Command::Shutdown, &path).unwrap();
+        let (received, addr) = recv_value_from::<Command>(&server, 64).unwrap();
+        assert_eq!(received, Command::Shutdown);
+        assert_eq!(addr.as_pathname(), Some(dir.path().join("client.sock").as_path()));
+    }
+}
@@ -0,0 +1,91 @@
+//! Parallel serialization of large sequences on the rayon thread pool.
+//!
+//! The output is byte for byte the serialization of a [`Vec`] of the same items.
+
+use rayon::prelude::*;
+
+use crate::serializable::length_prefix;
+use crate::{Error, FixedSize, Serializable};
+
+/// Number of items serialized by each rayon task
+const CHUNK_LEN: usize = 1024;
+
+/// Returns the same bytes as serializing `items` as a [`Vec`],
+/// encoding chunks of items in parallel and concatenating them in order
+pub fn serialize_par<T: Serializable + Sync>(items: &[T]) -> Vec<u8>
+{
+    let chunks: Vec<Vec<u8>> = items.par_chunks(CHUNK_LEN).map(|chunk| {
+        let mut out = Vec::new();
+        T::serialize_slice(chunk, &mut out);
+        out
+    }).collect();
+    let mut ret = Vec::with_capacity(4 + chunks.iter().map(Vec::len).sum::<usize>());
+    ret.extend_from_slice(&length_prefix(items.len()));
+    for chunk in chunks
+    {
+        ret.extend_from_slice(&chunk);
+    }
+    ret
+}
+
+/// Deserializes a [`Vec`] of fixed size items in parallel, returning it and the number of bytes read.
+/// Since every item has the same size their offsets are known before parsing them.
+pub fn deserialize_par<T: FixedSize + Send>(data: &[u8]) -> std::io::Result<(Vec<T>,usize)>
+{
+    if T::SIZE == 0
+    {
+        return Vec::<T>::deserialize(data);
+    }
+    let (len, read) = u32::deserialize(data)?;
+    let end = match (len as usize).checked_mul(T::SIZE).and_then(|size| size.checked_add(read))
+    {
+        Some(end) if end <= data.len() => end,
+        _ => return Err(Error::UnexpectedEof { needed: (len as usize).saturating_mul(T::SIZE).saturating_add(read), available: data.len() }.into()),
+    };
+    let ret = data[read..end].par_chunks_exact(T::SIZE)
+        .map(|chunk| T::deserialize(chunk).map(|(item, _)| item))
+        .collect::<std::io::Result<Vec<T>>>()?;
+    Ok((ret, end))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate as serializable;
+    use super::{deserialize_par, serialize_par};
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    pub struct Record
+    {
+        id: u64,
+        value: f32,
+        flag: u8,
+    }
+
+    #[test]
+    fn serialize_par_matches_sequential()
+    {
+        let records: Vec<Record> = (0..10_000).map(|i| Record { id: i, value: i as f32 / 3.0, flag: (i % 7) as u8 }).collect();
+        let serialized = serialize_par(&records);
+        assert_eq!(serialized, records.serialize());
+        let (deserialized, bytes_read) = deserialize_par::<Record>(&serialized).unwrap();
+        assert_eq!(records, deserialized);
+        assert_eq!(serialized.len(), bytes_read);
+        assert!(deserialize_par::<Record>(&serialized[..serialized.len() - 1]).is_err());
+
+        let strings: Vec<String> = (0..5000).map(|i| format!("item {i}")).collect();
+        assert_eq!(serialize_par(&strings), strings.serialize());
+    }
+
+    #[test]
+    fn serialize_par_empty_and_single()
+    {
+        let empty: Vec<u32> = Vec::new();
+        assert_eq!(serialize_par(&empty), empty.serialize());
+        assert_eq!(deserialize_par::<u32>(&empty.serialize()).unwrap(), (empty, 4));
+        let single = vec![Record { id: 1, value: 2.0, flag: 3 }];
+        assert_eq!(serialize_par(&single), single.serialize());
+        assert_eq!(deserialize_par::<Record>(&single.serialize()).unwrap(), (single, 17));
+    }
+}
@@ -0,0 +1,191 @@
+//! Partial updates of structs, with `#[serializable(patch)]`.
+//!
+//! The attribute makes the derive emit a companion struct named like the struct followed by `Patch`,
+//! with an `Option` for every field, and implement [`Patchable`] for the struct. [`Patchable::diff`]
+//! returns the fields that changed between two values, and [`Patchable::apply`] sets them on a copy
+//! of the original, so that only the changed fields need to be sent to keep a copy in sync.
+//!
+//! A patch is written as a bitmap with a bit for every field, the first field in the lowest bit of the
+//! first byte, followed by the values of the fields that are present, in order. A field marked with
+//! `#[serializable(patch)]` holds the patch of its own type instead of a whole new value, and is only
+//! present if something in it changed. The other fields must implement `Clone` and `PartialEq`.
+//!
+//! ```
+//! use serializable::patch::Patchable;
+//! use serializable::Serializable;
+//!
+//! #[derive(Serializable, Clone, Debug, PartialEq)]
+//! #[serializable(patch)]
+//! struct Counter
+//! {
+//!     name: String,
+//!     count: u32,
+//! }
+//!
+//! let old = Counter { name: "visits".to_string(), count: 41 };
+//! let new = Counter { name: "visits".to_string(), count: 42 };
+//! let patch = old.diff(&new).serialize();
+//! assert_eq!(patch, [0b10, 0, 0, 0, 42]);
+//!
+//! let mut copy = old.clone();
+//! copy.apply(CounterPatch::deserialize_exact(&patch).unwrap());
+//! assert_eq!(copy, new);
+//! ```
+
+use crate::serializable::error_at_offset;
+use crate::{Error, Serializable};
+
+/// A struct whose changes can be stored in a patch, implemented by `#[serializable(patch)]`
+pub trait Patchable
+{
+    /// The changes to a value, with an `Option` for every field
+    type Patch: Serializable;
+
+    /// Returns the changes that turn `self` into `other`
+    fn diff(&self, other: &Self) -> Self::Patch;
+
+    /// Sets the fields that are present in `patch`
+    fn apply(&mut self, patch: Self::Patch);
+
+    /// Returns `true` if `patch` changes nothing
+    fn is_unchanged(patch: &Self::Patch) -> bool;
+}
+
+/// Marks the field `index` as present in the bitmap at the start of `bytes`, used by derived code
+#[doc(hidden)]
+pub fn mark_present(bytes: &mut [u8], index: usize)
+{
+    bytes[index / 8] |= 1 << (index % 8);
+}
+
+/// Returns `true` if the field `index` is present in `bitmap`, used by derived code
+#[doc(hidden)]
+pub fn is_present(bitmap: &[u8], index: usize) -> bool
+{
+    bitmap[index / 8] & (1 << (index % 8)) != 0
+}
+
+/// Returns the bitmap of a patch of a struct with `fields` fields at the start of `data`,
+/// failing if it is truncated or marks fields that the struct doesn't have, used by derived code
+#[doc(hidden)]
+pub fn read_bitmap(data: &[u8], fields: usize) -> std::io::Result<&[u8]>
+{
+    let bitmap = match data.get(..fields.div_ceil(8))
+    {
+        Some(bitmap) => bitmap,
+        None => return Err(Error::UnexpectedEof { needed: fields.div_ceil(8), available: data.len() }.into()),
+    };
+    if let Some(last) = bitmap.last()
+    {
+        if !fields.is_multiple_of(8) && last >> (fields % 8) != 0
+        {
+            let error = Error::InvalidValue { type_name: "patch bitmap", reason: format!("fields past the {fields} of the struct are marked") };
+            return Err(error_at_offset(error.into(), bitmap.len() - 1));
+        }
+    }
+    Ok(bitmap)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::Patchable;
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Clone, Debug, PartialEq)]
+    #[serializable(patch)]
+    struct Position
+    {
+        x: i32,
+        y: i32,
+    }
+
+    #[derive(Serializable, Clone, Debug, PartialEq)]
+    #[serializable(patch)]
+    struct Player
+    {
+        name: String,
+        #[serializable(patch)]
+        position: Position,
+        inventory: Vec<String>,
+        score: u32,
+    }
+
+    #[derive(Serializable, Clone, Debug, PartialEq)]
+    #[serializable(patch)]
+    struct Pair<T>(T, T);
+
+    fn player() -> Player
+    {
+        Player {
+            name: "ada".to_string(),
+            position: Position { x: 10, y: -4 },
+            inventory: vec!["lamp".to_string()],
+            score: 120,
+        }
+    }
+
+    #[test]
+    fn diff_and_apply()
+    {
+        let old = player();
+        let mut new = player();
+        new.position.y = 3;
+        new.inventory.push("rope".to_string());
+        let patch = old.diff(&new);
+        assert!(patch.name.is_none() && patch.score.is_none());
+        let position = patch.position.as_ref().unwrap();
+        assert!(position.x.is_none());
+        assert_eq!(position.y, Some(3));
+
+        let serialized = patch.serialize();
+        let (deserialized, len) = PlayerPatch::deserialize(&serialized).unwrap();
+        assert_eq!(len, serialized.len());
+        let mut copy = old.clone();
+        copy.apply(deserialized);
+        assert_eq!(copy, new);
+    }
+
+    #[test]
+    fn compact_encoding()
+    {
+        let old = player();
+        let mut new = player();
+        new.score = 121;
+        new.position.y = 0;
+        // position and score, then y in the nested patch
+        assert_eq!(old.diff(&new).serialize(), [0b1010, 0b10, 0, 0, 0, 0, 0, 0, 0, 121]);
+        // Nothing changed, only the bitmap is written
+        let unchanged = old.diff(&old);
+        assert!(Player::is_unchanged(&unchanged));
+        assert_eq!(unchanged.serialize(), [0]);
+        let mut copy = old.clone();
+        copy.apply(PlayerPatch::deserialize_exact(&[0]).unwrap());
+        assert_eq!(copy, old);
+    }
+
+    #[test]
+    fn invalid_patches()
+    {
+        // The fifth bit names a field that Player doesn't have
+        let error = PlayerPatch::deserialize(&[0b10000]).err().unwrap();
+        assert!(matches!(crate::Error::find(&error), Some(crate::Error::InvalidValue { type_name: "patch bitmap", .. })), "{error}");
+        assert_eq!(crate::DeserializeError::find(&error).unwrap().type_name(), Some("PlayerPatch"));
+        assert!(PlayerPatch::deserialize(&[]).is_err());
+        // The score is marked as present but missing
+        assert!(PlayerPatch::deserialize(&[0b1000, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn tuple_struct()
+    {
+        let old = Pair("left".to_string(), "right".to_string());
+        let new = Pair("left".to_string(), "center".to_string());
+        let serialized = old.diff(&new).serialize();
+        let mut copy = old.clone();
+        copy.apply(PairPatch::<String>::deserialize_exact(&serialized).unwrap());
+        assert_eq!(copy, new);
+        assert!(PairPatch::<String>::default().1.is_none());
+    }
+}
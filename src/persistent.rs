@@ -0,0 +1,324 @@
+//! State that is loaded from a file at startup and saved back while the program runs.
+//!
+//! [`Persistent`] keeps the serialization of a value in a file, which is replaced with
+//! [`write_atomic`] so that a crash in the middle of a save leaves the previous state on the disk.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::envelope::Envelope;
+use crate::Serializable;
+
+/// Replaces the content of the file at `path` with `data`, so that the file holds either
+/// the old or the new content even if the program or the system crashes.
+///
+/// The data is written to a temporary file next to `path`, stored on the disk and renamed over `path`.
+pub fn write_atomic(path: impl AsRef<Path>, data: &[u8]) -> std::io::Result<()>
+{
+    let path = path.as_ref();
+    let file_name = match path.file_name()
+    {
+        Some(file_name) => file_name,
+        None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "The path has no file name")),
+    };
+    let mut tmp_name = file_name.to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    let result = (|| {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)
+    })();
+    if result.is_err()
+    {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result?;
+    sync_parent_dir(path)
+}
+
+/// Stores on the disk the directory holding `path`, which makes a rename to `path` durable
+pub(crate) fn sync_parent_dir(path: &Path) -> std::io::Result<()>
+{
+    #[cfg(unix)]
+    {
+        let dir = match path.parent()
+        {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// The last error of a [`Persistent`] value that could not be returned to the caller,
+/// like a failed autosave or a failed save on drop.
+///
+/// It can be cloned and kept after the value is dropped, to check how the last save went.
+#[derive(Debug, Clone, Default)]
+pub struct LastError(Arc<Mutex<Option<std::io::Error>>>);
+
+impl LastError
+{
+    /// Returns the last error, if any, and clears it
+    pub fn take(&self) -> Option<std::io::Error>
+    {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).take()
+    }
+
+    fn set(&self, error: std::io::Error)
+    {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(error);
+    }
+}
+
+/// A value stored in a file, that is read when opened and written back by [`Persistent::save`],
+/// and optionally after a number of mutations and when dropped.
+pub struct Persistent<T: Serializable + Default>
+{
+    path: PathBuf,
+    value: T,
+    dirty: bool,
+    mutations: usize,
+    autosave_every: Option<usize>,
+    save_on_drop: bool,
+    enveloped: bool,
+    last_error: LastError,
+}
+
+impl<T: Serializable + Default> Persistent<T>
+{
+    /// Reads the value stored at `path`, or uses `T::default()` if the file does not exist yet.
+    /// Fails if the file cannot be read or doesn't hold exactly a `T`.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self>
+    {
+        Self::open_with(path.as_ref(), false)
+    }
+
+    /// Like [`Persistent::open`], but the file holds the value in an [`Envelope`],
+    /// so that a file written by another program or for another type is rejected instead of misread
+    pub fn open_enveloped(path: impl AsRef<Path>) -> std::io::Result<Self>
+    {
+        Self::open_with(path.as_ref(), true)
+    }
+
+    fn open_with(path: &Path, enveloped: bool) -> std::io::Result<Self>
+    {
+        let value = match std::fs::read(path)
+        {
+            Ok(data) if enveloped => Envelope::<T>::open(&data)?,
+            Ok(data) => T::deserialize_exact(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => T::default(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            value,
+            dirty: false,
+            mutations: 0,
+            autosave_every: None,
+            save_on_drop: false,
+            enveloped,
+            last_error: LastError::default(),
+        })
+    }
+
+    /// Saves the value when it is dropped, if it was changed since the last save.
+    /// A failed save is stored in [`Persistent::last_error`].
+    pub fn with_save_on_drop(mut self, save_on_drop: bool) -> Self
+    {
+        self.save_on_drop = save_on_drop;
+        self
+    }
+
+    /// Saves the value once `mutations` calls to [`Persistent::get_mut`] have piled up since the last save.
+    /// The save happens at the next call, before handing out the reference, so that the changes made
+    /// through the previous references are complete. A failed save is stored in [`Persistent::last_error`].
+    pub fn with_autosave_every(mut self, mutations: usize) -> Self
+    {
+        self.autosave_every = Some(mutations.max(1));
+        self
+    }
+
+    /// Returns the value
+    pub fn get(&self) -> &T
+    {
+        &self.value
+    }
+
+    /// Returns the value to change it, marking it as changed
+    pub fn get_mut(&mut self) -> &mut T
+    {
+        if self.autosave_every.is_some_and(|every| self.mutations >= every)
+        {
+            if let Err(e) = self.save()
+            {
+                self.last_error.set(e);
+            }
+        }
+        self.dirty = true;
+        self.mutations += 1;
+        &mut self.value
+    }
+
+    /// Returns `true` if the value was changed since it was last saved or read
+    pub fn is_dirty(&self) -> bool
+    {
+        self.dirty
+    }
+
+    /// Writes the value to its file with [`write_atomic`], in an [`Envelope`] if it was opened with [`Persistent::open_enveloped`]
+    pub fn save(&mut self) -> std::io::Result<()>
+    {
+        let data = if self.enveloped { Envelope::wrap(&self.value) } else { self.value.serialize() };
+        write_atomic(&self.path, &data)?;
+        self.dirty = false;
+        self.mutations = 0;
+        Ok(())
+    }
+
+    /// Returns the path of the file holding the value
+    pub fn path(&self) -> &Path
+    {
+        &self.path
+    }
+
+    /// Returns the last error of a save that happened on its own, which is still available after the value is dropped
+    pub fn last_error(&self) -> LastError
+    {
+        self.last_error.clone()
+    }
+}
+
+impl<T: Serializable + Default> Drop for Persistent<T>
+{
+    fn drop(&mut self)
+    {
+        if self.save_on_drop && self.dirty
+        {
+            if let Err(e) = self.save()
+            {
+                self.last_error.set(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{write_atomic, Persistent};
+    use crate::envelope::MAGIC;
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, Default, PartialEq)]
+    struct Settings
+    {
+        runs: u32,
+        recent_files: Vec<String>,
+    }
+
+    #[test]
+    fn default_on_first_run()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.bin");
+        let settings = Persistent::<Settings>::open(&path).unwrap();
+        assert_eq!(*settings.get(), Settings::default());
+        assert!(!settings.is_dirty());
+        drop(settings);
+        // Nothing was changed, nothing is written
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn mutate_and_reopen()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.bin");
+        let mut settings = Persistent::<Settings>::open(&path).unwrap();
+        settings.get_mut().runs += 1;
+        settings.get_mut().recent_files.push("notes.txt".to_string());
+        assert!(settings.is_dirty());
+        settings.save().unwrap();
+        assert!(!settings.is_dirty());
+        drop(settings);
+
+        let mut settings = Persistent::<Settings>::open(&path).unwrap().with_save_on_drop(true);
+        assert_eq!(settings.get().runs, 1);
+        assert_eq!(settings.get().recent_files, ["notes.txt"]);
+        settings.get_mut().runs += 1;
+        drop(settings);
+        assert_eq!(Persistent::<Settings>::open(&path).unwrap().get().runs, 2);
+        // No temporary file is left behind
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn autosave_after_mutations()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.bin");
+        let mut settings = Persistent::<Settings>::open(&path).unwrap().with_autosave_every(3);
+        for _ in 0..3
+        {
+            settings.get_mut().runs += 1;
+        }
+        assert!(!path.exists());
+        // The fourth mutation saves the first three
+        settings.get_mut().runs += 1;
+        assert_eq!(Persistent::<Settings>::open(&path).unwrap().get().runs, 3);
+        assert!(settings.is_dirty());
+    }
+
+    #[test]
+    fn failed_save_is_reported()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing").join("settings.bin");
+        let mut settings = Persistent::<Settings>::open(&path).unwrap().with_save_on_drop(true);
+        settings.get_mut().runs = 7;
+        assert_eq!(settings.save().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        assert!(settings.is_dirty());
+        let last_error = settings.last_error();
+        assert!(last_error.take().is_none());
+        drop(settings);
+        assert_eq!(last_error.take().unwrap().kind(), std::io::ErrorKind::NotFound);
+        assert!(last_error.take().is_none());
+    }
+
+    #[test]
+    fn enveloped_file()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.bin");
+        let mut settings = Persistent::<Settings>::open_enveloped(&path).unwrap();
+        settings.get_mut().runs = 3;
+        settings.save().unwrap();
+        drop(settings);
+        assert_eq!(std::fs::read(&path).unwrap()[..4], MAGIC);
+        assert_eq!(Persistent::<Settings>::open_enveloped(&path).unwrap().get().runs, 3);
+        // A file of another type or without an envelope is rejected
+        assert!(Persistent::<(u32, Vec<String>)>::open_enveloped(&path).is_err());
+        write_atomic(&path, &Settings::default().serialize()).unwrap();
+        assert!(Persistent::<Settings>::open_enveloped(&path).is_err());
+    }
+
+    #[test]
+    fn corrupt_file_fails_to_open()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.bin");
+        let mut data = Settings { runs: 1, recent_files: vec!["a".to_string()] }.serialize();
+        data.pop();
+        write_atomic(&path, &data).unwrap();
+        assert!(Persistent::<Settings>::open(&path).is_err());
+    }
+}
@@ -0,0 +1,609 @@
+//! Encoding of structs in the protobuf wire format, so that services using code generated
+//! from a `.proto` file can read them.
+//!
+//! A struct deriving `Serializable` with `#[serializable(protobuf)]` implements [`Message`],
+//! every field needs a `#[serializable(field_index = N)]` which is its field number.
+//! Fields follow proto3 rules: values equal to their default are not written, missing fields are
+//! read as their default and repeated numbers are packed. Unknown fields are skipped when decoding.
+//!
+//! | Rust type | Protobuf type |
+//! |---|---|
+//! | `u32`, `u64`, `i32`, `i64`, `bool` | `uint32`, `uint64`, `int32`, `int64`, `bool` |
+//! | [`Sint32`], [`Sint64`] | `sint32`, `sint64` |
+//! | `f32`, `f64` | `float`, `double` |
+//! | `String`, `Vec<u8>` | `string`, `bytes` |
+//! | structs with `#[serializable(protobuf)]` | messages |
+//! | `Option<T>` | `optional T` |
+//! | `Vec<T>` | `repeated T` |
+//!
+//! ```
+//! use serializable::Serializable;
+//! use serializable::protobuf::Message;
+//!
+//! // message Point { int32 x = 1; int32 y = 2; }
+//! #[derive(Serializable, Debug, PartialEq)]
+//! #[serializable(protobuf)]
+//! struct Point
+//! {
+//!     #[serializable(field_index = 1)]
+//!     x: i32,
+//!     #[serializable(field_index = 2)]
+//!     y: i32,
+//! }
+//!
+//! let point = Point { x: 3, y: 0 };
+//! assert_eq!(point.to_protobuf(), [0x08, 3]);
+//! assert_eq!(Point::from_protobuf(&[0x08, 3]).unwrap(), point);
+//! ```
+
+use crate::schema::{Describe, Schema};
+use crate::serializable::error_at_offset;
+use crate::{Error, Serializable};
+
+/// Wire type of integers and bools encoded as varints
+pub const VARINT: u8 = 0;
+/// Wire type of 8 bytes little endian values
+pub const I64: u8 = 1;
+/// Wire type of values prefixed by their length as a varint
+pub const LEN: u8 = 2;
+/// Wire type of 4 bytes little endian values
+pub const I32: u8 = 5;
+
+/// Structs that can be encoded as a protobuf message, implemented by the derive
+pub trait Message: Sized
+{
+    /// Returns the fields of the message in the protobuf wire format
+    fn to_protobuf(&self) -> Vec<u8>;
+    /// Decodes a message from the protobuf wire format, skipping unknown fields
+    fn from_protobuf(data: &[u8]) -> std::io::Result<Self>;
+}
+
+/// Types that are a single protobuf value
+pub trait Value: Sized
+{
+    /// Wire type of the encoded value
+    const WIRE_TYPE: u8;
+
+    /// Appends the value, without its field key, to `out`
+    fn encode_value(&self, out: &mut Vec<u8>);
+    /// Decodes a value returning it and the number of bytes read
+    fn decode_value(data: &[u8]) -> std::io::Result<(Self,usize)>;
+
+    /// Returns true if the value is not written in a field without presence, such as `0` or `""`
+    fn is_default(&self) -> bool
+    {
+        false
+    }
+}
+
+/// Types that can be the field of a message
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no protobuf equivalent",
+    label = "unsupported protobuf field type",
+    note = "protobuf fields can be integers, bools, floats, strings, bytes, messages and `Option` or `Vec` of them",
+)]
+pub trait Field
+{
+    /// Appends the field with number `number` to `out`, if it is to be written
+    fn encode_field(&self, number: u32, out: &mut Vec<u8>);
+    /// Merges a value of the field read from `data` into the field, returning the number of bytes read
+    fn merge_field(&mut self, wire_type: u8, data: &[u8]) -> std::io::Result<usize>;
+}
+
+/// A singular field, not written when it has its default value
+impl<T: Value> Field for T
+{
+    fn encode_field(&self, number: u32, out: &mut Vec<u8>)
+    {
+        if !self.is_default()
+        {
+            write_key(number, T::WIRE_TYPE, out);
+            self.encode_value(out);
+        }
+    }
+
+    fn merge_field(&mut self, wire_type: u8, data: &[u8]) -> std::io::Result<usize>
+    {
+        check_wire_type(wire_type, T::WIRE_TYPE)?;
+        let (value, len) = T::decode_value(data)?;
+        *self = value;
+        Ok(len)
+    }
+}
+
+/// An optional field, written whenever it is present
+impl<T: Value> Field for Option<T>
+{
+    fn encode_field(&self, number: u32, out: &mut Vec<u8>)
+    {
+        if let Some(value) = self
+        {
+            write_key(number, T::WIRE_TYPE, out);
+            value.encode_value(out);
+        }
+    }
+
+    fn merge_field(&mut self, wire_type: u8, data: &[u8]) -> std::io::Result<usize>
+    {
+        check_wire_type(wire_type, T::WIRE_TYPE)?;
+        let (value, len) = T::decode_value(data)?;
+        *self = Some(value);
+        Ok(len)
+    }
+}
+
+/// A repeated field, packed in a single length delimited value if the items are numbers.
+/// Both packed and unpacked numbers are accepted when decoding.
+impl<T: Value> Field for Vec<T>
+{
+    fn encode_field(&self, number: u32, out: &mut Vec<u8>)
+    {
+        if self.is_empty()
+        {
+            return;
+        }
+        if T::WIRE_TYPE == LEN
+        {
+            for item in self
+            {
+                write_key(number, LEN, out);
+                item.encode_value(out);
+            }
+        }
+        else
+        {
+            let mut packed = Vec::new();
+            for item in self
+            {
+                item.encode_value(&mut packed);
+            }
+            write_key(number, LEN, out);
+            write_len_delimited(&packed, out);
+        }
+    }
+
+    fn merge_field(&mut self, wire_type: u8, data: &[u8]) -> std::io::Result<usize>
+    {
+        if wire_type == LEN && T::WIRE_TYPE != LEN
+        {
+            let (packed, len) = read_len_delimited(data)?;
+            let start = len - packed.len();
+            let mut offset = 0;
+            while offset < packed.len()
+            {
+                let (item, item_len) = T::decode_value(&packed[offset..]).map_err(|e| error_at_offset(e, start + offset))?;
+                self.push(item);
+                offset += item_len;
+            }
+            return Ok(len);
+        }
+        check_wire_type(wire_type, T::WIRE_TYPE)?;
+        let (item, len) = T::decode_value(data)?;
+        self.push(item);
+        Ok(len)
+    }
+}
+
+/// Implements [`Value`] for an integer type encoded as a varint, `$from` and `$to` convert it to
+/// and from the `u64` in the varint
+macro_rules! impl_varint_value {
+    ($ty:ty, $to:expr, $from:expr) => {
+        impl Value for $ty
+        {
+            const WIRE_TYPE: u8 = VARINT;
+
+            fn encode_value(&self, out: &mut Vec<u8>)
+            {
+                write_varint($to(*self), out);
+            }
+
+            fn decode_value(data: &[u8]) -> std::io::Result<(Self,usize)>
+            {
+                let (value, len) = read_varint(data)?;
+                Ok(($from(value), len))
+            }
+
+            fn is_default(&self) -> bool
+            {
+                *self == <$ty>::default()
+            }
+        }
+    };
+}
+
+// Negative int32 values are sign extended to 64 bits, and integers that are too wide are truncated, like protobuf does
+impl_varint_value!(u32, |value: u32| value as u64, |value: u64| value as u32);
+impl_varint_value!(u64, |value: u64| value, |value: u64| value);
+impl_varint_value!(i32, |value: i32| value as i64 as u64, |value: u64| value as i32);
+impl_varint_value!(i64, |value: i64| value as u64, |value: u64| value as i64);
+impl_varint_value!(bool, |value: bool| value as u64, |value: u64| value != 0);
+impl_varint_value!(Sint32, |value: Sint32| ((value.0 << 1) ^ (value.0 >> 31)) as u32 as u64, |value: u64| Sint32(((value as u32) >> 1) as i32 ^ -((value & 1) as i32)));
+impl_varint_value!(Sint64, |value: Sint64| ((value.0 << 1) ^ (value.0 >> 63)) as u64, |value: u64| Sint64((value >> 1) as i64 ^ -((value & 1) as i64)));
+
+impl Value for f32
+{
+    const WIRE_TYPE: u8 = I32;
+
+    fn encode_value(&self, out: &mut Vec<u8>)
+    {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode_value(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        match data.first_chunk::<4>()
+        {
+            Some(bytes) => Ok((f32::from_le_bytes(*bytes), 4)),
+            None => Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into()),
+        }
+    }
+
+    fn is_default(&self) -> bool
+    {
+        // Negative zero is written, as protobuf does
+        self.to_bits() == 0
+    }
+}
+
+impl Value for f64
+{
+    const WIRE_TYPE: u8 = I64;
+
+    fn encode_value(&self, out: &mut Vec<u8>)
+    {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode_value(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        match data.first_chunk::<8>()
+        {
+            Some(bytes) => Ok((f64::from_le_bytes(*bytes), 8)),
+            None => Err(Error::UnexpectedEof { needed: 8, available: data.len() }.into()),
+        }
+    }
+
+    fn is_default(&self) -> bool
+    {
+        self.to_bits() == 0
+    }
+}
+
+impl Value for String
+{
+    const WIRE_TYPE: u8 = LEN;
+
+    fn encode_value(&self, out: &mut Vec<u8>)
+    {
+        write_len_delimited(self.as_bytes(), out);
+    }
+
+    fn decode_value(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let (bytes, len) = read_len_delimited(data)?;
+        match std::str::from_utf8(bytes)
+        {
+            Ok(string) => Ok((string.to_owned(), len)),
+            Err(e) => {
+                Err(error_at_offset(Error::InvalidUtf8.into(), len - bytes.len() + e.valid_up_to()))
+            },
+        }
+    }
+
+    fn is_default(&self) -> bool
+    {
+        self.is_empty()
+    }
+}
+
+impl Value for Vec<u8>
+{
+    const WIRE_TYPE: u8 = LEN;
+
+    fn encode_value(&self, out: &mut Vec<u8>)
+    {
+        write_len_delimited(self, out);
+    }
+
+    fn decode_value(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        let (bytes, len) = read_len_delimited(data)?;
+        Ok((bytes.to_vec(), len))
+    }
+
+    fn is_default(&self) -> bool
+    {
+        self.is_empty()
+    }
+}
+
+/// An `i32` encoded as a protobuf `sint32`, which makes small negative numbers short.
+/// It is serialized like an `i32`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sint32(pub i32);
+
+impl Serializable for Sint32
+{
+    const FIXED_SIZE: Option<usize> = i32::FIXED_SIZE;
+
+    fn serialize(&self) -> Vec<u8> {
+        self.0.serialize()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (value, read) = i32::deserialize(data)?;
+        Ok((Self(value), read))
+    }
+}
+
+impl Describe for Sint32
+{
+    fn describe() -> Schema
+    {
+        i32::describe()
+    }
+}
+
+/// An `i64` encoded as a protobuf `sint64`, which makes small negative numbers short.
+/// It is serialized like an `i64`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Sint64(pub i64);
+
+impl Serializable for Sint64
+{
+    const FIXED_SIZE: Option<usize> = i64::FIXED_SIZE;
+
+    fn serialize(&self) -> Vec<u8> {
+        self.0.serialize()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (value, read) = i64::deserialize(data)?;
+        Ok((Self(value), read))
+    }
+}
+
+impl Describe for Sint64
+{
+    fn describe() -> Schema
+    {
+        i64::describe()
+    }
+}
+
+/// Appends `value` as a varint, 7 bits per byte starting from the least significant ones
+pub fn write_varint(mut value: u64, out: &mut Vec<u8>)
+{
+    while value >= 0x80
+    {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads a varint returning its value and its length, fails if it is longer than 10 bytes
+pub fn read_varint(data: &[u8]) -> std::io::Result<(u64,usize)>
+{
+    let mut value = 0;
+    for (i, byte) in data.iter().take(10).enumerate()
+    {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0
+        {
+            return Ok((value, i + 1));
+        }
+    }
+    match data.len()
+    {
+        0..10 => Err(Error::UnexpectedEof { needed: data.len() + 1, available: data.len() }.into()),
+        _ => Err(Error::InvalidValue { type_name: "varint", reason: "longer than 10 bytes".to_string() }.into()),
+    }
+}
+
+/// Appends `bytes` prefixed by their length
+pub fn write_len_delimited(bytes: &[u8], out: &mut Vec<u8>)
+{
+    write_varint(bytes.len() as u64, out);
+    out.extend_from_slice(bytes);
+}
+
+/// Reads a length delimited value returning its bytes and the length of the whole value
+pub fn read_len_delimited(data: &[u8]) -> std::io::Result<(&[u8],usize)>
+{
+    let (len, read) = read_varint(data)?;
+    match data[read..].get(..len.try_into().unwrap_or(usize::MAX))
+    {
+        Some(bytes) => Ok((bytes, read + bytes.len())),
+        None => Err(Error::UnexpectedEof { needed: (len as usize).saturating_add(read), available: data.len() }.into()),
+    }
+}
+
+/// Appends the key of the field `number` with the given wire type
+pub fn write_key(number: u32, wire_type: u8, out: &mut Vec<u8>)
+{
+    write_varint(((number as u64) << 3) | wire_type as u64, out);
+}
+
+/// Reads the key of a field returning its number, its wire type and its length
+pub fn read_key(data: &[u8]) -> std::io::Result<((u32,u8),usize)>
+{
+    let (key, len) = read_varint(data)?;
+    match u32::try_from(key >> 3)
+    {
+        Ok(number) if number != 0 => Ok(((number, (key & 7) as u8), len)),
+        _ => Err(Error::InvalidValue { type_name: "field key", reason: format!("{} is not a field number", key >> 3) }.into()),
+    }
+}
+
+/// Returns the length of a field value with the given wire type, to skip unknown fields.
+/// Groups, a deprecated wire type, are not supported.
+pub fn skip_field(wire_type: u8, data: &[u8]) -> std::io::Result<usize>
+{
+    let len = match wire_type
+    {
+        VARINT => read_varint(data)?.1,
+        I64 => 8,
+        LEN => read_len_delimited(data)?.1,
+        I32 => 4,
+        _ => return Err(invalid_wire_type(wire_type)),
+    };
+    match len <= data.len()
+    {
+        true => Ok(len),
+        false => Err(Error::UnexpectedEof { needed: len, available: data.len() }.into()),
+    }
+}
+
+fn check_wire_type(wire_type: u8, expected: u8) -> std::io::Result<()>
+{
+    match wire_type == expected
+    {
+        true => Ok(()),
+        false => Err(invalid_wire_type(wire_type)),
+    }
+}
+
+fn invalid_wire_type(wire_type: u8) -> std::io::Error
+{
+    Error::InvalidValue { type_name: "field key", reason: format!("unexpected wire type {wire_type}") }.into()
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{Message, Sint32, Sint64};
+    use crate as serializable;
+    use crate::Serializable;
+
+    // message Address { string city = 1; uint32 zip = 2; }
+    #[derive(Serializable, Debug, Clone, Default, PartialEq)]
+    #[serializable(protobuf)]
+    struct Address
+    {
+        #[serializable(field_index = 1)]
+        city: String,
+        #[serializable(field_index = 2)]
+        zip: u32,
+    }
+
+    // message Person {
+    //     string name = 1;
+    //     int32 id = 2;
+    //     optional string email = 3;
+    //     repeated uint32 scores = 4;
+    //     sint64 balance = 5;
+    //     optional Address address = 6;
+    //     repeated string tags = 7;
+    //     double ratio = 8;
+    //     bool active = 9;
+    // }
+    #[derive(Serializable, Debug, Clone, PartialEq)]
+    #[serializable(protobuf)]
+    struct Person
+    {
+        #[serializable(field_index = 1)]
+        name: String,
+        #[serializable(field_index = 2)]
+        id: i32,
+        #[serializable(field_index = 3)]
+        email: Option<String>,
+        #[serializable(field_index = 4)]
+        scores: Vec<u32>,
+        #[serializable(field_index = 5)]
+        balance: Sint64,
+        #[serializable(field_index = 6)]
+        address: Option<Address>,
+        #[serializable(field_index = 7)]
+        tags: Vec<String>,
+        #[serializable(field_index = 8)]
+        ratio: f64,
+        #[serializable(field_index = 9)]
+        active: bool,
+    }
+
+    /// The encoding of `person()` written by hand from the protobuf encoding rules
+    const PERSON: &[u8] = &[
+        0x0a, 3, b'a', b'd', b'a', // name = 1, LEN
+        0x10, 150, 1, // id = 2, varint 150
+        0x1a, 0, // email = 3, present but empty
+        0x22, 4, 1, 2, 0xac, 0x02, // scores = 4, packed [1, 2, 300]
+        0x28, 3, // balance = 5, zigzag(-2) = 3
+        0x32, 8, 0x0a, 3, b'R', b'o', b'm', 0x10, 0x8d, 0x01, // address = 6, { city: "Rom", zip: 141 }
+        0x3a, 1, b'x', 0x3a, 1, b'y', // tags = 7, one key per item
+        0x41, 0, 0, 0, 0, 0, 0, 0xe0, 0x3f, // ratio = 8, 0.5 as a little endian double
+        // active = 9 is false, so it is not written
+    ];
+
+    fn person() -> Person
+    {
+        Person {
+            name: "ada".to_string(),
+            id: 150,
+            email: Some(String::new()),
+            scores: vec![1, 2, 300],
+            balance: Sint64(-2),
+            address: Some(Address { city: "Rom".to_string(), zip: 141 }),
+            tags: vec!["x".to_string(), "y".to_string()],
+            ratio: 0.5,
+            active: false,
+        }
+    }
+
+    #[test]
+    fn encode_like_protobuf()
+    {
+        assert_eq!(person().to_protobuf(), PERSON);
+        assert_eq!(Person::from_protobuf(PERSON).unwrap(), person());
+        // The native serialization is unchanged
+        assert_eq!(Person::deserialize_exact(&person().serialize()).unwrap(), person());
+    }
+
+    #[test]
+    fn decode_missing_unknown_and_unpacked_fields()
+    {
+        let empty = Person::from_protobuf(&[]).unwrap();
+        assert_eq!(empty.name, "");
+        assert_eq!(empty.email, None);
+        assert_eq!(empty.address, None);
+        // Unknown fields of every wire type are skipped
+        let mut data = vec![0x50, 0xff, 0x01, 0x59, 1, 2, 3, 4, 5, 6, 7, 8, 0x62, 2, 9, 9, 0x6d, 1, 2, 3, 4];
+        // Numbers can also be repeated one key at a time
+        data.extend([0x20, 7, 0x20, 8]);
+        data.extend([0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        let person = Person::from_protobuf(&data).unwrap();
+        assert_eq!(person.scores, [7, 8]);
+        assert_eq!(person.id, -1);
+        assert_eq!(Person { id: -1, ..empty }.to_protobuf(), [0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    }
+
+    #[test]
+    fn reject_invalid_messages()
+    {
+        // The name has the wire type of a varint
+        assert!(Person::from_protobuf(&[0x08, 1]).is_err());
+        // The address is cut short
+        let error = Person::from_protobuf(&PERSON[..24]).unwrap_err();
+        assert_eq!(crate::DeserializeError::offset_of(&error), 19);
+        // Field number 0 and groups are not valid
+        assert!(Person::from_protobuf(&[0x00, 1]).is_err());
+        assert!(Person::from_protobuf(&[0x53]).is_err());
+        assert!(Person::from_protobuf(&[0x0a, 1, 0xff]).is_err());
+    }
+
+    #[test]
+    fn encode_zigzag_integers()
+    {
+        for (value, expected) in [(0, 0), (-1, 1), (1, 2), (-2, 3), (i32::MAX, 0xfffffffe), (i32::MIN, 0xffffffff)]
+        {
+            let mut out = Vec::new();
+            super::Value::encode_value(&Sint32(value), &mut out);
+            assert_eq!(super::read_varint(&out).unwrap().0, expected);
+            assert_eq!(<Sint32 as super::Value>::decode_value(&out).unwrap().0, Sint32(value));
+        }
+        let mut out = Vec::new();
+        super::Value::encode_value(&Sint64(i64::MIN), &mut out);
+        assert_eq!(super::read_varint(&out).unwrap().0, u64::MAX);
+        assert_eq!(<Sint64 as super::Value>::decode_value(&out).unwrap().0, Sint64(i64::MIN));
+    }
+}
@@ -0,0 +1,221 @@
+//! Exchanging serialized values with Python through `bytes` objects.
+//!
+//! [`py_serializable!`](crate::py_serializable) adds `to_bytes()` and `from_bytes(bytes)` to a
+//! `#[pyclass]` that is also [`Serializable`], and [`schema_to_dict`] lets Python code inspect the layout.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList};
+
+use crate::schema::{Describe, Field, Schema};
+use crate::Serializable;
+
+/// Serializable values that can be turned into a Python `bytes` object
+pub trait IntoPyBytes: Serializable
+{
+    /// Returns the serialization as a new `bytes` object
+    fn to_py_bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes>
+    {
+        PyBytes::new(py, &self.serialize())
+    }
+}
+
+impl<T: Serializable> IntoPyBytes for T {}
+
+/// Serializable values that can be read from a Python `bytes` object
+pub trait FromPyBytes: Describe
+{
+    /// Deserializes the whole content of `bytes`. Failures raise a `ValueError` with the
+    /// offset and the path of the field that could not be read, as given by [`crate::debug::failing_path`].
+    fn from_py_bytes(bytes: &Bound<'_, PyBytes>) -> PyResult<Self>
+    {
+        let data = bytes.as_bytes();
+        Self::deserialize_exact(data).map_err(|e| {
+            let message = match crate::debug::failing_path(&Self::describe(), data)
+            {
+                Some(path) if !path.is_empty() => format!("Cannot deserialize {}: {e} (field {path})", std::any::type_name::<Self>()),
+                _ => format!("Cannot deserialize {}: {e}", std::any::type_name::<Self>()),
+            };
+            PyValueError::new_err(message)
+        })
+    }
+}
+
+impl<T: Describe> FromPyBytes for T {}
+
+/// Returns the layout of a type as nested Python dicts. Every dict has a `kind`, one of
+/// `primitive`, `option`, `sequence`, `array`, `map`, `tuple`, `struct`, `enum` and `indexed_struct`, and the keys
+/// of that kind: `name` for primitives, structs and enums, `item` for options, sequences and arrays,
+/// `len` for arrays, `key` and `value` for maps, `items` for tuples, `fields` for structs and
+/// `variants` for enums. Fields and variants are dicts with a `name` and a `schema` or `fields`,
+/// the fields of indexed structs also have their `index`.
+pub fn schema_to_dict<'py>(py: Python<'py>, schema: &Schema) -> PyResult<Bound<'py, PyDict>>
+{
+    let ret = PyDict::new(py);
+    match schema
+    {
+        Schema::Primitive(name) => {
+            ret.set_item("kind", "primitive")?;
+            ret.set_item("name", name.rsplit("::").next().unwrap_or(name).trim())?;
+        },
+        Schema::Option(item) => {
+            ret.set_item("kind", "option")?;
+            ret.set_item("item", schema_to_dict(py, item)?)?;
+        },
+        Schema::Sequence(item) => {
+            ret.set_item("kind", "sequence")?;
+            ret.set_item("item", schema_to_dict(py, item)?)?;
+        },
+        Schema::Array(item, len) => {
+            ret.set_item("kind", "array")?;
+            ret.set_item("item", schema_to_dict(py, item)?)?;
+            ret.set_item("len", len)?;
+        },
+        Schema::Map(key, value) => {
+            ret.set_item("kind", "map")?;
+            ret.set_item("key", schema_to_dict(py, key)?)?;
+            ret.set_item("value", schema_to_dict(py, value)?)?;
+        },
+        Schema::Tuple(items) => {
+            ret.set_item("kind", "tuple")?;
+            let items = items.iter().map(|item| schema_to_dict(py, item)).collect::<PyResult<Vec<_>>>()?;
+            ret.set_item("items", PyList::new(py, items)?)?;
+        },
+        Schema::Struct { name, fields } => {
+            ret.set_item("kind", "struct")?;
+            ret.set_item("name", name)?;
+            ret.set_item("fields", fields_to_list(py, fields)?)?;
+        },
+        Schema::Enum { name, variants } => {
+            ret.set_item("kind", "enum")?;
+            ret.set_item("name", name)?;
+            let variants = variants.iter().map(|variant| {
+                let dict = PyDict::new(py);
+                dict.set_item("name", variant.name)?;
+                dict.set_item("fields", fields_to_list(py, &variant.fields)?)?;
+                Ok(dict)
+            }).collect::<PyResult<Vec<_>>>()?;
+            ret.set_item("variants", PyList::new(py, variants)?)?;
+        },
+        Schema::IndexedStruct { name, fields } => {
+            ret.set_item("kind", "indexed_struct")?;
+            ret.set_item("name", name)?;
+            let fields = fields.iter().map(|field| {
+                let dict = PyDict::new(py);
+                dict.set_item("index", field.index)?;
+                dict.set_item("name", field.field.name)?;
+                dict.set_item("schema", schema_to_dict(py, &field.field.schema)?)?;
+                Ok(dict)
+            }).collect::<PyResult<Vec<_>>>()?;
+            ret.set_item("fields", PyList::new(py, fields)?)?;
+        },
+    }
+    Ok(ret)
+}
+
+fn fields_to_list<'py>(py: Python<'py>, fields: &[Field]) -> PyResult<Bound<'py, PyList>>
+{
+    let fields = fields.iter().map(|field| {
+        let dict = PyDict::new(py);
+        dict.set_item("name", field.name)?;
+        dict.set_item("schema", schema_to_dict(py, &field.schema)?)?;
+        Ok(dict)
+    }).collect::<PyResult<Vec<_>>>()?;
+    PyList::new(py, fields)
+}
+
+/// Adds the `to_bytes()` method and the `from_bytes(bytes)` classmethod to a `#[pyclass]`
+/// that implements [`Serializable`] and [`Describe`], such as a type that derives both `Serializable` and `#[pyclass]` and is marked with `#[serializable(describe)]`.
+/// It expands to a `#[pymethods]` block, so other methods of the class need pyo3's `multiple-pymethods` feature.
+#[macro_export]
+macro_rules! py_serializable {
+    ($ty:ty) => {
+        #[::pyo3::pymethods]
+        impl $ty
+        {
+            /// Returns the serialization of the object as `bytes`
+            fn to_bytes<'py>(&self, py: ::pyo3::Python<'py>) -> ::pyo3::Bound<'py, ::pyo3::types::PyBytes>
+            {
+                $crate::python::IntoPyBytes::to_py_bytes(self, py)
+            }
+
+            /// Returns the object serialized in `bytes`, raising `ValueError` if it is not valid
+            #[classmethod]
+            fn from_bytes(
+                _cls: &::pyo3::Bound<'_, ::pyo3::types::PyType>,
+                bytes: &::pyo3::Bound<'_, ::pyo3::types::PyBytes>,
+            ) -> ::pyo3::PyResult<Self>
+            {
+                $crate::python::FromPyBytes::from_py_bytes(bytes)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests
+{
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    use super::schema_to_dict;
+    use crate as serializable;
+    use crate::schema::Describe;
+    use crate::Serializable;
+
+    #[pyclass]
+    #[derive(Serializable, Debug, Clone, PartialEq)]
+    #[serializable(describe)]
+    struct Measurement
+    {
+        #[pyo3(get)]
+        station: String,
+        #[pyo3(get)]
+        samples: Vec<u16>,
+    }
+
+    crate::py_serializable!(Measurement);
+
+    #[test]
+    fn roundtrip_through_python_bytes()
+    {
+        Python::initialize();
+        Python::attach(|py| {
+            let measurement = Measurement { station: "north".to_string(), samples: vec![1, 2, 3] };
+            let globals = PyDict::new(py);
+            globals.set_item("Measurement", py.get_type::<Measurement>()).unwrap();
+            globals.set_item("measurement", Py::new(py, measurement.clone()).unwrap()).unwrap();
+            py.run(c"
+data = measurement.to_bytes()
+assert isinstance(data, bytes)
+copy = Measurement.from_bytes(data)
+assert copy.station == 'north' and copy.samples == [1, 2, 3]
+try:
+    Measurement.from_bytes(data[:-1])
+    raise AssertionError('truncated bytes were accepted')
+except ValueError as e:
+    error = str(e)
+", Some(&globals), None).unwrap();
+            let data: Vec<u8> = globals.get_item("data").unwrap().unwrap().extract().unwrap();
+            assert_eq!(data, measurement.serialize());
+            let error: String = globals.get_item("error").unwrap().unwrap().extract().unwrap();
+            assert!(error.ends_with("while deserializing Measurement.samples at offset 13: Unexpected end of data, 6 bytes needed but 5 left (field samples[2])"), "{error}");
+        });
+    }
+
+    #[test]
+    fn render_schema_as_dict()
+    {
+        Python::initialize();
+        Python::attach(|py| {
+            let dict = schema_to_dict(py, &Measurement::describe()).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("schema", dict).unwrap();
+            py.run(c"
+assert schema['kind'] == 'struct' and schema['name'] == 'Measurement'
+assert [field['name'] for field in schema['fields']] == ['station', 'samples']
+assert schema['fields'][1]['schema'] == {'kind': 'sequence', 'item': {'kind': 'primitive', 'name': 'u16'}}
+", Some(&globals), None).unwrap();
+        });
+    }
+}
@@ -0,0 +1,535 @@
+//! Descriptions of the wire layout of serializable types.
+//!
+//! [`Describe::schema_hash`] condenses the layout into a number that two programs can
+//! compare before exchanging values, see [`crate::net::connection::Connection::handshake`].
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
+use std::time::SystemTime;
+
+use crate::serializable::length_prefix;
+use crate::Serializable;
+
+pub mod c_header;
+pub mod typescript;
+
+pub use c_header::{to_c_header, CHeader, CHeaderError};
+pub use typescript::to_typescript;
+
+/// The wire layout of a type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Schema
+{
+    /// A type with a builtin encoding, such as `u32` or `String`
+    Primitive(&'static str),
+    /// A presence byte followed by the value
+    Option(Box<Schema>),
+    /// A `u32` count followed by the items
+    Sequence(Box<Schema>),
+    /// A fixed number of items without a count
+    Array(Box<Schema>, usize),
+    /// A `u32` count followed by the entries
+    Map(Box<Schema>, Box<Schema>),
+    /// Values one after the other
+    Tuple(Vec<Schema>),
+    /// Fields one after the other
+    Struct
+    {
+        name: &'static str,
+        fields: Vec<Field>,
+    },
+    /// A variant index byte followed by the fields of the variant
+    Enum
+    {
+        name: &'static str,
+        variants: Vec<Variant>,
+    },
+    /// A struct marked with `#[serializable(indexed)]`: a `u16` count followed by every field
+    /// as its `u16` index, its `u32` length and its value
+    IndexedStruct
+    {
+        name: &'static str,
+        fields: Vec<IndexedField>,
+    },
+}
+
+/// A field of a struct or of an enum variant, unnamed fields are named by their position
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field
+{
+    pub name: &'static str,
+    pub schema: Schema,
+}
+
+/// A field of an indexed struct with its `field_index`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedField
+{
+    pub index: u16,
+    pub field: Field,
+}
+
+/// A variant of an enum
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant
+{
+    pub name: &'static str,
+    pub fields: Vec<Field>,
+}
+
+impl Schema
+{
+    /// Returns a hash of the layout that ignores the names of types, fields and variants.
+    /// It is computed with FNV-1a, so it is the same on every platform and compilation.
+    pub fn layout_hash(&self) -> u64
+    {
+        let mut layout = Vec::new();
+        self.write_layout(&mut layout);
+        layout.iter().fold(0xcbf29ce484222325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    fn write_layout(&self, out: &mut Vec<u8>)
+    {
+        match self
+        {
+            Schema::Primitive(name) => {
+                out.push(0);
+                out.extend(name.to_string().serialize());
+            },
+            Schema::Option(schema) => {
+                out.push(1);
+                schema.write_layout(out);
+            },
+            Schema::Sequence(schema) => {
+                out.push(2);
+                schema.write_layout(out);
+            },
+            Schema::Array(schema, len) => {
+                out.push(3);
+                out.extend((*len as u64).serialize());
+                schema.write_layout(out);
+            },
+            Schema::Map(key, value) => {
+                out.push(4);
+                key.write_layout(out);
+                value.write_layout(out);
+            },
+            Schema::Tuple(schemas) => {
+                out.push(5);
+                Self::write_all_layouts(schemas.iter(), out);
+            },
+            Schema::Struct { fields, .. } => {
+                out.push(6);
+                Self::write_all_layouts(fields.iter().map(|field| &field.schema), out);
+            },
+            Schema::Enum { variants, .. } => {
+                out.push(7);
+                out.extend(length_prefix(variants.len()));
+                for variant in variants
+                {
+                    Self::write_all_layouts(variant.fields.iter().map(|field| &field.schema), out);
+                }
+            },
+            Schema::IndexedStruct { fields, .. } => {
+                out.push(8);
+                out.extend(length_prefix(fields.len()));
+                for field in fields
+                {
+                    out.extend(field.index.serialize());
+                    field.field.schema.write_layout(out);
+                }
+            },
+        }
+    }
+
+    fn write_all_layouts<'a>(schemas: impl ExactSizeIterator<Item = &'a Schema>, out: &mut Vec<u8>)
+    {
+        out.extend(length_prefix(schemas.len()));
+        for schema in schemas
+        {
+            schema.write_layout(out);
+        }
+    }
+}
+
+/// Serializable types that can describe their wire layout.
+/// The derive implements it for the types marked with `#[serializable(describe)]`, whose fields must all implement it.
+/// Recursive types like `struct Node { children: Vec<Node> }` can't be marked, since their layout would be infinite.
+pub trait Describe: Serializable
+{
+    /// Returns the layout of the type
+    fn describe() -> Schema;
+
+    /// Returns a hash of the layout, see [`Schema::layout_hash`]
+    fn schema_hash() -> u64
+    {
+        Self::describe().layout_hash()
+    }
+
+    /// Returns why `data` cannot be deserialized as this type, followed by
+    /// the dump of [`crate::debug::annotate`] that shows where the bytes stop making sense
+    fn explain_failure(data: &[u8]) -> String
+    {
+        let summary = match Self::deserialize_exact(data)
+        {
+            Ok(_) => "No failure, the data is valid".to_string(),
+            Err(e) => format!("Deserialization failed: {e}"),
+        };
+        format!("{summary}\n{}", crate::debug::annotate(&Self::describe(), data))
+    }
+}
+
+macro_rules! impl_describe_primitive {
+    ($($ty:ty),*) => {
+        $(
+            impl Describe for $ty
+            {
+                fn describe() -> Schema
+                {
+                    Schema::Primitive(stringify!($ty))
+                }
+            }
+        )*
+    };
+}
+
+impl_describe_primitive!(u128, u64, u32, u16, u8, i128, i64, i32, i16, i8, f64, f32, bool, char, String, SystemTime);
+impl_describe_primitive!(std::net::SocketAddr, std::net::IpAddr, std::net::Ipv4Addr, std::net::SocketAddrV4);
+
+impl Describe for ()
+{
+    fn describe() -> Schema
+    {
+        Schema::Tuple(Vec::new())
+    }
+}
+
+impl<T: Describe> Describe for Vec<T>
+{
+    fn describe() -> Schema
+    {
+        Schema::Sequence(Box::new(T::describe()))
+    }
+}
+
+impl<T: Describe> Describe for VecDeque<T>
+{
+    fn describe() -> Schema
+    {
+        Schema::Sequence(Box::new(T::describe()))
+    }
+}
+
+/// Smart pointers are encoded like the value they point to
+impl<T: Describe> Describe for Box<T>
+{
+    fn describe() -> Schema
+    {
+        T::describe()
+    }
+}
+
+impl<T: Describe> Describe for std::rc::Rc<T>
+{
+    fn describe() -> Schema
+    {
+        T::describe()
+    }
+}
+
+impl<T: Describe> Describe for std::sync::Arc<T>
+{
+    fn describe() -> Schema
+    {
+        T::describe()
+    }
+}
+
+impl<T: Describe> Describe for Option<T>
+{
+    fn describe() -> Schema
+    {
+        Schema::Option(Box::new(T::describe()))
+    }
+}
+
+/// Results are encoded like an enum with the variants `Ok` and `Err`
+impl<T: Describe, E: Describe> Describe for Result<T, E>
+{
+    fn describe() -> Schema
+    {
+        Schema::Enum {
+            name: "Result",
+            variants: vec![
+                Variant { name: "Ok", fields: vec![Field { name: "0", schema: T::describe() }] },
+                Variant { name: "Err", fields: vec![Field { name: "0", schema: E::describe() }] },
+            ],
+        }
+    }
+}
+
+impl<const L: usize, T: Describe> Describe for [T; L]
+{
+    fn describe() -> Schema
+    {
+        Schema::Array(Box::new(T::describe()), L)
+    }
+}
+
+impl<K: Describe + Eq + Hash, V: Describe, S: BuildHasher + Default> Describe for HashMap<K, V, S>
+{
+    fn describe() -> Schema
+    {
+        Schema::Map(Box::new(K::describe()), Box::new(V::describe()))
+    }
+}
+
+impl<K: Describe + Ord, V: Describe> Describe for BTreeMap<K, V>
+{
+    fn describe() -> Schema
+    {
+        Schema::Map(Box::new(K::describe()), Box::new(V::describe()))
+    }
+}
+
+/// Sets are encoded like sequences
+impl<T: Describe + Eq + Hash, S: BuildHasher + Default> Describe for HashSet<T, S>
+{
+    fn describe() -> Schema
+    {
+        Schema::Sequence(Box::new(T::describe()))
+    }
+}
+
+impl<T: Describe + Ord> Describe for BTreeSet<T>
+{
+    fn describe() -> Schema
+    {
+        Schema::Sequence(Box::new(T::describe()))
+    }
+}
+
+macro_rules! impl_describe_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: Describe),+> Describe for ($($name,)+)
+        {
+            fn describe() -> Schema
+            {
+                Schema::Tuple(vec![$($name::describe()),+])
+            }
+        }
+    };
+}
+
+impl_describe_tuple!(A);
+impl_describe_tuple!(A, B);
+impl_describe_tuple!(A, B, C);
+impl_describe_tuple!(A, B, C, D);
+impl_describe_tuple!(A, B, C, D, E);
+impl_describe_tuple!(A, B, C, D, E, F);
+impl_describe_tuple!(A, B, C, D, E, F, G);
+impl_describe_tuple!(A, B, C, D, E, F, G, H);
+impl_describe_tuple!(A, B, C, D, E, F, G, H, I);
+impl_describe_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_describe_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_describe_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
+
+#[cfg(test)]
+mod tests
+{
+    use super::{Describe, Schema};
+    use crate as serializable;
+    use crate::Serializable;
+
+    mod v1
+    {
+        use crate as serializable;
+        use crate::Serializable;
+
+        #[derive(Serializable)]
+        #[serializable(describe)]
+        pub struct User
+        {
+            pub id: u64,
+            pub name: String,
+            pub tags: Vec<String>,
+        }
+    }
+
+    mod v1_copy
+    {
+        use crate as serializable;
+        use crate::Serializable;
+
+        #[derive(Serializable)]
+        #[serializable(describe)]
+        pub struct User
+        {
+            pub id: u64,
+            pub name: String,
+            pub tags: Vec<String>,
+        }
+    }
+
+    mod renamed
+    {
+        use crate as serializable;
+        use crate::Serializable;
+
+        #[derive(Serializable)]
+        #[serializable(describe)]
+        pub struct Account
+        {
+            pub account_id: u64,
+            pub display_name: String,
+            pub labels: Vec<String>,
+        }
+    }
+
+    mod retyped
+    {
+        use crate as serializable;
+        use crate::Serializable;
+
+        #[derive(Serializable)]
+        #[serializable(describe)]
+        pub struct User
+        {
+            pub id: u32,
+            pub name: String,
+            pub tags: Vec<String>,
+        }
+    }
+
+    mod reordered
+    {
+        use crate as serializable;
+        use crate::Serializable;
+
+        #[derive(Serializable)]
+        #[serializable(describe)]
+        pub struct User
+        {
+            pub name: String,
+            pub id: u64,
+            pub tags: Vec<String>,
+        }
+    }
+
+    mod indexed
+    {
+        use crate as serializable;
+        use crate::Serializable;
+
+        #[derive(Serializable, Default)]
+        #[serializable(describe, indexed)]
+        pub struct User
+        {
+            #[serializable(field_index = 1)]
+            pub id: u64,
+            #[serializable(field_index = 2)]
+            pub name: String,
+            #[serializable(field_index = 3)]
+            pub tags: Vec<String>,
+        }
+    }
+
+    mod reindexed
+    {
+        use crate as serializable;
+        use crate::Serializable;
+
+        #[derive(Serializable, Default)]
+        #[serializable(describe, indexed)]
+        pub struct User
+        {
+            #[serializable(field_index = 1)]
+            pub id: u64,
+            #[serializable(field_index = 2)]
+            pub name: String,
+            #[serializable(field_index = 4)]
+            pub tags: Vec<String>,
+        }
+    }
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    enum Command
+    {
+        Start,
+        Stop(u8),
+    }
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    enum SwappedCommand
+    {
+        Stop(u8),
+        Start,
+    }
+
+    /// An enum with the layout of `Result<u8, String>`
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    enum Outcome
+    {
+        Success(u8),
+        Failure(String),
+    }
+
+    #[test]
+    fn results_are_described_as_enums()
+    {
+        assert_eq!(Result::<u8, String>::schema_hash(), Outcome::schema_hash());
+        assert_ne!(Result::<u8, String>::schema_hash(), Result::<String, u8>::schema_hash());
+        assert_eq!(Result::<u8, String>::deserialize_exact(&Outcome::Failure("x".to_string()).serialize()).unwrap(), Err("x".to_string()));
+    }
+
+    #[test]
+    fn schema_hash_follows_the_wire_layout()
+    {
+        assert_eq!(v1::User::schema_hash(), v1_copy::User::schema_hash());
+        assert_eq!(v1::User::schema_hash(), renamed::Account::schema_hash());
+        assert_ne!(v1::User::schema_hash(), retyped::User::schema_hash());
+        assert_ne!(v1::User::schema_hash(), reordered::User::schema_hash());
+        assert_ne!(Command::schema_hash(), SwappedCommand::schema_hash());
+        assert_ne!(Vec::<u8>::schema_hash(), Option::<u8>::schema_hash());
+        assert_ne!(<[u8; 2]>::schema_hash(), <[u8; 3]>::schema_hash());
+        assert_ne!(v1::User::schema_hash(), indexed::User::schema_hash());
+        assert_ne!(indexed::User::schema_hash(), reindexed::User::schema_hash());
+        // The hash is part of the wire protocol, so it must never change
+        assert_eq!(u32::schema_hash(), 0x3d97_ef3c_bbcf_5100);
+    }
+
+    #[test]
+    fn describe_derived_types()
+    {
+        match v1::User::describe()
+        {
+            Schema::Struct { name, fields } => {
+                assert_eq!(name, "User");
+                assert_eq!(fields.iter().map(|field| field.name).collect::<Vec<_>>(), ["id", "name", "tags"]);
+                assert_eq!(fields[2].schema, Schema::Sequence(Box::new(Schema::Primitive("String"))));
+            },
+            other => panic!("Expected a struct, got {other:?}"),
+        }
+        match Command::describe()
+        {
+            Schema::Enum { variants, .. } => {
+                assert_eq!(variants[0].name, "Start");
+                assert!(variants[0].fields.is_empty());
+                assert_eq!(variants[1].fields[0].name, "0");
+                assert_eq!(variants[1].fields[0].schema, Schema::Primitive("u8"));
+            },
+            other => panic!("Expected an enum, got {other:?}"),
+        }
+        match indexed::User::describe()
+        {
+            Schema::IndexedStruct { name, fields } => {
+                assert_eq!(name, "User");
+                assert_eq!(fields.iter().map(|field| (field.index, field.field.name)).collect::<Vec<_>>(), [(1, "id"), (2, "name"), (3, "tags")]);
+            },
+            other => panic!("Expected an indexed struct, got {other:?}"),
+        }
+    }
+}
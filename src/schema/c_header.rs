@@ -0,0 +1,471 @@
+//! Generation of C declarations matching the wire layout of serializable types.
+//!
+//! Fixed-size structs become packed C structs that can be laid over the serialized bytes,
+//! enums become tag constants, and types of variable size are described in comments.
+//! Multi-byte numbers stay big endian, as they are on the wire.
+
+use std::collections::HashMap;
+
+use super::{Field, Schema};
+
+/// Generates a C header for the given structs and enums and every named type they contain,
+/// with the default type mappings of [`CHeader::new`]
+pub fn to_c_header(schemas: &[Schema]) -> Result<String, CHeaderError>
+{
+    CHeader::new().generate(schemas)
+}
+
+/// A C header generator with configurable mappings from primitives to C types
+#[derive(Debug, Clone)]
+pub struct CHeader
+{
+    types: HashMap<&'static str, String>,
+    include_guard: String,
+}
+
+impl CHeader
+{
+    /// Returns a generator that maps the fixed-size primitives to the `<stdint.h>` types,
+    /// `bool` to `uint8_t`, `char` to the `uint32_t` Unicode scalar value
+    /// and `SystemTime` to the `uint64_t` seconds since the UNIX epoch.
+    /// `u128`, `i128` and `SocketAddrV4` have no standard C type and are not mapped.
+    pub fn new() -> Self
+    {
+        let types = [
+            ("u8", "uint8_t"), ("u16", "uint16_t"), ("u32", "uint32_t"), ("u64", "uint64_t"),
+            ("i8", "int8_t"), ("i16", "int16_t"), ("i32", "int32_t"), ("i64", "int64_t"),
+            ("f32", "float"), ("f64", "double"), ("bool", "uint8_t"), ("char", "uint32_t"),
+            ("SystemTime", "uint64_t"), ("Ipv4Addr", "uint32_t"),
+        ];
+        Self {
+            types: types.into_iter().map(|(primitive, c_type)| (primitive, c_type.to_string())).collect(),
+            include_guard: "SERIALIZABLE_SCHEMA_H".to_string(),
+        }
+    }
+
+    /// Maps the primitive named `primitive`, such as `"u128"`, to `c_type`.
+    /// The C type must have the same size as the serialization of the primitive.
+    pub fn with_type(mut self, primitive: &'static str, c_type: impl Into<String>) -> Self
+    {
+        self.types.insert(primitive, c_type.into());
+        self
+    }
+
+    /// Sets the macro that guards the header against multiple inclusion
+    pub fn with_include_guard(mut self, include_guard: impl Into<String>) -> Self
+    {
+        self.include_guard = include_guard.into();
+        self
+    }
+
+    /// Generates a C header for the given structs and enums and every named type they contain.
+    /// Types are declared before the types that contain them.
+    pub fn generate(&self, schemas: &[Schema]) -> Result<String, CHeaderError>
+    {
+        let mut named = Vec::new();
+        for schema in schemas
+        {
+            match schema
+            {
+                Schema::Struct { .. } | Schema::Enum { .. } | Schema::IndexedStruct { .. } => collect_named(schema, &mut named)?,
+                other => return Err(CHeaderError::Unnamed(other.clone())),
+            }
+        }
+
+        let mut body = String::new();
+        for schema in &named
+        {
+            body.push('\n');
+            match schema
+            {
+                Schema::Struct { name, fields } => self.write_struct(&mut body, name, name, fields)?,
+                Schema::Enum { name, variants } => {
+                    body.push_str(&format!("/* {name}: a uint8_t tag followed by the fields of the variant */\n"));
+                    for (tag, variant) in variants.iter().enumerate()
+                    {
+                        body.push_str(&format!("#define {}_{} {tag}\n", screaming_snake(name), screaming_snake(variant.name)));
+                    }
+                    for variant in variants.iter().filter(|variant| !variant.fields.is_empty())
+                    {
+                        body.push('\n');
+                        self.write_struct(&mut body, &format!("{name}_{}", variant.name), &format!("{name}::{}", variant.name), &variant.fields)?;
+                    }
+                },
+                Schema::IndexedStruct { name, fields } => {
+                    body.push_str(&format!("/* {name}: variable size, a uint16_t field count, then every field as its uint16_t index,\n"));
+                    body.push_str(" * its uint32_t byte length and its value\n");
+                    for field in fields
+                    {
+                        body.push_str(&format!(" *   {}: {}: {}\n", field.index, identifier(field.field.name), self.describe(&field.field.schema)?));
+                    }
+                    body.push_str(" */\n");
+                },
+                _ => unreachable!("Only named schemas are collected"),
+            }
+        }
+
+        let mut ret = String::new();
+        ret.push_str("/* Generated by serializable, do not edit.\n");
+        ret.push_str(" * Fields are packed in wire order and multi-byte numbers are big endian. */\n");
+        ret.push_str(&format!("#ifndef {}\n#define {}\n\n#include <stdint.h>\n", self.include_guard, self.include_guard));
+        if named.iter().any(|schema| fixed_size(schema).is_none())
+        {
+            ret.push_str("\n/* Reads the big endian uint32_t that prefixes strings, sequences and maps */\n");
+            ret.push_str("static inline uint32_t serializable_read_len(const uint8_t *data)\n{\n");
+            ret.push_str("    return ((uint32_t)data[0] << 24) | ((uint32_t)data[1] << 16) | ((uint32_t)data[2] << 8) | (uint32_t)data[3];\n}\n");
+        }
+        ret.push_str(&body);
+        ret.push_str(&format!("\n#endif /* {} */\n", self.include_guard));
+        Ok(ret)
+    }
+
+    /// Writes a packed struct if all the fields have a fixed size, a layout comment otherwise
+    fn write_struct(&self, out: &mut String, c_name: &str, rust_name: &str, fields: &[Field]) -> Result<(), CHeaderError>
+    {
+        let size: Option<usize> = fields.iter().map(|field| fixed_size(&field.schema)).sum();
+        match size
+        {
+            Some(size) => {
+                out.push_str(&format!("/* {rust_name}: {size} bytes */\n"));
+                out.push_str(&format!("#define {}_SIZE {size}\n", screaming_snake(c_name)));
+                if size > 0
+                {
+                    out.push_str(&format!("typedef struct __attribute__((packed)) {c_name}\n{{\n"));
+                    for field in fields
+                    {
+                        for line in self.declare(&field.schema, identifier(field.name))?
+                        {
+                            out.push_str(&format!("    {line}\n"));
+                        }
+                    }
+                    out.push_str(&format!("}} {c_name};\n"));
+                }
+            },
+            None => {
+                out.push_str(&format!("/* {rust_name}: variable size, the fields follow each other in this order\n"));
+                for field in fields
+                {
+                    if fixed_size(&field.schema).is_some()
+                    {
+                        for line in self.declare(&field.schema, identifier(field.name))?
+                        {
+                            out.push_str(&format!(" *   {line}\n"));
+                        }
+                    }
+                    else
+                    {
+                        out.push_str(&format!(" *   {}: {}\n", identifier(field.name), self.describe(&field.schema)?));
+                    }
+                }
+                out.push_str(" */\n");
+            },
+        }
+        Ok(())
+    }
+
+    /// Returns the C declarations of a member named `declarator` with the fixed-size layout `schema`,
+    /// tuples take one member per item and values of size zero take none
+    fn declare(&self, schema: &Schema, declarator: String) -> Result<Vec<String>, CHeaderError>
+    {
+        match schema
+        {
+            _ if fixed_size(schema) == Some(0) => Ok(Vec::new()),
+            Schema::Primitive(name) => Ok(vec![format!("{} {declarator};", self.c_type(name)?)]),
+            Schema::Array(item, len) => self.declare(item, format!("{declarator}[{len}]")),
+            Schema::Tuple(items) => {
+                let mut ret = Vec::new();
+                for (i, item) in items.iter().enumerate()
+                {
+                    ret.extend(self.declare(item, format!("{declarator}_{i}"))?);
+                }
+                Ok(ret)
+            },
+            Schema::Struct { name, .. } => Ok(vec![format!("{name} {declarator};")]),
+            Schema::Enum { name, .. } => Ok(vec![format!("uint8_t {declarator}; /* {name} */")]),
+            Schema::Option(_) | Schema::Sequence(_) | Schema::Map(_, _) | Schema::IndexedStruct { .. } => unreachable!("Only fixed-size schemas are declared"),
+        }
+    }
+
+    /// Returns a description of the layout of `schema` for comments
+    fn describe(&self, schema: &Schema) -> Result<String, CHeaderError>
+    {
+        Ok(match schema
+        {
+            Schema::Primitive(name) => match primitive_key(name)
+            {
+                "String" => "uint32_t byte count, then the UTF-8 bytes".to_string(),
+                "IpAddr" => "uint8_t version (0 = IPv4, 1 = IPv6), then 4 or 16 address bytes".to_string(),
+                "SocketAddr" => "uint8_t version (0 = IPv4, 1 = IPv6), then 4 or 16 address bytes and the uint16_t port".to_string(),
+                _ => self.c_type(name)?.to_string(),
+            },
+            Schema::Option(item) => format!("uint8_t presence (0 = none, 1 = some), then {}", self.describe(item)?),
+            Schema::Sequence(item) => format!("uint32_t count, then the items, each {}", self.describe(item)?),
+            Schema::Array(item, len) => format!("{len} items, each {}", self.describe(item)?),
+            Schema::Map(key, value) => format!("uint32_t count, then the entries, each {} followed by {}", self.describe(key)?, self.describe(value)?),
+            Schema::Tuple(items) if items.is_empty() => "nothing".to_string(),
+            Schema::Tuple(items) => items.iter().map(|item| self.describe(item)).collect::<Result<Vec<_>, _>>()?.join(", then "),
+            Schema::Struct { name, .. } | Schema::Enum { name, .. } | Schema::IndexedStruct { name, .. } => name.to_string(),
+        })
+    }
+
+    fn c_type(&self, primitive: &'static str) -> Result<&str, CHeaderError>
+    {
+        match self.types.get(primitive_key(primitive))
+        {
+            Some(c_type) if primitive_size(primitive).is_some() => Ok(c_type),
+            _ => Err(CHeaderError::UnsupportedPrimitive(primitive)),
+        }
+    }
+}
+
+impl Default for CHeader
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+/// Constructs that cannot be represented in a C header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CHeaderError
+{
+    /// Only structs and enums can be declared, this schema was passed instead
+    Unnamed(Schema),
+    /// The primitive has no C type mapped, see [`CHeader::with_type`]
+    UnsupportedPrimitive(&'static str),
+    /// Two different types have the same name
+    DuplicateName(&'static str),
+}
+
+impl std::fmt::Display for CHeaderError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self
+        {
+            CHeaderError::Unnamed(schema) => write!(f, "Only structs and enums can be declared in C, found {schema:?}"),
+            CHeaderError::UnsupportedPrimitive(name) => write!(f, "No C type is mapped to {name}"),
+            CHeaderError::DuplicateName(name) => write!(f, "Different types are named {name}"),
+        }
+    }
+}
+
+impl std::error::Error for CHeaderError {}
+
+/// Appends the named schemas in `schema` to `out`, contained types first
+fn collect_named(schema: &Schema, out: &mut Vec<Schema>) -> Result<(), CHeaderError>
+{
+    match schema
+    {
+        Schema::Primitive(_) => {},
+        Schema::Option(item) | Schema::Sequence(item) | Schema::Array(item, _) => collect_named(item, out)?,
+        Schema::Map(key, value) => {
+            collect_named(key, out)?;
+            collect_named(value, out)?;
+        },
+        Schema::Tuple(items) => {
+            for item in items
+            {
+                collect_named(item, out)?;
+            }
+        },
+        Schema::Struct { name, fields } => {
+            for field in fields
+            {
+                collect_named(&field.schema, out)?;
+            }
+            push_named(name, schema, out)?;
+        },
+        Schema::Enum { name, variants } => {
+            for field in variants.iter().flat_map(|variant| &variant.fields)
+            {
+                collect_named(&field.schema, out)?;
+            }
+            push_named(name, schema, out)?;
+        },
+        Schema::IndexedStruct { name, fields } => {
+            for field in fields
+            {
+                collect_named(&field.field.schema, out)?;
+            }
+            push_named(name, schema, out)?;
+        },
+    }
+    Ok(())
+}
+
+fn push_named(name: &'static str, schema: &Schema, out: &mut Vec<Schema>) -> Result<(), CHeaderError>
+{
+    let same_name = out.iter().find(|other| matches!(other, Schema::Struct { name: other, .. } | Schema::Enum { name: other, .. } | Schema::IndexedStruct { name: other, .. } if *other == name));
+    match same_name
+    {
+        None => out.push(schema.clone()),
+        Some(other) if other == schema => {},
+        Some(_) => return Err(CHeaderError::DuplicateName(name)),
+    }
+    Ok(())
+}
+
+/// Returns the size of the serialization of values with this layout, if it is always the same.
+/// Enums have a fixed size only when none of their variants has fields.
+fn fixed_size(schema: &Schema) -> Option<usize>
+{
+    match schema
+    {
+        Schema::Primitive(name) => primitive_size(name),
+        Schema::Option(_) | Schema::Sequence(_) | Schema::Map(_, _) | Schema::IndexedStruct { .. } => None,
+        Schema::Array(item, len) => fixed_size(item).map(|size| size * len),
+        Schema::Tuple(items) => items.iter().map(fixed_size).sum(),
+        Schema::Struct { fields, .. } => fields.iter().map(|field| fixed_size(&field.schema)).sum(),
+        Schema::Enum { variants, .. } => variants.iter().all(|variant| variant.fields.is_empty()).then_some(1),
+    }
+}
+
+fn primitive_size(name: &str) -> Option<usize>
+{
+    match primitive_key(name)
+    {
+        "u8" | "i8" | "bool" => Some(1),
+        "u16" | "i16" => Some(2),
+        "u32" | "i32" | "f32" | "char" | "Ipv4Addr" => Some(4),
+        "SocketAddrV4" => Some(6),
+        "u64" | "i64" | "f64" | "SystemTime" => Some(8),
+        "u128" | "i128" => Some(16),
+        _ => None,
+    }
+}
+
+/// Returns the name of a primitive without its path, such as `Ipv4Addr` for `std::net::Ipv4Addr`
+fn primitive_key(name: &str) -> &str
+{
+    name.rsplit("::").next().unwrap_or(name).trim()
+}
+
+/// Returns a C identifier for a field, unnamed fields are named by their position and become `_0`, `_1`...
+fn identifier(name: &str) -> String
+{
+    if name.starts_with(|c: char| c.is_ascii_digit())
+    {
+        format!("_{name}")
+    }
+    else
+    {
+        name.to_string()
+    }
+}
+
+/// Converts `CamelCase` and `snake_case` names to `SCREAMING_SNAKE_CASE` for macros
+fn screaming_snake(name: &str) -> String
+{
+    let mut ret = String::new();
+    let mut previous: Option<char> = None;
+    for c in name.chars()
+    {
+        if c.is_uppercase() && previous.is_some_and(|previous| previous.is_lowercase() || previous.is_ascii_digit())
+        {
+            ret.push('_');
+        }
+        ret.extend(c.to_uppercase());
+        previous = Some(c);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{to_c_header, CHeader, CHeaderError};
+    use crate as serializable;
+    use crate::schema::{Describe, Schema};
+    use crate::Serializable;
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    struct SensorReading
+    {
+        sensor_id: u16,
+        celsius: f32,
+        taken_at: std::time::SystemTime,
+        flags: [u8; 3],
+        state: SensorState,
+    }
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    enum SensorState
+    {
+        Idle,
+        Measuring,
+        Faulty,
+    }
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    struct Position(i32, i32);
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    struct Telemetry
+    {
+        device: String,
+        origin: Position,
+        readings: Vec<SensorReading>,
+        battery: Option<u8>,
+        limits: (u16, u16),
+    }
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    enum Command
+    {
+        Reboot,
+        SetInterval { millis: u32 },
+        Rename(String),
+    }
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    struct Huge
+    {
+        value: u128,
+    }
+
+    #[test]
+    fn generate_golden_header()
+    {
+        let header = to_c_header(&[Telemetry::describe(), Command::describe()]).unwrap();
+        assert_eq!(header, include_str!("testdata/telemetry.h"));
+    }
+
+    #[test]
+    fn map_primitives_to_custom_types()
+    {
+        assert_eq!(to_c_header(&[Huge::describe()]), Err(CHeaderError::UnsupportedPrimitive("u128")));
+        let header = CHeader::new()
+            .with_type("u128", "unsigned __int128")
+            .with_include_guard("HUGE_H")
+            .generate(&[Huge::describe()])
+            .unwrap();
+        assert!(header.contains("    unsigned __int128 value;\n"));
+        assert!(header.contains("#define HUGE_SIZE 16\n"));
+        assert!(header.starts_with("/* Generated by serializable, do not edit."));
+        assert!(header.contains("#ifndef HUGE_H\n"));
+    }
+
+    #[test]
+    fn reject_constructs_without_c_representation()
+    {
+        assert_eq!(to_c_header(&[u32::describe()]), Err(CHeaderError::Unnamed(Schema::Primitive("u32"))));
+        mod other
+        {
+            use crate as serializable;
+            use crate::Serializable;
+
+            #[derive(Serializable)]
+            #[serializable(describe)]
+            pub struct Position(pub u8);
+        }
+        let error = to_c_header(&[Position::describe(), other::Position::describe()]).unwrap_err();
+        assert_eq!(error, CHeaderError::DuplicateName("Position"));
+    }
+}
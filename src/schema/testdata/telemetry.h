@@ -0,0 +1,63 @@
+/* Generated by serializable, do not edit.
+ * Fields are packed in wire order and multi-byte numbers are big endian. */
+#ifndef SERIALIZABLE_SCHEMA_H
+#define SERIALIZABLE_SCHEMA_H
+
+#include <stdint.h>
+
+/* Reads the big endian uint32_t that prefixes strings, sequences and maps */
+static inline uint32_t serializable_read_len(const uint8_t *data)
+{
+    return ((uint32_t)data[0] << 24) | ((uint32_t)data[1] << 16) | ((uint32_t)data[2] << 8) | (uint32_t)data[3];
+}
+
+/* Position: 8 bytes */
+#define POSITION_SIZE 8
+typedef struct __attribute__((packed)) Position
+{
+    int32_t _0;
+    int32_t _1;
+} Position;
+
+/* SensorState: a uint8_t tag followed by the fields of the variant */
+#define SENSOR_STATE_IDLE 0
+#define SENSOR_STATE_MEASURING 1
+#define SENSOR_STATE_FAULTY 2
+
+/* SensorReading: 18 bytes */
+#define SENSOR_READING_SIZE 18
+typedef struct __attribute__((packed)) SensorReading
+{
+    uint16_t sensor_id;
+    float celsius;
+    uint64_t taken_at;
+    uint8_t flags[3];
+    uint8_t state; /* SensorState */
+} SensorReading;
+
+/* Telemetry: variable size, the fields follow each other in this order
+ *   device: uint32_t byte count, then the UTF-8 bytes
+ *   Position origin;
+ *   readings: uint32_t count, then the items, each SensorReading
+ *   battery: uint8_t presence (0 = none, 1 = some), then uint8_t
+ *   uint16_t limits_0;
+ *   uint16_t limits_1;
+ */
+
+/* Command: a uint8_t tag followed by the fields of the variant */
+#define COMMAND_REBOOT 0
+#define COMMAND_SET_INTERVAL 1
+#define COMMAND_RENAME 2
+
+/* Command::SetInterval: 4 bytes */
+#define COMMAND_SET_INTERVAL_SIZE 4
+typedef struct __attribute__((packed)) Command_SetInterval
+{
+    uint32_t millis;
+} Command_SetInterval;
+
+/* Command::Rename: variable size, the fields follow each other in this order
+ *   _0: uint32_t byte count, then the UTF-8 bytes
+ */
+
+#endif /* SERIALIZABLE_SCHEMA_H */
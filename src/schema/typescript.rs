@@ -0,0 +1,244 @@
+//! Generation of TypeScript declarations documenting the wire layout of serializable types,
+//! for web frontends that receive the serialized bytes.
+
+use super::{Field, Schema};
+
+/// Returns TypeScript declarations for the structs and enums in `schemas` and every named type they contain.
+/// Structs become interfaces and enums unions of their variants, every field is documented with its wire layout.
+/// Numbers wider than 32 bits are `bigint`, addresses are strings and maps are `Map`s.
+pub fn to_typescript(schemas: &[Schema]) -> String
+{
+    let mut named = Vec::new();
+    for schema in schemas
+    {
+        collect_named(schema, &mut named);
+    }
+    let mut ret = String::from("// Generated by serializable, do not edit.\n");
+    for schema in named
+    {
+        ret.push('\n');
+        match schema
+        {
+            Schema::Struct { name, fields } => {
+                ret.push_str(&format!("/** {name}: the fields follow each other in this order */\n"));
+                ret.push_str(&format!("export interface {name}\n{{\n"));
+                write_fields(&mut ret, fields, "    ");
+                ret.push_str("}\n");
+            },
+            Schema::Enum { name, variants } => {
+                ret.push_str(&format!("/** {name}: a u8 tag followed by the fields of the variant */\n"));
+                ret.push_str(&format!("export type {name} =\n"));
+                for (tag, variant) in variants.iter().enumerate()
+                {
+                    ret.push_str(&format!("    | {{\n        tag: {tag};\n        variant: \"{}\";\n", variant.name));
+                    write_fields(&mut ret, &variant.fields, "        ");
+                    ret.push_str("    }\n");
+                }
+                ret.truncate(ret.len() - 1);
+                ret.push_str(";\n");
+            },
+            Schema::IndexedStruct { name, fields } => {
+                ret.push_str(&format!("/** {name}: a u16 field count, then every field as its u16 index, its u32 byte length and its value */\n"));
+                ret.push_str(&format!("export interface {name}\n{{\n"));
+                for field in fields
+                {
+                    write_field(&mut ret, &field.field, &format!("index {}, ", field.index), "    ");
+                }
+                ret.push_str("}\n");
+            },
+            _ => unreachable!("Only named schemas are collected"),
+        }
+    }
+    ret
+}
+
+fn write_fields(out: &mut String, fields: &[Field], indent: &str)
+{
+    for field in fields
+    {
+        write_field(out, field, "", indent);
+    }
+}
+
+/// Writes a field documented with its wire layout, preceded by `prefix`
+fn write_field(out: &mut String, field: &Field, prefix: &str, indent: &str)
+{
+    let name = if field.name.starts_with(|c: char| c.is_ascii_digit()) { format!("\"{}\"", field.name) } else { field.name.to_string() };
+    out.push_str(&format!("{indent}/** {prefix}{} */\n", wire_layout(&field.schema)));
+    out.push_str(&format!("{indent}{name}: {};\n", type_name(&field.schema)));
+}
+
+/// Appends the named schemas in `schema` to `out`, contained types first, keeping the first of types with the same name
+fn collect_named<'a>(schema: &'a Schema, out: &mut Vec<&'a Schema>)
+{
+    match schema
+    {
+        Schema::Primitive(_) => {},
+        Schema::Option(item) | Schema::Sequence(item) | Schema::Array(item, _) => collect_named(item, out),
+        Schema::Map(key, value) => {
+            collect_named(key, out);
+            collect_named(value, out);
+        },
+        Schema::Tuple(items) => items.iter().for_each(|item| collect_named(item, out)),
+        Schema::Struct { name, fields } => {
+            fields.iter().for_each(|field| collect_named(&field.schema, out));
+            push_named(name, schema, out);
+        },
+        Schema::Enum { name, variants } => {
+            variants.iter().flat_map(|variant| &variant.fields).for_each(|field| collect_named(&field.schema, out));
+            push_named(name, schema, out);
+        },
+        Schema::IndexedStruct { name, fields } => {
+            fields.iter().for_each(|field| collect_named(&field.field.schema, out));
+            push_named(name, schema, out);
+        },
+    }
+}
+
+fn push_named<'a>(name: &str, schema: &'a Schema, out: &mut Vec<&'a Schema>)
+{
+    let declared = out.iter().any(|other| matches!(other, Schema::Struct { name: other, .. } | Schema::Enum { name: other, .. } | Schema::IndexedStruct { name: other, .. } if *other == name));
+    if !declared
+    {
+        out.push(schema);
+    }
+}
+
+/// Returns the TypeScript type of the decoded value
+fn type_name(schema: &Schema) -> String
+{
+    match schema
+    {
+        Schema::Primitive(name) => match name.rsplit("::").next().unwrap_or(name).trim()
+        {
+            "u8" | "u16" | "u32" | "i8" | "i16" | "i32" | "f32" | "f64" => "number",
+            "u64" | "u128" | "i64" | "i128" | "SystemTime" => "bigint",
+            "bool" => "boolean",
+            "char" | "String" | "IpAddr" | "Ipv4Addr" | "SocketAddr" | "SocketAddrV4" => "string",
+            _ => "unknown",
+        }.to_string(),
+        Schema::Option(item) => format!("{} | null", type_name(item)),
+        Schema::Sequence(item) | Schema::Array(item, _) => match **item
+        {
+            Schema::Option(_) => format!("Array<{}>", type_name(item)),
+            _ => format!("{}[]", type_name(item)),
+        },
+        Schema::Map(key, value) => format!("Map<{}, {}>", type_name(key), type_name(value)),
+        Schema::Tuple(items) if items.is_empty() => "null".to_string(),
+        Schema::Tuple(items) => format!("[{}]", items.iter().map(type_name).collect::<Vec<_>>().join(", ")),
+        Schema::Struct { name, .. } | Schema::Enum { name, .. } | Schema::IndexedStruct { name, .. } => name.to_string(),
+    }
+}
+
+/// Returns a description of the bytes of a value
+fn wire_layout(schema: &Schema) -> String
+{
+    match schema
+    {
+        Schema::Primitive(name) => match name.rsplit("::").next().unwrap_or(name).trim()
+        {
+            "u8" | "i8" => format!("{name}, 1 byte"),
+            "bool" => "bool, 1 byte, 0 or 1".to_string(),
+            "char" => "u32 Unicode scalar value, big endian".to_string(),
+            "String" => "u32 byte count, then the UTF-8 bytes".to_string(),
+            "SystemTime" => "u64 seconds since the UNIX epoch, big endian".to_string(),
+            "Ipv4Addr" => "4 address bytes".to_string(),
+            "SocketAddrV4" => "4 address bytes, then the u16 port".to_string(),
+            "IpAddr" => "u8 version (0 = IPv4, 1 = IPv6), then 4 or 16 address bytes".to_string(),
+            "SocketAddr" => "u8 version (0 = IPv4, 1 = IPv6), then 4 or 16 address bytes and the u16 port".to_string(),
+            _ => format!("{name}, big endian"),
+        },
+        Schema::Option(item) => format!("u8 presence (0 = null, 1 = present), then {}", wire_layout(item)),
+        Schema::Sequence(item) => format!("u32 count, then the items, each {}", wire_layout(item)),
+        Schema::Array(item, len) => format!("{len} items, each {}", wire_layout(item)),
+        Schema::Map(key, value) => format!("u32 count, then the entries, each {} followed by {}", wire_layout(key), wire_layout(value)),
+        Schema::Tuple(items) if items.is_empty() => "nothing".to_string(),
+        Schema::Tuple(items) => items.iter().map(wire_layout).collect::<Vec<_>>().join(", then "),
+        Schema::Struct { name, .. } | Schema::Enum { name, .. } | Schema::IndexedStruct { name, .. } => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::to_typescript;
+    use crate as serializable;
+    use crate::schema::Describe;
+    use crate::Serializable;
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    struct Peer
+    {
+        addr: std::net::SocketAddr,
+        last_seen: std::time::SystemTime,
+    }
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    enum Event
+    {
+        Joined(Peer),
+        Left { id: u64, reason: Option<String> },
+    }
+
+    #[derive(Serializable)]
+    #[serializable(describe)]
+    struct Snapshot
+    {
+        version: u32,
+        checksum: [u8; 4],
+        peers: Vec<Peer>,
+        events: Vec<Event>,
+        scores: std::collections::BTreeMap<String, f64>,
+    }
+
+    #[test]
+    fn generate_typescript_declarations()
+    {
+        let expected = r#"// Generated by serializable, do not edit.
+
+/** Peer: the fields follow each other in this order */
+export interface Peer
+{
+    /** u8 version (0 = IPv4, 1 = IPv6), then 4 or 16 address bytes and the u16 port */
+    addr: string;
+    /** u64 seconds since the UNIX epoch, big endian */
+    last_seen: bigint;
+}
+
+/** Event: a u8 tag followed by the fields of the variant */
+export type Event =
+    | {
+        tag: 0;
+        variant: "Joined";
+        /** Peer */
+        "0": Peer;
+    }
+    | {
+        tag: 1;
+        variant: "Left";
+        /** u64, big endian */
+        id: bigint;
+        /** u8 presence (0 = null, 1 = present), then u32 byte count, then the UTF-8 bytes */
+        reason: string | null;
+    };
+
+/** Snapshot: the fields follow each other in this order */
+export interface Snapshot
+{
+    /** u32, big endian */
+    version: number;
+    /** 4 items, each u8, 1 byte */
+    checksum: number[];
+    /** u32 count, then the items, each Peer */
+    peers: Peer[];
+    /** u32 count, then the items, each Event */
+    events: Event[];
+    /** u32 count, then the entries, each u32 byte count, then the UTF-8 bytes followed by f64, big endian */
+    scores: Map<string, number>;
+}
+"#;
+        assert_eq!(to_typescript(&[Snapshot::describe()]), expected);
+    }
+}
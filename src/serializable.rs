@@ -1,3 +1,8 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::SystemTime;
 
 pub trait Serializable: Sized
@@ -6,33 +11,723 @@ pub trait Serializable: Sized
     fn serialize(&self) -> Vec<u8>;
     /// Returns the deserialized object and the number of bytes read
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)>;
+
+    /// Appends the serialized object to `out`. Containers and derived types call it for their items,
+    /// so that a value is written into a single buffer instead of a new one for every nested value.
+    /// The default appends the result of [`Serializable::serialize`].
+    fn serialize_append(&self, out: &mut Vec<u8>)
+    {
+        out.extend(self.serialize());
+    }
+
+    /// Returns the serialized object like [`Serializable::serialize`], but equal values always
+    /// give the same bytes. Types whose serialization depends on something other than their value,
+    /// like the iteration order of a [`HashMap`], override it; containers forward it to their items.
+    fn serialize_deterministic(&self) -> Vec<u8>
+    {
+        self.serialize()
+    }
+
+    /// Returns the deserialized object, failing if `data` contains bytes after it
+    fn deserialize_exact(data: &[u8]) -> std::io::Result<Self>
+    {
+        let (ret, len) = Self::deserialize(data)?;
+        if len != data.len()
+        {
+            return Err(error_at_offset(Error::TrailingBytes.into(), len));
+        }
+        Ok(ret)
+    }
+
+    /// Returns the length of the serialized object at the start of `data` without keeping it,
+    /// accepting and rejecting the same data as [`Serializable::deserialize`].
+    /// Strings, sequences, arrays, options, tuples and most derived types override it so that nothing is allocated.
+    /// Maps, sets, indexed structs and structs with tag fields are still deserialized,
+    /// because finding duplicate keys or reading a field needs the values read before it.
+    fn check_bytes(data: &[u8]) -> std::io::Result<usize>
+    {
+        Self::deserialize(data).map(|(_, len)| len)
+    }
+
+    /// Returns the deserialized object and the number of bytes read like [`Serializable::deserialize`],
+    /// skipping the checks that only fail on data that was not written by [`Serializable::serialize`]:
+    /// strings are not checked to be valid UTF-8, and bool, option and enum tags and duplicate map keys
+    /// and set items are only checked with `debug_assert!`. Lengths are still checked, truncated data is an error.
+    /// Indexed structs and structs with tag fields are deserialized with all the checks.
+    ///
+    /// # Safety
+    ///
+    /// `data` must start with the unmodified output of [`Serializable::serialize`] or
+    /// [`Serializable::serialize_deterministic`] for a value of the same type,
+    /// like data produced moments ago by the same process or protected by a checksum.
+    /// Anything else may build a `String` that is not UTF-8, which is undefined behavior.
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)>
+    {
+        Self::deserialize(data)
+    }
+
+    /// Writes the serialized object to `writer`.
+    /// Types that can write themselves without building the whole serialization first override it.
+    fn serialize_into(&self, writer: &mut impl std::io::Write) -> std::io::Result<()>
+    {
+        writer.write_all(&self.serialize())
+    }
+
+    /// Reads a single object from `reader` without consuming any byte after it,
+    /// returning it and the number of bytes read.
+    ///
+    /// Objects with a fixed size are read with a single exact read. Strings, collections, options, tuples
+    /// and derived types read their length prefixes, tags and fields in order, so each byte is read once.
+    /// Other types are read one byte at a time until the bytes read so far deserialize, so the cost grows
+    /// with the square of the length of the object, and invalid data is read until the end of the stream.
+    /// Fails with [`std::io::ErrorKind::UnexpectedEof`] if the stream ends before the object is complete.
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)>
+    {
+        if let Some(size) = Self::FIXED_SIZE
+        {
+            let mut buffer = vec![0; size];
+            reader.read_exact(&mut buffer)?;
+            return Self::deserialize(&buffer);
+        }
+        let mut buffer = Vec::new();
+        loop
+        {
+            if let Ok(ret) = Self::deserialize(&buffer)
+            {
+                return Ok(ret);
+            }
+            let mut byte = [0];
+            match reader.read(&mut byte)
+            {
+                Ok(0) => return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Stream ended inside a value")),
+                Ok(_) => buffer.push(byte[0]),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Writes the serialized object to `writer` like [`Serializable::serialize_into`],
+    /// returning the number of bytes written
+    fn write_to<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<usize>
+    {
+        let bytes = self.serialize();
+        writer.write_all(&bytes)?;
+        Ok(bytes.len())
+    }
+
+    /// Reads a single object from `reader` like [`Serializable::deserialize_from`],
+    /// without consuming any byte after it
+    fn read_from<R: std::io::Read>(reader: &mut R) -> std::io::Result<Self>
+    {
+        Self::deserialize_from(reader).map(|(ret, _)| ret)
+    }
+
+    /// Returns the number of bytes that [`Serializable::serialize`] writes for `self`
+    /// if it is known without serializing it, `Some` for the types with a fixed size
+    fn serialized_size_hint(&self) -> Option<usize>
+    {
+        Self::FIXED_SIZE
+    }
+
+    /// Returns the number of bytes that [`Serializable::serialize`] writes for `self`.
+    /// The provided impls and derived types add up the sizes of their parts without allocating,
+    /// the default serializes the value unless the type has a fixed size.
+    fn serialized_size(&self) -> usize
+    {
+        match Self::FIXED_SIZE
+        {
+            Some(size) => size,
+            None => self.serialize().len(),
+        }
+    }
+
+    /// Length of the serialization of every value if it is always the same,
+    /// equal to [`FixedSize::SIZE`] for the types implementing it
+    #[doc(hidden)]
+    const FIXED_SIZE: Option<usize> = None;
+
+    /// Appends the serialization of every item in `items` to `out`,
+    /// used by the collection impls so that primitive types can be encoded in bulk
+    #[doc(hidden)]
+    fn serialize_slice(items: &[Self], out: &mut Vec<u8>)
+    {
+        for item in items
+        {
+            item.serialize_append(out);
+        }
+    }
+
+    /// Deserializes `len` consecutive items, returning them and the number of bytes read,
+    /// used by the collection impls so that primitive types can be decoded in bulk
+    #[doc(hidden)]
+    fn deserialize_vec(data: &[u8], len: usize) -> std::io::Result<(Vec<Self>,usize)>
+    {
+        check_item_count::<Self>(len, data.len())?;
+        if let Some(size) = Self::FIXED_SIZE.filter(|size| *size > 0)
+        {
+            // Every item has the same size, so truncated input is detected before parsing anything
+            let total = len * size;
+            let mut ret = Vec::with_capacity(len);
+            for (i, chunk) in data[..total].chunks_exact(size).enumerate()
+            {
+                let (item, _) = Self::deserialize(chunk).map_err(|e| error_at_offset(crate::trace::in_item(e, i), i * size))?;
+                ret.push(item);
+            }
+            return Ok((ret, total));
+        }
+        let mut ret = Vec::with_capacity(bounded_capacity(len, data.len()));
+        let mut read = 0;
+        for i in 0..len
+        {
+            // A length past the end of the data is rejected instead of panicking or overflowing
+            let (item, item_len) = match data.get(read..)
+            {
+                Some(rest) => Self::deserialize(rest).map_err(|e| error_at_offset(crate::trace::in_item(e, i), read))?,
+                None => return Err(Error::UnexpectedEof { needed: read, available: data.len() }.into()),
+            };
+            ret.push(item);
+            read = read.checked_add(item_len)
+                .ok_or_else(|| std::io::Error::from(Error::UnexpectedEof { needed: usize::MAX, available: data.len() }))?;
+        }
+        Ok((ret, read))
+    }
+}
+
+/// Checks `len` consecutive items like [`Serializable::deserialize_vec`] without allocating,
+/// returning the number of bytes read
+pub(crate) fn check_items<T: Serializable>(data: &[u8], len: usize) -> std::io::Result<usize>
+{
+    check_item_count::<T>(len, data.len())?;
+    if let Some(size) = T::FIXED_SIZE.filter(|size| *size > 0)
+    {
+        let total = len * size;
+        for (i, chunk) in data[..total].chunks_exact(size).enumerate()
+        {
+            T::check_bytes(chunk).map_err(|e| error_at_offset(e, i * size))?;
+        }
+        return Ok(total);
+    }
+    let mut read: usize = 0;
+    for _ in 0..len
+    {
+        let item_len = match data.get(read..)
+        {
+            Some(rest) => T::check_bytes(rest).map_err(|e| error_at_offset(e, read))?,
+            None => return Err(Error::UnexpectedEof { needed: read, available: data.len() }.into()),
+        };
+        read = read.checked_add(item_len)
+            .ok_or_else(|| std::io::Error::from(Error::UnexpectedEof { needed: usize::MAX, available: data.len() }))?;
+    }
+    Ok(read)
+}
+
+/// Deserializes `len` consecutive items with [`Serializable::deserialize_trusted`],
+/// items with a fixed size are decoded in bulk like [`Serializable::deserialize_vec`] does
+///
+/// # Safety
+///
+/// Same as [`Serializable::deserialize_trusted`]
+pub(crate) unsafe fn deserialize_items_trusted<T: Serializable>(data: &[u8], len: usize) -> std::io::Result<(Vec<T>,usize)>
+{
+    if T::FIXED_SIZE.is_some()
+    {
+        return T::deserialize_vec(data, len);
+    }
+    check_item_count::<T>(len, data.len())?;
+    let mut ret = Vec::with_capacity(bounded_capacity(len, data.len()));
+    let mut read: usize = 0;
+    for i in 0..len
+    {
+        let (item, item_len) = match data.get(read..)
+        {
+            Some(rest) => unsafe { T::deserialize_trusted(rest) }.map_err(|e| error_at_offset(crate::trace::in_item(e, i), read))?,
+            None => return Err(Error::UnexpectedEof { needed: read, available: data.len() }.into()),
+        };
+        ret.push(item);
+        read = read.checked_add(item_len)
+            .ok_or_else(|| std::io::Error::from(Error::UnexpectedEof { needed: usize::MAX, available: data.len() }))?;
+    }
+    Ok((ret, read))
+}
+
+/// Types whose serialization always has the same length, known at compile time.
+/// The derive implements it for structs whose fields are all `FixedSize`.
+pub trait FixedSize: Serializable
+{
+    /// Length in bytes of the serialization of every value of the type
+    const SIZE: usize;
+
+    /// Writes the serialization in the first [`FixedSize::SIZE`] bytes of `out` without allocating,
+    /// panics if `out` is shorter than that
+    fn serialize_to_slice(&self, out: &mut [u8]);
+
+    /// Returns the serialized object as an array on the stack,
+    /// fails if `N` is not [`FixedSize::SIZE`]
+    fn serialize_fixed<const N: usize>(&self) -> std::io::Result<[u8; N]>
+    {
+        if N != Self::SIZE
+        {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Array length does not match the serialized size"));
+        }
+        let mut ret = [0; N];
+        self.serialize_to_slice(&mut ret);
+        Ok(ret)
+    }
+}
+
+/// Enums whose tag can be stored apart from the fields of the variant.
+/// The derive implements it for every enum, so that a struct field marked
+/// `#[serializable(tag_field = "kind")]` is written without its tag, which is taken from the
+/// preceding field `kind` instead. The tag of a variant is its position in the enum.
+///
+/// The schema of such a struct still describes the field as tagged.
+pub trait ExternallyTagged: Serializable
+{
+    /// Returns the tag of the variant, the value that the tag field must have
+    fn tag(&self) -> u8;
+
+    /// Deserializes the fields of the variant with the given tag, the tag itself is not in `data`
+    fn deserialize_variant(tag: u8, data: &[u8]) -> std::io::Result<(Self,usize)>;
+}
+
+/// Implements the fixed size, append and bulk slice methods of [`Serializable`] for a fixed-width number type
+/// by converting every item with `to_be_bytes`/`from_be_bytes` in a single pass
+macro_rules! bulk_number_methods {
+    ($ty:ty) => {
+        const FIXED_SIZE: Option<usize> = Some(std::mem::size_of::<$ty>());
+
+        fn serialize_append(&self, out: &mut Vec<u8>)
+        {
+            out.extend_from_slice(&self.to_be_bytes());
+        }
+
+        fn serialize_slice(items: &[Self], out: &mut Vec<u8>)
+        {
+            out.reserve(items.len() * std::mem::size_of::<$ty>());
+            for item in items
+            {
+                out.extend_from_slice(&item.to_be_bytes());
+            }
+        }
+
+        fn deserialize_vec(data: &[u8], len: usize) -> std::io::Result<(Vec<Self>,usize)>
+        {
+            const SIZE: usize = std::mem::size_of::<$ty>();
+            match len.checked_mul(SIZE)
+            {
+                Some(total) if total <= data.len() => {
+                    let ret = data[..total].chunks_exact(SIZE)
+                        .map(|chunk| <$ty>::from_be_bytes(chunk.try_into().expect("Chunk has the size of the type")))
+                        .collect();
+                    Ok((ret, total))
+                },
+                _ => Err(Error::UnexpectedEof { needed: len.saturating_mul(SIZE), available: data.len() }.into()),
+            }
+        }
+    };
+}
+
+/// Returns the sum of the fixed sizes of the fields of a type, or `None` if any of them is not fixed,
+/// used by the derive to compute [`Serializable::FIXED_SIZE`]
+#[doc(hidden)]
+pub const fn sum_fixed_sizes(sizes: &[Option<usize>]) -> Option<usize>
+{
+    let mut total: usize = 0;
+    let mut i = 0;
+    while i < sizes.len()
+    {
+        match sizes[i]
+        {
+            Some(size) => total += size,
+            None => return None,
+        }
+        i += 1;
+    }
+    Some(total)
+}
+
+/// Appends the deterministic serialization of every item to `out`, in bulk if they have a fixed size since they can't contain maps
+pub(crate) fn serialize_slice_deterministic<T: Serializable>(items: &[T], out: &mut Vec<u8>)
+{
+    if T::FIXED_SIZE.is_some()
+    {
+        T::serialize_slice(items, out);
+        return;
+    }
+    for item in items
+    {
+        out.extend(item.serialize_deterministic());
+    }
+}
+
+/// Returns the capacity to reserve for a collection whose length prefix claims `len` items
+/// while only `remaining` bytes are left to read.
+/// Every item is assumed to take at least one byte so that a forged prefix cannot force a huge allocation.
+pub(crate) fn bounded_capacity(len: usize, remaining: usize) -> usize
+{
+    std::cmp::min(len, remaining)
+}
+
+/// Returns the sum of the serialized sizes of `len` items, without looking at them if they have a fixed size
+pub(crate) fn items_size<'a, T: Serializable + 'a>(items: impl IntoIterator<Item = &'a T>, len: usize) -> usize
+{
+    match T::FIXED_SIZE
+    {
+        Some(size) => size * len,
+        None => items.into_iter().map(Serializable::serialized_size).sum(),
+    }
+}
+
+/// Default of [`set_max_zero_sized_items`]
+pub const DEFAULT_MAX_ZERO_SIZED_ITEMS: usize = 1 << 16;
+
+static MAX_ZERO_SIZED_ITEMS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_ZERO_SIZED_ITEMS);
+
+/// Sets the largest number of items that a deserialized collection can declare when its items are
+/// serialized in zero bytes, like `()` or a unit struct, for every thread. The number of other items
+/// is bounded by the length of the data, but these would be read in a loop as long as the length prefix.
+pub fn set_max_zero_sized_items(limit: usize)
+{
+    MAX_ZERO_SIZED_ITEMS.store(limit, Ordering::Relaxed);
+}
+
+/// Fails if a collection declaring `len` items can't be read from `remaining` bytes,
+/// before any item is read. Items without a fixed size take at least one byte.
+pub(crate) fn check_item_count<T: Serializable>(len: usize, remaining: usize) -> std::io::Result<()>
+{
+    match T::FIXED_SIZE
+    {
+        Some(0) if len > MAX_ZERO_SIZED_ITEMS.load(Ordering::Relaxed) => {
+            Err(Error::LimitExceeded { what: "zero sized items", limit: MAX_ZERO_SIZED_ITEMS.load(Ordering::Relaxed) }.into())
+        },
+        Some(0) => Ok(()),
+        Some(size) if len.checked_mul(size).is_some_and(|total| total <= remaining) => Ok(()),
+        None if len <= remaining => Ok(()),
+        // Items without a fixed size take at least one byte each
+        size => Err(Error::UnexpectedEof { needed: len.saturating_mul(size.unwrap_or(1)), available: remaining }.into()),
+    }
+}
+
+/// The reasons why a value can't be deserialized, detected by the impls of this crate and by derived code.
+/// It converts into a [`std::io::Error`] of kind [`std::io::ErrorKind::InvalidData`],
+/// use [`Error::find`] to get it back from the error returned by [`Serializable::deserialize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error
+{
+    /// The input ends before the value, `needed` bytes were required but only `available` were left
+    UnexpectedEof { needed: usize, available: usize },
+    /// The variant index `found` is not a variant of the enum `type_name`
+    InvalidVariant { found: u8, type_name: &'static str },
+    /// A string is not valid UTF-8
+    InvalidUtf8,
+    /// The value `found` is not a Unicode scalar value, so it is not a `char`
+    InvalidChar { found: u32 },
+    /// The input continues after the value
+    TrailingBytes,
+    /// A map holds the same key twice
+    DuplicateKey,
+    /// A set holds the same item twice
+    DuplicateItem,
+    /// An indexed struct holds the field with index `index` twice
+    DuplicateField { index: u16 },
+    /// The input declares more `what` than the configured `limit`
+    LimitExceeded { what: &'static str, limit: usize },
+    /// The bytes are well formed but do not encode a value of `type_name`, as explained by `reason`
+    InvalidValue { type_name: &'static str, reason: String },
+}
+
+impl Error
+{
+    /// Returns the reason carried by `error`, looking through the offset and the path of the failure
+    pub fn find(error: &std::io::Error) -> Option<&Error>
+    {
+        let error = DeserializeError::find(error).map_or(error, DeserializeError::error);
+        error.get_ref().and_then(|inner| inner.downcast_ref::<Error>())
+    }
+}
+
+impl std::fmt::Display for Error
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self
+        {
+            Error::UnexpectedEof { needed, available } => write!(f, "Unexpected end of data, {needed} bytes needed but {available} left"),
+            Error::InvalidVariant { found, type_name } => write!(f, "Invalid variant index {found} for {type_name}"),
+            Error::InvalidUtf8 => write!(f, "Invalid utf8 string format"),
+            Error::InvalidChar { found } => write!(f, "Invalid char value {found:#x}"),
+            Error::TrailingBytes => write!(f, "Trailing bytes after the serialized object"),
+            Error::DuplicateKey => write!(f, "Duplicate map key"),
+            Error::DuplicateItem => write!(f, "Duplicate set item"),
+            Error::DuplicateField { index } => write!(f, "Duplicate field index {index}"),
+            Error::LimitExceeded { what, limit } => write!(f, "Too many {what}, the limit is {limit}"),
+            Error::InvalidValue { type_name, reason } => write!(f, "Invalid {type_name}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for std::io::Error
+{
+    fn from(error: Error) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// A deserialization failure together with the offset in the input where it happened
+/// and the path of the value that failed.
+/// It is carried inside the [`std::io::Error`] returned by [`Serializable::deserialize`],
+/// use [`DeserializeError::find`] or [`DeserializeError::offset_of`] to get it back.
+#[derive(Debug)]
+pub struct DeserializeError
+{
+    offset: usize,
+    error: std::io::Error,
+    /// Path of the value that failed, recorded by derived types and collections
+    path: String,
+    /// Derived type that the path starts from
+    type_name: Option<&'static str>,
+}
+
+impl DeserializeError
+{
+    /// Returns the offset in the input of the value that could not be deserialized
+    pub fn offset(&self) -> usize
+    {
+        self.offset
+    }
+
+    /// Returns the error of the innermost value that could not be deserialized
+    pub fn error(&self) -> &std::io::Error
+    {
+        &self.error
+    }
+
+    /// Returns the offset information carried by `error`, if any
+    pub fn find(error: &std::io::Error) -> Option<&DeserializeError>
+    {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<DeserializeError>())
+    }
+
+    /// Returns the path of the value that could not be deserialized, like `peers[0].port`,
+    /// relative to the outermost derived type or collection that the error went through
+    pub fn path(&self) -> &str
+    {
+        &self.path
+    }
+
+    /// Returns the name of the derived type that the path starts from,
+    /// `None` if the outermost value that the error went through is not a derived type
+    pub fn type_name(&self) -> Option<&'static str>
+    {
+        self.type_name
+    }
+
+    /// Returns the offset in the input where the deserialization that returned `error` failed,
+    /// errors without offset information happened at the start of the input
+    pub fn offset_of(error: &std::io::Error) -> usize
+    {
+        Self::find(error).map_or(0, DeserializeError::offset)
+    }
+
+    fn new(offset: usize, error: std::io::Error) -> Self
+    {
+        DeserializeError { offset, error, path: String::new(), type_name: None }
+    }
+
+    /// Returns the offset information carried by `error`, adding it if it has none
+    fn take(error: std::io::Error) -> Self
+    {
+        let kind = error.kind();
+        match error.into_inner().map(|inner| inner.downcast::<DeserializeError>())
+        {
+            Some(Ok(inner)) => *inner,
+            Some(Err(inner)) => Self::new(0, std::io::Error::new(kind, inner)),
+            None => Self::new(0, std::io::Error::from(kind)),
+        }
+    }
+}
+
+impl std::fmt::Display for DeserializeError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.type_name.is_none() && self.path.is_empty()
+        {
+            return write!(f, "{} at offset {}", self.error, self.offset);
+        }
+        let separator = if self.type_name.is_some() && !self.path.is_empty() && !self.path.starts_with('[') { "." } else { "" };
+        write!(f, "while deserializing {}{separator}{} at offset {}: {}", self.type_name.unwrap_or(""), self.path, self.offset, self.error)
+    }
+}
+
+impl std::error::Error for DeserializeError
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Moves the offset of a deserialization error forward by `offset`, the position in the parent input
+/// of the value that failed. Every impl that deserializes values one after the other should
+/// call it on the errors of the inner values so that the offset is relative to the outermost input.
+#[doc(hidden)]
+pub fn error_at_offset(error: std::io::Error, offset: usize) -> std::io::Error
+{
+    if offset == 0
+    {
+        return error;
+    }
+    let kind = error.kind();
+    if DeserializeError::find(&error).is_some()
+    {
+        let mut inner = DeserializeError::take(error);
+        inner.offset += offset;
+        std::io::Error::new(kind, inner)
+    }
+    else
+    {
+        std::io::Error::new(kind, DeserializeError::new(offset, error))
+    }
+}
+
+/// Records that `error` happened while deserializing the derived type `type_name`, the root of its path
+pub(crate) fn error_in_type(error: std::io::Error, type_name: &'static str) -> std::io::Error
+{
+    let kind = error.kind();
+    let mut inner = DeserializeError::take(error);
+    inner.type_name = Some(type_name);
+    std::io::Error::new(kind, inner)
+}
+
+/// Returns the big endian `u32` prefix holding the length `len` of a string or a collection.
+///
+/// # Panics
+///
+/// Panics if `len` doesn't fit in a `u32`, instead of writing a wrapped length that would be read back as another value
+#[doc(hidden)]
+pub fn length_prefix(len: usize) -> [u8; 4]
+{
+    match try_length_prefix(len)
+    {
+        Ok(prefix) => prefix,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Returns the big endian `u32` prefix holding the length `len`, for the callers that can report an error.
+/// Fails with [`std::io::ErrorKind::InvalidInput`] if `len` doesn't fit in a `u32`.
+#[doc(hidden)]
+pub fn try_length_prefix(len: usize) -> std::io::Result<[u8; 4]>
+{
+    match u32::try_from(len)
+    {
+        Ok(len) => Ok(len.to_be_bytes()),
+        Err(_) => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("The length {len} does not fit in the u32 length prefix"))),
+    }
+}
+
+/// Returns the bytes of `data` from `offset`, failing instead of panicking when a value
+/// read before reported more bytes than `data` holds, used by derived code and by the containers
+#[doc(hidden)]
+pub fn remaining(data: &[u8], offset: usize) -> std::io::Result<&[u8]>
+{
+    match data.get(offset..)
+    {
+        Some(rest) => Ok(rest),
+        None => Err(Error::UnexpectedEof { needed: offset, available: data.len() }.into()),
+    }
+}
+
+/// Reads exactly `len` bytes from `reader` and appends them to `buffer`, which grows as the bytes
+/// arrive so that a length read from the stream can't allocate more memory than the stream holds
+pub(crate) fn read_bytes(reader: &mut impl std::io::Read, buffer: &mut Vec<u8>, len: usize) -> std::io::Result<()>
+{
+    use std::io::Read;
+    let start = buffer.len();
+    reader.take(len as u64).read_to_end(buffer)?;
+    if buffer.len() - start < len
+    {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Stream ended inside a value"));
+    }
+    Ok(())
+}
+
+/// Reads `len` consecutive items from `reader` like [`Serializable::deserialize_from`],
+/// returning them and the number of bytes read
+pub(crate) fn read_items<T: Serializable>(reader: &mut impl std::io::Read, len: usize) -> std::io::Result<(Vec<T>,usize)>
+{
+    // The stream has no known length, a forged one fails at its end
+    check_item_count::<T>(len, usize::MAX)?;
+    if let Some(size) = T::FIXED_SIZE.filter(|size| *size > 0)
+    {
+        // Checked not to overflow by check_item_count
+        let total = len * size;
+        let mut buffer = Vec::new();
+        read_bytes(reader, &mut buffer, total)?;
+        return T::deserialize_vec(&buffer, len);
+    }
+    let mut ret = Vec::new();
+    let mut read = 0;
+    for i in 0..len
+    {
+        let (item, item_len) = T::deserialize_from(reader).map_err(|e| error_at_offset(crate::trace::in_item(e, i), read))?;
+        ret.push(item);
+        read += item_len;
+    }
+    Ok((ret, read))
+}
+
+/// Prepends `segment`, a field name or an index like `[2]`, to the path of the value that failed.
+/// The path no longer starts from the type recorded before, which is forgotten.
+pub(crate) fn error_in_path(error: std::io::Error, segment: &str) -> std::io::Error
+{
+    let kind = error.kind();
+    let mut inner = DeserializeError::take(error);
+    if !inner.path.is_empty() && !inner.path.starts_with('[')
+    {
+        inner.path.insert(0, '.');
+    }
+    inner.path.insert_str(0, segment);
+    inner.type_name = None;
+    std::io::Error::new(kind, inner)
 }
 
 impl Serializable for std::net::SocketAddr
 {
     fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialized_size(&self) -> usize {
+        match self {
+            std::net::SocketAddr::V4(_) => 1 + 4 + 2,
+            std::net::SocketAddr::V6(_) => 1 + 16 + 2,
+        }
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
         match self {
             std::net::SocketAddr::V4(addr) => {
-                let mut vec = Vec::new();
-                vec.push(0);
-                vec.extend_from_slice(&addr.ip().octets());
-                vec.extend_from_slice(&addr.port().to_be_bytes());
-                vec
+                out.push(0);
+                out.extend_from_slice(&addr.ip().octets());
+                out.extend_from_slice(&addr.port().to_be_bytes());
             },
             std::net::SocketAddr::V6(addr) => {
-                let mut vec = Vec::new();
-                vec.push(1);
-                vec.extend_from_slice(&addr.ip().octets());
-                vec.extend_from_slice(&addr.port().to_be_bytes());
-                vec
-            }   
+                out.push(1);
+                out.extend_from_slice(&addr.ip().octets());
+                out.extend_from_slice(&addr.port().to_be_bytes());
+            }
         }
     }
 
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 1
+        if data.is_empty()
         {
-            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"));
+            Err(Error::UnexpectedEof { needed: 1, available: 0 }.into())
         }
         else 
         {
@@ -40,7 +735,7 @@ impl Serializable for std::net::SocketAddr
                 0 => {
                     if data.len() < 7
                     {
-                        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+                        Err(Error::UnexpectedEof { needed: 7, available: data.len() }.into())
                     }
                     else
                     {
@@ -53,7 +748,7 @@ impl Serializable for std::net::SocketAddr
                 1 => {
                     if data.len() < 19
                     {
-                        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+                        Err(Error::UnexpectedEof { needed: 19, available: data.len() }.into())
                     }
                     else
                     {
@@ -72,100 +767,577 @@ impl Serializable for std::net::SocketAddr
                         Ok((ret,19))
                     }
                 },
-                _ => {
-                    Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid address type"))
+                found => {
+                    Err(Error::InvalidVariant { found, type_name: "SocketAddr" }.into())
                 }
             }    
         }
     }
 }
 
-impl Serializable for String
+impl Serializable for std::net::IpAddr
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialized_size(&self) -> usize {
+        match self {
+            std::net::IpAddr::V4(_) => 1 + 4,
+            std::net::IpAddr::V6(_) => 1 + 16,
+        }
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        match self {
+            std::net::IpAddr::V4(addr) => {
+                out.push(0);
+                out.extend_from_slice(&addr.octets());
+            },
+            std::net::IpAddr::V6(addr) => {
+                out.push(1);
+                out.extend_from_slice(&addr.octets());
+            }
+        }
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        match data.first() {
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => {
+                match data.get(1..5) {
+                    Some(octets) => {
+                        let octets: [u8; 4] = octets.try_into().expect("Slice has 4 bytes");
+                        Ok((std::net::IpAddr::V4(octets.into()), 5))
+                    },
+                    None => Err(Error::UnexpectedEof { needed: 5, available: data.len() }.into()),
+                }
+            },
+            Some(1) => {
+                match data.get(1..17) {
+                    Some(octets) => {
+                        let octets: [u8; 16] = octets.try_into().expect("Slice has 16 bytes");
+                        Ok((std::net::IpAddr::V6(octets.into()), 17))
+                    },
+                    None => Err(Error::UnexpectedEof { needed: 17, available: data.len() }.into()),
+                }
+            },
+            Some(&found) => Err(Error::InvalidVariant { found, type_name: "IpAddr" }.into()),
+        }
+    }
+}
+
+/// Serialized as the address in network byte order, the same bytes as its octets
+impl Serializable for std::net::Ipv4Addr
+{
+    const FIXED_SIZE: Option<usize> = Some(4);
+
+    fn serialize(&self) -> Vec<u8> {
+        u32::from(*self).serialize()
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.octets());
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (addr, len) = u32::deserialize(data)?;
+        Ok((std::net::Ipv4Addr::from(addr), len))
+    }
+}
+
+impl FixedSize for std::net::Ipv4Addr
+{
+    const SIZE: usize = 4;
+
+    fn serialize_to_slice(&self, out: &mut [u8])
+    {
+        u32::from(*self).serialize_to_slice(out);
+    }
+}
+
+impl Serializable for std::net::SocketAddrV4
+{
+    const FIXED_SIZE: Option<usize> = Some(6);
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut vec = Vec::with_capacity(6);
+        self.serialize_append(&mut vec);
+        vec
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.ip().octets());
+        out.extend_from_slice(&self.port().to_be_bytes());
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        match data.first_chunk::<6>() {
+            Some(&[a, b, c, d, port_high, port_low]) => {
+                let ip = std::net::Ipv4Addr::new(a, b, c, d);
+                let port = u16::from_be_bytes([port_high, port_low]);
+                Ok((std::net::SocketAddrV4::new(ip, port), 6))
+            },
+            None => Err(Error::UnexpectedEof { needed: 6, available: data.len() }.into()),
+        }
+    }
+}
+
+impl FixedSize for std::net::SocketAddrV4
+{
+    const SIZE: usize = 6;
+
+    fn serialize_to_slice(&self, out: &mut [u8])
+    {
+        out[..4].copy_from_slice(&self.ip().octets());
+        out[4..6].copy_from_slice(&self.port().to_be_bytes());
+    }
+}
+
+impl Serializable for String
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut vec = Vec::with_capacity(4 + self.len());
+        self.serialize_append(&mut vec);
+        vec
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&length_prefix(self.len()));
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn serialized_size(&self) -> usize {
+        4 + self.len()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.len() < 4
+        {
+            Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into())
+        }
+        else
+        {
+            let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            let bytes = match 4usize.checked_add(len).and_then(|end| data.get(4..end))
+            {
+                Some(bytes) => bytes,
+                None => return Err(Error::UnexpectedEof { needed: len.saturating_add(4), available: data.len() }.into()),
+            };
+            match std::str::from_utf8(bytes)
+            {
+                Ok(string) => Ok((string.to_owned(), len + 4)),
+                Err(e) => {
+                    let error = Error::InvalidUtf8.into();
+                    Err(error_at_offset(error, 4 + e.valid_up_to()))
+                },
+            }
+        }
+    }
+
+    fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+        let len = match data.first_chunk::<4>()
+        {
+            Some(len) => u32::from_be_bytes(*len) as usize,
+            None => return Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into()),
+        };
+        let bytes = match 4usize.checked_add(len).and_then(|end| data.get(4..end))
+        {
+            Some(bytes) => bytes,
+            None => return Err(Error::UnexpectedEof { needed: len.saturating_add(4), available: data.len() }.into()),
+        };
+        match std::str::from_utf8(bytes)
+        {
+            Ok(_) => Ok(len + 4),
+            Err(e) => {
+                let error = Error::InvalidUtf8.into();
+                Err(error_at_offset(error, 4 + e.valid_up_to()))
+            },
+        }
+    }
+
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let len = match data.first_chunk::<4>()
+        {
+            Some(len) => u32::from_be_bytes(*len) as usize,
+            None => return Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into()),
+        };
+        let bytes = match 4usize.checked_add(len).and_then(|end| data.get(4..end))
+        {
+            Some(bytes) => bytes,
+            None => return Err(Error::UnexpectedEof { needed: len.saturating_add(4), available: data.len() }.into()),
+        };
+        debug_assert!(std::str::from_utf8(bytes).is_ok(), "Invalid utf8 string format");
+        // SAFETY: the caller guarantees that the bytes were written by serialize, from a valid string
+        Ok((unsafe { std::str::from_utf8_unchecked(bytes) }.to_owned(), len + 4))
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let mut buffer = Vec::new();
+        read_bytes(reader, &mut buffer, 4)?;
+        let len = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+        read_bytes(reader, &mut buffer, len)?;
+        Self::deserialize(&buffer)
+    }
+}
+
+impl <T: Serializable> Serializable for Vec<T>
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(4 + T::FIXED_SIZE.map_or(0, |size| size * self.len()));
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend(length_prefix(self.len()));
+        T::serialize_slice(self, out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        4 + items_size(self, self.len())
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        ret.extend(length_prefix(self.len()));
+        serialize_slice_deterministic(self, &mut ret);
+        ret
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.len() < 4
+        {
+            Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into())
+        }
+        else
+        {
+            let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            let (ret, read) = T::deserialize_vec(&data[4..], len as usize).map_err(|e| error_at_offset(e, 4))?;
+            match read.checked_add(4)
+            {
+                Some(read) => Ok((ret, read)),
+                None => Err(Error::UnexpectedEof { needed: usize::MAX, available: data.len() }.into()),
+            }
+        }
+    }
+
+    fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+        let len = match data.first_chunk::<4>()
+        {
+            Some(len) => u32::from_be_bytes(*len) as usize,
+            None => return Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into()),
+        };
+        let read = check_items::<T>(&data[4..], len).map_err(|e| error_at_offset(e, 4))?;
+        read.checked_add(4).ok_or_else(|| std::io::Error::from(Error::UnexpectedEof { needed: usize::MAX, available: data.len() }))
+    }
+
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let len = match data.first_chunk::<4>()
+        {
+            Some(len) => u32::from_be_bytes(*len) as usize,
+            None => return Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into()),
+        };
+        let (ret, read) = unsafe { deserialize_items_trusted::<T>(&data[4..], len) }.map_err(|e| error_at_offset(e, 4))?;
+        match read.checked_add(4)
+        {
+            Some(read) => Ok((ret, read)),
+            None => Err(Error::UnexpectedEof { needed: usize::MAX, available: data.len() }.into()),
+        }
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let (len, prefix) = u32::deserialize_from(reader)?;
+        let (ret, read) = read_items::<T>(reader, len as usize).map_err(|e| error_at_offset(e, prefix))?;
+        Ok((ret, prefix + read))
+    }
+}
+
+/// Serialized like [`Vec`], from the front to the back of the queue
+impl<T: Serializable> Serializable for VecDeque<T>
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend(length_prefix(self.len()));
+        let (front, back) = self.as_slices();
+        T::serialize_slice(front, out);
+        T::serialize_slice(back, out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        4 + items_size(self, self.len())
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        ret.extend(length_prefix(self.len()));
+        let (front, back) = self.as_slices();
+        serialize_slice_deterministic(front, &mut ret);
+        serialize_slice_deterministic(back, &mut ret);
+        ret
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (ret, read) = Vec::<T>::deserialize(data)?;
+        Ok((ret.into(), read))
+    }
+
+    fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+        Vec::<T>::check_bytes(data)
+    }
+
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (ret, read) = unsafe { Vec::<T>::deserialize_trusted(data) }?;
+        Ok((ret.into(), read))
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let (ret, read) = Vec::<T>::deserialize_from(reader)?;
+        Ok((ret.into(), read))
+    }
+}
+
+impl Serializable for u128
+{
+    fn serialize(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.len() < 16
+        {
+            Err(Error::UnexpectedEof { needed: 16, available: data.len() }.into())
+        }
+        else
+        {
+            let ret = u128::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
+                data[8], data[9], data[10], data[11], data[12], data[13], data[14], data[15]]);
+            Ok((ret, 16))
+        }
+    }
+
+    bulk_number_methods!(u128);
+}
+
+impl Serializable for u64
+{
+    fn serialize(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.len() < 8
+        {
+            Err(Error::UnexpectedEof { needed: 8, available: data.len() }.into())
+        }
+        else
+        {
+            let ret = u64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
+            Ok((ret, 8))
+        }
+    }
+
+    bulk_number_methods!(u64);
+}
+
+impl Serializable for u32
+{
+    fn serialize(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.len() < 4
+        {
+            Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into())
+        }
+        else
+        {
+            let ret = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            Ok((ret, 4))
+        }
+    }
+
+    bulk_number_methods!(u32);
+}
+
+impl Serializable for u16
+{
+    fn serialize(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.len() < 2
+        {
+            Err(Error::UnexpectedEof { needed: 2, available: data.len() }.into())
+        }
+        else
+        {
+            let ret = u16::from_be_bytes([data[0], data[1]]);
+            Ok((ret, 2))
+        }
+    }
+
+    bulk_number_methods!(u16);
+}
+
+impl Serializable for u8
+{
+    const FIXED_SIZE: Option<usize> = Some(1);
+
+    fn serialize_slice(items: &[Self], out: &mut Vec<u8>)
+    {
+        out.extend_from_slice(items);
+    }
+
+    fn deserialize_vec(data: &[u8], len: usize) -> std::io::Result<(Vec<Self>,usize)>
+    {
+        match data.get(..len)
+        {
+            Some(bytes) => Ok((bytes.to_vec(), len)),
+            None => Err(Error::UnexpectedEof { needed: len, available: data.len() }.into()),
+        }
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        vec![*self]
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.is_empty()
+        {
+            Err(Error::UnexpectedEof { needed: 1, available: 0 }.into())
+        }
+        else
+        {
+            Ok((data[0], 1))
+        }
+    }
+}
+
+impl Serializable for i128
 {
     fn serialize(&self) -> Vec<u8> {
-        let mut vec = Vec::new();
-        vec.extend_from_slice(&mut (self.len() as u32).to_be_bytes());
-        vec.extend_from_slice(self.as_bytes());
-        vec
+        self.to_be_bytes().to_vec()
     }
 
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 4
+        if data.len() < 16
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            Err(Error::UnexpectedEof { needed: 16, available: data.len() }.into())
         }
         else
         {
-            let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
-            if data.len() < (len + 4) as usize
-            {
-                Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
-            }
-            else
-            {
-                let mut vec = Vec::new();
-                vec.extend_from_slice(&data[4..(len + 4) as usize]);
-                let ret = String::from_utf8(vec).map_err(|e|std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Invalid utf8 string format {e}")))?;
-                Ok((ret, (len + 4) as usize))
-            }
+            let ret = i128::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
+                data[8], data[9], data[10], data[11], data[12], data[13], data[14], data[15]]);
+            Ok((ret, 16))
         }
     }
+
+    bulk_number_methods!(i128);
 }
 
-impl <T: Serializable> Serializable for Vec<T>
+impl Serializable for i64
 {
     fn serialize(&self) -> Vec<u8> {
-        let mut ret = Vec::new();
-        ret.extend((self.len() as u32).to_be_bytes());
-        for item in self
+        self.to_be_bytes().to_vec()
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.len() < 8
         {
-            ret.extend(item.serialize());
+            Err(Error::UnexpectedEof { needed: 8, available: data.len() }.into())
         }
-        ret
+        else
+        {
+            let ret = i64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
+            Ok((ret, 8))
+        }
+    }
+
+    bulk_number_methods!(i64);
+}
+
+impl Serializable for i32
+{
+    fn serialize(&self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
     }
 
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
         if data.len() < 4
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into())
         }
         else
         {
-            let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
-            let mut ret = Vec::new();
-            let mut read = 4;
-            for _ in 0..len
-            {
-                let (item, item_len) = T::deserialize(&data[read..])?;
-                ret.push(item);
-                read += item_len;
-            }
-            Ok((ret, read))
+            let ret = i32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            Ok((ret, 4))
         }
     }
+
+    bulk_number_methods!(i32);
 }
 
-impl Serializable for u128
+impl Serializable for i16
 {
     fn serialize(&self) -> Vec<u8> {
         self.to_be_bytes().to_vec()
     }
 
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 16
+        if data.len() < 2
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            Err(Error::UnexpectedEof { needed: 2, available: data.len() }.into())
         }
         else
         {
-            let ret = u128::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
-                data[8], data[9], data[10], data[11], data[12], data[13], data[14], data[15]]);
-            Ok((ret, 16))
+            let ret = i16::from_be_bytes([data[0], data[1]]);
+            Ok((ret, 2))
         }
     }
+
+    bulk_number_methods!(i16);
 }
 
-impl Serializable for u64
+impl Serializable for i8
+{
+    const FIXED_SIZE: Option<usize> = Some(1);
+
+    fn serialize(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.is_empty()
+        {
+            Err(Error::UnexpectedEof { needed: 1, available: 0 }.into())
+        }
+        else
+        {
+            Ok((data[0] as i8, 1))
+        }
+    }
+}
+
+/// The IEEE 754 bit pattern is preserved as-is, so NaN payloads and infinities survive a roundtrip
+impl Serializable for f64
 {
     fn serialize(&self) -> Vec<u8> {
         self.to_be_bytes().to_vec()
@@ -174,17 +1346,20 @@ impl Serializable for u64
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
         if data.len() < 8
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            Err(Error::UnexpectedEof { needed: 8, available: data.len() }.into())
         }
         else
         {
-            let ret = u64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
+            let ret = f64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
             Ok((ret, 8))
         }
     }
+
+    bulk_number_methods!(f64);
 }
 
-impl Serializable for u32
+/// The IEEE 754 bit pattern is preserved as-is, so NaN payloads and infinities survive a roundtrip
+impl Serializable for f32
 {
     fn serialize(&self) -> Vec<u8> {
         self.to_be_bytes().to_vec()
@@ -193,282 +1368,923 @@ impl Serializable for u32
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
         if data.len() < 4
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            Err(Error::UnexpectedEof { needed: 4, available: data.len() }.into())
         }
         else
         {
-            let ret = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            let ret = f32::from_be_bytes([data[0], data[1], data[2], data[3]]);
             Ok((ret, 4))
         }
     }
+
+    bulk_number_methods!(f32);
 }
 
-impl Serializable for u16
+/// Implements [`FixedSize`] for number types serialized with `to_be_bytes`
+macro_rules! impl_fixed_size_number {
+    ($($ty:ty),*) => {
+        $(
+            impl FixedSize for $ty
+            {
+                const SIZE: usize = std::mem::size_of::<$ty>();
+
+                fn serialize_to_slice(&self, out: &mut [u8])
+                {
+                    out[..Self::SIZE].copy_from_slice(&self.to_be_bytes());
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_size_number!(u128, u64, u32, u16, u8, i128, i64, i32, i16, i8, f64, f32);
+
+impl Serializable for bool
 {
+    const FIXED_SIZE: Option<usize> = Some(1);
+
     fn serialize(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+        match self {
+            false => vec![0],
+            true => vec![1]
+        }
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
     }
 
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 2
+        if data.is_empty()
+        {
+            Err(Error::UnexpectedEof { needed: 1, available: 0 }.into())
+        }
+        else if data[0] == 0
+        {
+            Ok((false, 1))
+        }
+        else if data[0] == 1
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            Ok((true, 1))
         }
         else
         {
-            let ret = u16::from_be_bytes([data[0], data[1]]);
-            Ok((ret, 2))
+            Err(Error::InvalidVariant { found: data[0], type_name: "bool" }.into())
+        }
+    }
+
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        match data.first()
+        {
+            Some(&value) => {
+                debug_assert!(value <= 1, "Invalid bool value");
+                Ok((value != 0, 1))
+            },
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
         }
     }
 }
 
-impl Serializable for u8
+impl FixedSize for bool
+{
+    const SIZE: usize = 1;
+
+    fn serialize_to_slice(&self, out: &mut [u8])
+    {
+        out[0] = *self as u8;
+    }
+}
+
+/// Serialized as the `u32` Unicode scalar value
+impl Serializable for char
 {
+    const FIXED_SIZE: Option<usize> = Some(4);
+
     fn serialize(&self) -> Vec<u8> {
-        vec![*self]
+        (*self as u32).serialize()
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        (*self as u32).serialize_append(out);
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (value, read) = u32::deserialize(data)?;
+        match char::from_u32(value)
+        {
+            Some(ret) => Ok((ret, read)),
+            None => Err(Error::InvalidChar { found: value }.into()),
+        }
+    }
+}
+
+impl FixedSize for char
+{
+    const SIZE: usize = 4;
+
+    fn serialize_to_slice(&self, out: &mut [u8])
+    {
+        (*self as u32).serialize_to_slice(out);
+    }
+}
+
+impl Serializable for ()
+{
+    const FIXED_SIZE: Option<usize> = Some(0);
+
+    fn serialize(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn serialize_append(&self, _out: &mut Vec<u8>) {}
+
+    fn deserialize(_data: &[u8]) -> std::io::Result<(Self,usize)> {
+        Ok(((), 0))
+    }
+}
+
+impl FixedSize for ()
+{
+    const SIZE: usize = 0;
+
+    fn serialize_to_slice(&self, _out: &mut [u8]) {}
+}
+
+impl Serializable for SystemTime
+{
+    const FIXED_SIZE: Option<usize> = u64::FIXED_SIZE;
+
+    fn serialize(&self) -> Vec<u8> {
+        let duration = self.duration_since(SystemTime::UNIX_EPOCH).expect("System date earlier than UNIX_EPOCH whick is wrong because today is 2023");
+        duration.as_secs().serialize()
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        let duration = self.duration_since(SystemTime::UNIX_EPOCH).expect("System date earlier than UNIX_EPOCH whick is wrong because today is 2023");
+        duration.as_secs().serialize_append(out);
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (secs, read) = u64::deserialize(data)?;
+        let ret = SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(secs));
+        match ret {
+            Some(time) => Ok((time, read)),
+            None => Err(Error::InvalidValue { type_name: "SystemTime", reason: format!("{secs} seconds after the UNIX epoch are out of range") }.into()),
+        }
+    }
+}
+
+impl<const L: usize, T: Serializable> Serializable for [T;L]
+{
+    const FIXED_SIZE: Option<usize> = match T::FIXED_SIZE
+    {
+        Some(size) => size.checked_mul(L),
+        None => None,
+    };
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        T::serialize_slice(self, out);
+    }
+
+    fn serialized_size(&self) -> usize {
+        items_size(self, L)
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        serialize_slice_deterministic(self, &mut ret);
+        ret
+    }
+
+    // The length of an array is part of its type, so arrays of items serialized in zero bytes
+    // are not bounded by the limit on the counts read from the data, see `set_max_zero_sized_items`
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (items, offset) = match T::FIXED_SIZE
+        {
+            Some(0) => zero_sized_items(L, || T::deserialize(data))?,
+            _ => T::deserialize_vec(data, L)?,
+        };
+        let ret: [T;L] = items.try_into().unwrap_or_else(|_| unreachable!("Exactly L items were deserialized"));
+        Ok((ret, offset))
+    }
+
+    fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+        match T::FIXED_SIZE
+        {
+            Some(0) => (0..L).try_fold(0, |_, i| T::check_bytes(data).map_err(|e| crate::trace::in_item(e, i))),
+            _ => check_items::<T>(data, L),
+        }
+    }
+
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (items, offset) = match T::FIXED_SIZE
+        {
+            Some(0) => zero_sized_items(L, || unsafe { T::deserialize_trusted(data) })?,
+            _ => unsafe { deserialize_items_trusted::<T>(data, L) }?,
+        };
+        let ret: [T;L] = items.try_into().unwrap_or_else(|_| unreachable!("Exactly L items were deserialized"));
+        Ok((ret, offset))
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let (items, offset) = match T::FIXED_SIZE
+        {
+            Some(0) => zero_sized_items(L, || T::deserialize_from(reader))?,
+            _ => read_items::<T>(reader, L)?,
+        };
+        let ret: [T;L] = items.try_into().unwrap_or_else(|_| unreachable!("Exactly L items were deserialized"));
+        Ok((ret, offset))
+    }
+}
+
+/// Reads `len` items serialized in zero bytes with `read`, without the limit of [`set_max_zero_sized_items`]
+fn zero_sized_items<T>(len: usize, mut read: impl FnMut() -> std::io::Result<(T,usize)>) -> std::io::Result<(Vec<T>,usize)>
+{
+    let items = (0..len).map(|i| read().map(|(item, _)| item).map_err(|e| crate::trace::in_item(e, i))).collect::<std::io::Result<Vec<T>>>()?;
+    Ok((items, 0))
+}
+
+impl<const L: usize, T: FixedSize> FixedSize for [T;L]
+{
+    const SIZE: usize = T::SIZE * L;
+
+    fn serialize_to_slice(&self, out: &mut [u8])
+    {
+        for (i, item) in self.iter().enumerate()
+        {
+            item.serialize_to_slice(&mut out[i * T::SIZE..]);
+        }
+    }
+}
+
+impl<T: Serializable> Serializable for Option<T>
+{
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(item) => {
+                out.push(1);
+                item.serialize_append(out);
+            },
+            None => {
+                out.push(0);
+            }
+        }
+    }
+
+    fn serialized_size(&self) -> usize {
+        1 + self.as_ref().map_or(0, Serializable::serialized_size)
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        match self {
+            Some(item) => {
+                let mut ret = vec![1];
+                ret.extend(item.serialize_deterministic());
+                ret
+            },
+            None => vec![0],
+        }
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        if data.is_empty()
+        {
+            Err(Error::UnexpectedEof { needed: 1, available: 0 }.into())
+        }
+        else
+        {
+            match data[0] {
+                0 => Ok((None, 1)),
+                1 => {
+                    let (item, len) = T::deserialize(&data[1..]).map_err(|e| error_at_offset(e, 1))?;
+                    Ok((Some(item), len + 1))
+                },
+                found => Err(Error::InvalidVariant { found, type_name: "Option" }.into())
+            }
+        }
+    }
+
+    fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+        match data.first()
+        {
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => Ok(1),
+            Some(1) => Ok(T::check_bytes(&data[1..]).map_err(|e| error_at_offset(e, 1))? + 1),
+            Some(&found) => Err(Error::InvalidVariant { found, type_name: "Option" }.into()),
+        }
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 1
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        match data.first()
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => Ok((None, 1)),
+            Some(&tag) => {
+                debug_assert!(tag == 1, "Invalid option type");
+                let (item, len) = unsafe { T::deserialize_trusted(&data[1..]) }.map_err(|e| error_at_offset(e, 1))?;
+                Ok((Some(item), len + 1))
+            },
         }
-        else
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        match u8::deserialize_from(reader)?.0
         {
-            Ok((data[0], 1))
+            0 => Ok((None, 1)),
+            1 => {
+                let (item, len) = T::deserialize_from(reader).map_err(|e| error_at_offset(e, 1))?;
+                Ok((Some(item), len + 1))
+            },
+            found => Err(Error::InvalidVariant { found, type_name: "Option" }.into()),
         }
     }
 }
 
-impl Serializable for i128
+/// Serialized as a tag byte, 0 for `Ok` and 1 for `Err`, followed by the value, like an enum with two variants
+impl<T: Serializable, E: Serializable> Serializable for Result<T, E>
 {
     fn serialize(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 16
-        {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        match self {
+            Ok(value) => {
+                out.push(0);
+                value.serialize_append(out);
+            },
+            Err(error) => {
+                out.push(1);
+                error.serialize_append(out);
+            }
         }
-        else
-        {
-            let ret = i128::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7],
-                data[8], data[9], data[10], data[11], data[12], data[13], data[14], data[15]]);
-            Ok((ret, 16))
+    }
+
+    fn serialized_size(&self) -> usize {
+        match self {
+            Ok(value) => 1 + value.serialized_size(),
+            Err(error) => 1 + error.serialized_size(),
         }
     }
-}
 
-impl Serializable for i64
-{
-    fn serialize(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        match self {
+            Ok(value) => {
+                ret.push(0);
+                ret.extend(value.serialize_deterministic());
+            },
+            Err(error) => {
+                ret.push(1);
+                ret.extend(error.serialize_deterministic());
+            }
+        }
+        ret
     }
 
     fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 8
+        match data.first()
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
-        }
-        else
-        {
-            let ret = i64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
-            Ok((ret, 8))
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => {
+                let (value, len) = T::deserialize(&data[1..]).map_err(|e| error_at_offset(e, 1))?;
+                Ok((Ok(value), len + 1))
+            },
+            Some(1) => {
+                let (error, len) = E::deserialize(&data[1..]).map_err(|e| error_at_offset(e, 1))?;
+                Ok((Err(error), len + 1))
+            },
+            Some(&found) => Err(Error::InvalidVariant { found, type_name: "Result" }.into()),
         }
     }
-}
 
-impl Serializable for i32
-{
-    fn serialize(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+    fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+        match data.first()
+        {
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => Ok(T::check_bytes(&data[1..]).map_err(|e| error_at_offset(e, 1))? + 1),
+            Some(1) => Ok(E::check_bytes(&data[1..]).map_err(|e| error_at_offset(e, 1))? + 1),
+            Some(&found) => Err(Error::InvalidVariant { found, type_name: "Result" }.into()),
+        }
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 4
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        match data.first()
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            None => Err(Error::UnexpectedEof { needed: 1, available: 0 }.into()),
+            Some(0) => {
+                let (value, len) = unsafe { T::deserialize_trusted(&data[1..]) }.map_err(|e| error_at_offset(e, 1))?;
+                Ok((Ok(value), len + 1))
+            },
+            Some(&tag) => {
+                debug_assert!(tag == 1, "Invalid result type");
+                let (error, len) = unsafe { E::deserialize_trusted(&data[1..]) }.map_err(|e| error_at_offset(e, 1))?;
+                Ok((Err(error), len + 1))
+            },
         }
-        else
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        match u8::deserialize_from(reader)?.0
         {
-            let ret = i32::from_be_bytes([data[0], data[1], data[2], data[3]]);
-            Ok((ret, 4))
+            0 => {
+                let (value, len) = T::deserialize_from(reader).map_err(|e| error_at_offset(e, 1))?;
+                Ok((Ok(value), len + 1))
+            },
+            1 => {
+                let (error, len) = E::deserialize_from(reader).map_err(|e| error_at_offset(e, 1))?;
+                Ok((Err(error), len + 1))
+            },
+            found => Err(Error::InvalidVariant { found, type_name: "Result" }.into()),
         }
     }
 }
 
-impl Serializable for i16
+/// Implements [`Serializable`] and [`FixedSize`] for a smart pointer, which is serialized like the value it points to
+macro_rules! impl_pointer {
+    ($($pointer:ident),+) => {
+        $(
+            impl<T: Serializable> Serializable for $pointer<T>
+            {
+                const FIXED_SIZE: Option<usize> = T::FIXED_SIZE;
+
+                fn serialize(&self) -> Vec<u8> {
+                    T::serialize(self)
+                }
+
+                fn serialize_append(&self, out: &mut Vec<u8>) {
+                    T::serialize_append(self, out);
+                }
+
+                fn serialized_size(&self) -> usize {
+                    T::serialized_size(self)
+                }
+
+                fn serialize_deterministic(&self) -> Vec<u8> {
+                    T::serialize_deterministic(self)
+                }
+
+                fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+                    let (value, len) = T::deserialize(data)?;
+                    Ok(($pointer::new(value), len))
+                }
+
+                fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+                    T::check_bytes(data)
+                }
+
+                unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+                    let (value, len) = unsafe { T::deserialize_trusted(data) }?;
+                    Ok(($pointer::new(value), len))
+                }
+
+                fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+                    let (value, len) = T::deserialize_from(reader)?;
+                    Ok(($pointer::new(value), len))
+                }
+            }
+
+            impl<T: FixedSize> FixedSize for $pointer<T>
+            {
+                const SIZE: usize = T::SIZE;
+
+                fn serialize_to_slice(&self, out: &mut [u8])
+                {
+                    T::serialize_to_slice(self, out);
+                }
+            }
+        )+
+    };
+}
+
+impl_pointer!(Box, Rc, Arc);
+
+/// Serialized as the number of entries followed by each key and value,
+/// the order of the entries is the iteration order of the map.
+/// [`Serializable::serialize_deterministic`] sorts the entries by the bytes of their keys.
+impl<K: Serializable + Eq + Hash, V: Serializable, S: BuildHasher + Default> Serializable for HashMap<K, V, S>
 {
     fn serialize(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 2
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend(length_prefix(self.len()));
+        for (key, value) in self
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            key.serialize_append(out);
+            value.serialize_append(out);
         }
-        else
+    }
+
+    fn serialized_size(&self) -> usize {
+        4 + items_size(self.keys(), self.len()) + items_size(self.values(), self.len())
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        // Sorting by the serialized key doesn't need K: Ord, and distinct keys never have the same bytes
+        let mut entries: Vec<(Vec<u8>, &V)> = self.iter().map(|(key, value)| (key.serialize_deterministic(), value)).collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let mut ret = Vec::new();
+        ret.extend(length_prefix(self.len()));
+        for (key, value) in entries
         {
-            let ret = i16::from_be_bytes([data[0], data[1]]);
-            Ok((ret, 2))
+            ret.extend(key);
+            ret.extend(value.serialize_deterministic());
         }
+        ret
     }
-}
 
-impl Serializable for i8
-{
-    fn serialize(&self) -> Vec<u8> {
-        vec![*self as u8]
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = HashMap::with_capacity_and_hasher(bounded_capacity(len as usize, data.len() - read), S::default());
+        for _ in 0..len
+        {
+            let entry_offset = read;
+            let (key, key_len) = K::deserialize(remaining(data, read)?).map_err(|e| error_at_offset(e, read))?;
+            read += key_len;
+            let (value, value_len) = V::deserialize(remaining(data, read)?).map_err(|e| error_at_offset(e, read))?;
+            read += value_len;
+            if ret.insert(key, value).is_some()
+            {
+                let error = Error::DuplicateKey.into();
+                return Err(error_at_offset(error, entry_offset));
+            }
+        }
+        Ok((ret, read))
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 1
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = HashMap::with_capacity_and_hasher(bounded_capacity(len as usize, data.len() - read), S::default());
+        for _ in 0..len
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            let (key, key_len) = unsafe { K::deserialize_trusted(remaining(data, read)?) }.map_err(|e| error_at_offset(e, read))?;
+            read += key_len;
+            let (value, value_len) = unsafe { V::deserialize_trusted(remaining(data, read)?) }.map_err(|e| error_at_offset(e, read))?;
+            read += value_len;
+            let previous = ret.insert(key, value);
+            debug_assert!(previous.is_none(), "Duplicate map key in trusted data");
         }
-        else
+        Ok((ret, read))
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize_from(reader)?;
+        let mut ret = HashMap::with_hasher(S::default());
+        for _ in 0..len
         {
-            Ok((data[0] as i8, 1))
+            let entry_offset = read;
+            let (key, key_len) = K::deserialize_from(reader).map_err(|e| error_at_offset(e, read))?;
+            read += key_len;
+            let (value, value_len) = V::deserialize_from(reader).map_err(|e| error_at_offset(e, read))?;
+            read += value_len;
+            if ret.insert(key, value).is_some()
+            {
+                let error = Error::DuplicateKey.into();
+                return Err(error_at_offset(error, entry_offset));
+            }
         }
+        Ok((ret, read))
     }
 }
 
-impl Serializable for f64
+/// Serialized like [`HashMap`], the entries are written in ascending key order
+impl<K: Serializable + Ord, V: Serializable> Serializable for BTreeMap<K, V>
 {
     fn serialize(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 8
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend(length_prefix(self.len()));
+        for (key, value) in self
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            key.serialize_append(out);
+            value.serialize_append(out);
         }
-        else
+    }
+
+    fn serialized_size(&self) -> usize {
+        4 + items_size(self.keys(), self.len()) + items_size(self.values(), self.len())
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        ret.extend(length_prefix(self.len()));
+        for (key, value) in self
         {
-            let ret = f64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]]);
-            Ok((ret, 8))
+            ret.extend(key.serialize_deterministic());
+            ret.extend(value.serialize_deterministic());
         }
+        ret
     }
-}
 
-impl Serializable for f32
-{
-    fn serialize(&self) -> Vec<u8> {
-        self.to_be_bytes().to_vec()
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = BTreeMap::new();
+        for _ in 0..len
+        {
+            let entry_offset = read;
+            let (key, key_len) = K::deserialize(remaining(data, read)?).map_err(|e| error_at_offset(e, read))?;
+            read += key_len;
+            let (value, value_len) = V::deserialize(remaining(data, read)?).map_err(|e| error_at_offset(e, read))?;
+            read += value_len;
+            if ret.insert(key, value).is_some()
+            {
+                let error = Error::DuplicateKey.into();
+                return Err(error_at_offset(error, entry_offset));
+            }
+        }
+        Ok((ret, read))
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 4
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = BTreeMap::new();
+        for _ in 0..len
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            let (key, key_len) = unsafe { K::deserialize_trusted(remaining(data, read)?) }.map_err(|e| error_at_offset(e, read))?;
+            read += key_len;
+            let (value, value_len) = unsafe { V::deserialize_trusted(remaining(data, read)?) }.map_err(|e| error_at_offset(e, read))?;
+            read += value_len;
+            let previous = ret.insert(key, value);
+            debug_assert!(previous.is_none(), "Duplicate map key in trusted data");
         }
-        else
+        Ok((ret, read))
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize_from(reader)?;
+        let mut ret = BTreeMap::new();
+        for _ in 0..len
         {
-            let ret = f32::from_be_bytes([data[0], data[1], data[2], data[3]]);
-            Ok((ret, 4))
+            let entry_offset = read;
+            let (key, key_len) = K::deserialize_from(reader).map_err(|e| error_at_offset(e, read))?;
+            read += key_len;
+            let (value, value_len) = V::deserialize_from(reader).map_err(|e| error_at_offset(e, read))?;
+            read += value_len;
+            if ret.insert(key, value).is_some()
+            {
+                let error = Error::DuplicateKey.into();
+                return Err(error_at_offset(error, entry_offset));
+            }
         }
+        Ok((ret, read))
     }
 }
 
-impl Serializable for bool
+/// Serialized like [`Vec`], the order of the items is the iteration order of the set.
+/// [`Serializable::serialize_deterministic`] sorts the items by their bytes.
+impl<T: Serializable + Eq + Hash, S: BuildHasher + Default> Serializable for HashSet<T, S>
 {
     fn serialize(&self) -> Vec<u8> {
-        match self {
-            false => vec![0],
-            true => vec![1]
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
+    }
+
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend(length_prefix(self.len()));
+        for item in self
+        {
+            item.serialize_append(out);
         }
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 1
+    fn serialized_size(&self) -> usize {
+        4 + items_size(self, self.len())
+    }
+
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        let mut items: Vec<Vec<u8>> = self.iter().map(Serializable::serialize_deterministic).collect();
+        items.sort_unstable();
+        let mut ret = Vec::new();
+        ret.extend(length_prefix(self.len()));
+        for item in items
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            ret.extend(item);
         }
-        else if data[0] == 0
+        ret
+    }
+
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = HashSet::with_capacity_and_hasher(bounded_capacity(len as usize, data.len() - read), S::default());
+        for _ in 0..len
         {
-            Ok((false, 1))
+            let (item, item_len) = T::deserialize(remaining(data, read)?).map_err(|e| error_at_offset(e, read))?;
+            if !ret.insert(item)
+            {
+                let error = Error::DuplicateItem.into();
+                return Err(error_at_offset(error, read));
+            }
+            read += item_len;
         }
-        else if data[0] == 1
+        Ok((ret, read))
+    }
+
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = HashSet::with_capacity_and_hasher(bounded_capacity(len as usize, data.len() - read), S::default());
+        for _ in 0..len
         {
-            Ok((true, 1))
+            let (item, item_len) = unsafe { T::deserialize_trusted(remaining(data, read)?) }.map_err(|e| error_at_offset(e, read))?;
+            let inserted = ret.insert(item);
+            debug_assert!(inserted, "Duplicate set item in trusted data");
+            read += item_len;
         }
-        else
+        Ok((ret, read))
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize_from(reader)?;
+        let mut ret = HashSet::with_hasher(S::default());
+        for _ in 0..len
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid bool value"))
+            let (item, item_len) = T::deserialize_from(reader).map_err(|e| error_at_offset(e, read))?;
+            if !ret.insert(item)
+            {
+                let error = Error::DuplicateItem.into();
+                return Err(error_at_offset(error, read));
+            }
+            read += item_len;
         }
+        Ok((ret, read))
     }
 }
 
-impl Serializable for SystemTime
+/// Serialized like [`Vec`], the items are written in ascending order
+impl<T: Serializable + Ord> Serializable for BTreeSet<T>
 {
     fn serialize(&self) -> Vec<u8> {
-        let duration = self.duration_since(SystemTime::UNIX_EPOCH).expect("System date earlier than UNIX_EPOCH whick is wrong because today is 2023");
-        duration.as_secs().serialize()
+        let mut ret = Vec::new();
+        self.serialize_append(&mut ret);
+        ret
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        let (secs, read) = u64::deserialize(data)?;
-        let ret = SystemTime::UNIX_EPOCH.checked_add(std::time::Duration::from_secs(secs));
-        match ret {
-            Some(time) => Ok((time, read)),
-            None => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid time")),
+    fn serialize_append(&self, out: &mut Vec<u8>) {
+        out.extend(length_prefix(self.len()));
+        for item in self
+        {
+            item.serialize_append(out);
         }
     }
-}
 
-impl<const L: usize, T: Serializable> Serializable for [T;L]
-{
-    fn serialize(&self) -> Vec<u8> {
-        return self.iter().flat_map(|x| x.serialize()).collect();
+    fn serialized_size(&self) -> usize {
+        4 + items_size(self, self.len())
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        let mut ret: [T;L] = unsafe { std::mem::MaybeUninit::uninit().assume_init() };
-        let mut offset = 0;
-        for i in 0..L
+    fn serialize_deterministic(&self) -> Vec<u8> {
+        let mut ret = Vec::new();
+        ret.extend(length_prefix(self.len()));
+        for item in self
         {
-            let (item, len) = T::deserialize(&data[offset..])?;
-            ret[i] = item;
-            offset += len;
+            ret.extend(item.serialize_deterministic());
         }
-        Ok((ret, offset))
+        ret
     }
-}
 
-impl<T: Serializable> Serializable for Option<T>
-{
-    fn serialize(&self) -> Vec<u8> {
-        let mut ret = Vec::new();
-        match self {
-            Some(item) => {
-                ret.push(1);
-                ret.extend(item.serialize());
-            },
-            None => {
-                ret.push(0);
+    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = BTreeSet::new();
+        for _ in 0..len
+        {
+            let (item, item_len) = T::deserialize(remaining(data, read)?).map_err(|e| error_at_offset(e, read))?;
+            if !ret.insert(item)
+            {
+                let error = Error::DuplicateItem.into();
+                return Err(error_at_offset(error, read));
             }
+            read += item_len;
         }
-        ret
+        Ok((ret, read))
     }
 
-    fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
-        if data.len() < 1
+    unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize(data)?;
+        let mut ret = BTreeSet::new();
+        for _ in 0..len
         {
-            Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid data length"))
+            let (item, item_len) = unsafe { T::deserialize_trusted(remaining(data, read)?) }.map_err(|e| error_at_offset(e, read))?;
+            let inserted = ret.insert(item);
+            debug_assert!(inserted, "Duplicate set item in trusted data");
+            read += item_len;
         }
-        else
+        Ok((ret, read))
+    }
+
+    fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+        let (len, mut read) = u32::deserialize_from(reader)?;
+        let mut ret = BTreeSet::new();
+        for _ in 0..len
         {
-            match data[0] {
-                0 => Ok((None, 1)),
-                1 => {
-                    let (item, len) = T::deserialize(&data[1..])?;
-                    Ok((Some(item), len + 1))
-                },
-                _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid option type"))
+            let (item, item_len) = T::deserialize_from(reader).map_err(|e| error_at_offset(e, read))?;
+            if !ret.insert(item)
+            {
+                let error = Error::DuplicateItem.into();
+                return Err(error_at_offset(error, read));
             }
+            read += item_len;
         }
+        Ok((ret, read))
     }
-}
\ No newline at end of file
+}
+
+/// Implements [`Serializable`] for a tuple, serializing its items in order with no separator
+macro_rules! impl_tuple {
+    ($($name:ident $index:tt),+) => {
+        impl<$($name: Serializable),+> Serializable for ($($name,)+)
+        {
+            const FIXED_SIZE: Option<usize> = sum_fixed_sizes(&[$($name::FIXED_SIZE),+]);
+
+            fn serialize(&self) -> Vec<u8> {
+                let mut ret = Vec::new();
+                self.serialize_append(&mut ret);
+                ret
+            }
+
+            fn serialize_append(&self, out: &mut Vec<u8>) {
+                $(self.$index.serialize_append(out);)+
+            }
+
+            fn serialized_size(&self) -> usize {
+                0 $(+ self.$index.serialized_size())+
+            }
+
+            fn serialize_deterministic(&self) -> Vec<u8> {
+                let mut ret = Vec::new();
+                $(ret.extend(self.$index.serialize_deterministic());)+
+                ret
+            }
+
+            fn deserialize(data: &[u8]) -> std::io::Result<(Self,usize)> {
+                let mut offset = 0;
+                let ret = ($({
+                    let (item, len) = $name::deserialize(remaining(data, offset)?).map_err(|e| error_at_offset(e, offset))?;
+                    offset += len;
+                    item
+                },)+);
+                Ok((ret, offset))
+            }
+
+            fn check_bytes(data: &[u8]) -> std::io::Result<usize> {
+                let mut offset = 0;
+                $(offset += $name::check_bytes(remaining(data, offset)?).map_err(|e| error_at_offset(e, offset))?;)+
+                Ok(offset)
+            }
+
+            unsafe fn deserialize_trusted(data: &[u8]) -> std::io::Result<(Self,usize)> {
+                let mut offset = 0;
+                let ret = ($({
+                    let (item, len) = unsafe { $name::deserialize_trusted(remaining(data, offset)?) }.map_err(|e| error_at_offset(e, offset))?;
+                    offset += len;
+                    item
+                },)+);
+                Ok((ret, offset))
+            }
+
+            fn deserialize_from(reader: &mut impl std::io::Read) -> std::io::Result<(Self,usize)> {
+                let mut offset = 0;
+                let ret = ($({
+                    let (item, len) = $name::deserialize_from(reader).map_err(|e| error_at_offset(e, offset))?;
+                    offset += len;
+                    item
+                },)+);
+                Ok((ret, offset))
+            }
+        }
+    };
+}
+
+impl_tuple!(A 0);
+impl_tuple!(A 0, B 1);
+impl_tuple!(A 0, B 1, C 2);
+impl_tuple!(A 0, B 1, C 2, D 3);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+impl_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
@@ -0,0 +1,146 @@
+//! Serialized values in database columns with sqlx.
+//!
+//! [`Stored`] binds a value as its serialization in a binary column, like `BLOB` in SQLite or
+//! `BYTEA` in Postgres, and deserializes it back when a row is fetched.
+
+use std::ops::{Deref, DerefMut};
+
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::{Database, Decode, Encode, Type};
+
+use crate::schema::Describe;
+use crate::Serializable;
+
+/// A column value holding the serialization of a `T`, for every database whose binary type is `Vec<u8>`.
+///
+/// Fetching fails with a decode error if the column doesn't deserialize exactly to a `T`,
+/// the error names the path of the field that could not be read, as given by [`crate::debug::failing_path`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Stored<T>(pub T);
+
+impl<T> Stored<T>
+{
+    /// Returns the stored value
+    pub fn into_inner(self) -> T
+    {
+        self.0
+    }
+}
+
+impl<T> From<T> for Stored<T>
+{
+    fn from(value: T) -> Self
+    {
+        Self(value)
+    }
+}
+
+impl<T> Deref for Stored<T>
+{
+    type Target = T;
+
+    fn deref(&self) -> &T
+    {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Stored<T>
+{
+    fn deref_mut(&mut self) -> &mut T
+    {
+        &mut self.0
+    }
+}
+
+impl<T, DB: Database> Type<DB> for Stored<T>
+where
+    Vec<u8>: Type<DB>,
+{
+    fn type_info() -> DB::TypeInfo
+    {
+        <Vec<u8> as Type<DB>>::type_info()
+    }
+
+    fn compatible(ty: &DB::TypeInfo) -> bool
+    {
+        <Vec<u8> as Type<DB>>::compatible(ty)
+    }
+}
+
+impl<'q, T: Serializable, DB: Database> Encode<'q, DB> for Stored<T>
+where
+    Vec<u8>: Encode<'q, DB>,
+{
+    fn encode_by_ref(&self, buf: &mut <DB as Database>::ArgumentBuffer<'q>) -> Result<IsNull, BoxDynError>
+    {
+        self.0.serialize().encode(buf)
+    }
+}
+
+impl<'r, T: Describe, DB: Database> Decode<'r, DB> for Stored<T>
+where
+    &'r [u8]: Decode<'r, DB>,
+{
+    fn decode(value: <DB as Database>::ValueRef<'r>) -> Result<Self, BoxDynError>
+    {
+        let data = <&[u8] as Decode<DB>>::decode(value)?;
+        T::deserialize_exact(data).map(Self).map_err(|e| {
+            let message = match crate::debug::failing_path(&T::describe(), data)
+            {
+                Some(path) if !path.is_empty() => format!("Cannot deserialize {}: {e} (field {path})", std::any::type_name::<T>()),
+                _ => format!("Cannot deserialize {}: {e}", std::any::type_name::<T>()),
+            };
+            std::io::Error::new(e.kind(), message).into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use sqlx::{Connection, SqliteConnection};
+
+    use super::Stored;
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, Clone, PartialEq)]
+    #[serializable(describe)]
+    struct Profile
+    {
+        name: String,
+        scores: Vec<u32>,
+        active: bool,
+    }
+
+    #[tokio::test]
+    async fn store_and_fetch_from_sqlite()
+    {
+        let mut connection = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        sqlx::query("CREATE TABLE profiles (id INTEGER PRIMARY KEY, profile BLOB NOT NULL)")
+            .execute(&mut connection).await.unwrap();
+        let profile = Profile { name: "ada".to_string(), scores: vec![3, 1, 4], active: true };
+        sqlx::query("INSERT INTO profiles (id, profile) VALUES (1, ?)")
+            .bind(Stored(profile.clone()))
+            .execute(&mut connection).await.unwrap();
+
+        let (fetched,): (Stored<Profile>,) = sqlx::query_as("SELECT profile FROM profiles WHERE id = 1")
+            .fetch_one(&mut connection).await.unwrap();
+        assert_eq!(fetched.name, "ada");
+        assert_eq!(fetched.into_inner(), profile);
+        let (raw,): (Vec<u8>,) = sqlx::query_as("SELECT profile FROM profiles WHERE id = 1")
+            .fetch_one(&mut connection).await.unwrap();
+        assert_eq!(raw, profile.serialize());
+
+        // The last score is cut short
+        sqlx::query("INSERT INTO profiles (id, profile) VALUES (2, ?)")
+            .bind(&raw[..raw.len() - 3])
+            .execute(&mut connection).await.unwrap();
+        let error = sqlx::query_as::<_, (Stored<Profile>,)>("SELECT profile FROM profiles WHERE id = 2")
+            .fetch_one(&mut connection).await.unwrap_err();
+        assert!(matches!(error, sqlx::Error::ColumnDecode { .. }));
+        assert!(error.to_string().contains("(field scores[2])"), "{error}");
+    }
+}
@@ -0,0 +1,382 @@
+//! A key-value map that survives restarts, kept in an append-only log.
+//!
+//! Every change to a [`KvStore`] is appended to a [`crate::log`] file as a put or a delete record,
+//! and opening the store replays the log to rebuild the map. [`KvStore::compact`] replaces the log
+//! with one put record for every entry, dropping the records that were overwritten or deleted.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+
+use crate as serializable;
+use crate::log::{LogReader, LogWriter};
+use crate::persistent::sync_parent_dir;
+use crate::Serializable;
+
+/// A change to the map, as stored in the log
+#[derive(Serializable)]
+enum Record<K, V>
+{
+    Put(K, V),
+    Delete(K),
+}
+
+/// A change to the map borrowing the key and the value from the caller or the map,
+/// that is serialized like the [`Record`] read back from the log
+enum RecordRef<'a, K, V>
+{
+    Put(&'a K, &'a V),
+    Delete(&'a K),
+}
+
+impl<K: Serializable, V: Serializable> RecordRef<'_, K, V>
+{
+    /// Returns the serialization of the matching [`Record`], its variant index followed by its fields
+    fn serialize(&self) -> Vec<u8>
+    {
+        let mut ret = Vec::new();
+        match self
+        {
+            RecordRef::Put(key, value) => {
+                ret.push(0);
+                key.serialize_append(&mut ret);
+                value.serialize_append(&mut ret);
+            },
+            RecordRef::Delete(key) => {
+                ret.push(1);
+                key.serialize_append(&mut ret);
+            },
+        }
+        ret
+    }
+}
+
+/// A `HashMap` whose changes are stored in a log file.
+///
+/// Changes are buffered like the appends of a [`LogWriter`], [`KvStore::sync`] stores them on the disk.
+/// A change torn by a crash is dropped when the store is opened again.
+pub struct KvStore<K: Serializable + Eq + Hash, V: Serializable>
+{
+    path: PathBuf,
+    map: HashMap<K, V>,
+    log: LogWriter<Record<K, V>>,
+    compact_threshold: Option<u64>,
+    next_compaction: u64,
+}
+
+impl<K: Serializable + Eq + Hash, V: Serializable> KvStore<K, V>
+{
+    /// Opens the store kept at `path`, creating an empty one if the file does not exist.
+    /// Fails if a record that is not torn cannot be deserialized, or if a corrupt record is followed by more records,
+    /// which are kept in the file.
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self>
+    {
+        let path = path.as_ref().to_path_buf();
+        let mut map = HashMap::new();
+        match LogReader::<Record<K, V>>::open(&path)
+        {
+            Ok(reader) => {
+                for record in reader
+                {
+                    match record?
+                    {
+                        Record::Put(key, value) => { map.insert(key, value); },
+                        Record::Delete(key) => { map.remove(&key); },
+                    }
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        let log = LogWriter::open(&path)?;
+        Ok(Self { path, map, log, compact_threshold: None, next_compaction: 0 })
+    }
+
+    /// Compacts the log after a change once it is longer than `bytes`,
+    /// or than twice its length after the last compaction if that is longer
+    pub fn with_compact_threshold(mut self, bytes: u64) -> Self
+    {
+        self.compact_threshold = Some(bytes);
+        self.next_compaction = bytes;
+        self
+    }
+
+    /// Returns the value of `key`
+    pub fn get<Q: ?Sized + Eq + Hash>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.map.get(key)
+    }
+
+    /// Returns `true` if the store has a value for `key`
+    pub fn contains_key<Q: ?Sized + Eq + Hash>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Sets the value of `key`, returning the previous one
+    pub fn insert(&mut self, key: K, value: V) -> std::io::Result<Option<V>>
+    {
+        self.log.append_serialized(&RecordRef::Put(&key, &value).serialize())?;
+        let previous = self.map.insert(key, value);
+        self.compact_if_needed()?;
+        Ok(previous)
+    }
+
+    /// Removes `key` from the store, returning its value.
+    /// Nothing is written to the log if the key is not in the store.
+    pub fn remove<Q: ?Sized + Eq + Hash>(&mut self, key: &Q) -> std::io::Result<Option<V>>
+    where
+        K: Borrow<Q>,
+    {
+        let stored_key = match self.map.get_key_value(key)
+        {
+            Some((stored_key, _)) => stored_key,
+            None => return Ok(None),
+        };
+        self.log.append_serialized(&RecordRef::<K, V>::Delete(stored_key).serialize())?;
+        let value = self.map.remove(key);
+        self.compact_if_needed()?;
+        Ok(value)
+    }
+
+    /// Iterates the entries of the store, in no particular order
+    pub fn iter(&self) -> std::collections::hash_map::Iter<'_, K, V>
+    {
+        self.map.iter()
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize
+    {
+        self.map.len()
+    }
+
+    /// Returns `true` if the store has no entries
+    pub fn is_empty(&self) -> bool
+    {
+        self.map.is_empty()
+    }
+
+    /// Returns the length in bytes of the log, including buffered changes
+    pub fn log_len(&self) -> u64
+    {
+        self.log.len()
+    }
+
+    /// Writes the buffered changes to the log and waits until they are stored on the disk
+    pub fn sync(&mut self) -> std::io::Result<()>
+    {
+        self.log.sync()
+    }
+
+    /// Replaces the log with one put record for every entry.
+    ///
+    /// The new log is written next to the old one and renamed over it,
+    /// so that a crash leaves either of them in place.
+    pub fn compact(&mut self) -> std::io::Result<()>
+    {
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".compact");
+        let tmp_path = self.path.with_file_name(tmp_name);
+        let result = self.write_snapshot(&tmp_path);
+        if result.is_err()
+        {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        sync_parent_dir(&self.path)?;
+        self.log = LogWriter::open(&self.path)?;
+        if let Some(threshold) = self.compact_threshold
+        {
+            self.next_compaction = threshold.max(self.log.len().saturating_mul(2));
+        }
+        Ok(())
+    }
+
+    fn write_snapshot(&self, path: &Path) -> std::io::Result<()>
+    {
+        // Whatever is left from an interrupted compaction would be kept by LogWriter::open
+        match std::fs::remove_file(path)
+        {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {},
+        }
+        let mut snapshot = LogWriter::<Record<K, V>>::open(path)?;
+        for (key, value) in &self.map
+        {
+            snapshot.append_serialized(&RecordRef::Put(key, value).serialize())?;
+        }
+        snapshot.sync()
+    }
+
+    fn compact_if_needed(&mut self) -> std::io::Result<()>
+    {
+        if self.compact_threshold.is_some() && self.log.len() > self.next_compaction
+        {
+            self.compact()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::Write;
+
+    use super::{KvStore, Record, RecordRef};
+    use crate::Serializable;
+
+    #[test]
+    fn record_refs_are_serialized_like_records()
+    {
+        let (key, value) = ("primes".to_string(), vec![2u32, 3, 5]);
+        let put = RecordRef::Put(&key, &value).serialize();
+        assert!(matches!(Record::<String, Vec<u32>>::deserialize_exact(&put).unwrap(), Record::Put(k, v) if k == key && v == value));
+        let delete = RecordRef::<String, Vec<u32>>::Delete(&key).serialize();
+        assert!(matches!(Record::<String, Vec<u32>>::deserialize_exact(&delete).unwrap(), Record::Delete(k) if k == key));
+        assert_eq!(delete, Record::<String, Vec<u32>>::Delete(key).serialize());
+    }
+
+    #[test]
+    fn reopen_after_writes()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        let mut store = KvStore::<String, Vec<u32>>::open(&path).unwrap();
+        assert!(store.is_empty());
+        assert_eq!(store.insert("primes".to_string(), vec![2, 3, 5]).unwrap(), None);
+        assert_eq!(store.insert("squares".to_string(), vec![1, 4, 9]).unwrap(), None);
+        assert_eq!(store.insert("primes".to_string(), vec![2, 3, 5, 7]).unwrap(), Some(vec![2, 3, 5]));
+        store.sync().unwrap();
+        drop(store);
+
+        let store = KvStore::<String, Vec<u32>>::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.get("primes"), Some(&vec![2, 3, 5, 7]));
+        assert_eq!(store.get("squares"), Some(&vec![1, 4, 9]));
+        let mut keys: Vec<_> = store.iter().map(|(key, _)| key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, ["primes", "squares"]);
+    }
+
+    #[test]
+    fn deletes_survive_replay()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        let mut store = KvStore::<u32, String>::open(&path).unwrap();
+        for i in 0..5
+        {
+            store.insert(i, format!("value {i}")).unwrap();
+        }
+        assert_eq!(store.remove(&1).unwrap(), Some("value 1".to_string()));
+        let log_len = store.log_len();
+        assert_eq!(store.remove(&1).unwrap(), None);
+        assert_eq!(store.log_len(), log_len);
+        store.remove(&3).unwrap();
+        // A key can be inserted again after being deleted
+        store.insert(3, "again".to_string()).unwrap();
+        drop(store);
+
+        let store = KvStore::<u32, String>::open(&path).unwrap();
+        assert_eq!(store.len(), 4);
+        assert!(!store.contains_key(&1));
+        assert_eq!(store.get(&3).map(String::as_str), Some("again"));
+    }
+
+    #[test]
+    fn torn_record_is_dropped_on_recovery()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        let mut store = KvStore::<u32, u64>::open(&path).unwrap();
+        store.insert(1, 10).unwrap();
+        store.insert(2, 20).unwrap();
+        drop(store);
+        let len = std::fs::metadata(&path).unwrap().len();
+        // Half of a third record, as left by a crash
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(&[0, 0, 0, 13, 1, 2]).unwrap();
+
+        let mut store = KvStore::<u32, u64>::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.log_len(), len);
+        store.insert(3, 30).unwrap();
+        drop(store);
+        assert_eq!(KvStore::<u32, u64>::open(&path).unwrap().get(&3), Some(&30));
+    }
+
+    #[test]
+    fn corrupt_record_before_others_is_kept()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        let mut store = KvStore::<u32, u64>::open(&path).unwrap();
+        store.insert(1, 10).unwrap();
+        store.insert(2, 20).unwrap();
+        drop(store);
+        let mut data = std::fs::read(&path).unwrap();
+        // The last byte of the value of the first record
+        data[8 + 12] ^= 0xFF;
+        std::fs::write(&path, &data).unwrap();
+
+        assert_eq!(KvStore::<u32, u64>::open(&path).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+    }
+
+    #[test]
+    fn compaction_keeps_contents()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        let mut store = KvStore::<u32, u64>::open(&path).unwrap();
+        for round in 0..10
+        {
+            for key in 0..20
+            {
+                store.insert(key, round * 100 + key as u64).unwrap();
+            }
+        }
+        for key in 10..20
+        {
+            store.remove(&key).unwrap();
+        }
+        let log_len = store.log_len();
+        store.compact().unwrap();
+        assert!(store.log_len() < log_len / 10);
+        assert_eq!(store.len(), 10);
+        assert_eq!(store.get(&4), Some(&904));
+        store.insert(20, 1).unwrap();
+        drop(store);
+
+        let store = KvStore::<u32, u64>::open(&path).unwrap();
+        assert_eq!(store.len(), 11);
+        assert!((0..10).all(|key| store.get(&key) == Some(&(900 + key as u64))));
+        assert_eq!(store.get(&20), Some(&1));
+        // Only the log is left in the directory
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn compaction_after_threshold()
+    {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store.log");
+        let mut store = KvStore::<u32, u64>::open(&path).unwrap().with_compact_threshold(1024);
+        for i in 0..1000
+        {
+            store.insert(i % 4, i as u64).unwrap();
+            assert!(store.log_len() <= 1024);
+        }
+        drop(store);
+        let store = KvStore::<u32, u64>::open(&path).unwrap();
+        assert_eq!(store.len(), 4);
+        assert_eq!(store.get(&3), Some(&999));
+    }
+}
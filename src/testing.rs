@@ -0,0 +1,181 @@
+//! Helpers for tests that pin the wire format.
+//!
+//! A round-trip test passes even if the encoding changes, because both directions change together.
+//! [`assert_golden`] compares the serialization with bytes checked into the repository instead,
+//! so that changes of the format show up in review. Run the tests with `UPDATE_GOLDEN=1`
+//! to write the files the first time or after a deliberate change.
+
+use std::fmt::Debug;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::Serializable;
+
+/// Environment variable that makes the golden helpers write the files instead of checking them
+pub const UPDATE_ENV: &str = "UPDATE_GOLDEN";
+
+/// Bytes shown on every row of the hex diff
+const ROW_LEN: usize = 16;
+/// Maximum number of differing rows in the hex diff
+const MAX_ROWS: usize = 32;
+
+/// Panics with a hex diff if the serialization of `value` is not the content of the file at `path`.
+/// If [`UPDATE_ENV`] is set to `1` the file is written instead, creating its directory if needed.
+pub fn assert_golden<T: Serializable>(value: &T, path: impl AsRef<Path>)
+{
+    let path = path.as_ref();
+    let actual = value.serialize();
+    if std::env::var(UPDATE_ENV).is_ok_and(|update| update == "1")
+    {
+        if let Some(dir) = path.parent()
+        {
+            std::fs::create_dir_all(dir).unwrap_or_else(|e| panic!("Cannot create {}: {e}", dir.display()));
+        }
+        std::fs::write(path, &actual).unwrap_or_else(|e| panic!("Cannot write {}: {e}", path.display()));
+        return;
+    }
+    let expected = match std::fs::read(path)
+    {
+        Ok(expected) => expected,
+        Err(e) => panic!("Cannot read golden file {}: {e}, run with {UPDATE_ENV}=1 to create it", path.display()),
+    };
+    if expected != actual
+    {
+        panic!(
+            "Serialization of {} differs from golden file {}, run with {UPDATE_ENV}=1 if the change is deliberate\n{}",
+            std::any::type_name::<T>(), path.display(), hex_diff(&expected, &actual)
+        );
+    }
+}
+
+/// Like [`assert_golden`], and also checks that the bytes of the golden file deserialize to `value`,
+/// so that data written by older versions can still be read
+pub fn assert_golden_roundtrip<T: Serializable + PartialEq + Debug>(value: &T, path: impl AsRef<Path>)
+{
+    let path = path.as_ref();
+    assert_golden(value, path);
+    let bytes = std::fs::read(path).unwrap_or_else(|e| panic!("Cannot read golden file {}: {e}", path.display()));
+    match T::deserialize_exact(&bytes)
+    {
+        Ok(deserialized) => assert_eq!(&deserialized, value, "Golden file {} deserializes to a different value", path.display()),
+        Err(e) => panic!("Golden file {} cannot be deserialized as {}: {e}", path.display(), std::any::type_name::<T>()),
+    }
+}
+
+/// Returns the rows of `expected` and `actual` that differ, side by side in hex with their offsets,
+/// marking the differing bytes with `^`
+pub fn hex_diff(expected: &[u8], actual: &[u8]) -> String
+{
+    let mut ret = String::new();
+    let first = expected.iter().zip(actual).position(|(a, b)| a != b).unwrap_or(expected.len().min(actual.len()));
+    let _ = writeln!(ret, "expected {} bytes, found {} bytes, first difference at offset {first:#06x}", expected.len(), actual.len());
+    let rows = expected.len().max(actual.len()).div_ceil(ROW_LEN);
+    let mut shown = 0;
+    for row in 0..rows
+    {
+        let range = row * ROW_LEN..(row + 1) * ROW_LEN;
+        let expected_row = expected.get(range.start..range.end.min(expected.len())).unwrap_or_default();
+        let actual_row = actual.get(range.start..range.end.min(actual.len())).unwrap_or_default();
+        if expected_row == actual_row
+        {
+            continue;
+        }
+        if shown == MAX_ROWS
+        {
+            let _ = writeln!(ret, "...");
+            break;
+        }
+        shown += 1;
+        let _ = writeln!(ret, "{:06x} - {}", range.start, hex_row(expected_row));
+        let _ = writeln!(ret, "{:06x} + {}", range.start, hex_row(actual_row));
+        let markers: String = (0..ROW_LEN).map(|i| if expected_row.get(i) == actual_row.get(i) { "   " } else { "^^ " }).collect();
+        let _ = writeln!(ret, "         {}", markers.trim_end());
+    }
+    ret
+}
+
+fn hex_row(bytes: &[u8]) -> String
+{
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::collections::{BTreeMap, HashMap, VecDeque};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+    use std::time::{Duration, SystemTime};
+
+    use super::{assert_golden, assert_golden_roundtrip, hex_diff};
+
+    fn golden(name: &str) -> String
+    {
+        format!("{}/src/testdata/golden/{name}.bin", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    #[test]
+    fn pin_builtin_encodings()
+    {
+        assert_golden_roundtrip(&0xABu8, golden("u8"));
+        assert_golden_roundtrip(&0xABCDu16, golden("u16"));
+        assert_golden_roundtrip(&0x0123_4567u32, golden("u32"));
+        assert_golden_roundtrip(&0x0123_4567_89AB_CDEFu64, golden("u64"));
+        assert_golden_roundtrip(&0x0123_4567_89AB_CDEF_FEDC_BA98_7654_3210u128, golden("u128"));
+        assert_golden_roundtrip(&-2i8, golden("i8"));
+        assert_golden_roundtrip(&-2i16, golden("i16"));
+        assert_golden_roundtrip(&-2i32, golden("i32"));
+        assert_golden_roundtrip(&-2i64, golden("i64"));
+        assert_golden_roundtrip(&-2i128, golden("i128"));
+        assert_golden_roundtrip(&1.5f32, golden("f32"));
+        assert_golden_roundtrip(&-1.5f64, golden("f64"));
+        assert_golden_roundtrip(&true, golden("bool"));
+        assert_golden_roundtrip(&(), golden("unit"));
+        assert_golden_roundtrip(&"Hello, wörld".to_string(), golden("string"));
+        assert_golden_roundtrip(&(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)), golden("system_time"));
+        assert_golden_roundtrip(&Ipv4Addr::new(192, 0, 2, 1), golden("ipv4_addr"));
+        assert_golden_roundtrip(&SocketAddrV4::new(Ipv4Addr::new(192, 0, 2, 1), 8080), golden("socket_addr_v4"));
+        assert_golden_roundtrip(&IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), golden("ip_addr_v4"));
+        assert_golden_roundtrip(&IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)), golden("ip_addr_v6"));
+        assert_golden_roundtrip(&SocketAddr::from(([192, 0, 2, 1], 8080)), golden("socket_addr_ipv4"));
+        assert_golden_roundtrip(&SocketAddr::from((Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 8080)), golden("socket_addr_ipv6"));
+        assert_golden_roundtrip(&vec![1u32, 2, 3], golden("vec_u32"));
+        assert_golden_roundtrip(&vec!["a".to_string(), "bc".to_string()], golden("vec_string"));
+        assert_golden_roundtrip(&VecDeque::from([1u16, 2]), golden("vec_deque"));
+        assert_golden_roundtrip(&Some(7u8), golden("option_some"));
+        assert_golden_roundtrip(&None::<u8>, golden("option_none"));
+        assert_golden_roundtrip(&[1u16, 2, 3], golden("array"));
+        assert_golden_roundtrip(&HashMap::from([(1u8, "one".to_string())]), golden("hash_map"));
+        assert_golden_roundtrip(&BTreeMap::from([(2u8, 20u16), (1, 10)]), golden("btree_map"));
+        assert_golden_roundtrip(&(1u8, "two".to_string(), 3u32), golden("tuple"));
+    }
+
+    #[test]
+    #[should_panic(expected = "differs from golden file")]
+    fn fail_on_changed_encoding()
+    {
+        // A copy of the golden file, so that running with UPDATE_GOLDEN=1 cannot overwrite the real one
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("u32.bin");
+        std::fs::copy(golden("u32"), &path).unwrap();
+        assert_golden(&0x0123_4568u32, &path);
+    }
+
+    #[test]
+    fn diff_differing_rows()
+    {
+        let expected: Vec<u8> = (0..40).collect();
+        let mut actual = expected.clone();
+        actual[18] = 0xff;
+        actual.push(0xee);
+        let diff = hex_diff(&expected, &actual);
+        assert_eq!(diff, "\
+expected 40 bytes, found 41 bytes, first difference at offset 0x0012
+000010 - 10 11 12 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f
+000010 + 10 11 ff 13 14 15 16 17 18 19 1a 1b 1c 1d 1e 1f
+               ^^
+000020 - 20 21 22 23 24 25 26 27
+000020 + 20 21 22 23 24 25 26 27 ee
+                                 ^^
+");
+    }
+}
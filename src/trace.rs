@@ -0,0 +1,283 @@
+//! Diagnostics of serialization through `tracing`, enabled by the `tracing` feature.
+//!
+//! Derived types enter a span named after the type in `serialize` and `deserialize`,
+//! and record the number of bytes written or read in a `trace` event with the `serializable` target.
+//! A failed deserialization records a `debug` event with the offset and the path of the value that failed,
+//! which is also available from [`crate::DeserializeError::path`]. The framing and datagram helpers
+//! record the frames and datagrams they write and read the same way.
+//!
+//! Without the feature the hooks only record the path and the type of a failure in the error,
+//! the rest of them are empty inline functions.
+
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+pub use tracing;
+
+/// Enters a span named after a type until the end of the enclosing block, used by derived code
+#[cfg(feature = "tracing")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_span {
+    ($name:literal, $operation:literal) => {
+        let _span = $crate::trace::tracing::trace_span!(target: "serializable", $name, operation = $operation).entered();
+    };
+}
+
+/// Enters a span named after a type until the end of the enclosing block, used by derived code
+#[cfg(not(feature = "tracing"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __trace_span {
+    ($name:literal, $operation:literal) => {};
+}
+
+/// Records the number of bytes written by `serialize`
+#[doc(hidden)]
+#[inline(always)]
+pub fn serialized(bytes: &[u8])
+{
+    #[cfg(feature = "tracing")]
+    tracing::trace!(target: "serializable", bytes = bytes.len(), "serialized");
+    #[cfg(not(feature = "tracing"))]
+    let _ = bytes;
+}
+
+/// Records the number of bytes read by `deserialize` of `type_name`, or the offset and path of the failure.
+/// A failure also remembers `type_name` as the root of its path, even without the feature.
+#[doc(hidden)]
+#[inline(always)]
+pub fn deserialized<T>(type_name: &'static str, result: std::io::Result<(T,usize)>) -> std::io::Result<(T,usize)>
+{
+    #[cfg(feature = "tracing")]
+    match &result
+    {
+        Ok((_, len)) => tracing::trace!(target: "serializable", bytes = len, "deserialized"),
+        Err(e) => failed(type_name, e),
+    }
+    result.map_err(|e| crate::serializable::error_in_type(e, type_name))
+}
+
+/// Records a failed deserialization of `type_name` in a `debug` event
+#[cfg(feature = "tracing")]
+pub(crate) fn failed(type_name: &str, error: &std::io::Error)
+{
+    let (offset, path) = match crate::DeserializeError::find(error)
+    {
+        Some(inner) => (inner.offset(), inner.path()),
+        None => (0, ""),
+    };
+    tracing::debug!(target: "serializable", r#type = type_name, offset, path, error = %error, "deserialization failed");
+}
+
+/// Adds the name of the field that failed to the path of `error`, used by derived code
+#[doc(hidden)]
+#[inline(always)]
+pub fn in_field(error: std::io::Error, name: &'static str) -> std::io::Error
+{
+    crate::serializable::error_in_path(error, name)
+}
+
+/// Adds the index of the item that failed to the path of `error`, used by collections
+#[inline(always)]
+pub(crate) fn in_item(error: std::io::Error, index: usize) -> std::io::Error
+{
+    crate::serializable::error_in_path(error, &format!("[{index}]"))
+}
+
+/// Records a frame or a datagram of `len` bytes that was written
+#[inline(always)]
+pub(crate) fn written(len: usize)
+{
+    #[cfg(feature = "tracing")]
+    tracing::trace!(target: "serializable", bytes = len, "written");
+    #[cfg(not(feature = "tracing"))]
+    let _ = len;
+}
+
+/// Records a frame or a datagram of `len` bytes that was read
+#[inline(always)]
+pub(crate) fn read(len: usize)
+{
+    #[cfg(feature = "tracing")]
+    tracing::trace!(target: "serializable", bytes = len, "read");
+    #[cfg(not(feature = "tracing"))]
+    let _ = len;
+}
+
+/// Records the result of deserializing a `T` from a frame or a datagram, a failure in a `debug` event
+#[inline(always)]
+pub(crate) fn read_value<T>(result: std::io::Result<T>) -> std::io::Result<T>
+{
+    #[cfg(feature = "tracing")]
+    if let Err(e) = &result
+    {
+        failed(std::any::type_name::<T>(), e);
+    }
+    result
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests
+{
+    use std::sync::{Arc, Mutex};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate as serializable;
+    use crate::{DeserializeError, Error, Serializable};
+
+    /// The fields of an event, or of a span with the name of the span in `name`
+    #[derive(Debug, Default)]
+    struct Recorded
+    {
+        name: String,
+        fields: Vec<(String, String)>,
+    }
+
+    impl Recorded
+    {
+        fn get(&self, name: &str) -> Option<&str>
+        {
+            self.fields.iter().find(|(field, _)| field == name).map(|(_, value)| value.as_str())
+        }
+    }
+
+    impl Visit for Recorded
+    {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug)
+        {
+            self.fields.push((field.name().to_string(), format!("{value:?}")));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str)
+        {
+            self.fields.push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    /// Keeps the spans and events with the `serializable` target
+    #[derive(Default, Clone)]
+    struct Collector
+    {
+        spans: Arc<Mutex<Vec<Recorded>>>,
+        events: Arc<Mutex<Vec<Recorded>>>,
+    }
+
+    impl Subscriber for Collector
+    {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool
+        {
+            metadata.target() == "serializable"
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id
+        {
+            let mut recorded = Recorded { name: span.metadata().name().to_string(), fields: Vec::new() };
+            span.record(&mut recorded);
+            let mut spans = self.spans.lock().unwrap();
+            spans.push(recorded);
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>)
+        {
+            let mut recorded = Recorded { name: event.metadata().name().to_string(), fields: Vec::new() };
+            event.record(&mut recorded);
+            self.events.lock().unwrap().push(recorded);
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Endpoint
+    {
+        host: String,
+        port: u16,
+        secure: bool,
+    }
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Route
+    {
+        name: String,
+        endpoints: Vec<Endpoint>,
+    }
+
+    #[test]
+    fn spans_and_byte_counts()
+    {
+        let collector = Collector::default();
+        let route = Route { name: "api".to_string(), endpoints: vec![Endpoint { host: "a".to_string(), port: 80, secure: false }] };
+        let serialized = tracing::subscriber::with_default(collector.clone(), || {
+            let serialized = route.serialize();
+            assert_eq!(Route::deserialize(&serialized).unwrap().0, route);
+            serialized
+        });
+        let spans = collector.spans.lock().unwrap();
+        let names: Vec<_> = spans.iter().map(|span| (span.name.as_str(), span.get("operation").unwrap())).collect();
+        assert_eq!(names, [("Route", "serialize"), ("Endpoint", "serialize"), ("Route", "deserialize"), ("Endpoint", "deserialize")]);
+        let events = collector.events.lock().unwrap();
+        let byte_counts: Vec<_> = events.iter().map(|event| event.get("bytes").unwrap()).collect();
+        let (route_len, endpoint_len) = (serialized.len().to_string(), (4 + 1 + 2 + 1).to_string());
+        assert_eq!(byte_counts, [&endpoint_len, &route_len, &endpoint_len, &route_len]);
+    }
+
+    #[test]
+    fn failure_names_the_field()
+    {
+        let collector = Collector::default();
+        let route = Route {
+            name: "api".to_string(),
+            endpoints: vec![
+                Endpoint { host: "a".to_string(), port: 80, secure: false },
+                Endpoint { host: "b".to_string(), port: 443, secure: true },
+            ],
+        };
+        let mut serialized = route.serialize();
+        // The secure flag of the second endpoint
+        let last = serialized.len() - 1;
+        serialized[last] = 7;
+        let error = tracing::subscriber::with_default(collector.clone(), || Route::deserialize(&serialized).unwrap_err());
+        let inner = DeserializeError::find(&error).unwrap();
+        assert_eq!(inner.path(), "endpoints[1].secure");
+        assert_eq!(inner.offset(), last);
+
+        let events = collector.events.lock().unwrap();
+        let failures: Vec<_> = events.iter().filter(|event| event.get("path").is_some()).collect();
+        // Every derived type on the way out reports the path relative to itself
+        let paths: Vec<_> = failures.iter().map(|event| (event.get("type").unwrap(), event.get("path").unwrap())).collect();
+        assert_eq!(paths, [("Endpoint", "secure"), ("Route", "endpoints[1].secure")]);
+        assert_eq!(failures[1].get("offset").unwrap(), last.to_string());
+        let reason = Error::InvalidVariant { found: 7, type_name: "bool" };
+        assert_eq!(Error::find(&error), Some(&reason));
+        assert!(failures[1].get("error").unwrap().contains(&reason.to_string()));
+    }
+
+    #[test]
+    fn frames_are_recorded()
+    {
+        let collector = Collector::default();
+        let endpoint = Endpoint { host: "a".to_string(), port: 80, secure: false };
+        let mut stream = Vec::new();
+        tracing::subscriber::with_default(collector.clone(), || {
+            crate::framing::write_value(&mut stream, &endpoint).unwrap();
+            stream[4 + 7] = 2;
+            assert!(crate::framing::read_value::<_, Endpoint>(&mut stream.as_slice(), 1024).is_err());
+        });
+        let events = collector.events.lock().unwrap();
+        assert!(events.iter().any(|event| event.get("message") == Some("written") && event.get("bytes") == Some("8")));
+        assert!(events.iter().any(|event| event.get("message") == Some("read") && event.get("bytes") == Some("8")));
+        let failure = events.iter().rev().find(|event| event.get("path").is_some()).unwrap();
+        assert!(failure.get("type").unwrap().ends_with("Endpoint"));
+        assert_eq!(failure.get("path"), Some("secure"));
+    }
+}
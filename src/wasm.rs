@@ -0,0 +1,54 @@
+//! Passing serialized values between WebAssembly and JavaScript.
+//!
+//! The bytes cross the boundary as a `Uint8Array`, the TypeScript side can document their layout
+//! with the declarations of [`crate::schema::to_typescript`].
+
+use js_sys::Uint8Array;
+use wasm_bindgen::JsValue;
+
+use crate::Serializable;
+
+/// Returns the serialization of `value` as a new `Uint8Array`
+pub fn serialize_to_js<T: Serializable>(value: &T) -> Uint8Array
+{
+    Uint8Array::from(value.serialize().as_slice())
+}
+
+/// Deserializes the whole content of `array`, failures become a JavaScript `Error` with the message
+/// of the deserialization error, which `wasm_bindgen` throws when returned from an exported function
+pub fn deserialize_from_js<T: Serializable>(array: &Uint8Array) -> Result<T, JsValue>
+{
+    T::deserialize_exact(&array.to_vec()).map_err(|e| js_sys::Error::new(&e.to_string()).into())
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests
+{
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::{deserialize_from_js, serialize_to_js};
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct ChatMessage
+    {
+        room: u32,
+        author: String,
+        text: String,
+    }
+
+    #[wasm_bindgen_test]
+    fn roundtrip_through_uint8_array()
+    {
+        let message = ChatMessage { room: 7, author: "ada".to_string(), text: "Hello world".to_string() };
+        let array = serialize_to_js(&message);
+        assert_eq!(array.length() as usize, message.serialize().len());
+        assert_eq!(deserialize_from_js::<ChatMessage>(&array).unwrap(), message);
+
+        let truncated = array.subarray(0, array.length() - 1);
+        let error = deserialize_from_js::<ChatMessage>(&truncated).unwrap_err();
+        let error: js_sys::Error = error.into();
+        assert_eq!(String::from(error.message()), "while deserializing ChatMessage.text at offset 11: Unexpected end of data, 15 bytes needed but 14 left");
+    }
+}
@@ -0,0 +1,126 @@
+//! Exchanging serialized values as binary WebSocket messages with tungstenite.
+
+use std::io::{Read, Write};
+
+use tungstenite::{Message, WebSocket};
+
+use crate::{Error, Serializable};
+
+/// Sends and receives serialized values as binary messages over a [`WebSocket`]
+pub trait SerializableWebSocketExt
+{
+    /// Sends the serialization of `value` as a single binary message
+    fn send_serialized<T: Serializable>(&mut self, value: &T) -> std::io::Result<()>;
+    /// Waits for the next binary message and deserializes it, the value must take the whole message.
+    /// Ping and pong messages are skipped, pings are answered while waiting.
+    /// Text messages are rejected with [`std::io::ErrorKind::InvalidData`] and a close message
+    /// fails with [`std::io::ErrorKind::ConnectionAborted`].
+    fn recv_serialized<T: Serializable>(&mut self) -> std::io::Result<T>;
+}
+
+impl<S: Read + Write> SerializableWebSocketExt for WebSocket<S>
+{
+    fn send_serialized<T: Serializable>(&mut self, value: &T) -> std::io::Result<()>
+    {
+        self.send(Message::binary(value.serialize())).map_err(into_io_error)
+    }
+
+    fn recv_serialized<T: Serializable>(&mut self) -> std::io::Result<T>
+    {
+        loop
+        {
+            match self.read().map_err(into_io_error)?
+            {
+                Message::Binary(payload) => return T::deserialize_exact(&payload),
+                Message::Text(_) => {
+                    let reason = "a text message was received instead of a binary one".to_string();
+                    return Err(Error::InvalidValue { type_name: std::any::type_name::<T>(), reason }.into());
+                },
+                Message::Close(_) => return Err(std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "WebSocket closed")),
+                // tungstenite queues the pong itself, flushing sends it right away
+                Message::Ping(_) => self.flush().map_err(into_io_error)?,
+                Message::Pong(_) | Message::Frame(_) => {},
+            }
+        }
+    }
+}
+
+fn into_io_error(error: tungstenite::Error) -> std::io::Error
+{
+    match error
+    {
+        tungstenite::Error::Io(error) => error,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => {
+            std::io::Error::new(std::io::ErrorKind::ConnectionAborted, error)
+        },
+        error => std::io::Error::other(error),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::net::TcpListener;
+
+    use tungstenite::Message;
+
+    use super::SerializableWebSocketExt;
+    use crate as serializable;
+    use crate::Serializable;
+
+    #[derive(Serializable, Debug, PartialEq)]
+    struct Chat
+    {
+        room: String,
+        text: String,
+    }
+
+    /// Accepts a single client, pings it, then echoes every binary message
+    /// and answers the text "text" with a text message
+    fn spawn_echo_server() -> (std::net::SocketAddr, std::thread::JoinHandle<()>)
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            socket.send(Message::Ping(Vec::new().into())).unwrap();
+            loop
+            {
+                match socket.read()
+                {
+                    Ok(Message::Binary(payload)) => socket.send(Message::Binary(payload)).unwrap(),
+                    Ok(Message::Text(_)) => socket.send(Message::text("text")).unwrap(),
+                    Ok(_) => {},
+                    Err(_) => break,
+                }
+            }
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn websocket_echo()
+    {
+        let (addr, server) = spawn_echo_server();
+        let (mut socket, _) = tungstenite::connect(format!("ws://{addr}")).unwrap();
+
+        let chat = Chat { room: "general".to_string(), text: "hello".to_string() };
+        socket.send_serialized(&chat).unwrap();
+        assert_eq!(socket.recv_serialized::<Chat>().unwrap(), chat);
+
+        let numbers: Vec<u64> = (0..1000).collect();
+        socket.send_serialized(&numbers).unwrap();
+        assert_eq!(socket.recv_serialized::<Vec<u64>>().unwrap(), numbers);
+
+        socket.send(Message::text("text")).unwrap();
+        assert_eq!(socket.recv_serialized::<Chat>().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        socket.send_serialized(&chat).unwrap();
+        assert_eq!(socket.recv_serialized::<u32>().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+        socket.close(None).unwrap();
+        drop(socket);
+        server.join().unwrap();
+    }
+}